@@ -0,0 +1,362 @@
+//! Burst detection, to review or collapse burst frames apart from similar images.
+//!
+//! This module provides functions to:
+//! - Group frames that share an Apple `BurstUUID`
+//! - Group frames named as a burst by Pixel (`..._BURST20190101123456789`,
+//!   `PXL_..._BURST`) and Samsung (`20230514_101112_001`) cameras
+//! - Group frames from the same camera whose sub-second capture times follow
+//!   each other within a window
+//!
+//! A file belongs to at most one burst; the BurstUUID wins over the name,
+//! and the name over timestamps. Only timestamps with SubSecTimeOriginal take
+//! part, since whole seconds can't tell a burst from a quick series of shots.
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::media_types::MediaCategory;
+use crate::metadata::{self, ExifMetadata};
+use crate::metadata_index;
+use crate::state::AppState;
+
+/// Frames further apart than this (ms) are separate shots by default
+const DEFAULT_WINDOW_MS: u64 = 1000;
+
+/// Timestamp-only bursts need at least this many frames by default
+const DEFAULT_MIN_FRAMES: usize = 3;
+
+/// What put the frames of a burst together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BurstSource {
+    /// Shared Apple BurstUUID
+    BurstUuid,
+    /// Pixel or Samsung burst file names
+    FileName,
+    /// Consecutive sub-second capture times from one camera
+    Timestamp,
+}
+
+/// A single frame of a burst
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurstFile {
+    pub path: String,
+    pub size: u64,
+    pub date_taken: Option<String>,
+    /// The frame the camera picked (Pixel `COVER`), else the first frame
+    pub cover: bool,
+}
+
+/// Frames shot as one burst, in capture order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurstGroup {
+    pub files: Vec<BurstFile>,
+    pub source: BurstSource,
+    pub camera_model: Option<String>,
+}
+
+/// Result of a burst scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurstResult {
+    pub burst_groups: Vec<BurstGroup>,
+    pub total_groups: usize,
+    pub total_files: usize,
+}
+
+/// What grouping needs to know about one file
+#[derive(Debug, Clone)]
+pub struct BurstCandidate {
+    pub path: String,
+    pub size: u64,
+    pub burst_uuid: Option<String>,
+    pub camera_model: Option<String>,
+    pub date_time_original: Option<String>,
+    pub sub_sec_time_original: Option<String>,
+}
+
+impl BurstCandidate {
+    fn from_metadata(path: String, size: u64, metadata: Option<ExifMetadata>) -> Self {
+        let Some(metadata) = metadata else {
+            return BurstCandidate {
+                path,
+                size,
+                burst_uuid: None,
+                camera_model: None,
+                date_time_original: None,
+                sub_sec_time_original: None,
+            };
+        };
+        BurstCandidate {
+            path,
+            size,
+            burst_uuid: metadata.burst_uuid.clone(),
+            date_time_original: metadata.date_time_original.clone(),
+            sub_sec_time_original: metadata.sub_sec_time_original.clone(),
+            camera_model: metadata::camera_model_of(metadata),
+        }
+    }
+
+    /// Capture time in ms, only when it has sub-second precision
+    fn taken_ms(&self) -> Option<i64> {
+        let subsec = self.sub_sec_time_original.as_deref()?.trim();
+        if subsec.is_empty() || !subsec.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let date = NaiveDateTime::parse_from_str(self.date_time_original.as_deref()?.get(..19)?, "%Y:%m:%d %H:%M:%S").ok()?;
+        // "5" is 500 ms and "123456" is 123 ms
+        let ms: i64 = format!("{:0<3}", subsec).get(..3)?.parse().ok()?;
+        Some(date.and_utc().timestamp_millis() + ms)
+    }
+
+    fn stem(&self) -> String {
+        let path = Path::new(&self.path);
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
+        format!("{}/{}", path.parent().map(|p| p.to_string_lossy()).unwrap_or_default(), stem)
+    }
+}
+
+fn burst_name_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // Older Pixel: 00000IMG_00000_BURST20190101123456789_COVER
+            r"(?i)^(?:\d+IMG_\d+_)?BURST(\d{14,17})",
+            // Pixel: PXL_20210101_120000000.BURST-01.COVER or PXL_..._BURST
+            r"(?i)^(PXL_\d{8}_\d{6,9})[._-]BURST",
+            // Samsung: 20230514_101112_001
+            r"^(\d{8}_\d{6})_\d{3}$",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("built-in burst pattern"))
+        .collect()
+    })
+}
+
+/// The burst a file name marks, scoped to its folder
+fn burst_name_key(file_path: &str) -> Option<String> {
+    let path = Path::new(file_path);
+    let name = path.file_name()?.to_str()?;
+    // The Samsung pattern is anchored on the stem, the Pixel ones on the name
+    let stem = name.split('.').next().unwrap_or(name);
+    burst_name_patterns().iter().find_map(|pattern| {
+        let captures = pattern.captures(name).or_else(|| pattern.captures(stem))?;
+        Some(format!("{}/{}", path.parent()?.to_string_lossy(), captures[1].to_lowercase()))
+    })
+}
+
+/// Order frames by capture time and mark the cover
+fn make_group(mut members: Vec<&BurstCandidate>, source: BurstSource) -> BurstGroup {
+    members.sort_by(|a, b| a.taken_ms().cmp(&b.taken_ms()).then_with(|| a.path.cmp(&b.path)));
+    let cover = members
+        .iter()
+        .position(|c| Path::new(&c.path).file_name().is_some_and(|n| n.to_string_lossy().to_uppercase().contains("COVER")))
+        .unwrap_or(0);
+    BurstGroup {
+        camera_model: members.iter().find_map(|c| c.camera_model.clone()),
+        files: members
+            .iter()
+            .enumerate()
+            .map(|(i, c)| BurstFile {
+                path: c.path.clone(),
+                size: c.size,
+                date_taken: c.date_time_original.clone(),
+                cover: i == cover,
+            })
+            .collect(),
+        source,
+    }
+}
+
+/// Number of shots among `members`, counting a RAW+JPEG pair once
+fn distinct_shots(members: &[&BurstCandidate]) -> usize {
+    members.iter().map(|c| c.stem()).collect::<HashSet<_>>().len()
+}
+
+/// Group `candidates` into bursts. Frames further than `window_ms` apart
+/// split a timestamp burst, which needs `min_frames` shots to count.
+pub fn group_bursts(candidates: &[BurstCandidate], window_ms: u64, min_frames: usize) -> Vec<BurstGroup> {
+    let mut groups = Vec::new();
+    let mut taken = vec![false; candidates.len()];
+
+    // BurstUUID, then burst names: any two frames make a burst
+    for source in [BurstSource::BurstUuid, BurstSource::FileName] {
+        let mut by_key: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, candidate) in candidates.iter().enumerate() {
+            let key = match source {
+                BurstSource::BurstUuid => candidate.burst_uuid.clone().filter(|u| !u.trim().is_empty()),
+                _ => burst_name_key(&candidate.path),
+            };
+            if let Some(key) = key.filter(|_| !taken[i]) {
+                by_key.entry(key).or_default().push(i);
+            }
+        }
+        for indexes in by_key.into_values() {
+            let members: Vec<&BurstCandidate> = indexes.iter().map(|&i| &candidates[i]).collect();
+            if distinct_shots(&members) < 2 {
+                continue;
+            }
+            indexes.iter().for_each(|&i| taken[i] = true);
+            groups.push(make_group(members, source));
+        }
+    }
+
+    // Consecutive sub-second timestamps from one camera
+    let mut by_camera: BTreeMap<String, Vec<(i64, usize)>> = BTreeMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        if taken[i] {
+            continue;
+        }
+        if let (Some(camera), Some(ms)) = (&candidate.camera_model, candidate.taken_ms()) {
+            by_camera.entry(camera.clone()).or_default().push((ms, i));
+        }
+    }
+    let window_ms = window_ms as i64;
+    for mut frames in by_camera.into_values() {
+        frames.sort();
+        let mut runs: Vec<Vec<(i64, usize)>> = Vec::new();
+        for frame in frames {
+            match runs.last_mut() {
+                Some(run) if frame.0 - run.last().map_or(frame.0, |f| f.0) <= window_ms => run.push(frame),
+                _ => runs.push(vec![frame]),
+            }
+        }
+        for run in runs {
+            let members: Vec<&BurstCandidate> = run.iter().map(|&(_, i)| &candidates[i]).collect();
+            if distinct_shots(&members) >= min_frames.max(2) {
+                groups.push(make_group(members, BurstSource::Timestamp));
+            }
+        }
+    }
+
+    groups.sort_by(|a, b| a.files[0].path.cmp(&b.files[0].path));
+    groups
+}
+
+#[derive(Clone, Serialize)]
+struct BurstProgress {
+    id: String,
+    count: usize,
+}
+
+/// Find burst groups among the photos under `path` (async, cancellable)
+#[tauri::command]
+pub async fn find_bursts(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+    operation_id: String,
+    window_ms: Option<u64>,
+    min_frames: Option<usize>,
+) -> Result<BurstResult, String> {
+    use tauri::Emitter;
+
+    let cancel_token = state.register_token(&operation_id);
+    let types = state.media_types();
+
+    let mut candidates = Vec::new();
+    let walked = metadata::walk_media_files(&types, &path, &cancel_token, |file| {
+        if !matches!(types.category(file), Some(MediaCategory::Photo | MediaCategory::Raw)) {
+            return Ok(());
+        }
+        let file_path = file.to_string_lossy().to_string();
+        let size = std::fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+        let exif = metadata_index::read_cached(&state, &file_path);
+        candidates.push(BurstCandidate::from_metadata(file_path, size, exif));
+
+        if candidates.len() % 10 == 0 {
+            let _ = app_handle.emit("burst-progress", BurstProgress {
+                id: operation_id.clone(),
+                count: candidates.len(),
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    state.metadata_index.flush()?;
+    walked?;
+
+    let groups = group_bursts(
+        &candidates,
+        window_ms.unwrap_or(DEFAULT_WINDOW_MS),
+        min_frames.unwrap_or(DEFAULT_MIN_FRAMES),
+    );
+    Ok(BurstResult {
+        total_groups: groups.len(),
+        total_files: groups.iter().map(|g| g.files.len()).sum(),
+        burst_groups: groups,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, uuid: Option<&str>, camera: Option<&str>, date: Option<&str>, subsec: Option<&str>) -> BurstCandidate {
+        BurstCandidate {
+            path: path.to_string(),
+            size: 1,
+            burst_uuid: uuid.map(str::to_string),
+            camera_model: camera.map(str::to_string),
+            date_time_original: date.map(str::to_string),
+            sub_sec_time_original: subsec.map(str::to_string),
+        }
+    }
+
+    fn paths(group: &BurstGroup) -> Vec<&str> {
+        group.files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_burst_uuid_and_names() {
+        let candidates = [
+            candidate("/p/IMG_0002.HEIC", Some("U1"), Some("Apple iPhone 13"), Some("2023:05:14 10:11:12"), Some("300")),
+            candidate("/p/IMG_0001.HEIC", Some("U1"), Some("Apple iPhone 13"), Some("2023:05:14 10:11:12"), Some("100")),
+            candidate("/p/IMG_0003.HEIC", Some("U2"), Some("Apple iPhone 13"), None, None),
+            candidate("/p/00000IMG_00000_BURST20190101123456789.jpg", None, None, None, None),
+            candidate("/p/00001IMG_00001_BURST20190101123456789_COVER.jpg", None, None, None, None),
+            candidate("/s/20230514_101112_001.jpg", None, None, None, None),
+            candidate("/s/20230514_101112_002.jpg", None, None, None, None),
+        ];
+        let groups = group_bursts(&candidates, DEFAULT_WINDOW_MS, DEFAULT_MIN_FRAMES);
+        assert_eq!(groups.len(), 3);
+
+        assert_eq!(groups[0].source, BurstSource::FileName);
+        assert_eq!(groups[0].files.iter().find(|f| f.cover).unwrap().path, "/p/00001IMG_00001_BURST20190101123456789_COVER.jpg");
+
+        assert_eq!(groups[1].source, BurstSource::BurstUuid);
+        assert_eq!(paths(&groups[1]), vec!["/p/IMG_0001.HEIC", "/p/IMG_0002.HEIC"]);
+        assert!(groups[1].files[0].cover);
+
+        assert_eq!(groups[2].source, BurstSource::FileName);
+        assert_eq!(groups[2].files.len(), 2);
+    }
+
+    #[test]
+    fn test_timestamp_bursts_per_camera() {
+        let candidates = [
+            candidate("/p/DSC_0001.JPG", None, Some("Nikon D750"), Some("2022:01:01 09:00:00"), Some("10")),
+            candidate("/p/DSC_0001.NEF", None, Some("Nikon D750"), Some("2022:01:01 09:00:00"), Some("10")),
+            candidate("/p/DSC_0002.JPG", None, Some("Nikon D750"), Some("2022:01:01 09:00:00"), Some("30")),
+            candidate("/p/DSC_0003.JPG", None, Some("Nikon D750"), Some("2022:01:01 09:00:00"), Some("50")),
+            // Too far after the others
+            candidate("/p/DSC_0004.JPG", None, Some("Nikon D750"), Some("2022:01:01 09:00:05"), Some("00")),
+            // Same time, other camera
+            candidate("/p/IMG_0001.JPG", None, Some("Canon EOS R"), Some("2022:01:01 09:00:00"), Some("20")),
+            // No sub-second precision
+            candidate("/p/IMG_0002.JPG", None, Some("Canon EOS R"), Some("2022:01:01 09:00:00"), None),
+        ];
+        let groups = group_bursts(&candidates, DEFAULT_WINDOW_MS, DEFAULT_MIN_FRAMES);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].source, BurstSource::Timestamp);
+        assert_eq!(groups[0].camera_model.as_deref(), Some("Nikon D750"));
+        assert_eq!(paths(&groups[0]), vec!["/p/DSC_0001.JPG", "/p/DSC_0001.NEF", "/p/DSC_0002.JPG", "/p/DSC_0003.JPG"]);
+
+        // A RAW+JPEG pair alone is not a burst
+        assert!(group_bursts(&candidates[..2], DEFAULT_WINDOW_MS, 2).is_empty());
+    }
+}
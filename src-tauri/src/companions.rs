@@ -0,0 +1,460 @@
+//! Companion files that belong together.
+//!
+//! This module provides functions to:
+//! - Pair iPhone Live Photos (photo + MOV) by ContentIdentifier or basename
+//! - Pair Android motion-photo videos, RAW+JPEG shots and Apple edit files
+//!   (`.AAE` and `IMG_E1234` renders) with their originals
+//! - Attach the companions of each file to scan results, and lend a dated
+//!   companion's capture date to a partner without one
+//!
+//! Files pair up when they sit in the same folder and share a basename
+//! (ignoring case, Google's `.MP` suffix and Apple's `E`/`O` edit prefix), or
+//! when they carry the same ContentIdentifier. A pair by name alone also needs
+//! both capture times, no more than `MAX_PAIR_SECONDS` apart, so
+//! `IMG_0001.JPG` and `IMG_0001.MOV` from a counter rollover stay separate.
+//! Times are compared as instants: a photo's wall-clock time takes its
+//! `OffsetTimeOriginal` or the zone of its GPS position, and a video's
+//! QuickTime `CreateDate` is UTC. A wall-clock time whose zone can't be told
+//! matches an instant that differs by a whole UTC offset (in 15 minute steps).
+//!
+//! An edit belongs to its original but not the other way round: deleting an
+//! original takes its `.AAE` along, deleting the `.AAE` leaves the original.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::date_patterns::DateConfidence;
+use crate::media_types::{MediaCategory, MediaTypeRegistry};
+use crate::metadata::{ExifMetadata, ExtractedDate, FileMetadataInfo};
+use crate::metadata_index;
+use crate::state::AppState;
+use crate::timezone;
+
+/// Capture times of name-matched companions may differ by this much
+const MAX_PAIR_SECONDS: i64 = 60;
+
+/// Largest UTC offset in use, for wall-clock times in an unknown zone
+const MAX_OFFSET_SECONDS: i64 = 14 * 3600;
+
+/// How a companion relates to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompanionKind {
+    /// Apple Live Photo still and video
+    LivePhoto,
+    /// A photo and its separate motion clip from another phone
+    MotionPhoto,
+    /// The RAW and JPEG of one shot
+    RawJpeg,
+    /// An Apple `.AAE` edit file or edited render
+    Edit,
+}
+
+/// Another file that belongs with a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Companion {
+    pub file_path: String,
+    pub kind: CompanionKind,
+}
+
+/// When a file was captured, as far as its metadata tells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureTime {
+    /// A known instant
+    Instant(DateTime<Utc>),
+    /// Wall-clock time in an unknown zone
+    Local(NaiveDateTime),
+}
+
+impl CaptureTime {
+    /// The capture time of a file from its metadata
+    pub fn of(metadata: &ExifMetadata, category: MediaCategory) -> Option<Self> {
+        if let Some(local) = metadata.date_time_original.as_deref().and_then(parse_date) {
+            let value = metadata.date_time_original.as_deref().unwrap_or_default();
+            // An inline zone (`+02:00`, `Z`) follows the seconds and any fraction
+            let inline = value.get(19..).map(|rest| rest.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit()));
+            let offset = inline
+                .filter(|zone| !zone.is_empty())
+                .or(metadata.offset_time_original.as_deref())
+                .and_then(|zone| timezone::parse_zone(zone).ok())
+                .or_else(|| metadata.gps.as_ref().and_then(|gps| timezone::guess_zone_for_location(gps.latitude, gps.longitude)))
+                .and_then(|zone| zone.offset_at(&local));
+            return Some(match offset {
+                Some(offset) => CaptureTime::Instant((local - offset).and_utc()),
+                None => CaptureTime::Local(local),
+            });
+        }
+        let created = parse_date(metadata.create_date.as_deref()?)?;
+        // QuickTime stores CreateDate in UTC
+        Some(if category == MediaCategory::Video { CaptureTime::Instant(created.and_utc()) } else { CaptureTime::Local(created) })
+    }
+
+    /// Whether two capture times can be the same moment, `MAX_PAIR_SECONDS` apart at most
+    fn matches(&self, other: &CaptureTime) -> bool {
+        use CaptureTime::{Instant, Local};
+        let within = |seconds: i64| seconds.abs() <= MAX_PAIR_SECONDS;
+        match (self, other) {
+            (Instant(a), Instant(b)) => within((*a - *b).num_seconds()),
+            (Local(a), Local(b)) => within((*a - *b).num_seconds()),
+            (Local(local), Instant(instant)) | (Instant(instant), Local(local)) => {
+                // The local time is the instant plus some offset we don't know
+                let offset = (*local - instant.naive_utc()).num_seconds();
+                let step = 15 * 60;
+                let nearest = (offset as f64 / step as f64).round() as i64 * step;
+                nearest.abs() <= MAX_OFFSET_SECONDS && within(offset - nearest)
+            }
+        }
+    }
+}
+
+/// What pairing needs to know about one file
+#[derive(Debug, Clone)]
+pub struct PairCandidate {
+    pub file_path: String,
+    pub category: MediaCategory,
+    pub content_identifier: Option<String>,
+    pub capture_time: Option<CaptureTime>,
+}
+
+/// Lowercase basename used for matching, and whether the name marks an Apple edit
+fn match_key(file_path: &str) -> Option<(String, bool)> {
+    let stem = Path::new(file_path).file_stem()?.to_str()?.to_lowercase();
+    // Google's motion photos: PXL_20210101_120000000.MP.jpg
+    let stem = stem.strip_suffix(".mp").unwrap_or(&stem).to_string();
+
+    // IMG_E1234 (edited render) and IMG_O1234 (original adjustments) belong to IMG_1234
+    if let Some(number) = stem.strip_prefix("img_e").or_else(|| stem.strip_prefix("img_o")) {
+        if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+            return Some((format!("img_{}", number), true));
+        }
+    }
+    Some((stem, false))
+}
+
+fn is_edit_file(candidate: &PairCandidate) -> bool {
+    candidate.category == MediaCategory::Sidecar
+}
+
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.get(..19)?, "%Y:%m:%d %H:%M:%S").ok()
+}
+
+fn has_extension(file_path: &str, extensions: &[&str]) -> bool {
+    Path::new(file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// How two files relate, if they are companions at all
+fn relation(a: &PairCandidate, b: &PairCandidate, edited: bool, same_identifier: bool) -> Option<CompanionKind> {
+    if is_edit_file(a) || is_edit_file(b) || edited {
+        return Some(CompanionKind::Edit);
+    }
+
+    if !same_identifier && a.capture_time.zip(b.capture_time).is_none_or(|(a, b)| !a.matches(&b)) {
+        return None;
+    }
+
+    use MediaCategory::{Photo, Raw, Video};
+    match (a.category, b.category) {
+        (Raw, Photo) | (Photo, Raw) => Some(CompanionKind::RawJpeg),
+        (Photo, Video) | (Video, Photo) => {
+            let (photo, video) = if a.category == Photo { (a, b) } else { (b, a) };
+            let apple = same_identifier
+                || (has_extension(&photo.file_path, &["heic", "jpg", "jpeg"]) && has_extension(&video.file_path, &["mov"]));
+            Some(if apple { CompanionKind::LivePhoto } else { CompanionKind::MotionPhoto })
+        }
+        _ => None,
+    }
+}
+
+/// Whether deleting `file_path` should take `companion` along. An edit
+/// (`.AAE`, `IMG_E1234`) never takes its original with it.
+pub(crate) fn follows_delete(file_path: &str, companion: &Companion) -> bool {
+    let is_edit = has_extension(file_path, &["aae", "xmp"]) || match_key(file_path).is_some_and(|(_, edited)| edited);
+    companion.kind != CompanionKind::Edit || !is_edit
+}
+
+/// Companions of every file among `candidates`, keyed by file path. Only
+/// files with at least one companion are listed.
+pub fn find_companions(candidates: &[PairCandidate]) -> HashMap<String, Vec<Companion>> {
+    let keys: Vec<Option<(String, bool)>> = candidates.iter().map(|c| match_key(&c.file_path)).collect();
+
+    // Only files sharing a folder and a basename or a ContentIdentifier can
+    // pair, so compare within those groups instead of every pair
+    let mut groups: HashMap<(Option<&Path>, &str, bool), Vec<usize>> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        let folder = Path::new(&candidate.file_path).parent();
+        if let Some((key, _)) = &keys[i] {
+            groups.entry((folder, key, false)).or_default().push(i);
+        }
+        if let Some(id) = &candidate.content_identifier {
+            groups.entry((folder, id, true)).or_default().push(i);
+        }
+    }
+    let mut pairs: BTreeSet<(usize, usize)> = BTreeSet::new();
+    for members in groups.values() {
+        for (n, &i) in members.iter().enumerate() {
+            pairs.extend(members[n + 1..].iter().map(|&j| (i.min(j), i.max(j))));
+        }
+    }
+
+    let mut companions: HashMap<String, Vec<Companion>> = HashMap::new();
+    for (i, j) in pairs {
+        let (a, b) = (&candidates[i], &candidates[j]);
+        let same_identifier = a.content_identifier.is_some() && a.content_identifier == b.content_identifier;
+        let edited = match (&keys[i], &keys[j]) {
+            (Some((key_a, edited_a)), Some((key_b, edited_b))) => key_a == key_b && edited_a != edited_b,
+            _ => false,
+        };
+        // Two edit files of one shot are not each other's companions
+        if is_edit_file(a) && is_edit_file(b) {
+            continue;
+        }
+        let Some(kind) = relation(a, b, edited, same_identifier) else { continue };
+        companions.entry(a.file_path.clone()).or_default().push(Companion { file_path: b.file_path.clone(), kind });
+        companions.entry(b.file_path.clone()).or_default().push(Companion { file_path: a.file_path.clone(), kind });
+    }
+    companions
+}
+
+/// Pairing candidates for every media and `.AAE` file in a folder
+fn folder_candidates(state: &AppState, types: &MediaTypeRegistry, dir: &Path) -> Vec<PairCandidate> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut candidates: Vec<PairCandidate> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let category = types.category(&path)?;
+            let file_path = path.to_string_lossy().to_string();
+            if category == MediaCategory::Sidecar {
+                return has_extension(&file_path, &["aae"]).then_some(PairCandidate {
+                    file_path,
+                    category,
+                    content_identifier: None,
+                    capture_time: None,
+                });
+            }
+            let metadata = metadata_index::read_cached(state, &file_path);
+            Some(PairCandidate {
+                content_identifier: metadata.as_ref().and_then(|m| m.content_identifier.clone()),
+                // Videos often only have a QuickTime CreateDate
+                capture_time: metadata.as_ref().and_then(|m| CaptureTime::of(m, category)),
+                file_path,
+                category,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    candidates
+}
+
+/// Companions of each file in the folders of `file_paths`, one listing per folder
+pub(crate) fn companions_of_files(state: &AppState, file_paths: &[&str]) -> HashMap<String, Vec<Companion>> {
+    let types = state.media_types();
+    let mut folders: Vec<&Path> = file_paths.iter().filter_map(|p| Path::new(p).parent()).collect();
+    folders.sort();
+    folders.dedup();
+
+    let mut companions = HashMap::new();
+    for folder in folders {
+        companions.extend(find_companions(&folder_candidates(state, &types, folder)));
+    }
+    // Whatever had to be read is worth keeping
+    let _ = state.metadata_index.flush();
+    companions
+}
+
+/// Fill in `companions` of scan results. A file without a date or a
+/// suggestion gets the capture date of a dated Live Photo, motion photo or
+/// RAW+JPEG partner, so date fixes cover the whole pair.
+pub(crate) fn add_companions(state: &AppState, results: &mut [FileMetadataInfo]) {
+    let paths: Vec<&str> = results.iter().map(|r| r.file_path.as_str()).collect();
+    let mut companions = companions_of_files(state, &paths);
+
+    let dated: HashMap<String, String> = companions
+        .values()
+        .flatten()
+        .filter(|c| c.kind != CompanionKind::Edit)
+        .filter_map(|c| {
+            let metadata = state.metadata_index.lookup(&c.file_path)?.metadata?;
+            Some((c.file_path.clone(), metadata.date_time_original?))
+        })
+        .collect();
+
+    for result in results.iter_mut() {
+        result.companions = companions.remove(&result.file_path).unwrap_or_default();
+        if result.has_date || result.extracted_date.is_some() {
+            continue;
+        }
+        let borrowed = result.companions.iter().find_map(|c| Some((c, parse_date(dated.get(&c.file_path)?)?)));
+        if let Some((companion, datetime)) = borrowed {
+            let name = Path::new(&companion.file_path).file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            result.extracted_date = Some(ExtractedDate {
+                date: datetime.format("%Y-%m-%d").to_string(),
+                time: Some(datetime.format("%H:%M:%S").to_string()),
+                source: format!("Companion {}", name),
+                subsec: None,
+                confidence: DateConfidence::High,
+                offset: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(file_path: &str, category: MediaCategory, id: Option<&str>, date: Option<&str>) -> PairCandidate {
+        PairCandidate {
+            file_path: file_path.to_string(),
+            category,
+            content_identifier: id.map(str::to_string),
+            capture_time: date.and_then(parse_date).map(CaptureTime::Local),
+        }
+    }
+
+    fn kinds(companions: &HashMap<String, Vec<Companion>>, file_path: &str) -> Vec<(String, CompanionKind)> {
+        let mut list: Vec<_> = companions
+            .get(file_path)
+            .map(|c| c.iter().map(|c| (c.file_path.clone(), c.kind)).collect())
+            .unwrap_or_default();
+        list.sort_by(|a: &(String, CompanionKind), b| a.0.cmp(&b.0));
+        list
+    }
+
+    #[test]
+    fn test_live_photo_with_edits() {
+        use MediaCategory::*;
+        let candidates = [
+            candidate("/p/IMG_1234.HEIC", Photo, Some("ABC"), Some("2023:05:14 10:11:12")),
+            candidate("/p/IMG_1234.MOV", Video, Some("ABC"), Some("2023:05:14 10:11:13")),
+            candidate("/p/IMG_1234.AAE", Sidecar, None, None),
+            candidate("/p/IMG_E1234.HEIC", Photo, None, Some("2023:05:14 10:11:12")),
+            candidate("/p/IMG_1235.HEIC", Photo, Some("DEF"), Some("2023:05:14 10:12:00")),
+        ];
+        let companions = find_companions(&candidates);
+        assert_eq!(
+            kinds(&companions, "/p/IMG_1234.HEIC"),
+            vec![
+                ("/p/IMG_1234.AAE".to_string(), CompanionKind::Edit),
+                ("/p/IMG_1234.MOV".to_string(), CompanionKind::LivePhoto),
+                ("/p/IMG_E1234.HEIC".to_string(), CompanionKind::Edit),
+            ]
+        );
+        assert!(!companions.contains_key("/p/IMG_1235.HEIC"));
+    }
+
+    #[test]
+    fn test_renamed_live_photo_pairs_by_identifier() {
+        use MediaCategory::*;
+        let candidates = [
+            candidate("/p/2023-05-14 beach.jpg", Photo, Some("ABC"), None),
+            candidate("/p/clip_0007.mov", Video, Some("ABC"), None),
+        ];
+        let companions = find_companions(&candidates);
+        assert_eq!(kinds(&companions, "/p/clip_0007.mov"), vec![("/p/2023-05-14 beach.jpg".to_string(), CompanionKind::LivePhoto)]);
+    }
+
+    #[test]
+    fn test_raw_jpeg_and_motion_photo() {
+        use MediaCategory::*;
+        let candidates = [
+            candidate("/p/DSC_0001.NEF", Raw, None, Some("2022:01:01 09:00:00")),
+            candidate("/p/DSC_0001.JPG", Photo, None, Some("2022:01:01 09:00:00")),
+            candidate("/p/PXL_20210101_120000000.MP.jpg", Photo, None, Some("2021:01:01 12:00:00")),
+            candidate("/p/PXL_20210101_120000000.mp4", Video, None, Some("2021:01:01 12:00:01")),
+            candidate("/q/DSC_0001.MP4", Video, None, None),
+        ];
+        let companions = find_companions(&candidates);
+        assert_eq!(kinds(&companions, "/p/DSC_0001.NEF"), vec![("/p/DSC_0001.JPG".to_string(), CompanionKind::RawJpeg)]);
+        assert_eq!(
+            kinds(&companions, "/p/PXL_20210101_120000000.mp4"),
+            vec![("/p/PXL_20210101_120000000.MP.jpg".to_string(), CompanionKind::MotionPhoto)]
+        );
+        assert!(!companions.contains_key("/q/DSC_0001.MP4"));
+    }
+
+    #[test]
+    fn test_same_name_different_times_do_not_pair() {
+        use MediaCategory::*;
+        let candidates = [
+            candidate("/p/IMG_0001.JPG", Photo, None, Some("2019:03:01 10:00:00")),
+            candidate("/p/IMG_0001.MOV", Video, None, Some("2021:07:09 18:30:00")),
+        ];
+        assert!(find_companions(&candidates).is_empty());
+
+        // Without both times a name is not enough
+        let candidates = [
+            candidate("/p/IMG_0001.JPG", Photo, None, Some("2019:03:01 10:00:00")),
+            candidate("/p/IMG_0001.MOV", Video, None, None),
+        ];
+        assert!(find_companions(&candidates).is_empty());
+    }
+
+    #[test]
+    fn test_capture_times_compare_as_instants() {
+        use crate::isobmff::GpsCoordinates;
+        use MediaCategory::*;
+        let photo = |date: &str, offset: Option<&str>, gps: Option<(f64, f64)>| {
+            let metadata = ExifMetadata {
+                date_time_original: Some(date.to_string()),
+                offset_time_original: offset.map(str::to_string),
+                gps: gps.map(|(latitude, longitude)| GpsCoordinates { latitude, longitude, altitude: None }),
+                ..Default::default()
+            };
+            CaptureTime::of(&metadata, Photo).unwrap()
+        };
+        let video = |date: &str| {
+            let metadata = ExifMetadata { create_date: Some(date.to_string()), ..Default::default() };
+            CaptureTime::of(&metadata, Video).unwrap()
+        };
+
+        // 14:00 at +02:00 is the video's 12:00 UTC
+        assert!(photo("2021:01:01 14:00:00", Some("+02:00"), None).matches(&video("2021:01:01 12:00:01")));
+        assert!(photo("2021:01:01 14:00:00.123+02:00", None, None).matches(&video("2021:01:01 12:00:01")));
+        assert!(!photo("2021:01:01 14:00:00", Some("+01:00"), None).matches(&video("2021:01:01 12:00:01")));
+        // Karachi is +05:00
+        let karachi = Some((24.86, 67.01));
+        assert!(photo("2021:01:01 17:00:00", None, karachi).matches(&video("2021:01:01 12:00:30")));
+        assert!(!photo("2021:01:01 17:30:00", None, karachi).matches(&video("2021:01:01 12:00:30")));
+        // Without a zone any whole offset will do, but not an odd one
+        assert!(photo("2021:01:01 17:30:20", None, None).matches(&video("2021:01:01 12:00:00")));
+        assert!(!photo("2021:01:01 17:37:00", None, None).matches(&video("2021:01:01 12:00:00")));
+
+        let candidates = [
+            PairCandidate {
+                file_path: "/p/PXL_20210101_140000000.MP.jpg".to_string(),
+                category: Photo,
+                content_identifier: None,
+                capture_time: Some(photo("2021:01:01 14:00:00", Some("+02:00"), None)),
+            },
+            PairCandidate {
+                file_path: "/p/PXL_20210101_140000000.mp4".to_string(),
+                category: Video,
+                content_identifier: None,
+                capture_time: Some(video("2021:01:01 12:00:01")),
+            },
+        ];
+        assert_eq!(
+            kinds(&find_companions(&candidates), "/p/PXL_20210101_140000000.mp4"),
+            vec![("/p/PXL_20210101_140000000.MP.jpg".to_string(), CompanionKind::MotionPhoto)]
+        );
+    }
+
+    #[test]
+    fn test_delete_never_follows_edit_to_original() {
+        let edit = Companion { file_path: "/p/IMG_1234.AAE".to_string(), kind: CompanionKind::Edit };
+        let original = Companion { file_path: "/p/IMG_1234.HEIC".to_string(), kind: CompanionKind::Edit };
+        assert!(follows_delete("/p/IMG_1234.HEIC", &edit));
+        assert!(!follows_delete("/p/IMG_1234.AAE", &original));
+        assert!(!follows_delete("/p/IMG_E1234.HEIC", &original));
+        let video = Companion { file_path: "/p/IMG_1234.MOV".to_string(), kind: CompanionKind::LivePhoto };
+        assert!(follows_delete("/p/IMG_1234.HEIC", &video));
+    }
+}
@@ -0,0 +1,376 @@
+//! Sanity checks for capture dates.
+//!
+//! This module provides functions to:
+//! - Classify each file's DateTimeOriginal as valid, missing, a placeholder
+//!   (Unix epoch, QuickTime epoch, camera factory default), in the future or
+//!   unreadable
+//! - Flag dates that disagree with the filename, a Google Takeout sidecar or
+//!   the file's modification time
+//! - Suggest the best available date, with the reason it was chosen
+//!
+//! The modification time only counts against a date when the file claims to
+//! have been modified before it was taken; copies and edits make it later all
+//! the time. Checking only reports; a placeholder or future date is replaced
+//! only when a write asks for it with `replace_implausible` (see
+//! `metadata::write_exif_date_if_missing`). Unreadable dates are never replaced.
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::date_patterns::DateConfidence;
+use crate::metadata::{self, ExtractedDate};
+use crate::state::AppState;
+use crate::takeout::SidecarIndex;
+use crate::xmp_sidecar;
+
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Days two dates may differ by before they count as disagreeing
+pub const DEFAULT_TOLERANCE_DAYS: i64 = 2;
+
+/// Dates clocks fall back to when they were never set: the QuickTime, DOS and
+/// Unix epochs, and the factory defaults of many cameras
+const PLACEHOLDER_DAYS: &[(i32, u32, u32)] = &[(1904, 1, 1), (1970, 1, 1), (1980, 1, 1), (2000, 1, 1), (2001, 1, 1)];
+
+/// What is wrong with a file's date, most serious first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateStatus {
+    Missing,
+    /// A placeholder date
+    EpochDefault,
+    Future,
+    /// A date that can't be parsed; reported, but not known to be wrong
+    Unknown,
+    TakeoutMismatch,
+    FilenameMismatch,
+    /// The file was modified before the date says it was taken
+    ModifiedMismatch,
+    Valid,
+}
+
+/// A date to write instead, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateSuggestion {
+    #[serde(flatten)]
+    pub extracted: ExtractedDate,
+    pub reason: String,
+}
+
+/// Result of checking one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateCheck {
+    pub file_path: String,
+    /// The most serious of `issues`, or `Valid`
+    pub status: DateStatus,
+    pub issues: Vec<DateStatus>,
+    pub date_time_original: Option<String>,
+    pub filename_date: Option<ExtractedDate>,
+    pub takeout_date: Option<ExtractedDate>,
+    /// Modification time in local time, exiftool formatting
+    pub modified: Option<String>,
+    pub suggestion: Option<DateSuggestion>,
+}
+
+#[derive(Clone, Serialize)]
+struct DateCheckProgress {
+    id: String,
+    count: usize,
+}
+
+/// Everything known about when one file was taken
+#[derive(Debug, Default)]
+struct DateEvidence {
+    file_path: String,
+    date_time_original: Option<String>,
+    create_date: Option<String>,
+    filename_date: Option<ExtractedDate>,
+    takeout_date: Option<ExtractedDate>,
+    modified: Option<NaiveDateTime>,
+}
+
+/// Parse an exiftool date, ignoring sub-seconds and any zone suffix
+fn parse_exif_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.get(..19)?, EXIF_DATE_FORMAT).ok()
+}
+
+/// The local date-time of a suggestion; date-only suggestions are taken at midnight
+fn extracted_datetime(extracted: &ExtractedDate) -> Option<NaiveDateTime> {
+    let date = NaiveDate::parse_from_str(&extracted.date, "%Y-%m-%d").ok()?;
+    match &extracted.time {
+        Some(time) => NaiveDateTime::parse_from_str(&format!("{} {}", extracted.date, time), "%Y-%m-%d %H:%M:%S").ok(),
+        None => date.and_hms_opt(0, 0, 0),
+    }
+}
+
+fn is_placeholder(datetime: &NaiveDateTime) -> bool {
+    // A day either side, for clocks that were set in UTC and shown in local time
+    PLACEHOLDER_DAYS.iter().any(|&(y, m, d)| {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .is_some_and(|midnight| (*datetime - midnight).num_hours().abs() < 24)
+    })
+}
+
+/// The problem with a recorded date on its own, if any
+fn classify(value: &str, now: NaiveDateTime) -> Option<DateStatus> {
+    match parse_exif_date(value) {
+        None => Some(DateStatus::Unknown),
+        Some(datetime) if is_placeholder(&datetime) => Some(DateStatus::EpochDefault),
+        // A day of slack for files from zones ahead of this one
+        Some(datetime) if datetime > now + Duration::days(1) => Some(DateStatus::Future),
+        Some(_) => None,
+    }
+}
+
+/// Whether a recorded date is a placeholder or in the future, and so no real
+/// date. Unreadable dates are not.
+pub(crate) fn is_implausible(value: &str) -> bool {
+    matches!(classify(value, Local::now().naive_local()), Some(DateStatus::EpochDefault | DateStatus::Future))
+}
+
+/// Whether `other` is more than `tolerance_days` from `taken`. Date-only values
+/// are compared by day.
+fn disagrees(taken: &NaiveDateTime, other: &ExtractedDate, tolerance_days: i64) -> bool {
+    let Some(other_datetime) = extracted_datetime(other) else { return false };
+    let days = match other.time {
+        Some(_) => (*taken - other_datetime).num_seconds().abs() as f64 / 86_400.0,
+        None => (taken.date() - other_datetime.date()).num_days().abs() as f64,
+    };
+    days > tolerance_days as f64
+}
+
+fn to_extracted(datetime: &NaiveDateTime, source: &str, confidence: DateConfidence) -> ExtractedDate {
+    ExtractedDate {
+        date: datetime.format("%Y-%m-%d").to_string(),
+        time: Some(datetime.format("%H:%M:%S").to_string()),
+        source: source.to_string(),
+        subsec: None,
+        confidence,
+        offset: None,
+    }
+}
+
+/// The best replacement for a date with `issues`, most trusted evidence first
+fn suggest(evidence: &DateEvidence, issues: &[DateStatus], now: NaiveDateTime) -> Option<DateSuggestion> {
+    let suggestion = |extracted: &ExtractedDate, reason: &str| DateSuggestion {
+        extracted: extracted.clone(),
+        reason: reason.to_string(),
+    };
+    let current_is_bad =
+        issues.iter().any(|i| matches!(i, DateStatus::Missing | DateStatus::EpochDefault | DateStatus::Future));
+
+    if let Some(takeout) = &evidence.takeout_date {
+        if current_is_bad || issues.contains(&DateStatus::TakeoutMismatch) {
+            return Some(suggestion(takeout, "Capture time recorded by Google Photos"));
+        }
+    }
+    if let Some(filename) = &evidence.filename_date {
+        let trusted = filename.confidence == DateConfidence::High;
+        if current_is_bad || (trusted && issues.contains(&DateStatus::FilenameMismatch)) {
+            return Some(suggestion(filename, &format!("Date in the filename ({})", filename.source)));
+        }
+    }
+    if !current_is_bad {
+        return None;
+    }
+    if let Some(create_date) = evidence.create_date.as_deref().filter(|d| classify(d, now).is_none()) {
+        let datetime = parse_exif_date(create_date)?;
+        let extracted = to_extracted(&datetime, "CreateDate", DateConfidence::Medium);
+        return Some(suggestion(&extracted, "CreateDate recorded by the camera"));
+    }
+    let modified = evidence.modified.filter(|m| !is_placeholder(m) && *m <= now)?;
+    let extracted = to_extracted(&modified, "File modification time", DateConfidence::Low);
+    Some(suggestion(&extracted, "Last modification time of the file; only an upper bound on the capture time"))
+}
+
+fn check(evidence: DateEvidence, now: NaiveDateTime, tolerance_days: i64) -> DateCheck {
+    let mut issues = Vec::new();
+    match evidence.date_time_original.as_deref() {
+        None => issues.push(DateStatus::Missing),
+        Some(value) => match classify(value, now) {
+            Some(status) => issues.push(status),
+            None => {
+                let taken = parse_exif_date(value).expect("classified dates parse");
+                if evidence.takeout_date.as_ref().is_some_and(|t| disagrees(&taken, t, tolerance_days)) {
+                    issues.push(DateStatus::TakeoutMismatch);
+                }
+                if evidence.filename_date.as_ref().is_some_and(|f| disagrees(&taken, f, tolerance_days)) {
+                    issues.push(DateStatus::FilenameMismatch);
+                }
+                if evidence.modified.is_some_and(|m| taken - m > Duration::days(tolerance_days)) {
+                    issues.push(DateStatus::ModifiedMismatch);
+                }
+            }
+        },
+    }
+
+    let suggestion = suggest(&evidence, &issues, now);
+    DateCheck {
+        file_path: evidence.file_path,
+        status: issues.iter().min().copied().unwrap_or(DateStatus::Valid),
+        issues,
+        date_time_original: evidence.date_time_original,
+        filename_date: evidence.filename_date,
+        takeout_date: evidence.takeout_date,
+        modified: evidence.modified.map(|m| m.format(EXIF_DATE_FORMAT).to_string()),
+        suggestion,
+    }
+}
+
+/// Check the date of every media file under `directory`.
+///
+/// `tolerance_days` defaults to `DEFAULT_TOLERANCE_DAYS`.
+#[tauri::command]
+pub async fn check_dates(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    operation_id: String,
+    tolerance_days: Option<i64>,
+) -> Result<Vec<DateCheck>, String> {
+    use tauri::Emitter;
+
+    let cancel_token = state.register_token(&operation_id);
+    let backend = metadata::select_backend(&state);
+    let patterns = state.date_patterns();
+    let tolerance_days = tolerance_days.unwrap_or(DEFAULT_TOLERANCE_DAYS).max(0);
+    let now = Local::now().naive_local();
+    let mut sidecars = SidecarIndex::new();
+
+    let mut results = Vec::new();
+    let walked = metadata::walk_media_files(&state.media_types(), &directory, &cancel_token, |path| {
+        let file_path = path.to_string_lossy().to_string();
+        let exif = xmp_sidecar::read_merged(backend, &file_path).ok();
+        let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let modified = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|time| chrono::DateTime::<Local>::from(time).naive_local());
+
+        let evidence = DateEvidence {
+            date_time_original: exif.as_ref().and_then(|m| m.date_time_original.clone()),
+            create_date: exif.and_then(|m| m.create_date),
+            filename_date: patterns.extract(filename),
+            takeout_date: sidecars.lookup(Path::new(&file_path)).and_then(|t| t.extracted_date()),
+            modified,
+            file_path,
+        };
+        results.push(check(evidence, now, tolerance_days));
+
+        if results.len() % 10 == 0 {
+            let _ = app_handle.emit("date-check-progress", DateCheckProgress {
+                id: operation_id.clone(),
+                count: results.len(),
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    walked.map(|_| results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        parse_exif_date(value).unwrap()
+    }
+
+    fn extracted(date: &str, time: Option<&str>, confidence: DateConfidence) -> ExtractedDate {
+        ExtractedDate {
+            date: date.to_string(),
+            time: time.map(str::to_string),
+            source: "Test".to_string(),
+            subsec: None,
+            confidence,
+            offset: None,
+        }
+    }
+
+    fn evidence(date_time_original: Option<&str>) -> DateEvidence {
+        DateEvidence {
+            file_path: "/a/IMG_1.jpg".to_string(),
+            date_time_original: date_time_original.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    const NOW: &str = "2024:06:01 12:00:00";
+
+    #[test]
+    fn test_classify_placeholders_and_future() {
+        let now = at(NOW);
+        assert_eq!(classify("1970:01:01 00:00:00", now), Some(DateStatus::EpochDefault));
+        // Unix epoch shown in a zone behind UTC
+        assert_eq!(classify("1969:12:31 19:00:00", now), Some(DateStatus::EpochDefault));
+        assert_eq!(classify("2000:01:01 00:00:12+01:00", now), Some(DateStatus::EpochDefault));
+        assert_eq!(classify("0000:00:00 00:00:00", now), Some(DateStatus::Unknown));
+        assert!(!is_implausible("0000:00:00 00:00:00"));
+        assert!(is_implausible("1970:01:01 00:00:00"));
+        assert_eq!(classify("2031:03:04 10:00:00", now), Some(DateStatus::Future));
+        assert_eq!(classify("2024:06:02 08:00:00", now), None);
+        assert_eq!(classify("2000:01:03 10:00:00", now), None);
+    }
+
+    #[test]
+    fn test_valid_date_agreeing_with_evidence() {
+        let mut e = evidence(Some("2023:05:14 10:11:12"));
+        e.filename_date = Some(extracted("2023-05-14", None, DateConfidence::High));
+        e.modified = Some(at("2023:05:14 10:11:40"));
+        let result = check(e, at(NOW), DEFAULT_TOLERANCE_DAYS);
+        assert_eq!(result.status, DateStatus::Valid);
+        assert!(result.issues.is_empty());
+        assert!(result.suggestion.is_none());
+    }
+
+    #[test]
+    fn test_placeholder_suggests_takeout_over_filename() {
+        let mut e = evidence(Some("1970:01:01 00:00:00"));
+        e.filename_date = Some(extracted("2019-08-03", None, DateConfidence::Medium));
+        e.takeout_date = Some(extracted("2019-08-02", Some("18:30:00"), DateConfidence::High));
+        let result = check(e, at(NOW), DEFAULT_TOLERANCE_DAYS);
+        assert_eq!(result.status, DateStatus::EpochDefault);
+        let suggestion = result.suggestion.unwrap();
+        assert_eq!(suggestion.extracted.date, "2019-08-02");
+        assert!(suggestion.reason.contains("Google Photos"));
+    }
+
+    #[test]
+    fn test_missing_date_falls_back_to_create_date_then_mtime() {
+        let mut e = evidence(None);
+        e.create_date = Some("2022:02:03 04:05:06".to_string());
+        e.modified = Some(at("2023:01:01 10:00:00"));
+        let suggestion = check(e, at(NOW), DEFAULT_TOLERANCE_DAYS).suggestion.unwrap();
+        assert_eq!(suggestion.extracted.source, "CreateDate");
+        assert_eq!(suggestion.extracted.time, Some("04:05:06".to_string()));
+
+        let mut e = evidence(None);
+        e.modified = Some(at("2023:01:01 10:00:00"));
+        let result = check(e, at(NOW), DEFAULT_TOLERANCE_DAYS);
+        assert_eq!(result.status, DateStatus::Missing);
+        assert_eq!(result.suggestion.unwrap().extracted.confidence, DateConfidence::Low);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let mut e = evidence(Some("2023:05:14 10:11:12"));
+        e.filename_date = Some(extracted("2023-05-20", Some("09:00:00"), DateConfidence::High));
+        e.takeout_date = Some(extracted("2023-05-15", Some("10:11:12"), DateConfidence::High));
+        e.modified = Some(at("2021:01:01 00:00:00"));
+        let result = check(e, at(NOW), DEFAULT_TOLERANCE_DAYS);
+        assert_eq!(result.issues, vec![DateStatus::FilenameMismatch, DateStatus::ModifiedMismatch]);
+        assert_eq!(result.status, DateStatus::FilenameMismatch);
+        assert_eq!(result.suggestion.unwrap().extracted.date, "2023-05-20");
+
+        // A low-confidence filename date is reported but not suggested
+        let mut e = evidence(Some("2023:05:14 10:11:12"));
+        e.filename_date = Some(extracted("2023-06-30", None, DateConfidence::Low));
+        let result = check(e, at(NOW), 30);
+        assert_eq!(result.status, DateStatus::FilenameMismatch);
+        assert!(result.suggestion.is_none());
+    }
+}
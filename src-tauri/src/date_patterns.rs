@@ -0,0 +1,643 @@
+//! Registry of filename date patterns.
+//!
+//! Each pattern is a regex with named captures (`year`, `month`, `day`,
+//! `hour`, `minute`, `second`, `subsec` or `epoch_ms`), a source label
+//! reported in `ExtractedDate::source`, a confidence level and a priority.
+//! Built-in patterns are ordinary entries of the registry; user patterns from
+//! settings are merged over them by name. Regexes are compiled once when the
+//! registry is built.
+//!
+//! UTC stamps (Pixel names, epoch milliseconds) stay in UTC and are tagged
+//! with a `+00:00` offset, so writing them records the right instant no
+//! matter which zone the machine is in.
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::metadata::ExtractedDate;
+
+/// Settings key holding user-defined patterns
+pub const SETTINGS_KEY: &str = "dateFilenamePatterns";
+
+/// Plausible capture years; anything outside is treated as a false match
+const MIN_YEAR: i32 = 1990;
+const MAX_YEAR: i32 = 2100;
+
+/// How reliable a filename date is as the capture time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateConfidence {
+    /// Save/download time or a bare date found anywhere in the name
+    Low,
+    /// Date written by an app, but not necessarily the capture time
+    #[default]
+    Medium,
+    /// Timestamp written by the capturing camera or OS
+    High,
+}
+
+/// A filename date pattern as stored in settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatePattern {
+    /// Unique name; a user entry with a built-in's name replaces it
+    pub name: String,
+    /// Regex with named captures
+    pub pattern: String,
+    /// Label for `ExtractedDate::source`, e.g. "WhatsApp"
+    pub source: String,
+    /// Patterns are tried in ascending priority order
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub confidence: DateConfidence,
+    /// Captured date/time is UTC; it is reported with a `+00:00` offset
+    #[serde(default)]
+    pub utc: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl DatePattern {
+    fn builtin(name: &str, pattern: &str, source: &str, priority: i32, confidence: DateConfidence) -> Self {
+        Self {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            source: source.to_string(),
+            priority,
+            confidence,
+            utc: false,
+            enabled: true,
+        }
+    }
+
+    fn in_utc(mut self) -> Self {
+        self.utc = true;
+        self
+    }
+
+    /// Compile the regex and check it has enough captures to produce a date
+    pub fn compile(&self) -> Result<CompiledPattern, String> {
+        let regex = Regex::new(&self.pattern)
+            .map_err(|e| format!("Invalid pattern '{}': {}", self.name, e))?;
+
+        let names: Vec<&str> = regex.capture_names().flatten().collect();
+        let has_date = ["year", "month", "day"].iter().all(|n| names.contains(n));
+        if !has_date && !names.contains(&"epoch_ms") {
+            return Err(format!(
+                "Pattern '{}' needs (?P<year>..), (?P<month>..) and (?P<day>..) captures, or (?P<epoch_ms>..)",
+                self.name
+            ));
+        }
+
+        Ok(CompiledPattern {
+            source: self.source.clone(),
+            confidence: self.confidence,
+            utc: self.utc,
+            regex,
+        })
+    }
+}
+
+/// The built-in patterns, in their default order
+///
+/// - Pixel: PXL_20230514_101112345.jpg (UTC, milliseconds)
+/// - WhatsApp Android: IMG-20240115-WA0042.jpg, VID-20220101-WA0003.mp4
+/// - WhatsApp iOS: WhatsApp Image 2024-01-15 at 10.30.45.jpeg
+/// - Screenshot Mac: Screenshot 2024-01-15 at 14.30.00.png
+/// - Screen Recording Mac: Screen Recording 2023-01-05 at 10.11.12.mov
+/// - Samsung: Screenshot_20230514-101112_Chrome.jpg,
+///   Screen_Recording_20230514-101112_YouTube.mp4
+/// - Signal: signal-2023-04-01-101010_002.jpeg, signal-2023-04-01-10-10-10-123.jpg
+/// - Telegram: photo_2023-02-03_10-11-12.jpg
+/// - DJI: DJI_20230514101112_0001_D.JPG, dji_fly_20230514_101112_0001_..._photo.jpg
+/// - Android Camera: 20240115_143000.jpg
+/// - iOS Camera: IMG_20240115_143000.jpg
+/// - GoPro Quik exports: GX010234_1605365734623.MP4 (export time)
+/// - Facebook: FB_IMG_1600000000000.jpg (save time)
+/// - Epoch milliseconds as the whole name: 1684059072000.jpg, 1684059072000 (1).jpg
+/// - Generic: YYYY-MM-DD or YYYYMMDD anywhere in the filename
+pub fn builtin_patterns() -> Vec<DatePattern> {
+    use DateConfidence::{High, Low, Medium};
+
+    vec![
+        DatePattern::builtin(
+            "pixel",
+            r"PXL_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})(?P<subsec>\d{3})",
+            "Pixel",
+            5,
+            High,
+        )
+        .in_utc(),
+        DatePattern::builtin(
+            "whatsapp_android",
+            r"(?:IMG|VID|AUD|PTT|STK)-(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-WA",
+            "WhatsApp",
+            10,
+            Medium,
+        ),
+        DatePattern::builtin(
+            "whatsapp_ios",
+            r"WhatsApp.*(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})(?:\s+at\s+(?P<hour>\d{2})\.(?P<minute>\d{2})\.(?P<second>\d{2}))?",
+            "WhatsApp",
+            20,
+            Medium,
+        ),
+        DatePattern::builtin(
+            "screenshot_mac",
+            r"Screen ?[Ss]hot\s+(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})\s+at\s+(?P<hour>\d{2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+            "Screenshot",
+            30,
+            High,
+        ),
+        DatePattern::builtin(
+            "screen_recording_mac",
+            r"Screen Recording\s+(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})\s+at\s+(?P<hour>\d{2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+            "Screen Recording",
+            31,
+            High,
+        ),
+        DatePattern::builtin(
+            "samsung_screenshot",
+            r"Screenshot_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+            "Samsung Screenshot",
+            37,
+            High,
+        ),
+        DatePattern::builtin(
+            "samsung_screen_recording",
+            r"Screen_Recording_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+            "Samsung Screen Recording",
+            38,
+            High,
+        ),
+        DatePattern::builtin(
+            "signal_dashed",
+            r"signal-(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})-(?P<hour>\d{2})-(?P<minute>\d{2})-(?P<second>\d{2})(?:-(?P<subsec>\d{3}))?",
+            "Signal",
+            32,
+            Medium,
+        ),
+        DatePattern::builtin(
+            "signal",
+            r"signal-(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})-(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+            "Signal",
+            33,
+            Medium,
+        ),
+        DatePattern::builtin(
+            "telegram",
+            r"(?:photo|video|file)_(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})_(?P<hour>\d{2})-(?P<minute>\d{2})-(?P<second>\d{2})",
+            "Telegram",
+            34,
+            Medium,
+        ),
+        DatePattern::builtin(
+            "dji",
+            r"DJI_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})_\d{4}",
+            "DJI",
+            35,
+            High,
+        ),
+        DatePattern::builtin(
+            "dji_fly",
+            r"dji_fly_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+            "DJI",
+            36,
+            High,
+        ),
+        DatePattern::builtin(
+            "camera",
+            r"(?:IMG_)?(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+            "Camera",
+            40,
+            High,
+        ),
+        DatePattern::builtin(
+            "gopro_quik",
+            r"^G(?:OPR|P|H|X|S)\w*?_(?P<epoch_ms>1\d{12})(?:\D|$)",
+            "GoPro",
+            50,
+            Low,
+        ),
+        DatePattern::builtin(
+            "facebook",
+            r"FB_IMG_(?P<epoch_ms>1\d{12})",
+            "Facebook",
+            60,
+            Low,
+        ),
+        DatePattern::builtin(
+            "epoch_ms",
+            // 2001-09-09 to 2033-05-18; anything else around the digits is
+            // more likely a counter or an ID than a timestamp
+            r"^(?P<epoch_ms>1\d{12})(?:[-_ ]\d{1,3}| ?\(\d+\))?\.\w+$",
+            "Epoch",
+            900,
+            Low,
+        ),
+        DatePattern::builtin(
+            "generic",
+            r"(?P<year>\d{4})[-_]?(?P<month>\d{2})[-_]?(?P<day>\d{2})",
+            "Filename",
+            1000,
+            Low,
+        ),
+    ]
+}
+
+/// A pattern ready for matching
+#[derive(Debug, Clone)]
+pub struct CompiledPattern {
+    pub source: String,
+    pub confidence: DateConfidence,
+    utc: bool,
+    regex: Regex,
+}
+
+impl CompiledPattern {
+    /// Extract a date if the first match of the pattern is a plausible date
+    pub fn extract(&self, filename: &str) -> Option<ExtractedDate> {
+        let caps = self.regex.captures(filename)?;
+        let num = |name: &str| caps.name(name).and_then(|m| m.as_str().parse::<i64>().ok());
+
+        if let Some(epoch_ms) = num("epoch_ms") {
+            let dt = DateTime::<Utc>::from_timestamp_millis(epoch_ms)?;
+            let subsec = format!("{:03}", epoch_ms.rem_euclid(1000));
+            return self.build(dt.naive_utc(), true, true, Some(subsec));
+        }
+
+        let year = num("year")? as i32;
+        let month = num("month")? as u32;
+        let day = num("day")? as u32;
+
+        // Validate it looks like a real date
+        let date = NaiveDate::from_ymd_opt(year, month, day)?;
+
+        let time = match (num("hour"), num("minute")) {
+            (Some(hour), Some(minute)) => {
+                let second = num("second").unwrap_or(0);
+                Some(NaiveTime::from_hms_opt(hour as u32, minute as u32, second as u32)?)
+            }
+            _ => None,
+        };
+        let subsec = caps.name("subsec").map(|m| m.as_str().to_string());
+
+        self.build(
+            date.and_time(time.unwrap_or_default()),
+            time.is_some(),
+            self.utc,
+            subsec,
+        )
+    }
+
+    fn build(
+        &self,
+        datetime: NaiveDateTime,
+        has_time: bool,
+        is_utc: bool,
+        subsec: Option<String>,
+    ) -> Option<ExtractedDate> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&datetime.year()) {
+            return None;
+        }
+
+        Some(ExtractedDate {
+            date: datetime.format("%Y-%m-%d").to_string(),
+            time: has_time.then(|| datetime.format("%H:%M:%S").to_string()),
+            source: self.source.clone(),
+            subsec: subsec.filter(|_| has_time),
+            confidence: self.confidence,
+            offset: (is_utc && has_time).then(|| "+00:00".to_string()),
+        })
+    }
+}
+
+/// Ordered, compiled set of patterns
+#[derive(Debug, Clone)]
+pub struct PatternRegistry {
+    patterns: Vec<CompiledPattern>,
+}
+
+impl PatternRegistry {
+    /// Build a registry from built-ins plus user overrides.
+    ///
+    /// Invalid user patterns are skipped and reported in the returned errors
+    /// so a typo in settings never disables date extraction altogether.
+    pub fn with_overrides(user_patterns: &[DatePattern]) -> (Self, Vec<String>) {
+        let mut entries = builtin_patterns();
+        for user in user_patterns {
+            match entries.iter_mut().find(|p| p.name == user.name) {
+                Some(existing) => *existing = user.clone(),
+                None => entries.push(user.clone()),
+            }
+        }
+        // Stable sort keeps insertion order between equal priorities
+        entries.sort_by_key(|p| p.priority);
+
+        let mut errors = Vec::new();
+        let patterns = entries
+            .iter()
+            .filter(|p| p.enabled)
+            .filter_map(|p| p.compile().map_err(|e| errors.push(e)).ok())
+            .collect();
+
+        (Self { patterns }, errors)
+    }
+
+    /// Registry from the settings JSON (`dateFilenamePatterns` array)
+    pub fn from_settings_json(settings: &str) -> (Self, Vec<String>) {
+        let user_patterns: Vec<DatePattern> = serde_json::from_str::<serde_json::Value>(settings)
+            .ok()
+            .and_then(|v| v.get(SETTINGS_KEY).cloned())
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+        Self::with_overrides(&user_patterns)
+    }
+
+    /// The registry with only built-in patterns, compiled once per process
+    pub fn builtin() -> &'static PatternRegistry {
+        static BUILTIN: OnceLock<PatternRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(|| Self::with_overrides(&[]).0)
+    }
+
+    /// Try each pattern in priority order
+    pub fn extract(&self, filename: &str) -> Option<ExtractedDate> {
+        self.patterns.iter().find_map(|p| p.extract(filename))
+    }
+}
+
+impl Default for PatternRegistry {
+    fn default() -> Self {
+        Self::builtin().clone()
+    }
+}
+
+/// A filename matched by a pattern under validation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternSample {
+    pub filename: String,
+    pub extracted: ExtractedDate,
+}
+
+/// Result of validating a pattern against a directory listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternValidation {
+    pub files_checked: usize,
+    pub match_count: usize,
+    pub samples: Vec<PatternSample>,
+}
+
+/// Validate a pattern and show which files in `directory` it would date
+#[tauri::command]
+pub fn validate_date_pattern(
+    pattern: DatePattern,
+    directory: String,
+    max_samples: Option<usize>,
+) -> Result<PatternValidation, String> {
+    let compiled = pattern.compile()?;
+    let max_samples = max_samples.unwrap_or(20);
+
+    let mut filenames: Vec<String> = std::fs::read_dir(&directory)
+        .map_err(|e| format!("Failed to read directory: {}", e))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+        .collect();
+    filenames.sort();
+
+    let mut samples = Vec::new();
+    let mut match_count = 0;
+    for filename in &filenames {
+        if let Some(extracted) = compiled.extract(filename) {
+            match_count += 1;
+            if samples.len() < max_samples {
+                samples.push(PatternSample { filename: filename.clone(), extracted });
+            }
+        }
+    }
+
+    Ok(PatternValidation {
+        files_checked: filenames.len(),
+        match_count,
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn user_pattern(name: &str, pattern: &str, priority: i32) -> DatePattern {
+        DatePattern {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            source: "Custom".to_string(),
+            priority,
+            confidence: DateConfidence::Medium,
+            utc: false,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_builtin_patterns_all_compile() {
+        let (_, errors) = PatternRegistry::with_overrides(&[]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_user_pattern_runs_before_builtins() {
+        let custom = user_pattern("dads_phone", r"DAD_(?P<day>\d{2})(?P<month>\d{2})(?P<year>\d{4})", 0);
+        let (registry, errors) = PatternRegistry::with_overrides(&[custom]);
+        assert!(errors.is_empty());
+
+        let ext = registry.extract("DAD_15012024.jpg").unwrap();
+        assert_eq!(ext.date, "2024-01-15");
+        assert_eq!(ext.source, "Custom");
+    }
+
+    #[test]
+    fn test_user_pattern_overrides_builtin_by_name() {
+        let mut disabled = user_pattern("generic", r"(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})", 1000);
+        disabled.enabled = false;
+        let (registry, _) = PatternRegistry::with_overrides(&[disabled]);
+        assert!(registry.extract("photo_2024-03-20_something.jpg").is_none());
+    }
+
+    #[test]
+    fn test_invalid_user_pattern_is_reported_and_skipped() {
+        let broken = user_pattern("broken", r"(?P<year>\d{4}", 0);
+        let no_captures = user_pattern("no_captures", r"\d{8}", 0);
+        let (registry, errors) = PatternRegistry::with_overrides(&[broken, no_captures]);
+        assert_eq!(errors.len(), 2);
+        assert!(registry.extract("IMG-20240115-WA0042.jpg").is_some());
+    }
+
+    #[test]
+    fn test_epoch_ms_capture() {
+        let pattern = user_pattern("epoch", r"FB_IMG_(?P<epoch_ms>\d{13})", 0);
+        let ext = pattern.compile().unwrap().extract("FB_IMG_1600000000000.jpg").unwrap();
+        assert_eq!(ext.date, "2020-09-13");
+        assert_eq!(ext.time, Some("12:26:40".to_string()));
+        assert_eq!(ext.offset, Some("+00:00".to_string()));
+    }
+
+    fn builtin_named(name: &str) -> CompiledPattern {
+        builtin_patterns().into_iter().find(|p| p.name == name).unwrap().compile().unwrap()
+    }
+
+    #[test]
+    fn test_pixel_is_utc_with_milliseconds() {
+        let ext = PatternRegistry::builtin().extract("PXL_20230514_101112345.jpg").unwrap();
+        assert_eq!(ext.source, "Pixel");
+        assert_eq!(ext.date, "2023-05-14");
+        assert_eq!(ext.time, Some("10:11:12".to_string()));
+        assert_eq!(ext.offset, Some("+00:00".to_string()));
+        assert_eq!(ext.subsec, Some("345".to_string()));
+        assert_eq!(ext.confidence, DateConfidence::High);
+    }
+
+    #[test]
+    fn test_whatsapp_video() {
+        let ext = PatternRegistry::builtin().extract("VID-20220101-WA0003.mp4").unwrap();
+        assert_eq!(ext.date, "2022-01-01");
+        assert_eq!(ext.source, "WhatsApp");
+        assert!(ext.time.is_none());
+        assert!(ext.offset.is_none());
+    }
+
+    #[test]
+    fn test_signal_names() {
+        let ext = PatternRegistry::builtin().extract("signal-2023-04-01-101010_002.jpeg").unwrap();
+        assert_eq!(ext.date, "2023-04-01");
+        assert_eq!(ext.time, Some("10:10:10".to_string()));
+        assert_eq!(ext.source, "Signal");
+        assert!(ext.subsec.is_none());
+
+        let ext = PatternRegistry::builtin().extract("signal-2023-04-01-10-10-10-123.jpg").unwrap();
+        assert_eq!(ext.time, Some("10:10:10".to_string()));
+        assert_eq!(ext.subsec, Some("123".to_string()));
+    }
+
+    #[test]
+    fn test_facebook_epoch_ms() {
+        let ext = PatternRegistry::builtin().extract("FB_IMG_1600000000123.jpg").unwrap();
+        assert_eq!(ext.source, "Facebook");
+        assert_eq!(ext.date, "2020-09-13");
+        assert_eq!(ext.time, Some("12:26:40".to_string()));
+        assert_eq!(ext.subsec, Some("123".to_string()));
+        assert_eq!(ext.confidence, DateConfidence::Low);
+    }
+
+    #[test]
+    fn test_screen_recording_mac() {
+        let ext = PatternRegistry::builtin().extract("Screen Recording 2023-01-05 at 10.11.12.mov").unwrap();
+        assert_eq!(ext.date, "2023-01-05");
+        assert_eq!(ext.time, Some("10:11:12".to_string()));
+        assert_eq!(ext.source, "Screen Recording");
+    }
+
+    #[test]
+    fn test_telegram() {
+        let ext = PatternRegistry::builtin().extract("photo_2023-02-03_10-11-12.jpg").unwrap();
+        assert_eq!(ext.date, "2023-02-03");
+        assert_eq!(ext.time, Some("10:11:12".to_string()));
+        assert_eq!(ext.source, "Telegram");
+    }
+
+    #[test]
+    fn test_dji_names() {
+        let ext = PatternRegistry::builtin().extract("DJI_20230514101112_0001_D.JPG").unwrap();
+        assert_eq!(ext.date, "2023-05-14");
+        assert_eq!(ext.time, Some("10:11:12".to_string()));
+        assert_eq!(ext.source, "DJI");
+
+        let ext = PatternRegistry::builtin()
+            .extract("dji_fly_20230514_101112_0001_1684059072000_photo.jpg")
+            .unwrap();
+        assert_eq!(ext.source, "DJI");
+        assert_eq!(ext.time, Some("10:11:12".to_string()));
+    }
+
+    #[test]
+    fn test_gopro_quik_export() {
+        let ext = PatternRegistry::builtin().extract("GX010234_1605365734623.MP4").unwrap();
+        assert_eq!(ext.source, "GoPro");
+        assert_eq!(ext.confidence, DateConfidence::Low);
+        // Plain GoPro names carry no date
+        assert!(PatternRegistry::builtin().extract("GOPR0001.JPG").is_none());
+    }
+
+    #[test]
+    fn test_bare_epoch_ms() {
+        let ext = PatternRegistry::builtin().extract("1684059072000.png").unwrap();
+        assert_eq!(ext.source, "Epoch");
+        assert_eq!(PatternRegistry::builtin().extract("1684059072000 (1).png").unwrap().source, "Epoch");
+        // Thirteen digits inside a longer name are an ID, not a time
+        assert!(PatternRegistry::builtin().extract("order-1684059072000.png").is_none());
+        assert!(PatternRegistry::builtin().extract("99999999999999.png").is_none());
+    }
+
+    #[test]
+    fn test_samsung_names() {
+        let ext = PatternRegistry::builtin().extract("Screenshot_20230514-101112_Chrome.jpg").unwrap();
+        assert_eq!(ext.source, "Samsung Screenshot");
+        assert_eq!((ext.date.as_str(), ext.time.as_deref()), ("2023-05-14", Some("10:11:12")));
+        assert!(ext.offset.is_none());
+
+        let ext = PatternRegistry::builtin().extract("Screen_Recording_20230514-101112_YouTube.mp4").unwrap();
+        assert_eq!(ext.source, "Samsung Screen Recording");
+        assert_eq!(ext.confidence, DateConfidence::High);
+        // The camera's own names
+        assert_eq!(PatternRegistry::builtin().extract("20230514_101112(0).jpg").unwrap().source, "Camera");
+    }
+
+    #[test]
+    fn test_rejects_impossible_dates() {
+        let compiled = builtin_named("camera");
+        assert!(compiled.extract("20240230_101010.jpg").is_none());
+        assert!(compiled.extract("20240115_256000.jpg").is_none());
+    }
+
+    #[test]
+    fn test_from_settings_json() {
+        let settings = r#"{
+            "archivePath": "/archive",
+            "dateFilenamePatterns": [
+                {"name": "scan", "pattern": "SCAN(?P<year>\\d{4})(?P<month>\\d{2})(?P<day>\\d{2})", "source": "Scanner"}
+            ]
+        }"#;
+        let (registry, errors) = PatternRegistry::from_settings_json(settings);
+        assert!(errors.is_empty());
+        assert_eq!(registry.extract("SCAN19990101.tif").unwrap().source, "Scanner");
+    }
+
+    #[test]
+    fn test_validate_date_pattern_samples_directory() {
+        let dir = tempdir().unwrap();
+        fs::File::create(dir.path().join("DAD_15012024.jpg")).unwrap();
+        fs::File::create(dir.path().join("DAD_16012024.jpg")).unwrap();
+        fs::File::create(dir.path().join("other.jpg")).unwrap();
+
+        let pattern = user_pattern("dads_phone", r"DAD_(?P<day>\d{2})(?P<month>\d{2})(?P<year>\d{4})", 0);
+        let result = validate_date_pattern(pattern, dir.path().to_string_lossy().to_string(), Some(1)).unwrap();
+        assert_eq!(result.files_checked, 3);
+        assert_eq!(result.match_count, 2);
+        assert_eq!(result.samples.len(), 1);
+        assert_eq!(result.samples[0].extracted.date, "2024-01-15");
+    }
+
+    #[test]
+    fn test_validate_date_pattern_invalid_regex() {
+        let pattern = user_pattern("broken", r"(?P<year", 0);
+        assert!(validate_date_pattern(pattern, ".".to_string(), None).is_err());
+    }
+}
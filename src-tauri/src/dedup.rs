@@ -3,12 +3,19 @@
 //! This module provides functions to:
 //! - Find exact duplicates (hash-based)
 //! - Find similar images (perceptual hash)
+//! - Find broken files, as an alternative integrity check backend
 //! - Parse czkawka JSON output
+//! - Add capture date and camera from the metadata index, to help pick the keeper
 //! - Delete files to system Trash
 
 use serde::{Deserialize, Serialize};
 use std::process::Command;
 
+use crate::companions;
+use crate::metadata;
+use crate::metadata_index;
+use crate::state::AppState;
+
 /// Represents a group of duplicate files
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DuplicateGroup {
@@ -22,6 +29,8 @@ pub struct DuplicateFile {
     pub path: String,
     pub size: u64,
     pub modified: Option<String>,
+    pub date_taken: Option<String>,
+    pub camera_model: Option<String>,
 }
 
 /// Represents a group of similar images
@@ -39,6 +48,8 @@ pub struct SimilarFile {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub similarity: u32,  // Similarity difference (0 = identical, higher = more different)
+    pub date_taken: Option<String>,
+    pub camera_model: Option<String>,
 }
 
 /// Result of a dedup scan
@@ -134,7 +145,12 @@ pub async fn find_duplicates(
     let json_content = std::fs::read_to_string(&output_file)
         .map_err(|e| format!("czkawka did not produce output file. Is czkawka_cli installed? Error: {}", e))?;
 
-    parse_duplicate_json(&json_content)
+    let mut result = parse_duplicate_json(&json_content)?;
+    for file in result.duplicates.iter_mut().flat_map(|g| g.files.iter_mut()) {
+        (file.date_taken, file.camera_model) = indexed_details(&state, &file.path);
+    }
+    state.metadata_index.flush()?;
+    Ok(result)
 }
 
 /// Find similar images using perceptual hash (async, cancellable)
@@ -180,7 +196,85 @@ pub async fn find_similar_images(
     let json_content = std::fs::read_to_string(&output_file)
         .map_err(|e| format!("czkawka did not produce output file. Is czkawka_cli installed? Error: {}", e))?;
 
-    parse_similar_json(&json_content)
+    let mut result = parse_similar_json(&json_content)?;
+    for file in result.similar_groups.iter_mut().flat_map(|g| g.files.iter_mut()) {
+        (file.date_taken, file.camera_model) = indexed_details(&state, &file.path);
+    }
+    state.metadata_index.flush()?;
+    Ok(result)
+}
+
+/// A file czkawka could not open or decode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrokenFile {
+    pub path: String,
+    pub error: String,
+}
+
+/// Find broken files with czkawka's `broken` mode (cancellable through `operation_id`)
+pub(crate) fn find_broken_files(
+    state: &AppState,
+    path: &str,
+    czkawka_path: Option<String>,
+    operation_id: &str,
+) -> Result<Vec<BrokenFile>, String> {
+    let czkawka = czkawka_path.unwrap_or_else(|| "czkawka_cli".to_string());
+
+    let output_file = std::env::temp_dir().join("tasaveer_broken_results.json");
+    let output_path = output_file.to_string_lossy().to_string();
+    // A stale file from an earlier run must not pass for this one's results
+    let _ = std::fs::remove_file(&output_file);
+
+    let child = Command::new(&czkawka)
+        .args([
+            "broken",
+            "-d", path,
+            "-C", &output_path,
+        ])
+        .spawn()
+        .map_err(|e| format!("Failed to spawn czkawka: {}", e))?;
+
+    state.running_processes.lock().unwrap().insert(operation_id.to_string(), child.id());
+    let output = child.wait_with_output();
+    state.running_processes.lock().unwrap().remove(operation_id);
+    output.map_err(|e| format!("Failed to wait for czkawka: {}", e))?;
+
+    let json_content = std::fs::read_to_string(&output_file)
+        .map_err(|e| format!("czkawka did not produce output file. Is czkawka_cli installed? Error: {}", e))?;
+    parse_broken_json(&json_content)
+}
+
+/// Parse czkawka broken files JSON output
+/// czkawka 10.0 outputs: [{"path":..., "size":..., "type_of_file":..., "error_string":...}, ...]
+fn parse_broken_json(json: &str) -> Result<Vec<BrokenFile>, String> {
+    if json.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let parsed: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    let files = parsed
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| {
+            let path = file.get("path").and_then(|p| p.as_str())?.to_string();
+            let error = file.get("error_string")
+                .and_then(|e| e.as_str())
+                .unwrap_or("Broken file")
+                .to_string();
+            Some(BrokenFile { path, error })
+        })
+        .collect();
+    Ok(files)
+}
+
+/// Capture date and camera of a file, read only if it changed since it was indexed
+fn indexed_details(state: &AppState, path: &str) -> (Option<String>, Option<String>) {
+    match metadata_index::read_cached(state, path) {
+        Some(exif) => (exif.date_time_original.clone(), metadata::camera_model_of(exif)),
+        None => (None, None),
+    }
 }
 
 /// Parse czkawka duplicate JSON output
@@ -234,7 +328,7 @@ fn parse_duplicate_json(json: &str) -> Result<DedupResult, String> {
                             }
                             group_size = size;
 
-                            files.push(DuplicateFile { path, size, modified });
+                            files.push(DuplicateFile { path, size, modified, date_taken: None, camera_model: None });
                         }
 
                         if files.len() > 1 {
@@ -300,7 +394,15 @@ fn parse_similar_json(json: &str) -> Result<SimilarResult, String> {
                         max_similarity = similarity;
                     }
 
-                    files.push(SimilarFile { path, size, width, height, similarity });
+                    files.push(SimilarFile {
+                        path,
+                        size,
+                        width,
+                        height,
+                        similarity,
+                        date_taken: None,
+                        camera_model: None,
+                    });
                 }
 
                 if files.len() > 1 {
@@ -319,12 +421,55 @@ fn parse_similar_json(json: &str) -> Result<SimilarResult, String> {
     })
 }
 
+/// Companions that deleting `files` would take along: Live Photo videos,
+/// RAW or JPEG partners and the originals' edits, but never an edit's original
+fn trash_companions(state: &AppState, files: &[String]) -> Vec<String> {
+    let paths: Vec<&str> = files.iter().map(String::as_str).collect();
+    let companions = companions::companions_of_files(state, &paths);
+    let mut extra: Vec<String> = Vec::new();
+    for file in files {
+        for companion in companions.get(file).into_iter().flatten() {
+            if companions::follows_delete(file, companion)
+                && !files.contains(&companion.file_path)
+                && !extra.contains(&companion.file_path)
+            {
+                extra.push(companion.file_path.clone());
+            }
+        }
+    }
+    extra
+}
+
+/// List the companions `delete_to_trash` would also delete with `include_companions`
+/// (async, since it reads the files' folders)
+#[tauri::command]
+pub async fn list_trash_companions(state: tauri::State<'_, AppState>, files: Vec<String>) -> Result<Vec<String>, String> {
+    Ok(trash_companions(&state, &files))
+}
+
 /// Delete files to system Trash (recoverable)
+///
+/// With `include_companions` each file's companions (see `list_trash_companions`)
+/// go with it, so no half is left behind. Off unless asked for.
 #[tauri::command]
-pub fn delete_to_trash(files: Vec<String>) -> Result<String, String> {
+pub async fn delete_to_trash(
+    state: tauri::State<'_, AppState>,
+    files: Vec<String>,
+    include_companions: Option<bool>,
+) -> Result<String, String> {
     let mut deleted = 0;
     let mut errors = Vec::new();
 
+    let extra = match include_companions.unwrap_or(false) {
+        true => trash_companions(&state, &files),
+        false => Vec::new(),
+    };
+    let companion_count = extra.len();
+    let files: Vec<String> = files.into_iter().chain(extra).collect();
+
+    // Canonical paths can only be resolved while the files still exist
+    let _ = state.metadata_index.invalidate(&files);
+
     for file in &files {
         match trash::delete(file) {
             Ok(_) => deleted += 1,
@@ -332,7 +477,9 @@ pub fn delete_to_trash(files: Vec<String>) -> Result<String, String> {
         }
     }
 
-    if errors.is_empty() {
+    if errors.is_empty() && companion_count > 0 {
+        Ok(format!("Deleted {} files to Trash, including {} companion files", deleted, companion_count))
+    } else if errors.is_empty() {
         Ok(format!("Deleted {} files to Trash", deleted))
     } else {
         Err(format!(
@@ -377,6 +524,19 @@ mod tests {
         assert_eq!(result.total_wasted_space, 1000);
     }
 
+    #[test]
+    fn test_parse_broken_json() {
+        let json = r#"[
+            {"path": "/a/half.jpg", "modified_date": 1700000000, "size": 1024, "type_of_file": "Image", "error_string": "unexpected EOF"},
+            {"path": "/a/clip.mp4", "size": 0, "type_of_file": "Video"}
+        ]"#;
+        let files = parse_broken_json(json).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], BrokenFile { path: "/a/half.jpg".to_string(), error: "unexpected EOF".to_string() });
+        assert_eq!(files[1].error, "Broken file");
+        assert!(parse_broken_json("[]").unwrap().is_empty());
+    }
+
     #[test]
     fn test_parse_similar_json_empty() {
         let result = parse_similar_json("[]").unwrap();
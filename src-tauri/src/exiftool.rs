@@ -0,0 +1,473 @@
+//! Persistent ExifTool process pool.
+//!
+//! Spawning `exiftool` once per file is dominated by Perl startup, which makes
+//! scans of large archives take hours. This module keeps a small set of
+//! `exiftool -stay_open True -@ -` workers alive and multiplexes requests over
+//! them:
+//! - Arguments are written one per line to the worker's stdin
+//! - Each request ends with `-executeN`, and output is read until `{readyN}`
+//! - Crashed workers are discarded and replaced on the next request
+//! - `shutdown` asks every worker to exit cleanly when the app closes
+//!
+//! It also provides `stream_json_array` for parsing large `-json` outputs
+//! record by record instead of buffering them whole.
+
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+/// Upper bound on concurrently running exiftool workers
+const MAX_WORKERS: usize = 4;
+
+/// How long to wait for stderr to catch up once stdout reported ready
+const STDERR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Output of a single exiftool request
+#[derive(Debug, Clone, Default)]
+pub struct ExiftoolOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ExiftoolOutput {
+    /// Mirrors exiftool's exit status: a request failed if it reported any error
+    pub fn success(&self) -> bool {
+        !self
+            .stderr
+            .lines()
+            .any(|line| line.trim_start().starts_with("Error"))
+    }
+}
+
+/// A single long-lived `exiftool -stay_open` process
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: Receiver<String>,
+}
+
+impl Worker {
+    fn spawn(program: &str) -> Result<Self, String> {
+        let mut child = Command::new(program)
+            .args(["-stay_open", "True", "-@", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run exiftool: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or("Failed to open exiftool stdin")?;
+        let stdout = child.stdout.take().ok_or("Failed to open exiftool stdout")?;
+        let stderr = child.stderr.take().ok_or("Failed to open exiftool stderr")?;
+
+        // Drain stderr on a separate thread so a chatty request can never
+        // block the worker while we are waiting on stdout.
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                match line {
+                    Ok(line) => {
+                        if tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr: rx,
+        })
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn execute(&mut self, args: &[String], seq: u64) -> Result<ExiftoolOutput, String> {
+        let marker = format!("{{ready{}}}", seq);
+
+        let mut request = String::new();
+        for arg in args {
+            if arg.contains('\n') {
+                return Err(format!("exiftool argument contains a newline: {:?}", arg));
+            }
+            request.push_str(arg);
+            request.push('\n');
+        }
+        // -echo4 prints the marker to stderr once the command has finished,
+        // so stderr can be matched up with the request that produced it.
+        request.push_str(&format!("-echo4\n{}\n-execute{}\n", marker, seq));
+
+        self.stdin
+            .write_all(request.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Failed to write to exiftool: {}", e))?;
+
+        let stdout = read_until_ready(&mut self.stdout, &marker)?;
+
+        let mut stderr = String::new();
+        loop {
+            match self.stderr.recv_timeout(STDERR_TIMEOUT) {
+                Ok(line) if line.trim_end() == marker => break,
+                Ok(line) => {
+                    stderr.push_str(&line);
+                    stderr.push('\n');
+                }
+                Err(_) => return Err("exiftool stopped responding on stderr".to_string()),
+            }
+        }
+
+        Ok(ExiftoolOutput { stdout, stderr })
+    }
+
+    fn shutdown(mut self) {
+        let _ = self.stdin.write_all(b"-stay_open\nFalse\n");
+        let _ = self.stdin.flush();
+        drop(self.stdin);
+        let _ = self.child.wait();
+    }
+}
+
+/// Read stdout lines until the `{readyN}` marker, returning everything before it
+fn read_until_ready<R: BufRead>(reader: &mut R, marker: &str) -> Result<String, String> {
+    let mut output = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read from exiftool: {}", e))?;
+        if read == 0 {
+            return Err("exiftool exited unexpectedly".to_string());
+        }
+        if line.trim_end() == marker {
+            return Ok(output);
+        }
+        output.push_str(&line);
+    }
+}
+
+/// Parse a JSON array from `reader` one element at a time.
+///
+/// `on_record` is called for every element as soon as it has been read, and
+/// may return an error (e.g. on cancellation) to stop parsing early. Returns
+/// the number of records seen. Empty input is treated as an empty array,
+/// which is what exiftool prints when no files matched.
+pub fn stream_json_array<R, F>(reader: R, on_record: F) -> Result<usize, String>
+where
+    R: Read,
+    F: FnMut(serde_json::Value) -> Result<(), String>,
+{
+    struct RecordSeed<'a, F> {
+        on_record: &'a mut F,
+        count: &'a mut usize,
+        callback_error: &'a mut Option<String>,
+    }
+
+    impl<'de, F> DeserializeSeed<'de> for RecordSeed<'_, F>
+    where
+        F: FnMut(serde_json::Value) -> Result<(), String>,
+    {
+        type Value = ();
+
+        fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_seq(self)
+        }
+    }
+
+    impl<'de, F> Visitor<'de> for RecordSeed<'_, F>
+    where
+        F: FnMut(serde_json::Value) -> Result<(), String>,
+    {
+        type Value = ();
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a JSON array of exiftool records")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            while let Some(record) = seq.next_element::<serde_json::Value>()? {
+                *self.count += 1;
+                if let Err(e) = (self.on_record)(record) {
+                    *self.callback_error = Some(e);
+                    return Err(serde::de::Error::custom("stopped by callback"));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut on_record = on_record;
+    let mut count = 0;
+    let mut callback_error = None;
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let result = RecordSeed {
+        on_record: &mut on_record,
+        count: &mut count,
+        callback_error: &mut callback_error,
+    }
+    .deserialize(&mut deserializer);
+
+    if let Some(e) = callback_error {
+        return Err(e);
+    }
+    match result {
+        Ok(()) => Ok(count),
+        Err(e) if e.is_eof() && count == 0 => Ok(0),
+        Err(e) => Err(format!("Failed to parse exiftool output: {}", e)),
+    }
+}
+
+/// Pool of persistent exiftool workers shared through `AppState`
+pub struct ExiftoolPool {
+    program: String,
+    max_workers: usize,
+    idle: Mutex<Vec<Worker>>,
+    // Number of workers currently alive, both idle and checked out
+    spawned: Mutex<usize>,
+    available: Condvar,
+    next_seq: AtomicU64,
+    shut_down: AtomicBool,
+    installed: OnceLock<bool>,
+}
+
+impl ExiftoolPool {
+    pub fn new() -> Self {
+        let max_workers = std::thread::available_parallelism()
+            .map(|n| n.get().min(MAX_WORKERS))
+            .unwrap_or(2);
+        Self::with_program("exiftool", max_workers)
+    }
+
+    pub fn with_program(program: &str, max_workers: usize) -> Self {
+        Self {
+            program: program.to_string(),
+            max_workers: max_workers.max(1),
+            idle: Mutex::new(Vec::new()),
+            spawned: Mutex::new(0),
+            available: Condvar::new(),
+            next_seq: AtomicU64::new(1),
+            shut_down: AtomicBool::new(false),
+            installed: OnceLock::new(),
+        }
+    }
+
+    /// Whether the exiftool binary can be run at all (checked once, then cached)
+    pub fn is_installed(&self) -> bool {
+        *self.installed.get_or_init(|| {
+            Command::new(&self.program)
+                .arg("-ver")
+                .output()
+                .map(|out| out.status.success())
+                .unwrap_or(false)
+        })
+    }
+
+    /// Run one exiftool command (the same arguments you would pass on the
+    /// command line) on a pooled worker.
+    ///
+    /// If the worker died mid-request it is replaced and the request retried once.
+    pub fn execute<S: AsRef<str>>(&self, args: &[S]) -> Result<ExiftoolOutput, String> {
+        let args: Vec<String> = args.iter().map(|a| a.as_ref().to_string()).collect();
+
+        let mut last_error = String::new();
+        for _ in 0..2 {
+            let mut worker = self.checkout()?;
+            let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+            match worker.execute(&args, seq) {
+                Ok(output) => {
+                    self.checkin(worker);
+                    return Ok(output);
+                }
+                Err(e) => {
+                    // The worker's stream position is unknown now, so never reuse it
+                    self.discard(worker);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Ask all idle workers to exit and refuse further requests
+    pub fn shutdown(&self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+        let workers: Vec<Worker> = self.idle.lock().unwrap().drain(..).collect();
+        for worker in workers {
+            *self.spawned.lock().unwrap() -= 1;
+            worker.shutdown();
+        }
+        self.available.notify_all();
+    }
+
+    fn checkout(&self) -> Result<Worker, String> {
+        loop {
+            if self.shut_down.load(Ordering::SeqCst) {
+                return Err("exiftool pool has been shut down".to_string());
+            }
+
+            if let Some(mut worker) = self.idle.lock().unwrap().pop() {
+                if worker.is_alive() {
+                    return Ok(worker);
+                }
+                self.discard(worker);
+                continue;
+            }
+
+            let mut spawned = self.spawned.lock().unwrap();
+            if *spawned < self.max_workers {
+                *spawned += 1;
+                drop(spawned);
+                return Worker::spawn(&self.program).inspect_err(|_| {
+                    *self.spawned.lock().unwrap() -= 1;
+                });
+            }
+
+            // Everyone is busy; wait for a worker to be returned
+            let _unused = self
+                .available
+                .wait_timeout(spawned, Duration::from_millis(100))
+                .unwrap();
+        }
+    }
+
+    fn checkin(&self, worker: Worker) {
+        if self.shut_down.load(Ordering::SeqCst) {
+            *self.spawned.lock().unwrap() -= 1;
+            worker.shutdown();
+            return;
+        }
+        self.idle.lock().unwrap().push(worker);
+        self.available.notify_one();
+    }
+
+    fn discard(&self, mut worker: Worker) {
+        let _ = worker.child.kill();
+        let _ = worker.child.wait();
+        *self.spawned.lock().unwrap() -= 1;
+        self.available.notify_one();
+    }
+}
+
+impl Default for ExiftoolPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ExiftoolPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_until_ready_returns_output_before_marker() {
+        let mut reader = Cursor::new("[{\"SourceFile\": \"a.jpg\"}]\n{ready7}\nleftover\n");
+        let output = read_until_ready(&mut reader, "{ready7}").unwrap();
+        assert_eq!(output, "[{\"SourceFile\": \"a.jpg\"}]\n");
+    }
+
+    #[test]
+    fn test_read_until_ready_ignores_other_markers() {
+        let mut reader = Cursor::new("{ready6}\nline\n{ready7}\n");
+        let output = read_until_ready(&mut reader, "{ready7}").unwrap();
+        assert_eq!(output, "{ready6}\nline\n");
+    }
+
+    #[test]
+    fn test_read_until_ready_eof_is_error() {
+        let mut reader = Cursor::new("partial output\n");
+        assert!(read_until_ready(&mut reader, "{ready1}").is_err());
+    }
+
+    #[test]
+    fn test_output_success_detects_errors() {
+        let ok = ExiftoolOutput {
+            stdout: "    1 image files updated\n".to_string(),
+            stderr: "Warning: [minor] Ignored empty rational value\n".to_string(),
+        };
+        assert!(ok.success());
+
+        let failed = ExiftoolOutput {
+            stdout: String::new(),
+            stderr: "Error: File not found - missing.jpg\n".to_string(),
+        };
+        assert!(!failed.success());
+    }
+
+    #[test]
+    fn test_stream_json_array_yields_each_record() {
+        let json = r#"[{"SourceFile": "a.jpg"},
+{"SourceFile": "b.jpg", "Make": "Canon"}]"#;
+        let mut seen = Vec::new();
+        let count = stream_json_array(json.as_bytes(), |record| {
+            seen.push(record["SourceFile"].as_str().unwrap().to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(seen, vec!["a.jpg", "b.jpg"]);
+    }
+
+    #[test]
+    fn test_stream_json_array_empty_input() {
+        let count = stream_json_array("".as_bytes(), |_| Ok(())).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_stream_json_array_callback_error_stops_parsing() {
+        let json = r#"[{"SourceFile": "a.jpg"}, {"SourceFile": "b.jpg"}]"#;
+        let mut calls = 0;
+        let result = stream_json_array(json.as_bytes(), |_| {
+            calls += 1;
+            Err("Operation cancelled".to_string())
+        });
+        assert_eq!(result.unwrap_err(), "Operation cancelled");
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_stream_json_array_truncated_is_error() {
+        let json = r#"[{"SourceFile": "a.jpg"}, {"SourceFile": "#;
+        assert!(stream_json_array(json.as_bytes(), |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_pool_missing_binary_is_error() {
+        let pool = ExiftoolPool::with_program("/nonexistent/exiftool-binary", 1);
+        let result = pool.execute(&["-ver"]);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Failed to run exiftool"));
+    }
+
+    #[test]
+    fn test_pool_missing_binary_is_not_installed() {
+        let pool = ExiftoolPool::with_program("/nonexistent/exiftool-binary", 1);
+        assert!(!pool.is_installed());
+    }
+
+    #[test]
+    fn test_pool_rejects_requests_after_shutdown() {
+        let pool = ExiftoolPool::with_program("/nonexistent/exiftool-binary", 1);
+        pool.shutdown();
+        assert!(pool.execute(&["-ver"]).is_err());
+    }
+}
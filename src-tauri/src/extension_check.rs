@@ -0,0 +1,440 @@
+//! Verify file extensions against file contents and repair mismatches.
+//!
+//! This module provides functions to:
+//! - Sniff each media file's real container from its magic bytes
+//! - Report files whose extension names another format (`.jpg` that is HEIC or PNG)
+//! - Rename them to the usual extension, carrying `.xmp`, `.aae` and Takeout
+//!   `.json` sidecars along, and journal the renames so they can be reverted
+//!
+//! Extensions of one family (`.jpg`/`.jpeg`, `.mp4`/`.mov`, TIFF-based RAW)
+//! are never flagged, since tools treat them alike. Name collisions are
+//! resolved the way phockup resolves them when organizing: a file with the
+//! same content is a duplicate and left alone, otherwise `-2`, `-3`, ... is
+//! appended to the stem. A suffixed file takes its companions (the Live
+//! Photo `.MOV`, the RAW of a RAW+JPEG pair) to the new stem so they still pair.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::companions;
+use crate::journal;
+use crate::media_types::{self, MediaCategory, MediaTypeRegistry, SniffedType};
+use crate::metadata;
+use crate::state::AppState;
+use crate::takeout::SidecarIndex;
+
+/// Extensions that name the same format as far as every tool we feed cares
+const EQUIVALENT_EXTENSIONS: &[&[&str]] = &[
+    &["jpg", "jpeg", "jpe", "jfif"],
+    &["tif", "tiff"],
+    &["heic", "heif", "hif"],
+    &["mp4", "m4v", "mov", "qt", "3gp", "3g2"],
+    &["ts", "mts", "m2ts"],
+    &["mpg", "mpeg"],
+];
+
+/// A sidecar moved (or to be moved) along with its media file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SidecarRename {
+    pub from: String,
+    pub to: String,
+}
+
+/// A file whose extension doesn't match its content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionMismatch {
+    pub file_path: String,
+    /// Current extension, empty when the file has none
+    pub extension: String,
+    pub detected_extension: String,
+    pub detected_mime_type: String,
+    pub category: MediaCategory,
+    /// Name the file gets, or got when `renamed`
+    pub new_path: Option<String>,
+    pub renamed: bool,
+    pub sidecars: Vec<SidecarRename>,
+    /// Companion files moved to the new stem, with their sidecars in `sidecars`
+    pub companions: Vec<SidecarRename>,
+    /// An existing file with the same content at the corrected name
+    pub duplicate_of: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Whether `extension` is a fine name for content sniffed as `sniffed`
+pub fn extension_matches(types: &MediaTypeRegistry, extension: &str, sniffed: &SniffedType) -> bool {
+    let extension = extension.to_lowercase();
+    if extension == sniffed.extension {
+        return true;
+    }
+    if EQUIVALENT_EXTENSIONS
+        .iter()
+        .any(|family| family.contains(&extension.as_str()) && family.contains(&sniffed.extension))
+    {
+        return true;
+    }
+    // NEF, ARW, DNG and most other RAW formats are TIFF inside
+    sniffed.extension == "tif" && types.lookup(&extension).is_some_and(|t| t.category == MediaCategory::Raw)
+}
+
+/// Whether two files hold the same bytes, compared in chunks after the sizes
+fn same_content(a: &Path, b: &Path) -> bool {
+    let size = |p: &Path| std::fs::metadata(p).map(|m| m.len()).ok();
+    if size(a).is_none() || size(a) != size(b) {
+        return false;
+    }
+    let (Ok(a), Ok(b)) = (File::open(a), File::open(b)) else {
+        return false;
+    };
+    let (mut a, mut b) = (BufReader::new(a), BufReader::new(b));
+    let (mut chunk_a, mut chunk_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let Ok(read) = a.read(&mut chunk_a) else { return false };
+        if read == 0 {
+            // Sizes matched, so `b` must be done too unless it grew meanwhile
+            return matches!(b.read(&mut chunk_b), Ok(0));
+        }
+        if b.read_exact(&mut chunk_b[..read]).is_err() || chunk_a[..read] != chunk_b[..read] {
+            return false;
+        }
+    }
+}
+
+/// Where a file renamed to `extension` goes: the plain name if free, a
+/// duplicate if a file with the same content is there, else `stem-N`.
+/// A `stem-N` is only taken when `stem-N.<ext>` is free for every extension
+/// in `companion_extensions` too.
+pub fn rename_target(path: &Path, extension: &str, companion_extensions: &[&str]) -> Result<PathBuf, PathBuf> {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    // Keep the case style of the old extension
+    let uppercase = path.extension().and_then(|e| e.to_str()).is_some_and(|e| !e.is_empty() && e == e.to_uppercase());
+    let extension = if uppercase { extension.to_uppercase() } else { extension.to_string() };
+
+    let mut target = path.with_file_name(format!("{}.{}", stem, extension));
+    let mut suffix = 1;
+    loop {
+        if target.exists() {
+            if same_content(path, &target) {
+                return Err(target);
+            }
+        } else if suffix == 1
+            || companion_extensions
+                .iter()
+                .all(|ext| !path.with_file_name(format!("{}-{}.{}", stem, suffix, ext)).exists())
+        {
+            return Ok(target);
+        }
+        suffix += 1;
+        target = path.with_file_name(format!("{}-{}.{}", stem, suffix, extension));
+    }
+}
+
+/// Companions of `path` sharing its stem, which must follow it to a new stem
+fn stem_companions(state: &AppState, path: &Path) -> Vec<PathBuf> {
+    let file_path = path.to_string_lossy().to_string();
+    let stem = path.file_stem();
+    companions::companions_of_files(state, &[&file_path])
+        .remove(&file_path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| PathBuf::from(c.file_path))
+        .filter(|c| c.file_stem() == stem)
+        .collect()
+}
+
+/// Sidecars of `path` and the names they need once it is `new_path`.
+///
+/// Sidecars named after the whole file (`IMG.jpg.xmp`, `IMG.jpg.json`) follow
+/// the new name; those named after the stem (`IMG.xmp`, `IMG.AAE`) only move
+/// when the stem changes.
+pub fn sidecar_renames(path: &Path, new_path: &Path, takeout: &mut SidecarIndex) -> Vec<SidecarRename> {
+    let (Some(dir), Some(name), Some(new_name)) = (
+        path.parent(),
+        path.file_name().and_then(|n| n.to_str()),
+        new_path.file_name().and_then(|n| n.to_str()),
+    ) else {
+        return Vec::new();
+    };
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let new_stem = new_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+
+    let mut candidates: Vec<PathBuf> = ["xmp", "XMP"].iter().map(|ext| dir.join(format!("{}.{}", name, ext))).collect();
+    if stem != new_stem {
+        candidates.extend(["xmp", "XMP", "aae", "AAE"].iter().map(|ext| dir.join(format!("{}.{}", stem, ext))));
+    }
+    candidates.extend(takeout.find(path));
+
+    let mut renames: Vec<SidecarRename> = Vec::new();
+    for sidecar in candidates.into_iter().filter(|c| c.is_file()) {
+        let Some(sidecar_name) = sidecar.file_name().and_then(|n| n.to_str()) else { continue };
+        // Takeout sidecars shared by edited copies or renamed by a counter stay put
+        let renamed = if let Some(rest) = sidecar_name.strip_prefix(name).filter(|r| r.starts_with('.')) {
+            format!("{}{}", new_name, rest)
+        } else if let Some(rest) = sidecar_name.strip_prefix(stem).filter(|r| r.starts_with('.') && stem != new_stem) {
+            format!("{}{}", new_stem, rest)
+        } else {
+            continue;
+        };
+        let from = sidecar.to_string_lossy().to_string();
+        if renames.iter().all(|r| r.from != from) {
+            renames.push(SidecarRename { from, to: dir.join(renamed).to_string_lossy().to_string() });
+        }
+    }
+    renames
+}
+
+/// Attempts at a free name before giving up on a file whose targets keep
+/// being taken by someone else
+const MAX_RENAME_ATTEMPTS: usize = 5;
+
+/// Pick the corrected name for `mismatch` and the renames of its sidecars and
+/// companions. False when a file with the same content already has the name.
+fn plan_rename(state: &AppState, takeout: &mut SidecarIndex, path: &Path, mismatch: &mut ExtensionMismatch) -> bool {
+    let companion_files = stem_companions(state, path);
+    let companion_extensions: Vec<&str> =
+        companion_files.iter().filter_map(|c| c.extension().and_then(|e| e.to_str())).collect();
+    let target = match rename_target(path, &mismatch.detected_extension, &companion_extensions) {
+        Ok(target) => target,
+        Err(duplicate) => {
+            mismatch.duplicate_of = Some(duplicate.to_string_lossy().to_string());
+            return false;
+        }
+    };
+    mismatch.new_path = Some(target.to_string_lossy().to_string());
+    mismatch.sidecars = sidecar_renames(path, &target, takeout);
+    mismatch.companions.clear();
+
+    let new_stem = target.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    if target.file_stem() != path.file_stem() {
+        // An `.AAE` edit is both a companion and a stem-named sidecar
+        let moving: Vec<&PathBuf> =
+            companion_files.iter().filter(|c| mismatch.sidecars.iter().all(|s| Path::new(&s.from) != c.as_path())).collect();
+        for companion in moving {
+            let extension = companion.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            let moved = companion.with_file_name(format!("{}.{}", new_stem, extension));
+            for sidecar in sidecar_renames(companion, &moved, takeout) {
+                if mismatch.sidecars.iter().all(|s| s.from != sidecar.from) {
+                    mismatch.sidecars.push(sidecar);
+                }
+            }
+            mismatch.companions.push(SidecarRename {
+                from: companion.to_string_lossy().to_string(),
+                to: moved.to_string_lossy().to_string(),
+            });
+        }
+    }
+    true
+}
+
+/// Check one file's extension against its content. None if it matches.
+fn check_file(types: &MediaTypeRegistry, path: &Path) -> Option<ExtensionMismatch> {
+    let sniffed = media_types::sniff(path)?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_string();
+    if !extension.is_empty() && extension_matches(types, &extension, &sniffed) {
+        return None;
+    }
+
+    Some(ExtensionMismatch {
+        file_path: path.to_string_lossy().to_string(),
+        extension,
+        detected_extension: sniffed.extension.to_string(),
+        detected_mime_type: sniffed.mime_type.to_string(),
+        category: sniffed.category,
+        new_path: None,
+        renamed: false,
+        sidecars: Vec::new(),
+        companions: Vec::new(),
+        duplicate_of: None,
+        error: None,
+    })
+}
+
+/// Pick the corrected name for a mismatched file and, when `rename` is set,
+/// move it and its sidecars and companions there
+fn repair(
+    state: &AppState,
+    takeout: &mut SidecarIndex,
+    mut mismatch: ExtensionMismatch,
+    rename: bool,
+    operation_id: &str,
+) -> ExtensionMismatch {
+    let file_path = mismatch.file_path.clone();
+    let path = Path::new(&file_path);
+    if !plan_rename(state, takeout, path, &mut mismatch) || !rename {
+        return mismatch;
+    }
+
+    // Canonical paths can only be resolved while the file is still there
+    let _ = state.metadata_index.invalidate(std::slice::from_ref(&file_path));
+    // A name taken since it was picked means picking again: the next `-N`
+    let mut attempts = 1;
+    loop {
+        let target = mismatch.new_path.clone().unwrap_or_default();
+        match journal::journaled_rename(state, operation_id, &file_path, &target) {
+            Ok(true) => break,
+            Ok(false) if attempts < MAX_RENAME_ATTEMPTS => {
+                attempts += 1;
+                if !plan_rename(state, takeout, path, &mut mismatch) {
+                    return mismatch;
+                }
+            }
+            Ok(false) => {
+                mismatch.error = Some(format!("{} already exists", target));
+                return mismatch;
+            }
+            Err(e) => {
+                mismatch.error = Some(e);
+                return mismatch;
+            }
+        }
+    }
+    mismatch.renamed = true;
+    let failed: Vec<String> = mismatch
+        .companions
+        .iter()
+        .chain(&mismatch.sidecars)
+        .filter(|r| !matches!(journal::journaled_rename(state, operation_id, &r.from, &r.to), Ok(true)))
+        .map(|r| r.from.clone())
+        .collect();
+    if !failed.is_empty() {
+        mismatch.error = Some(format!("Not moved: {}", failed.join(", ")));
+    }
+    mismatch
+}
+
+#[derive(Clone, Serialize)]
+struct ExtensionCheckProgress {
+    id: String,
+    count: usize,
+}
+
+/// Find media files under `directory` whose extension doesn't match their
+/// content (async, cancellable). With `rename` they get the right extension.
+#[tauri::command]
+pub async fn check_extensions(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    operation_id: String,
+    rename: Option<bool>,
+) -> Result<Vec<ExtensionMismatch>, String> {
+    use tauri::Emitter;
+
+    let cancel_token = state.register_token(&operation_id);
+    let types = state.media_types();
+    let rename = rename.unwrap_or(false);
+    let mut takeout = SidecarIndex::new();
+
+    // Renaming inside the walk would change the directories being walked, so
+    // names are only picked, and files moved, once it is done
+    let mut checked = 0;
+    let mut mismatches = Vec::new();
+    let walked = metadata::walk_media_files(&types, &directory, &cancel_token, |path| {
+        mismatches.extend(check_file(&types, path));
+        checked += 1;
+        if checked % 10 == 0 {
+            let _ = app_handle.emit("extension-check-progress", ExtensionCheckProgress {
+                id: operation_id.clone(),
+                count: checked,
+            });
+        }
+        Ok(())
+    });
+
+    let repaired = walked.and_then(|_| {
+        let mut results = Vec::new();
+        for mismatch in mismatches {
+            if cancel_token.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err("Operation cancelled".to_string());
+            }
+            results.push(repair(&state, &mut takeout, mismatch, rename, &operation_id));
+        }
+        Ok(results)
+    });
+
+    state.remove_token(&operation_id);
+    repaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_extension_matches() {
+        let types = MediaTypeRegistry::builtin();
+        let sniffed = |header: &[u8]| media_types::sniff_bytes(header).unwrap();
+        let jpeg = sniffed(&[0xFF, 0xD8, 0xFF, 0xE0]);
+        let heic = sniffed(b"\0\0\0\x18ftypheic");
+        let tiff = sniffed(b"II*\0\x08\0\0\0");
+
+        assert!(extension_matches(types, "JPEG", &jpeg));
+        assert!(!extension_matches(types, "jpg", &heic));
+        assert!(!extension_matches(types, "png", &jpeg));
+        assert!(extension_matches(types, "NEF", &tiff));
+        assert!(extension_matches(types, "mov", &sniffed(b"\0\0\0\x18ftypisom")));
+    }
+
+    #[test]
+    fn test_rename_target_collisions() {
+        let dir = tempdir().unwrap();
+        let photo = dir.path().join("IMG_1.JPG");
+        fs::write(&photo, b"png data").unwrap();
+        assert_eq!(rename_target(&photo, "png", &[]), Ok(dir.path().join("IMG_1.PNG")));
+
+        fs::write(dir.path().join("IMG_1.PNG"), b"other").unwrap();
+        assert_eq!(rename_target(&photo, "png", &[]), Ok(dir.path().join("IMG_1-2.PNG")));
+
+        // The Live Photo video can't move to IMG_1-2.MOV, so both go to -3
+        fs::write(dir.path().join("IMG_1-2.MOV"), b"video").unwrap();
+        assert_eq!(rename_target(&photo, "png", &["MOV"]), Ok(dir.path().join("IMG_1-3.PNG")));
+
+        fs::write(dir.path().join("IMG_1-2.PNG"), b"png data").unwrap();
+        assert_eq!(rename_target(&photo, "png", &[]), Err(dir.path().join("IMG_1-2.PNG")));
+    }
+
+    #[test]
+    fn test_same_content() {
+        let dir = tempdir().unwrap();
+        let big: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let mut changed = big.clone();
+        changed[150_000] ^= 1;
+        for (name, data) in [("a", &big), ("b", &big), ("c", &changed), ("d", &big[..1000].to_vec())] {
+            fs::write(dir.path().join(name), data).unwrap();
+        }
+        let path = |name: &str| dir.path().join(name);
+        assert!(same_content(&path("a"), &path("b")));
+        assert!(!same_content(&path("a"), &path("c")));
+        assert!(!same_content(&path("a"), &path("d")));
+        assert!(!same_content(&path("a"), &path("missing")));
+    }
+
+    #[test]
+    fn test_sidecar_renames() {
+        let dir = tempdir().unwrap();
+        for name in ["IMG_1.jpg", "IMG_1.jpg.xmp", "IMG_1.AAE", "IMG_1.jpg.supplemental-metadata.json"] {
+            fs::write(dir.path().join(name), b"x").unwrap();
+        }
+        let photo = dir.path().join("IMG_1.jpg");
+        let names = |renames: Vec<SidecarRename>| -> Vec<(String, String)> {
+            let name = |p: &str| Path::new(p).file_name().unwrap().to_string_lossy().to_string();
+            renames.iter().map(|r| (name(&r.from), name(&r.to))).collect()
+        };
+
+        let renames = sidecar_renames(&photo, &dir.path().join("IMG_1.heic"), &mut SidecarIndex::new());
+        assert_eq!(
+            names(renames),
+            vec![
+                ("IMG_1.jpg.xmp".to_string(), "IMG_1.heic.xmp".to_string()),
+                ("IMG_1.jpg.supplemental-metadata.json".to_string(), "IMG_1.heic.supplemental-metadata.json".to_string()),
+            ]
+        );
+
+        // A new stem takes the stem-named sidecars too
+        let renames = sidecar_renames(&photo, &dir.path().join("IMG_1-2.heic"), &mut SidecarIndex::new());
+        assert!(names(renames).contains(&("IMG_1.AAE".to_string(), "IMG_1-2.AAE".to_string())));
+    }
+}
@@ -0,0 +1,568 @@
+//! Offline reverse geocoding into the IPTC and XMP location fields.
+//!
+//! This module provides functions to:
+//! - Find the city, state and country nearest to a GPS position
+//! - Preview places for every photo in a folder that has GPS but no location
+//! - Write `XMP-photoshop:City/State/Country`, `XMP-iptcCore:Location` and
+//!   the IPTC location fields, optionally with a `Places|Country|City` keyword
+//!
+//! Places come from GeoNames' `cities15000.txt` and `admin1CodesASCII.txt`
+//! when they are in the app data dir, and otherwise from the compact cities
+//! table also used to infer time zones (see `timezone`). Country names come
+//! from a bundled table. No position ever leaves the machine.
+//!
+//! A position within `MAX_CITY_DISTANCE_KM` of a city gets that city. The
+//! tables hold points, not borders, so a country is only given when no city
+//! of another country is nearly as close (see `is_contested`), and a state
+//! only when no city of another state is; otherwise the photo is left alone,
+//! or gets its country without a state. Beyond `MAX_REGION_DISTANCE_KM` from
+//! every city there is no place at all.
+//!
+//! `IPTC:Sub-location` and `XMP-iptcCore:Location` get the name of the
+//! nearest place in the table, and only when it is within
+//! `MAX_CITY_DISTANCE_KM`. With the GeoNames files that is the nearest town
+//! of 15,000 people or more, so it is often the city itself.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+
+use crate::isobmff::GpsCoordinates;
+use crate::metadata::{self, PreparedWrite};
+use crate::state::AppState;
+use crate::timezone;
+use crate::write_plan::FilePlan;
+use crate::xmp_sidecar::{self, WriteTarget};
+
+/// Country names by ISO 3166 code
+const COUNTRIES: &str = include_str!("../resources/countries.tsv");
+
+/// GeoNames files used instead of the compact table when in the app data dir
+pub const GEONAMES_CITIES_FILE: &str = "cities15000.txt";
+pub const GEONAMES_ADMIN1_FILE: &str = "admin1CodesASCII.txt";
+
+/// Positions this close to a city are taken to be in it
+const MAX_CITY_DISTANCE_KM: f64 = 50.0;
+
+/// Positions further from every known city get no place at all
+const MAX_REGION_DISTANCE_KM: f64 = 200.0;
+
+/// A city of another country or state within this many times the distance
+/// to the nearest city, plus `BORDER_MARGIN_KM`, leaves the position undecided
+const BORDER_RATIO: f64 = 2.0;
+const BORDER_MARGIN_KM: f64 = 20.0;
+
+/// Root of the keywords added for places
+const PLACES_KEYWORD: &str = "Places";
+
+/// Location tags read before writing so an existing location is never overwritten
+const LOCATION_TAGS: &[&str] = &[
+    "-XMP-photoshop:City",
+    "-XMP-photoshop:State",
+    "-XMP-photoshop:Country",
+    "-XMP-iptcCore:Location",
+    "-IPTC:City",
+    "-IPTC:Sub-location",
+    "-IPTC:Province-State",
+    "-IPTC:Country-PrimaryLocationName",
+];
+
+/// A named place near a position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Place {
+    /// None when the position is too far from the nearest city to be in it
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: String,
+    pub country_code: String,
+    /// Kilometres to the nearest known city
+    pub distance_km: f64,
+}
+
+impl Place {
+    /// `Places|Pakistan|Lahore`, or `Places|Pakistan` without a city
+    pub fn keyword(&self) -> String {
+        let mut levels = vec![PLACES_KEYWORD, &self.country];
+        levels.extend(self.city.as_deref());
+        levels.join("|")
+    }
+
+    /// exiftool assignments for the XMP location tags, which sidecars hold too
+    pub fn xmp_assignments(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        args.extend(self.city.as_ref().map(|city| format!("-XMP-photoshop:City={}", city)));
+        args.extend(self.city.as_ref().map(|place| format!("-XMP-iptcCore:Location={}", place)));
+        args.extend(self.state.as_ref().map(|state| format!("-XMP-photoshop:State={}", state)));
+        args.push(format!("-XMP-photoshop:Country={}", self.country));
+        args.push(format!("-XMP-iptcCore:CountryCode={}", self.country_code));
+        args
+    }
+
+    /// exiftool assignments for every location tag embedded in a media file
+    pub fn embedded_assignments(&self) -> Vec<String> {
+        // IPTC strings are Latin-1 unless the record says otherwise
+        let mut args = vec!["-IPTC:CodedCharacterSet=UTF8".to_string()];
+        args.extend(self.city.as_ref().map(|city| format!("-IPTC:City={}", city)));
+        args.extend(self.city.as_ref().map(|place| format!("-IPTC:Sub-location={}", place)));
+        args.extend(self.state.as_ref().map(|state| format!("-IPTC:Province-State={}", state)));
+        args.push(format!("-IPTC:Country-PrimaryLocationName={}", self.country));
+        args.push(format!("-IPTC:Country-PrimaryLocationCode={}", self.country_code));
+        args.extend(self.xmp_assignments());
+        args
+    }
+
+    /// Short form for messages: `Lahore, Punjab, Pakistan`
+    pub fn display(&self) -> String {
+        let parts: Vec<&str> = self.city.iter().chain(self.state.iter()).map(String::as_str).collect();
+        parts.into_iter().chain([self.country.as_str()]).collect::<Vec<_>>().join(", ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct City {
+    name: String,
+    /// Name of the first-level division (state, province), if known
+    region: String,
+    /// Identifies the division: `PK.04` from GeoNames, else `PK.Punjab`
+    region_key: String,
+    country_code: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Cities loaded from GeoNames files by `load_geonames`
+static GEONAMES: OnceLock<Vec<City>> = OnceLock::new();
+
+fn compact_cities() -> &'static [City] {
+    static CITIES: OnceLock<Vec<City>> = OnceLock::new();
+    CITIES.get_or_init(|| {
+        // name, region, country, latitude, longitude, zone
+        timezone::data_lines(timezone::CITIES)
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split('\t').collect();
+                let region = columns.get(1)?.to_string();
+                let country_code = columns.get(2)?.to_string();
+                Some(City {
+                    name: columns.first()?.to_string(),
+                    region_key: format!("{}.{}", country_code, region),
+                    region,
+                    country_code,
+                    latitude: columns.get(3)?.parse().ok()?,
+                    longitude: columns.get(4)?.parse().ok()?,
+                })
+            })
+            .collect()
+    })
+}
+
+fn cities() -> &'static [City] {
+    GEONAMES.get().map(Vec::as_slice).unwrap_or_else(compact_cities)
+}
+
+/// Parse GeoNames' `cities15000.txt`, naming divisions from `admin1CodesASCII.txt`
+fn parse_geonames(cities: &str, admin1: &str) -> Vec<City> {
+    // code (`PK.04`), name, ascii name, geonameid
+    let regions: HashMap<&str, &str> = admin1
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            Some((columns.next()?, columns.next()?))
+        })
+        .collect();
+
+    // geonameid, name, asciiname, alternatenames, latitude, longitude, feature
+    // class, feature code, country code, cc2, admin1 code, ...
+    cities
+        .lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split('\t').collect();
+            let country_code = columns.get(8)?.to_string();
+            let region_key = format!("{}.{}", country_code, columns.get(10)?);
+            Some(City {
+                name: columns.get(1)?.to_string(),
+                region: regions.get(region_key.as_str()).map(|r| r.to_string()).unwrap_or_default(),
+                region_key,
+                country_code,
+                latitude: columns.get(4)?.parse().ok()?,
+                longitude: columns.get(5)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Use GeoNames' cities and divisions instead of the compact table, when both
+/// files exist. Returns whether they were loaded.
+pub fn load_geonames(cities_path: &std::path::Path, admin1_path: &std::path::Path) -> Result<bool, String> {
+    if !cities_path.exists() || !admin1_path.exists() {
+        return Ok(false);
+    }
+    let read = |path: &std::path::Path| {
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    };
+    let cities = parse_geonames(&read(cities_path)?, &read(admin1_path)?);
+    if cities.is_empty() {
+        return Err(format!("No cities in {}", cities_path.display()));
+    }
+    Ok(GEONAMES.set(cities).is_ok())
+}
+
+/// Whether a city that differs from `nearest` by `key` is nearly as close
+fn is_contested(distances: &[(f64, &City)], nearest: (f64, &City), key: fn(&City) -> &str) -> bool {
+    let own = key(nearest.1);
+    let radius = nearest.0 * BORDER_RATIO + BORDER_MARGIN_KM;
+    distances.iter().any(|(distance, city)| *distance <= radius && key(city) != own)
+}
+
+fn country_names() -> &'static HashMap<&'static str, &'static str> {
+    static NAMES: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+    NAMES.get_or_init(|| timezone::data_lines(COUNTRIES).filter_map(|line| line.split_once('\t')).collect())
+}
+
+/// The place at a position, from the city tables
+pub fn lookup(latitude: f64, longitude: f64) -> Option<Place> {
+    let distances: Vec<(f64, &City)> = cities()
+        .iter()
+        .map(|c| (timezone::haversine_km(latitude, longitude, c.latitude, c.longitude), c))
+        .collect();
+    let &(distance_km, city) = distances.iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
+    if distance_km > MAX_REGION_DISTANCE_KM {
+        return None;
+    }
+    // Never name the country of a city across a border
+    if is_contested(&distances, (distance_km, city), |c| c.country_code.as_str()) {
+        return None;
+    }
+    let state_contested = is_contested(&distances, (distance_km, city), |c| c.region_key.as_str());
+
+    let country = country_names().get(city.country_code.as_str())?;
+    Some(Place {
+        city: (distance_km <= MAX_CITY_DISTANCE_KM).then(|| city.name.clone()),
+        state: Some(city.region.clone()).filter(|r| !r.is_empty() && !state_contested),
+        country: country.to_string(),
+        country_code: city.country_code.clone(),
+        distance_km,
+    })
+}
+
+/// Look up the place at a position
+#[tauri::command]
+pub fn reverse_geocode(latitude: f64, longitude: f64) -> Option<Place> {
+    lookup(latitude, longitude)
+}
+
+/// GPS position of a file, or else of its sidecar
+pub(crate) fn read_position(state: &AppState, file_path: &str) -> Option<GpsCoordinates> {
+    metadata::read_gps(&state.exiftool, file_path).or_else(|| {
+        let sidecar = xmp_sidecar::find_sidecar(file_path)?;
+        metadata::read_gps(&state.exiftool, &sidecar.to_string_lossy())
+    })
+}
+
+/// The first location value already set in `file_path`, if any
+fn existing_location(state: &AppState, file_path: &str) -> Option<String> {
+    if !state.exiftool.is_installed() {
+        return None;
+    }
+    let mut args = vec!["-json"];
+    args.extend_from_slice(LOCATION_TAGS);
+    args.push(file_path);
+    let output = state.exiftool.execute(&args).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&output.stdout).ok()?;
+    json.get(0)?
+        .as_object()?
+        .iter()
+        .filter(|(key, _)| *key != "SourceFile")
+        .find_map(|(_, value)| value.as_str().filter(|v| !v.is_empty()).map(|v| v.to_string()))
+}
+
+/// An existing location in the file or its sidecar
+fn current_location(state: &AppState, file_path: &str) -> Option<String> {
+    existing_location(state, file_path).or_else(|| {
+        let sidecar = xmp_sidecar::find_sidecar(file_path)?;
+        existing_location(state, &sidecar.to_string_lossy())
+    })
+}
+
+/// Place found for one photo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeocodePreview {
+    pub file_path: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub place: Place,
+}
+
+/// A photo the preview leaves alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeocodeSkip {
+    pub file_path: String,
+    pub reason: String,
+}
+
+/// Preview of a reverse geocoding run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeocodePlan {
+    pub files: Vec<GeocodePreview>,
+    pub skipped: Vec<GeocodeSkip>,
+}
+
+/// Outcome of applying a reverse geocoding preview
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeocodeResult {
+    pub written: usize,
+    /// Files that gained a location since the preview
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct GeocodeProgress {
+    id: String,
+    count: usize,
+    total: usize,
+}
+
+/// Find the place of every photo under `directory` that has GPS but no location
+#[tauri::command]
+pub async fn preview_reverse_geocode(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    operation_id: String,
+) -> Result<GeocodePlan, String> {
+    use tauri::Emitter;
+
+    let cancel_token = state.register_token(&operation_id);
+    let mut plan = GeocodePlan::default();
+    let mut count = 0;
+    let walked = metadata::walk_media_files(&state.media_types(), &directory, &cancel_token, |path| {
+        let file_path = path.to_string_lossy().to_string();
+        let mut skip = |reason: &str| {
+            plan.skipped.push(GeocodeSkip {
+                file_path: file_path.clone(),
+                reason: reason.to_string(),
+            })
+        };
+
+        match read_position(&state, &file_path) {
+            None => skip("No GPS"),
+            Some(_) if current_location(&state, &file_path).is_some() => skip("Already has a location"),
+            Some(gps) => match lookup(gps.latitude, gps.longitude) {
+                None => skip("No known place nearby"),
+                Some(place) => plan.files.push(GeocodePreview {
+                    file_path: file_path.clone(),
+                    latitude: gps.latitude,
+                    longitude: gps.longitude,
+                    place,
+                }),
+            },
+        }
+
+        count += 1;
+        if count % 10 == 0 {
+            let _ = app_handle.emit("geocode-progress", GeocodeProgress {
+                id: operation_id.clone(),
+                count,
+                total: 0,
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    walked.map(|_| plan)
+}
+
+/// Decide what writing `place` to a file involves. Like dates, the file is
+/// written only without a location of its own, and the sidecar only when
+/// neither has one.
+fn prepare_location_write(state: &AppState, file_path: &str, place: &Place, target: WriteTarget) -> PreparedWrite {
+    let embedded = existing_location(state, file_path);
+    let write_file = target.in_place() && embedded.is_none();
+    let write_sidecar = target.sidecar() && current_location(state, file_path).is_none();
+    if !write_file && !write_sidecar {
+        return PreparedWrite::Skip {
+            plan: FilePlan::skipped(file_path, "XMP-photoshop:City", embedded, "Location already exists"),
+            message: "Location already exists, skipping".to_string(),
+        };
+    }
+
+    let mut writes = Vec::new();
+    if write_file {
+        let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+        args.extend(place.embedded_assignments());
+        args.push(file_path.to_string());
+        writes.push(args);
+    }
+    if write_sidecar {
+        let mut args = vec!["-overwrite_original".to_string()];
+        args.extend(place.xmp_assignments());
+        args.push(xmp_sidecar::sidecar_path(file_path));
+        writes.push(args);
+    }
+    PreparedWrite::Write {
+        writes,
+        message: format!("Location written: {}", place.display()),
+    }
+}
+
+/// Write previewed places to files that still have no location, with an
+/// optional `Places|Country|City` keyword
+#[tauri::command]
+pub async fn apply_reverse_geocode(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    files: Vec<GeocodePreview>,
+    add_keyword: Option<bool>,
+    operation_id: String,
+    write_target: Option<WriteTarget>,
+) -> Result<GeocodeResult, String> {
+    use tauri::Emitter;
+
+    let target = write_target.unwrap_or_else(|| state.write_target());
+    let add_keyword = add_keyword.unwrap_or(false);
+    let cancel_token = state.register_token(&operation_id);
+    let mut result = GeocodeResult::default();
+
+    for (index, preview) in files.iter().enumerate() {
+        if cancel_token.load(Ordering::Relaxed) {
+            state.remove_token(&operation_id);
+            return Err("Operation cancelled".to_string());
+        }
+
+        let written = match prepare_location_write(&state, &preview.file_path, &preview.place, target) {
+            PreparedWrite::Skip { .. } => {
+                result.skipped.push(preview.file_path.clone());
+                None
+            }
+            write => Some(write.run(&state, &preview.file_path, &operation_id, false).and_then(|_| {
+                if !add_keyword {
+                    return Ok(());
+                }
+                let keyword = vec![preview.place.keyword()];
+                metadata::prepare_keyword_write(&state, &preview.file_path, &keyword, target)?
+                    .run(&state, &preview.file_path, &operation_id, false)
+                    .map(|_| ())
+            })),
+        };
+        match written {
+            Some(Ok(())) => result.written += 1,
+            Some(Err(e)) => result.failed.push(format!("{}: {}", preview.file_path, e)),
+            None => {}
+        }
+
+        let _ = app_handle.emit("geocode-progress", GeocodeProgress {
+            id: operation_id.clone(),
+            count: index + 1,
+            total: files.len(),
+        });
+    }
+
+    state.remove_token(&operation_id);
+    Ok(result)
+}
+
+/// Fill in the place of every scanned file that has GPS, for `scan_missing_dates`
+pub(crate) fn add_places(
+    state: &AppState,
+    files: &mut [metadata::FileMetadataInfo],
+    cancel_token: &std::sync::atomic::AtomicBool,
+) -> Result<(), String> {
+    for file in files {
+        if cancel_token.load(Ordering::Relaxed) {
+            return Err("Operation cancelled".to_string());
+        }
+        file.place = read_position(state, &file.file_path).and_then(|gps| lookup(gps.latitude, gps.longitude));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_city() {
+        // Badshahi Mosque, Lahore
+        let place = lookup(31.588, 74.310).unwrap();
+        assert_eq!(place.city.as_deref(), Some("Lahore"));
+        assert_eq!(place.state.as_deref(), Some("Punjab"));
+        assert_eq!(place.country, "Pakistan");
+        assert_eq!(place.country_code, "PK");
+        assert!(place.distance_km < 10.0);
+
+        let place = lookup(48.8584, 2.2945).unwrap(); // Eiffel Tower
+        assert_eq!(place.display(), "Paris, Ile-de-France, France");
+    }
+
+    #[test]
+    fn test_lookup_far_from_cities() {
+        // Cholistan desert, 90 km from Bahawalpur: the region but no city
+        let place = lookup(29.0, 72.5).unwrap();
+        assert_eq!(place.city, None);
+        assert_eq!(place.display(), "Punjab, Pakistan");
+        // Middle of the South Pacific
+        assert!(lookup(-40.0, -130.0).is_none());
+    }
+
+    #[test]
+    fn test_lookup_never_crosses_a_border() {
+        // Wagah, on the border between Lahore and Amritsar
+        assert!(lookup(31.60, 74.57).is_none());
+        // Just outside Amritsar is still India
+        assert_eq!(lookup(31.64, 74.88).unwrap().country_code, "IN");
+    }
+
+    #[test]
+    fn test_parse_geonames() {
+        let cities = "1172451\tLahore\tLahore\tLahor\t31.558\t74.35071\tP\tPPLA\tPK\t\t04\t\t\t\t6310888\t\t217\tAsia/Karachi\t2019-12-06\n\
+                      1176734\tHyderabad\tHyderabad\t\t25.39242\t68.37366\tP\tPPL\tPK\t\t05\t\t\t\t1386330\t\t24\tAsia/Karachi\t2019-12-06\n";
+        let admin1 = "PK.04\tPunjab\tPunjab\t1167710\n";
+        let parsed = parse_geonames(cities, admin1);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((parsed[0].name.as_str(), parsed[0].region.as_str()), ("Lahore", "Punjab"));
+        assert_eq!(parsed[0].region_key, "PK.04");
+        // A division missing from the admin1 table has a key but no name
+        assert_eq!((parsed[1].region.as_str(), parsed[1].region_key.as_str()), ("", "PK.05"));
+    }
+
+    #[test]
+    fn test_place_keyword_and_assignments() {
+        let place = lookup(31.55, 74.34).unwrap();
+        assert_eq!(place.keyword(), "Places|Pakistan|Lahore");
+        assert_eq!(
+            place.embedded_assignments(),
+            vec![
+                "-IPTC:CodedCharacterSet=UTF8",
+                "-IPTC:City=Lahore",
+                "-IPTC:Sub-location=Lahore",
+                "-IPTC:Province-State=Punjab",
+                "-IPTC:Country-PrimaryLocationName=Pakistan",
+                "-IPTC:Country-PrimaryLocationCode=PK",
+                "-XMP-photoshop:City=Lahore",
+                "-XMP-iptcCore:Location=Lahore",
+                "-XMP-photoshop:State=Punjab",
+                "-XMP-photoshop:Country=Pakistan",
+                "-XMP-iptcCore:CountryCode=PK",
+            ]
+        );
+
+        let regional = Place { city: None, ..place };
+        assert_eq!(regional.keyword(), "Places|Pakistan");
+        assert!(!regional.xmp_assignments().iter().any(|a| a.contains("City") || a.contains("Location=")));
+        assert!(!regional.embedded_assignments().iter().any(|a| a.contains("Sub-location")));
+    }
+
+    #[test]
+    fn test_bundled_tables_cover_every_city() {
+        assert_eq!(cities().len(), timezone::data_lines(timezone::CITIES).count());
+        // Every city's country must have a name, or its photos get no place
+        let missing: Vec<&str> = cities()
+            .iter()
+            .map(|c| c.country_code.as_str())
+            .filter(|code| !country_names().contains_key(code))
+            .collect();
+        assert!(missing.is_empty(), "no country name for {:?}", missing);
+    }
+}
@@ -0,0 +1,695 @@
+//! Geotagging from GPS track logs.
+//!
+//! This module provides functions to:
+//! - Read track points from GPX, KML (`gx:Track` and timestamped placemarks)
+//!   and GeoJSON (per-coordinate times or timestamped points)
+//! - Locate a moment on the merged track, interpolating between points
+//! - Preview positions for every photo in a folder from its capture time
+//! - Write GPS tags to photos that have none
+//!
+//! Capture times are local wall-clock times. They are placed on the track's
+//! UTC timeline using, in order: the zone given in `GeotagOptions`, the
+//! photo's own offset, then the system zone. `camera_offset_seconds` corrects
+//! a camera clock that ran fast (positive) or slow (negative).
+//!
+//! A photo between two track points at most `max_gap_seconds` apart gets a
+//! linearly interpolated position. Otherwise it takes the nearest point, if
+//! that is within `max_gap_seconds`, and is left untagged if not.
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+
+use crate::isobmff::GpsCoordinates;
+use crate::journal;
+use crate::metadata::{self, PreparedWrite};
+use crate::state::AppState;
+use crate::timezone;
+use crate::write_plan::FilePlan;
+use crate::xmp_sidecar::{self, WriteTarget};
+
+const DEFAULT_MAX_GAP_SECONDS: i64 = 300;
+
+/// A timestamped position from a track log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    /// Milliseconds since the Unix epoch, UTC
+    pub time: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+/// How a photo's position was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMethod {
+    Interpolated,
+    Nearest,
+}
+
+/// A position on the track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackMatch {
+    pub coordinates: GpsCoordinates,
+    pub method: MatchMethod,
+    /// Seconds to the closest track point
+    pub gap_seconds: i64,
+}
+
+/// Points of one or more track logs, in time order
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    points: Vec<TrackPoint>,
+}
+
+impl Track {
+    pub fn new(mut points: Vec<TrackPoint>) -> Self {
+        points.sort_by_key(|p| p.time);
+        points.dedup_by_key(|p| p.time);
+        Self { points }
+    }
+
+    /// Read and merge track files
+    pub fn from_files(paths: &[String]) -> Result<Self, String> {
+        let mut points = Vec::new();
+        for path in paths {
+            points.extend(read_track_file(path)?);
+        }
+        Ok(Self::new(points))
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// First and last point times
+    pub fn span(&self) -> Option<(i64, i64)> {
+        Some((self.points.first()?.time, self.points.last()?.time))
+    }
+
+    /// The position at `time` (ms since epoch, UTC)
+    pub fn locate(&self, time: i64, max_gap_seconds: i64) -> Option<TrackMatch> {
+        let max_gap = max_gap_seconds * 1000;
+        let index = self.points.partition_point(|p| p.time <= time);
+        let before = index.checked_sub(1).and_then(|i| self.points.get(i));
+        let after = self.points.get(index);
+
+        let nearest = match (before, after) {
+            (Some(b), Some(a)) if time - b.time <= a.time - time => b,
+            (Some(b), None) => b,
+            (_, Some(a)) => a,
+            (None, None) => return None,
+        };
+        let gap_seconds = (time - nearest.time).abs() / 1000;
+
+        if let (Some(b), Some(a)) = (before, after) {
+            if a.time - b.time <= max_gap {
+                return Some(TrackMatch {
+                    coordinates: interpolate(b, a, time),
+                    method: MatchMethod::Interpolated,
+                    gap_seconds,
+                });
+            }
+        }
+        if (time - nearest.time).abs() <= max_gap {
+            return Some(TrackMatch {
+                coordinates: GpsCoordinates {
+                    latitude: nearest.latitude,
+                    longitude: nearest.longitude,
+                    altitude: nearest.altitude,
+                },
+                method: MatchMethod::Nearest,
+                gap_seconds,
+            });
+        }
+        None
+    }
+}
+
+fn interpolate(before: &TrackPoint, after: &TrackPoint, time: i64) -> GpsCoordinates {
+    let span = (after.time - before.time) as f64;
+    let fraction = if span > 0.0 { (time - before.time) as f64 / span } else { 0.0 };
+    let lerp = |a: f64, b: f64| a + (b - a) * fraction;
+    GpsCoordinates {
+        latitude: lerp(before.latitude, after.latitude),
+        longitude: lerp(before.longitude, after.longitude),
+        altitude: match (before.altitude, after.altitude) {
+            (Some(a), Some(b)) => Some(lerp(a, b)),
+            (a, b) => a.or(b),
+        },
+    }
+}
+
+/// Parse a track timestamp: RFC 3339, a zone-less ISO time taken as UTC, or epoch milliseconds
+fn parse_track_time(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.timestamp_millis());
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(time.and_utc().timestamp_millis());
+    }
+    value.parse::<i64>().ok()
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Track points (`trkpt`) of a GPX file
+pub fn parse_gpx(xml: &str) -> Vec<TrackPoint> {
+    static TRKPT: OnceLock<Regex> = OnceLock::new();
+    static LAT: OnceLock<Regex> = OnceLock::new();
+    static LON: OnceLock<Regex> = OnceLock::new();
+    static TIME: OnceLock<Regex> = OnceLock::new();
+    static ELE: OnceLock<Regex> = OnceLock::new();
+    let trkpt = regex(&TRKPT, r"(?s)<(?:\w+:)?trkpt\s([^>]*)>(.*?)</(?:\w+:)?trkpt>");
+    let lat = regex(&LAT, r#"\blat\s*=\s*["']([^"']+)["']"#);
+    let lon = regex(&LON, r#"\blon\s*=\s*["']([^"']+)["']"#);
+    let time = regex(&TIME, r"<(?:\w+:)?time>([^<]+)</");
+    let ele = regex(&ELE, r"<(?:\w+:)?ele>([^<]+)</");
+
+    trkpt
+        .captures_iter(xml)
+        .filter_map(|point| {
+            let (attributes, body) = (&point[1], &point[2]);
+            Some(TrackPoint {
+                time: parse_track_time(&time.captures(body)?[1])?,
+                latitude: lat.captures(attributes)?[1].parse().ok()?,
+                longitude: lon.captures(attributes)?[1].parse().ok()?,
+                altitude: ele.captures(body).and_then(|e| e[1].trim().parse().ok()),
+            })
+        })
+        .collect()
+}
+
+/// KML `lon,lat[,alt]` or `gx:coord` `lon lat [alt]` into a point
+fn kml_point(time: i64, coordinates: &str) -> Option<TrackPoint> {
+    let mut values = coordinates.split([',', ' ']).filter(|v| !v.is_empty()).map(|v| v.trim().parse::<f64>());
+    let longitude = values.next()?.ok()?;
+    let latitude = values.next()?.ok()?;
+    let altitude = values.next().and_then(|v| v.ok());
+    Some(TrackPoint { time, latitude, longitude, altitude })
+}
+
+/// Points of a KML file: `gx:Track` elements and placemarks with a `TimeStamp`
+pub fn parse_kml(xml: &str) -> Vec<TrackPoint> {
+    static TRACK: OnceLock<Regex> = OnceLock::new();
+    static WHEN: OnceLock<Regex> = OnceLock::new();
+    static COORD: OnceLock<Regex> = OnceLock::new();
+    static PLACEMARK: OnceLock<Regex> = OnceLock::new();
+    static STAMPED_POINT: OnceLock<Regex> = OnceLock::new();
+    let track = regex(&TRACK, r"(?s)<gx:Track>(.*?)</gx:Track>");
+    let when = regex(&WHEN, r"<when>([^<]+)</when>");
+    let coord = regex(&COORD, r"<gx:coord>([^<]+)</gx:coord>");
+    let placemark = regex(&PLACEMARK, r"(?s)<Placemark[^>]*>(.*?)</Placemark>");
+    let stamped_point = regex(
+        &STAMPED_POINT,
+        r"(?s)<TimeStamp[^>]*>.*?<when>([^<]+)</when>.*?</TimeStamp>.*?<Point[^>]*>.*?<coordinates>([^<]+)</coordinates>",
+    );
+
+    let mut points = Vec::new();
+    for body in track.captures_iter(xml) {
+        let times = when.captures_iter(&body[1]).map(|w| parse_track_time(&w[1]));
+        let coords = coord.captures_iter(&body[1]).map(|c| c[1].to_string());
+        points.extend(times.zip(coords).filter_map(|(time, c)| kml_point(time?, &c)));
+    }
+    for body in placemark.captures_iter(xml) {
+        if let Some(stamped) = stamped_point.captures(&body[1]) {
+            points.extend(parse_track_time(&stamped[1]).and_then(|time| kml_point(time, &stamped[2])));
+        }
+    }
+    points
+}
+
+/// GeoJSON position `[lon, lat, alt?]` into a point
+fn geojson_point(time: i64, position: &serde_json::Value) -> Option<TrackPoint> {
+    let position = position.as_array()?;
+    Some(TrackPoint {
+        time,
+        longitude: position.first()?.as_f64()?,
+        latitude: position.get(1)?.as_f64()?,
+        altitude: position.get(2).and_then(|a| a.as_f64()),
+    })
+}
+
+fn json_time(value: &serde_json::Value) -> Option<i64> {
+    match value {
+        serde_json::Value::String(s) => parse_track_time(s),
+        serde_json::Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+/// Points of a GeoJSON file. Lines need per-coordinate times, as written by
+/// togeojson (`coordTimes` or `coordinateProperties.times`); points need a
+/// `time` or `timestamp` property.
+pub fn parse_geojson(json: &str) -> Result<Vec<TrackPoint>, String> {
+    let root: serde_json::Value = serde_json::from_str(json).map_err(|e| format!("Invalid GeoJSON: {}", e))?;
+    let features = match root.get("features").and_then(|f| f.as_array()) {
+        Some(features) => features.clone(),
+        None => vec![root],
+    };
+
+    let mut points = Vec::new();
+    for feature in &features {
+        let (Some(geometry), properties) = (feature.get("geometry"), feature.get("properties")) else {
+            continue;
+        };
+        let property = |name: &str| properties.and_then(|p| p.get(name));
+        let kind = geometry.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+        let coordinates = geometry.get("coordinates");
+
+        match kind {
+            "Point" => {
+                let time = property("time").or_else(|| property("timestamp")).and_then(json_time);
+                points.extend(time.zip(coordinates).and_then(|(time, c)| geojson_point(time, c)));
+            }
+            "LineString" | "MultiLineString" => {
+                let times = property("coordTimes")
+                    .or_else(|| property("coordinateProperties").and_then(|c| c.get("times")))
+                    .and_then(|t| t.as_array())
+                    .cloned()
+                    .unwrap_or_default();
+                // A MultiLineString has one list of times per line
+                let (lines, times): (Vec<_>, Vec<_>) = if kind == "LineString" {
+                    (vec![coordinates.cloned().unwrap_or_default()], vec![serde_json::Value::Array(times)])
+                } else {
+                    let lines = coordinates.and_then(|c| c.as_array()).cloned().unwrap_or_default();
+                    (lines, times)
+                };
+                for (line, line_times) in lines.iter().zip(times.iter()) {
+                    let (Some(line), Some(line_times)) = (line.as_array(), line_times.as_array()) else {
+                        continue;
+                    };
+                    points.extend(
+                        line.iter()
+                            .zip(line_times)
+                            .filter_map(|(position, time)| geojson_point(json_time(time)?, position)),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(points)
+}
+
+/// Read a track file, choosing the format by extension
+pub fn read_track_file(path: &str) -> Result<Vec<TrackPoint>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let points = match extension.as_str() {
+        "gpx" => parse_gpx(&content),
+        "kml" => parse_kml(&content),
+        "geojson" | "json" => parse_geojson(&content)?,
+        _ => return Err(format!("Unsupported track format: {}", path)),
+    };
+    if points.is_empty() {
+        return Err(format!("No timestamped points in {}", path));
+    }
+    Ok(points)
+}
+
+/// How photos are matched to the track
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeotagOptions {
+    /// How far the camera clock was ahead of true time
+    #[serde(default)]
+    pub camera_offset_seconds: i64,
+    /// Largest gap between track points to interpolate across, and furthest
+    /// a photo may be from the nearest point otherwise
+    #[serde(default = "default_max_gap")]
+    pub max_gap_seconds: i64,
+    /// Zone the camera clock was set to (`+05:00`, `Asia/Karachi`)
+    pub time_zone: Option<String>,
+}
+
+fn default_max_gap() -> i64 {
+    DEFAULT_MAX_GAP_SECONDS
+}
+
+/// Position found for one photo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeotagPreview {
+    pub file_path: String,
+    pub date_time_original: String,
+    /// The capture time on the track's clock, RFC 3339 UTC
+    pub track_time: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+    pub method: MatchMethod,
+    pub gap_seconds: i64,
+}
+
+/// A photo the preview leaves alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeotagSkip {
+    pub file_path: String,
+    pub reason: String,
+}
+
+/// Preview of a geotagging run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeotagPlan {
+    pub track_points: usize,
+    pub track_start: Option<String>,
+    pub track_end: Option<String>,
+    pub files: Vec<GeotagPreview>,
+    pub skipped: Vec<GeotagSkip>,
+}
+
+/// Outcome of applying a geotagging preview
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeotagResult {
+    pub tagged: usize,
+    /// Files that gained GPS since the preview
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct GeotagProgress {
+    id: String,
+    count: usize,
+    total: usize,
+}
+
+fn format_millis(millis: i64) -> String {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Place a local capture time on the UTC timeline, in ms since the epoch.
+///
+/// `date_time_original` may carry its own offset (`2024:01:15 10:30:00+05:00`);
+/// `zone` overrides it, and without either the system zone is used.
+pub fn capture_time_utc(
+    date_time_original: &str,
+    file_offset: Option<&str>,
+    zone: Option<&timezone::ZoneSpec>,
+    camera_offset_seconds: i64,
+) -> Option<i64> {
+    let local = NaiveDateTime::parse_from_str(date_time_original.get(..19)?, "%Y:%m:%d %H:%M:%S").ok()?;
+    let inline_offset = date_time_original.get(19..).map(|s| s.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit()));
+    let file_zone = inline_offset
+        .filter(|o| !o.is_empty())
+        .or(file_offset)
+        .and_then(|o| timezone::parse_zone(o).ok());
+
+    let utc = match zone.or(file_zone.as_ref()) {
+        Some(zone) => local - chrono::Duration::seconds(zone.offset_at(&local)?.local_minus_utc() as i64),
+        None => Local.from_local_datetime(&local).earliest()?.naive_utc(),
+    };
+    Some((utc - chrono::Duration::seconds(camera_offset_seconds)).and_utc().timestamp_millis())
+}
+
+/// Whether the file, or its sidecar, already has a position
+fn has_gps(state: &AppState, file_path: &str) -> bool {
+    let sidecar = xmp_sidecar::find_sidecar(file_path).map(|p| p.to_string_lossy().to_string());
+    metadata::read_gps(&state.exiftool, file_path).is_some()
+        || sidecar.is_some_and(|s| metadata::read_gps(&state.exiftool, &s).is_some())
+}
+
+/// Match every photo under `directory` to the tracks
+#[tauri::command]
+pub async fn preview_geotag(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    track_files: Vec<String>,
+    directory: String,
+    options: GeotagOptions,
+    operation_id: String,
+) -> Result<GeotagPlan, String> {
+    use tauri::Emitter;
+
+    let track = Track::from_files(&track_files)?;
+    if track.is_empty() {
+        return Err("No track files given".to_string());
+    }
+    let zone = options.time_zone.as_deref().map(timezone::parse_zone).transpose()?;
+    let backend = metadata::select_backend(&state);
+    let cancel_token = state.register_token(&operation_id);
+
+    let (start, end) = track.span().unzip();
+    let mut plan = GeotagPlan {
+        track_points: track.len(),
+        track_start: start.map(format_millis),
+        track_end: end.map(format_millis),
+        ..Default::default()
+    };
+    let mut count = 0;
+    let walked = metadata::walk_media_files(&state.media_types(), &directory, &cancel_token, |path| {
+        let file_path = path.to_string_lossy().to_string();
+        let mut skip = |reason: &str| {
+            plan.skipped.push(GeotagSkip {
+                file_path: file_path.clone(),
+                reason: reason.to_string(),
+            })
+        };
+
+        let date = xmp_sidecar::read_merged(backend, &file_path).ok().and_then(|m| m.date_time_original);
+        let file_offset = state
+            .exiftool
+            .is_installed()
+            .then(|| journal::read_tag(&state.exiftool, &file_path, "OffsetTimeOriginal").ok().flatten())
+            .flatten()
+            .and_then(|v| v.as_str().map(|s| s.to_string()));
+
+        match date {
+            None => skip("No capture date"),
+            Some(_) if has_gps(&state, &file_path) => skip("Already has GPS"),
+            Some(date) => {
+                let time = capture_time_utc(&date, file_offset.as_deref(), zone.as_ref(), options.camera_offset_seconds);
+                match time.and_then(|t| track.locate(t, options.max_gap_seconds).map(|m| (t, m))) {
+                    None if time.is_none() => skip("Unreadable capture date"),
+                    None => skip("No track point close enough"),
+                    Some((time, found)) => plan.files.push(GeotagPreview {
+                        file_path: file_path.clone(),
+                        date_time_original: date,
+                        track_time: format_millis(time),
+                        latitude: found.coordinates.latitude,
+                        longitude: found.coordinates.longitude,
+                        altitude: found.coordinates.altitude,
+                        method: found.method,
+                        gap_seconds: found.gap_seconds,
+                    }),
+                }
+            }
+        }
+
+        count += 1;
+        if count % 10 == 0 {
+            let _ = app_handle.emit("geotag-progress", GeotagProgress {
+                id: operation_id.clone(),
+                count,
+                total: 0,
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    walked.map(|_| plan)
+}
+
+/// Decide what writing `gps` to a file involves. Like dates, the file is
+/// written only without embedded GPS, and the sidecar only when neither has any.
+fn prepare_gps_write(state: &AppState, file_path: &str, gps: &GpsCoordinates, target: WriteTarget) -> PreparedWrite {
+    let embedded = metadata::read_gps(&state.exiftool, file_path).is_some();
+    let write_file = target.in_place() && !embedded;
+    let write_sidecar = target.sidecar() && !has_gps(state, file_path);
+    if !write_file && !write_sidecar {
+        return PreparedWrite::Skip {
+            plan: FilePlan::skipped(file_path, "GPSLatitude", None, "Already has GPS"),
+            message: "GPS already present, skipping".to_string(),
+        };
+    }
+
+    let mut writes = Vec::new();
+    if write_file {
+        let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+        args.extend(gps.exif_assignments());
+        args.push(file_path.to_string());
+        writes.push(args);
+    }
+    if write_sidecar {
+        let mut args = vec!["-overwrite_original".to_string()];
+        args.extend(gps.xmp_assignments());
+        args.push(xmp_sidecar::sidecar_path(file_path));
+        writes.push(args);
+    }
+    PreparedWrite::Write {
+        writes,
+        message: format!("GPS written: {}, {}", gps.latitude, gps.longitude),
+    }
+}
+
+/// Write previewed positions to files that still have no GPS
+#[tauri::command]
+pub async fn apply_geotag(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    files: Vec<GeotagPreview>,
+    operation_id: String,
+    write_target: Option<WriteTarget>,
+) -> Result<GeotagResult, String> {
+    use tauri::Emitter;
+
+    let target = write_target.unwrap_or_else(|| state.write_target());
+    let cancel_token = state.register_token(&operation_id);
+    let mut result = GeotagResult::default();
+
+    for (index, preview) in files.iter().enumerate() {
+        if cancel_token.load(Ordering::Relaxed) {
+            state.remove_token(&operation_id);
+            return Err("Operation cancelled".to_string());
+        }
+
+        let gps = GpsCoordinates {
+            latitude: preview.latitude,
+            longitude: preview.longitude,
+            altitude: preview.altitude,
+        };
+        match prepare_gps_write(&state, &preview.file_path, &gps, target) {
+            PreparedWrite::Skip { .. } => result.skipped.push(preview.file_path.clone()),
+            write => match write.run(&state, &preview.file_path, &operation_id, false) {
+                Ok(_) => result.tagged += 1,
+                Err(e) => result.failed.push(format!("{}: {}", preview.file_path, e)),
+            },
+        }
+
+        let _ = app_handle.emit("geotag-progress", GeotagProgress {
+            id: operation_id.clone(),
+            count: index + 1,
+            total: files.len(),
+        });
+    }
+
+    state.remove_token(&operation_id);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(rfc3339: &str) -> i64 {
+        parse_track_time(rfc3339).unwrap()
+    }
+
+    fn point(time: &str, latitude: f64, longitude: f64, altitude: Option<f64>) -> TrackPoint {
+        TrackPoint { time: millis(time), latitude, longitude, altitude }
+    }
+
+    const GPX: &str = r#"<?xml version="1.0"?>
+<gpx version="1.1" creator="test"><trk><trkseg>
+  <trkpt lat="31.5000" lon="74.3000"><ele>210</ele><time>2024-03-01T10:00:00Z</time></trkpt>
+  <trkpt lon="74.3100" lat="31.5100"><ele>230</ele><time>2024-03-01T10:01:00Z</time></trkpt>
+  <trkpt lat="31.6000" lon="74.4000"><time>2024-03-01T11:00:00Z</time></trkpt>
+</trkseg></trk></gpx>"#;
+
+    #[test]
+    fn test_parse_gpx() {
+        let points = parse_gpx(GPX);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[1], point("2024-03-01T10:01:00Z", 31.51, 74.31, Some(230.0)));
+        assert_eq!(points[2].altitude, None);
+    }
+
+    #[test]
+    fn test_parse_kml() {
+        let kml = r#"<kml><Document>
+<Placemark><gx:Track>
+  <when>2024-03-01T10:00:00Z</when><when>2024-03-01T10:00:30Z</when>
+  <gx:coord>74.3 31.5 210</gx:coord><gx:coord>74.31 31.51 220</gx:coord>
+</gx:Track></Placemark>
+<Placemark><TimeStamp><when>2024-03-01T12:00:00+05:00</when></TimeStamp>
+  <Point><coordinates>74.35,31.55,0</coordinates></Point></Placemark>
+</Document></kml>"#;
+        let points = parse_kml(kml);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[1], point("2024-03-01T10:00:30Z", 31.51, 74.31, Some(220.0)));
+        assert_eq!(points[2].time, millis("2024-03-01T07:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_geojson() {
+        let json = r#"{"type": "FeatureCollection", "features": [
+  {"type": "Feature", "properties": {"coordTimes": ["2024-03-01T10:00:00Z", "2024-03-01T10:01:00Z"]},
+   "geometry": {"type": "LineString", "coordinates": [[74.3, 31.5, 210], [74.31, 31.51]]}},
+  {"type": "Feature", "properties": {"time": 1709290800000},
+   "geometry": {"type": "Point", "coordinates": [74.4, 31.6]}},
+  {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates": [0, 0]}}
+]}"#;
+        let points = parse_geojson(json).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], point("2024-03-01T10:00:00Z", 31.5, 74.3, Some(210.0)));
+        assert_eq!(points[2].time, 1_709_290_800_000);
+    }
+
+    #[test]
+    fn test_locate_interpolates_within_gap() {
+        let track = Track::new(parse_gpx(GPX));
+        let found = track.locate(millis("2024-03-01T10:00:30Z"), 300).unwrap();
+        assert_eq!(found.method, MatchMethod::Interpolated);
+        assert!((found.coordinates.latitude - 31.505).abs() < 1e-9);
+        assert!((found.coordinates.longitude - 74.305).abs() < 1e-9);
+        assert_eq!(found.coordinates.altitude, Some(220.0));
+        assert_eq!(found.gap_seconds, 30);
+    }
+
+    #[test]
+    fn test_locate_respects_max_gap() {
+        let track = Track::new(parse_gpx(GPX));
+        // 10:03 lies in the 59-minute gap but close to the 10:01 point
+        let found = track.locate(millis("2024-03-01T10:03:00Z"), 300).unwrap();
+        assert_eq!(found.method, MatchMethod::Nearest);
+        assert_eq!(found.coordinates.latitude, 31.51);
+        // Mid-gap and far from both ends
+        assert!(track.locate(millis("2024-03-01T10:30:00Z"), 300).is_none());
+        // Before the track starts
+        assert!(track.locate(millis("2024-03-01T09:00:00Z"), 300).is_none());
+        assert_eq!(track.locate(millis("2024-03-01T09:58:00Z"), 300).unwrap().method, MatchMethod::Nearest);
+    }
+
+    #[test]
+    fn test_capture_time_utc() {
+        let karachi = timezone::parse_zone("Asia/Karachi").unwrap();
+        let expected = millis("2024-03-01T10:00:30Z");
+        assert_eq!(capture_time_utc("2024:03:01 15:00:30", None, Some(&karachi), 0), Some(expected));
+        // The photo's own offset, inline or from OffsetTimeOriginal
+        assert_eq!(capture_time_utc("2024:03:01 15:00:30+05:00", None, None, 0), Some(expected));
+        assert_eq!(capture_time_utc("2024:03:01 15:00:30.25+05:00", None, None, 0), Some(expected));
+        assert_eq!(capture_time_utc("2024:03:01 15:00:30", Some("+05:00"), None, 0), Some(expected));
+        // A camera running two minutes fast
+        assert_eq!(capture_time_utc("2024:03:01 15:02:30", Some("+05:00"), None, 120), Some(expected));
+        assert_eq!(capture_time_utc("garbage", None, None, 0), None);
+    }
+
+    #[test]
+    fn test_read_track_file_rejects_unknown_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.txt");
+        std::fs::write(&path, GPX).unwrap();
+        assert!(read_track_file(&path.to_string_lossy()).is_err());
+        let path = dir.path().join("track.gpx");
+        std::fs::write(&path, GPX).unwrap();
+        assert_eq!(read_track_file(&path.to_string_lossy()).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,642 @@
+//! Detection of corrupt and truncated media files.
+//!
+//! This module provides functions to:
+//! - Flag zero-byte files
+//! - Walk JPEG markers from SOI through the scans to EOI
+//! - Verify PNG chunk CRCs and the closing `IEND` chunk
+//! - Check that ISO-BMFF boxes fit in the file and a video has its `moov` box
+//! - Optionally decode JPEG and PNG image data to find damage inside the scans
+//! - Use czkawka's `broken` mode instead, and move bad files to a quarantine folder
+//!
+//! The native checks are structural: a half-written file from a failing card
+//! or an interrupted recording fails, but damaged entropy data between intact
+//! markers passes. With `decode` JPEG and PNG files that pass are decoded in
+//! full too (zune-jpeg in strict mode, png), which is much slower. Videos and
+//! HEIC are never decoded. czkawka doesn't say how many files it looked at, so
+//! its results carry no `checked` count.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::dedup;
+use crate::media_types::{self, MediaCategory};
+use crate::metadata;
+use crate::state::AppState;
+
+/// A zero-filled tail this long is what a card leaves when a write never finished
+const ZERO_FILL_LEN: usize = 1024;
+
+/// Top-level ISO-BMFF boxes; one of these running past the end is a truncation,
+/// anything else after `moov` or `meta` is a vendor trailer
+const TOP_LEVEL_BOXES: &[&[u8; 4]] = &[
+    b"ftyp", b"styp", b"moov", b"mdat", b"meta", b"free", b"skip", b"wide", b"uuid", b"moof", b"mfra", b"sidx",
+    b"pdin",
+];
+
+/// What the check found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    Ok,
+    /// Zero bytes long
+    Empty,
+    /// Ends before the format's closing structure
+    Truncated,
+    /// Structure or checksums are broken
+    Corrupt,
+    /// The file could not be read
+    Unreadable,
+    /// No structural check exists for the format
+    Unchecked,
+}
+
+impl IntegrityStatus {
+    /// Whether the file should be kept out of the archive
+    pub fn is_bad(self) -> bool {
+        matches!(self, IntegrityStatus::Empty | IntegrityStatus::Truncated | IntegrityStatus::Corrupt)
+    }
+}
+
+/// Which checker to run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityBackend {
+    /// The structural checks in this module
+    #[default]
+    Native,
+    /// czkawka's `broken` mode, which decodes images and reads videos
+    Czkawka,
+}
+
+/// The classification of one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub file_path: String,
+    pub size: u64,
+    pub status: IntegrityStatus,
+    /// Detected format, e.g. `jpg`
+    pub format: Option<String>,
+    pub detail: Option<String>,
+    /// Where the file was moved, when it was quarantined
+    pub quarantined_to: Option<String>,
+}
+
+/// Result of an integrity scan; only files that are not `Ok` are listed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityResult {
+    pub files: Vec<IntegrityReport>,
+    /// Files looked at; None when czkawka did the checking
+    pub checked: Option<usize>,
+    pub bad: usize,
+    pub quarantined: usize,
+}
+
+type Check = (IntegrityStatus, Option<String>);
+
+fn ok() -> Check {
+    (IntegrityStatus::Ok, None)
+}
+
+fn truncated(detail: impl Into<String>) -> Check {
+    (IntegrityStatus::Truncated, Some(detail.into()))
+}
+
+fn corrupt(detail: impl Into<String>) -> Check {
+    (IntegrityStatus::Corrupt, Some(detail.into()))
+}
+
+fn be_u16(data: &[u8], pos: usize) -> Option<usize> {
+    data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Note when the data ends in a run of zeros
+fn zero_fill_note(data: &[u8]) -> &'static str {
+    let zeros = data.iter().rev().take_while(|&&b| b == 0).count();
+    if zeros >= ZERO_FILL_LEN {
+        " (zero-filled tail)"
+    } else {
+        ""
+    }
+}
+
+/// Walk the markers of a JPEG. Data after EOI (motion photo videos,
+/// Samsung trailers) is allowed.
+pub fn check_jpeg(data: &[u8]) -> Check {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return corrupt("Missing SOI marker");
+    }
+    let mut pos = 2;
+    let mut has_frame = false;
+    let mut has_scan = false;
+    loop {
+        // Markers may be preceded by any number of 0xFF fill bytes
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let Some(&marker) = data.get(pos + 1) else {
+            return truncated(format!("Ends inside the headers{}", zero_fill_note(data)));
+        };
+        if data[pos] != 0xFF {
+            return corrupt(format!("Invalid marker at byte {}", pos));
+        }
+        match marker {
+            0xD9 if has_scan => return ok(),
+            0xD9 => return corrupt("No image data before EOI"),
+            0xD0..=0xD7 | 0x01 => {
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(length) = be_u16(data, pos + 2) else {
+            return truncated("Ends inside a marker segment");
+        };
+        if length < 2 {
+            return corrupt(format!("Invalid segment length at byte {}", pos));
+        }
+        let end = pos + 2 + length;
+        if end > data.len() {
+            return truncated("Ends inside a marker segment");
+        }
+        match marker {
+            // SOF0..SOF15, except DHT, JPG and DAC which share the range
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                if be_u16(data, pos + 7) == Some(0) {
+                    return corrupt("Frame header has zero width");
+                }
+                has_frame = true;
+            }
+            0xDA => {
+                if !has_frame {
+                    return corrupt("Scan before frame header");
+                }
+                has_scan = true;
+                // Entropy-coded data runs to the next marker that isn't a
+                // stuffed byte or restart marker
+                let next = data[end..]
+                    .windows(2)
+                    .position(|w| w[0] == 0xFF && !matches!(w[1], 0x00 | 0xD0..=0xD7 | 0xFF));
+                match next {
+                    Some(offset) => pos = end + offset,
+                    None => return truncated(format!("Missing EOI marker{}", zero_fill_note(data))),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        pos = end;
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        table
+    });
+    !bytes.iter().fold(!0u32, |crc, &b| table[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Check every PNG chunk's length and CRC, up to `IEND`
+pub fn check_png(data: &[u8]) -> Check {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return corrupt("Missing PNG signature");
+    }
+    let mut pos = 8;
+    let mut has_data = false;
+    loop {
+        let (Some(length), Some(chunk_type)) = (be_u32(data, pos), data.get(pos + 4..pos + 8)) else {
+            return truncated(format!("Missing IEND chunk{}", zero_fill_note(data)));
+        };
+        let name = String::from_utf8_lossy(chunk_type).to_string();
+        if pos == 8 && chunk_type != b"IHDR" {
+            return corrupt("First chunk is not IHDR");
+        }
+        let end = pos + 12 + length as usize;
+        if end > data.len() {
+            return truncated(format!("Ends inside the {} chunk", name));
+        }
+        if be_u32(data, end - 4) != Some(crc32(&data[pos + 4..end - 4])) {
+            return corrupt(format!("CRC mismatch in {} chunk", name));
+        }
+        match chunk_type {
+            b"IDAT" => has_data = true,
+            b"IEND" if has_data => return ok(),
+            b"IEND" => return corrupt("No IDAT chunk"),
+            _ => {}
+        }
+        pos = end;
+    }
+}
+
+/// Check that the top-level boxes fit in the file. A video needs `moov`
+/// (missing when a recording was cut off) and an image needs `meta`. Data
+/// after `moov` or `meta` that isn't a box, like Samsung's SEF trailer, is
+/// allowed.
+pub fn check_isobmff<R: Read + Seek>(reader: &mut R, len: u64, category: MediaCategory) -> Check {
+    let mut pos = 0u64;
+    let mut types: Vec<[u8; 4]> = Vec::new();
+    while pos < len {
+        let trailer_allowed = types.iter().any(|t| t == b"moov" || t == b"meta");
+        if len - pos < 8 {
+            if trailer_allowed {
+                break;
+            }
+            return truncated("Ends inside a box header");
+        }
+        let mut header = [0u8; 16];
+        let want = if len - pos >= 16 { 16 } else { 8 };
+        if reader.seek(SeekFrom::Start(pos)).and_then(|_| reader.read_exact(&mut header[..want])).is_err() {
+            return truncated("Ends inside a box header");
+        }
+        let box_type = [header[4], header[5], header[6], header[7]];
+        let name = String::from_utf8_lossy(&box_type).to_string();
+        let known = TOP_LEVEL_BOXES.contains(&&box_type);
+        if !box_type.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            if trailer_allowed {
+                break;
+            }
+            return corrupt(format!("Invalid box type at byte {}", pos));
+        }
+
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => len - pos,
+            1 if want == 16 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            1 => return truncated("Ends inside a box header"),
+            n => n as u64,
+        };
+        if size < 8 {
+            if trailer_allowed && !known {
+                break;
+            }
+            return corrupt(format!("Invalid size for '{}' box", name));
+        }
+        // pos < len, so this can't overflow even for a corrupt 64-bit size
+        if size > len - pos {
+            if trailer_allowed && !known {
+                break;
+            }
+            return truncated(format!("'{}' box runs past the end of the file", name));
+        }
+        types.push(box_type);
+        pos += size;
+    }
+
+    let has = |t: &[u8; 4]| types.contains(t);
+    match category {
+        MediaCategory::Video if !has(b"moov") => truncated("No moov box"),
+        MediaCategory::Photo | MediaCategory::Raw if !has(b"meta") && !has(b"moov") => corrupt("No meta box"),
+        _ => ok(),
+    }
+}
+
+/// Decode a structurally sound JPEG or PNG in full
+pub fn decode_image(data: &[u8], format: &str) -> Check {
+    let result = match format {
+        "jpg" => {
+            let options = zune_core::options::DecoderOptions::default().set_strict_mode(true);
+            zune_jpeg::JpegDecoder::new_with_options(data, options).decode().map(|_| ()).map_err(|e| format!("{:?}", e))
+        }
+        "png" => {
+            // The default 64 MB limit would fail large panoramas that are fine
+            let limits = png::Limits { bytes: usize::MAX };
+            png::Decoder::new_with_limits(Cursor::new(data), limits)
+                .read_info()
+                .and_then(|mut reader| {
+                    let mut buf = vec![0; reader.output_buffer_size()];
+                    reader.next_frame(&mut buf).map(|_| ())
+                })
+                .map_err(|e| e.to_string())
+        }
+        _ => return ok(),
+    };
+    match result {
+        Ok(()) => ok(),
+        Err(e) => corrupt(format!("Image data doesn't decode: {}", e)),
+    }
+}
+
+/// Classify one file with the native checks, decoding JPEG and PNG with `decode`
+pub fn check_file(path: &Path, decode: bool) -> IntegrityReport {
+    let file_path = path.to_string_lossy().to_string();
+    let report = |size, (status, detail): Check, format: Option<&str>| IntegrityReport {
+        file_path: file_path.clone(),
+        size,
+        status,
+        format: format.map(str::to_string),
+        detail,
+        quarantined_to: None,
+    };
+
+    let size = match std::fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(e) => return report(0, (IntegrityStatus::Unreadable, Some(e.to_string())), None),
+    };
+    if size == 0 {
+        return report(0, (IntegrityStatus::Empty, None), None);
+    }
+    let Some(sniffed) = media_types::sniff(path) else {
+        return report(size, (IntegrityStatus::Unchecked, Some("Unknown format".to_string())), None);
+    };
+    let format = Some(sniffed.extension);
+
+    let check = match sniffed.extension {
+        "jpg" | "png" => match std::fs::read(path) {
+            Ok(data) => {
+                let check = if sniffed.extension == "jpg" { check_jpeg(&data) } else { check_png(&data) };
+                if decode && check.0 == IntegrityStatus::Ok {
+                    decode_image(&data, sniffed.extension)
+                } else {
+                    check
+                }
+            }
+            Err(e) => (IntegrityStatus::Unreadable, Some(e.to_string())),
+        },
+        "mp4" | "mov" | "m4v" | "3gp" | "3g2" | "heic" | "heif" | "avif" | "cr3" => match File::open(path) {
+            Ok(mut file) => check_isobmff(&mut file, size, sniffed.category),
+            Err(e) => (IntegrityStatus::Unreadable, Some(e.to_string())),
+        },
+        _ => (IntegrityStatus::Unchecked, None),
+    };
+    report(size, check, format)
+}
+
+/// A free path for `path` under `quarantine_dir`, keeping its place below `root`
+fn quarantine_path(root: &Path, quarantine_dir: &Path, path: &Path) -> PathBuf {
+    let relative = path.strip_prefix(root).ok().map(Path::to_path_buf).or_else(|| path.file_name().map(PathBuf::from)).unwrap_or_default();
+    let target = quarantine_dir.join(&relative);
+    let stem = target.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+    let extension = target.extension().and_then(|e| e.to_str()).map(|e| format!(".{}", e)).unwrap_or_default();
+
+    // Same `-N` suffixes phockup uses for collisions
+    let mut candidate = target.clone();
+    let mut suffix = 1;
+    while candidate.exists() {
+        suffix += 1;
+        candidate = target.with_file_name(format!("{}-{}{}", stem, suffix, extension));
+    }
+    candidate
+}
+
+/// Move a file into quarantine, copying when it sits on another volume
+fn quarantine(root: &Path, quarantine_dir: &Path, path: &Path) -> Result<PathBuf, String> {
+    let target = quarantine_path(root, quarantine_dir, path);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    if std::fs::rename(path, &target).is_err() {
+        std::fs::copy(path, &target).map_err(|e| format!("Failed to copy to quarantine: {}", e))?;
+        std::fs::remove_file(path).map_err(|e| format!("Failed to remove after copying to quarantine: {}", e))?;
+    }
+    Ok(target)
+}
+
+#[derive(Clone, Serialize)]
+struct IntegrityProgress {
+    id: String,
+    count: usize,
+}
+
+/// Check the media files under `directory` for corruption and truncation
+/// (async, cancellable). With `quarantine_dir` bad files are moved there,
+/// keeping their folder structure. `decode` makes the native backend decode
+/// JPEG and PNG image data too.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn check_integrity(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    operation_id: String,
+    backend: Option<IntegrityBackend>,
+    czkawka_path: Option<String>,
+    quarantine_dir: Option<String>,
+    decode: Option<bool>,
+) -> Result<IntegrityResult, String> {
+    use tauri::Emitter;
+
+    let mut files = Vec::new();
+    let checked = match backend.unwrap_or_default() {
+        IntegrityBackend::Native => {
+            let mut checked = 0;
+            let cancel_token = state.register_token(&operation_id);
+            let walked = metadata::walk_media_files(&state.media_types(), &directory, &cancel_token, |path| {
+                let report = check_file(path, decode.unwrap_or(false));
+                if report.status != IntegrityStatus::Ok {
+                    files.push(report);
+                }
+                checked += 1;
+                if checked % 10 == 0 {
+                    let _ = app_handle.emit("integrity-progress", IntegrityProgress {
+                        id: operation_id.clone(),
+                        count: checked,
+                    });
+                }
+                Ok(())
+            });
+            state.remove_token(&operation_id);
+            walked?;
+            Some(checked)
+        }
+        IntegrityBackend::Czkawka => {
+            let _ = app_handle.emit("integrity-progress", IntegrityProgress {
+                id: operation_id.clone(),
+                count: 0,
+            });
+            for broken in dedup::find_broken_files(&state, &directory, czkawka_path, &operation_id)? {
+                let size = std::fs::metadata(&broken.path).map(|m| m.len()).unwrap_or(0);
+                files.push(IntegrityReport {
+                    format: Path::new(&broken.path).extension().map(|e| e.to_string_lossy().to_lowercase()),
+                    file_path: broken.path,
+                    size,
+                    status: if size == 0 { IntegrityStatus::Empty } else { IntegrityStatus::Corrupt },
+                    detail: Some(broken.error),
+                    quarantined_to: None,
+                });
+            }
+            None
+        }
+    };
+
+    let mut quarantined = 0;
+    if let Some(quarantine_dir) = quarantine_dir {
+        let bad: Vec<String> = files.iter().filter(|f| f.status.is_bad()).map(|f| f.file_path.clone()).collect();
+        // Canonical paths can only be resolved while the files are still there
+        let _ = state.metadata_index.invalidate(&bad);
+        for report in files.iter_mut().filter(|f| f.status.is_bad()) {
+            match quarantine(Path::new(&directory), Path::new(&quarantine_dir), Path::new(&report.file_path)) {
+                Ok(target) => {
+                    report.quarantined_to = Some(target.to_string_lossy().to_string());
+                    quarantined += 1;
+                }
+                Err(e) => report.detail = Some(format!("{}; {}", report.detail.clone().unwrap_or_default(), e)),
+            }
+        }
+    }
+
+    Ok(IntegrityResult {
+        bad: files.iter().filter(|f| f.status.is_bad()).count(),
+        checked,
+        quarantined,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn jpeg() -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // SOF0: 8-bit, 16x16, one component
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x10, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00]);
+        // SOS, then entropy data with a stuffed byte and a restart marker
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        data.extend_from_slice(&[0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56]);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    fn png_chunk(chunk_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut chunk = (content.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(content);
+        let crc = crc32(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        chunk
+    }
+
+    fn iso_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    #[test]
+    fn test_check_jpeg() {
+        let data = jpeg();
+        assert_eq!(check_jpeg(&data).0, IntegrityStatus::Ok);
+
+        // A motion photo trailer after EOI is fine
+        let with_trailer = [data.as_slice(), b"\0\0\0\x18ftypmp42"].concat();
+        assert_eq!(check_jpeg(&with_trailer).0, IntegrityStatus::Ok);
+
+        let mut half = data[..data.len() - 2].to_vec();
+        half.extend(vec![0u8; ZERO_FILL_LEN]);
+        assert_eq!(check_jpeg(&half), truncated("Missing EOI marker (zero-filled tail)"));
+        assert_eq!(check_jpeg(&data[..10]).0, IntegrityStatus::Truncated);
+        assert_eq!(check_jpeg(b"not a jpeg").0, IntegrityStatus::Corrupt);
+    }
+
+    #[test]
+    fn test_check_png() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        let header = png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        let image = png_chunk(b"IDAT", &[0x78, 0x9C, 0x63, 0x00, 0x00]);
+        let png = [b"\x89PNG\r\n\x1a\n".as_slice(), &header, &image, &png_chunk(b"IEND", &[])].concat();
+        assert_eq!(check_png(&png).0, IntegrityStatus::Ok);
+
+        let mut damaged = png.clone();
+        damaged[8 + header.len() + 9] ^= 0xFF;
+        assert_eq!(check_png(&damaged), corrupt("CRC mismatch in IDAT chunk"));
+        assert_eq!(check_png(&png[..png.len() - 12]).0, IntegrityStatus::Truncated);
+    }
+
+    /// A decodable 8x8 grey baseline JPEG whose one block has `entropy` as scan data
+    fn decodable_jpeg(entropy: u8) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x43, 0x00];
+        data.extend([1u8; 64]);
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01, 0x01, 0x11, 0x00]);
+        // DC and AC tables with a single one-bit code for symbol 0
+        for class in [0x00, 0x10] {
+            data.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x14, class, 0x01]);
+            data.extend([0u8; 15]);
+            data.push(0x00);
+        }
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        data.extend_from_slice(&[entropy, 0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_decode_image() {
+        // DC difference 0 and end of block, padded with ones
+        let good = decodable_jpeg(0b0011_1111);
+        assert_eq!(check_jpeg(&good).0, IntegrityStatus::Ok);
+        assert_eq!(decode_image(&good, "jpg"), ok());
+        // No Huffman code starts with a one bit, though the markers are intact
+        let damaged = decodable_jpeg(0b1011_1111);
+        assert_eq!(check_jpeg(&damaged).0, IntegrityStatus::Ok);
+        assert_eq!(decode_image(&damaged, "jpg").0, IntegrityStatus::Corrupt);
+
+        // 1x1 grey PNG in a stored zlib block; filter type 9 doesn't exist
+        let png = |filter: u8| {
+            let adler = ((1 + filter as u32) * 2) << 16 | (1 + filter as u32);
+            let mut zlib = vec![0x78, 0x01, 0x01, 0x02, 0x00, 0xFD, 0xFF, filter, 0x00];
+            zlib.extend_from_slice(&adler.to_be_bytes());
+            let header = png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+            [b"\x89PNG\r\n\x1a\n".as_slice(), &header, &png_chunk(b"IDAT", &zlib), &png_chunk(b"IEND", &[])].concat()
+        };
+        assert_eq!(check_png(&png(9)).0, IntegrityStatus::Ok);
+        assert_eq!(decode_image(&png(0), "png"), ok());
+        assert_eq!(decode_image(&png(9), "png").0, IntegrityStatus::Corrupt);
+    }
+
+    #[test]
+    fn test_check_isobmff() {
+        let complete = [iso_box(b"ftyp", b"isom\0\0\0\0"), iso_box(b"mdat", &[1; 32]), iso_box(b"moov", &[2; 16])].concat();
+        let check = |data: &[u8], category| check_isobmff(&mut Cursor::new(data), data.len() as u64, category);
+        assert_eq!(check(&complete, MediaCategory::Video).0, IntegrityStatus::Ok);
+
+        // Recording cut off before the moov box was written
+        let no_moov = [iso_box(b"ftyp", b"isom\0\0\0\0"), iso_box(b"mdat", &[1; 32])].concat();
+        assert_eq!(check(&no_moov, MediaCategory::Video), truncated("No moov box"));
+        assert_eq!(check(&complete[..40], MediaCategory::Video).0, IntegrityStatus::Truncated);
+        assert_eq!(check(&no_moov, MediaCategory::Photo), corrupt("No meta box"));
+
+        // A 64-bit size that would wrap the position around
+        let mut huge = [iso_box(b"ftyp", b"isom\0\0\0\0"), vec![0, 0, 0, 1], b"mdat".to_vec()].concat();
+        huge.extend_from_slice(&(u64::MAX - 8).to_be_bytes());
+        huge.extend_from_slice(&[0; 16]);
+        assert_eq!(check(&huge, MediaCategory::Video).0, IntegrityStatus::Truncated);
+
+        // Samsung appends its SEF records after the last box
+        let trailer = [complete.clone(), b"SEFH\x6a\0\0\0samsung trailer".to_vec()].concat();
+        assert_eq!(check(&trailer, MediaCategory::Video).0, IntegrityStatus::Ok);
+        let cut_mdat = [iso_box(b"ftyp", b"isom\0\0\0\0"), iso_box(b"moov", &[2; 16]), iso_box(b"mdat", &[1; 32])[..20].to_vec()].concat();
+        assert_eq!(check(&cut_mdat, MediaCategory::Video).0, IntegrityStatus::Truncated);
+    }
+
+    #[test]
+    fn test_quarantine_keeps_structure() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("staging");
+        let quarantine_dir = dir.path().join("quarantine");
+        std::fs::create_dir_all(root.join("2023")).unwrap();
+        let file = root.join("2023").join("IMG_1.jpg");
+        std::fs::write(&file, b"").unwrap();
+        std::fs::create_dir_all(quarantine_dir.join("2023")).unwrap();
+        std::fs::write(quarantine_dir.join("2023").join("IMG_1.jpg"), b"old").unwrap();
+
+        assert_eq!(check_file(&file, false).status, IntegrityStatus::Empty);
+        let target = quarantine(&root, &quarantine_dir, &file).unwrap();
+        assert_eq!(target, quarantine_dir.join("2023").join("IMG_1-2.jpg"));
+        assert!(!file.exists() && target.exists());
+    }
+}
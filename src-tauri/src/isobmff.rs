@@ -0,0 +1,888 @@
+//! Native ISO base media file format (MP4/MOV/M4V/HEIC) metadata parser.
+//!
+//! This module provides functions to:
+//! - Walk the box tree without reading `mdat` payloads
+//! - Read `mvhd`/`tkhd` creation times (seconds since 1904-01-01 UTC)
+//! - Read the movie duration, video track size and video codec (`stsd`)
+//! - Read `©xyz` GPS and Apple `com.apple.quicktime.*` keys from `moov/meta`
+//! - Locate the Exif and XMP items of HEIC/HEIF files via `iinf`/`iloc`
+//!
+//! Dates are returned in exiftool's formatting so results can be mixed freely
+//! with the exiftool backend.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::metadata::ExifMetadata;
+use crate::native_exif::{self, MetadataBlocks};
+
+/// Seconds between 1904-01-01 (QuickTime epoch) and 1970-01-01
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Boxes that only contain other boxes
+const CONTAINER_BOXES: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"udta", b"edts", b"minf", b"stbl"];
+
+/// GPS position in decimal degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GpsCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+impl GpsCoordinates {
+    /// exiftool assignments for the EXIF GPS tags and their N/S, E/W and
+    /// above/below sea level references
+    pub fn exif_assignments(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("-GPSLatitude={}", self.latitude.abs()),
+            format!("-GPSLatitudeRef={}", if self.latitude < 0.0 { "S" } else { "N" }),
+            format!("-GPSLongitude={}", self.longitude.abs()),
+            format!("-GPSLongitudeRef={}", if self.longitude < 0.0 { "W" } else { "E" }),
+        ];
+        if let Some(altitude) = self.altitude {
+            args.push(format!("-GPSAltitude={}", altitude.abs()));
+            args.push(format!("-GPSAltitudeRef={}", if altitude < 0.0 { 1 } else { 0 }));
+        }
+        args
+    }
+
+    /// exiftool assignments for the XMP GPS tags, which carry the hemisphere in the value
+    pub fn xmp_assignments(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("-XMP-exif:GPSLatitude={}", self.latitude),
+            format!("-XMP-exif:GPSLongitude={}", self.longitude),
+        ];
+        if let Some(altitude) = self.altitude {
+            args.push(format!("-XMP-exif:GPSAltitude={}", altitude.abs()));
+            args.push(format!("-XMP-exif:GPSAltitudeRef={}", if altitude < 0.0 { 1 } else { 0 }));
+        }
+        args
+    }
+}
+
+/// Metadata found in an ISO-BMFF container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IsoBmffMetadata {
+    pub file_path: String,
+    /// `ftyp` major brand, e.g. `qt  `, `isom`, `heic`
+    pub major_brand: Option<String>,
+    /// Movie header creation time (UTC)
+    pub create_date: Option<String>,
+    /// Earliest track header creation time (UTC)
+    pub track_create_date: Option<String>,
+    /// Apple `com.apple.quicktime.creationdate`, local time with offset
+    pub creation_date: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub software: Option<String>,
+    /// Apple `com.apple.quicktime.content.identifier`, shared with a Live Photo's still
+    pub content_identifier: Option<String>,
+    pub gps: Option<GpsCoordinates>,
+    /// Movie duration in seconds
+    pub duration: Option<f64>,
+    /// Largest track header width and height
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Sample entry type of the first video track, e.g. `avc1`, `hvc1`
+    pub video_codec: Option<String>,
+    /// EXIF read from a HEIC/HEIF Exif item
+    pub exif: Option<ExifMetadata>,
+}
+
+/// MIME type of a file by its major brand. None for brands we don't know,
+/// rather than guessing MP4 for what may be a RAW or an image sequence.
+fn brand_mime_type(brand: &str) -> Option<&'static str> {
+    Some(match brand.trim() {
+        "qt" => "video/quicktime",
+        "heic" | "heix" | "heim" | "heis" => "image/heic",
+        "hevc" | "hevx" => "image/heic-sequence",
+        "mif1" => "image/heif",
+        "msf1" => "image/heif-sequence",
+        "avif" => "image/avif",
+        "avis" => "image/avif-sequence",
+        "crx" => "image/x-canon-cr3",
+        "M4V" | "M4VH" | "M4VP" => "video/x-m4v",
+        "M4A" => "audio/mp4",
+        "3gp4" | "3gp5" | "3gp6" | "3ge6" | "3gg6" => "video/3gpp",
+        "3g2a" => "video/3gpp2",
+        "isom" | "iso2" | "iso4" | "iso5" | "iso6" | "mp41" | "mp42" | "avc1" | "dash" | "MSNV" | "XAVC" => "video/mp4",
+        _ => return None,
+    })
+}
+
+impl IsoBmffMetadata {
+    /// Collapse into the common `ExifMetadata` shape.
+    ///
+    /// The capture date prefers embedded EXIF, then Apple's creation date key.
+    /// Container header times are UTC and only fill `create_date`.
+    pub fn into_exif_metadata(self) -> ExifMetadata {
+        let exif = self.exif.unwrap_or_default();
+        let mime_type = self.major_brand.as_deref().and_then(brand_mime_type);
+
+        ExifMetadata {
+            file_path: self.file_path,
+            date_time_original: exif.date_time_original.or(self.creation_date),
+            create_date: exif.create_date.or(self.create_date).or(self.track_create_date),
+            make: exif.make.or(self.make),
+            model: exif.model.or(self.model),
+            software: exif.software.or(self.software),
+            keywords: exif.keywords,
+            hierarchical_keywords: exif.hierarchical_keywords,
+            gps: self.gps.or(exif.gps),
+            image_width: exif.image_width.or(self.width),
+            image_height: exif.image_height.or(self.height),
+            orientation: exif.orientation,
+            duration: self.duration,
+            video_codec: self.video_codec,
+            lens_model: exif.lens_model,
+            iso: exif.iso,
+            exposure_time: exif.exposure_time,
+            f_number: exif.f_number,
+            mime_type: mime_type.map(str::to_string),
+            sub_sec_time_original: exif.sub_sec_time_original,
+            offset_time_original: exif.offset_time_original,
+            image_unique_id: exif.image_unique_id,
+            content_identifier: exif.content_identifier.or(self.content_identifier),
+            burst_uuid: exif.burst_uuid,
+            rating: exif.rating,
+            description: exif.description,
+            sources: exif.sources,
+        }
+    }
+}
+
+/// Whether the file starts with an `ftyp` box
+pub fn is_isobmff(path: &Path) -> bool {
+    let mut header = [0u8; 8];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .map(|_| &header[4..8] == b"ftyp")
+        .unwrap_or(false)
+}
+
+/// Parse the container metadata of an MP4/MOV/HEIC file
+pub fn read_isobmff(file_path: &str) -> Result<IsoBmffMetadata, String> {
+    let file = File::open(file_path).map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+    let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut parser = Parser {
+        reader: BufReader::new(file),
+        meta: IsoBmffMetadata { file_path: file_path.to_string(), ..Default::default() },
+        heif_items: HeifItems::default(),
+        in_video_track: false,
+    };
+
+    parser.walk(0, file_len, 0)?;
+    if parser.meta.major_brand.is_none() {
+        return Err(format!("Not an ISO base media file: {}", file_path));
+    }
+    parser.read_heif_items()?;
+    Ok(parser.meta)
+}
+
+/// Read an MP4/MOV/HEIC file into `ExifMetadata`
+pub fn read_metadata(file_path: &str) -> Result<ExifMetadata, String> {
+    read_isobmff(file_path).map(IsoBmffMetadata::into_exif_metadata)
+}
+
+/// Inspect the container metadata (dates, GPS, Apple keys) of a video or HEIC file
+#[tauri::command]
+pub fn read_container_metadata(file_path: String) -> Result<IsoBmffMetadata, String> {
+    read_isobmff(&file_path)
+}
+
+/// Header of one box
+struct BoxHeader {
+    box_type: [u8; 4],
+    /// Offset of the box content (after the header)
+    content_start: u64,
+    /// Offset just past the end of the box
+    end: u64,
+}
+
+/// HEIF item bookkeeping collected from `iinf` and `iloc`
+#[derive(Default)]
+struct HeifItems {
+    exif_id: Option<u32>,
+    xmp_id: Option<u32>,
+    // item_id -> extents of (file offset, length)
+    locations: Vec<(u32, Vec<(u64, u64)>)>,
+}
+
+struct Parser<R> {
+    reader: R,
+    meta: IsoBmffMetadata,
+    heif_items: HeifItems,
+    /// Whether the `trak` being walked has a `vide` media handler
+    in_video_track: bool,
+}
+
+impl<R: Read + Seek> Parser<R> {
+    fn read_header(&mut self, pos: u64, limit: u64) -> Result<Option<BoxHeader>, String> {
+        if limit.checked_sub(pos).is_none_or(|left| left < 8) {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        let mut header = [0u8; 8];
+        if self.reader.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        let size32 = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let box_type = [header[4], header[5], header[6], header[7]];
+
+        let (size, header_len) = match size32 {
+            0 => (limit - pos, 8),
+            1 => {
+                let mut large = [0u8; 8];
+                self.reader.read_exact(&mut large).map_err(|e| e.to_string())?;
+                (u64::from_be_bytes(large), 16)
+            }
+            n => (n, 8),
+        };
+        // Sizes come from the file, so compare against what's left rather than
+        // adding to `pos`, which a corrupt 64-bit size would overflow
+        if size < header_len || size > limit - pos {
+            return Err(format!("Malformed '{}' box", String::from_utf8_lossy(&box_type)));
+        }
+
+        Ok(Some(BoxHeader { box_type, content_start: pos + header_len, end: pos + size }))
+    }
+
+    fn read_content(&mut self, header: &BoxHeader) -> Result<Vec<u8>, String> {
+        let len = header.end - header.content_start;
+        // Metadata boxes are small; refuse anything that would mean slurping media
+        if len > 16 * 1024 * 1024 {
+            return Err(format!("'{}' box too large", String::from_utf8_lossy(&header.box_type)));
+        }
+        self.reader.seek(SeekFrom::Start(header.content_start)).map_err(|e| e.to_string())?;
+        let mut data = vec![0u8; len as usize];
+        self.reader.read_exact(&mut data).map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+
+    fn walk(&mut self, start: u64, end: u64, depth: usize) -> Result<(), String> {
+        if depth > 8 {
+            return Ok(());
+        }
+        let mut pos = start;
+        while let Some(header) = self.read_header(pos, end)? {
+            match &header.box_type {
+                b"ftyp" if depth == 0 => {
+                    let data = self.read_content(&header)?;
+                    self.meta.major_brand = data.get(0..4).map(|b| String::from_utf8_lossy(b).to_string());
+                }
+                b"mvhd" => {
+                    let data = self.read_content(&header)?;
+                    self.meta.create_date = header_creation_time(&data);
+                    self.meta.duration = movie_duration(&data);
+                }
+                b"tkhd" => {
+                    let data = self.read_content(&header)?;
+                    // Width and height are the last two 16.16 fixed-point fields
+                    if let (Some(width), Some(height)) = (
+                        data.len().checked_sub(8).and_then(|pos| be_u32(&data, pos)),
+                        data.len().checked_sub(4).and_then(|pos| be_u32(&data, pos)),
+                    ) {
+                        let (width, height) = (width >> 16, height >> 16);
+                        let area = |w: Option<u32>, h: Option<u32>| w.unwrap_or(0) as u64 * h.unwrap_or(0) as u64;
+                        if area(Some(width), Some(height)) > area(self.meta.width, self.meta.height) {
+                            self.meta.width = Some(width);
+                            self.meta.height = Some(height);
+                        }
+                    }
+                    if let Some(date) = header_creation_time(&data) {
+                        // Keep the earliest track, the date strings sort chronologically
+                        if self.meta.track_create_date.as_ref().is_none_or(|d| date < *d) {
+                            self.meta.track_create_date = Some(date);
+                        }
+                    }
+                }
+                b"trak" => {
+                    self.in_video_track = false;
+                    self.walk(header.content_start, header.end, depth + 1)?;
+                }
+                b"hdlr" => {
+                    let data = self.read_content(&header)?;
+                    // QuickTime also has a `dhlr` data handler inside `minf`
+                    if data.get(4..8) != Some(b"dhlr") {
+                        self.in_video_track = data.get(8..12) == Some(b"vide");
+                    }
+                }
+                b"stsd" if self.in_video_track && self.meta.video_codec.is_none() => {
+                    let data = self.read_content(&header)?;
+                    // Full box header and entry count, then the first entry's size and type
+                    self.meta.video_codec =
+                        data.get(12..16).map(|t| String::from_utf8_lossy(t).trim().to_string()).filter(|t| !t.is_empty());
+                }
+                b"\xA9xyz" => {
+                    let data = self.read_content(&header)?;
+                    if self.meta.gps.is_none() {
+                        self.meta.gps = udta_string(&data).and_then(|s| parse_iso6709(&s));
+                    }
+                }
+                b"meta" => self.read_meta(&header, depth)?,
+                t if CONTAINER_BOXES.contains(&t) => {
+                    self.walk(header.content_start, header.end, depth + 1)?;
+                }
+                _ => {}
+            }
+            pos = header.end;
+        }
+        Ok(())
+    }
+
+    /// `meta` is a full box in ISO files but a plain atom in QuickTime `moov/meta`
+    fn read_meta(&mut self, header: &BoxHeader, depth: usize) -> Result<(), String> {
+        let mut peek = [0u8; 8];
+        self.reader.seek(SeekFrom::Start(header.content_start)).map_err(|e| e.to_string())?;
+        let is_full_box = self.reader.read_exact(&mut peek).is_ok() && &peek[4..8] != b"hdlr";
+        let start = header.content_start + if is_full_box { 4 } else { 0 };
+
+        let mut keys: Vec<String> = Vec::new();
+        let mut ilst: Option<BoxHeader> = None;
+
+        let mut pos = start;
+        while let Some(child) = self.read_header(pos, header.end)? {
+            pos = child.end;
+            match &child.box_type {
+                b"keys" => keys = parse_keys(&self.read_content(&child)?),
+                b"iinf" => {
+                    let data = self.read_content(&child)?;
+                    self.parse_iinf(&data);
+                }
+                b"iloc" => self.heif_items.locations = parse_iloc(&self.read_content(&child)?),
+                b"ilst" => ilst = Some(child),
+                t if CONTAINER_BOXES.contains(&t) => {
+                    self.walk(child.content_start, child.end, depth + 1)?;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(ilst) = ilst {
+            self.read_ilst(&ilst, &keys)?;
+        }
+        Ok(())
+    }
+
+    /// Values in `ilst` are boxes whose type is the 1-based index into `keys`
+    fn read_ilst(&mut self, ilst: &BoxHeader, keys: &[String]) -> Result<(), String> {
+        let mut pos = ilst.content_start;
+        while let Some(item) = self.read_header(pos, ilst.end)? {
+            let index = u32::from_be_bytes(item.box_type) as usize;
+            if let Some(key) = index.checked_sub(1).and_then(|i| keys.get(i)) {
+                let value = self.read_content(&item).ok().and_then(|d| ilst_string(&d));
+                if let Some(value) = value {
+                    match key.as_str() {
+                        "com.apple.quicktime.creationdate" => {
+                            self.meta.creation_date = Some(quicktime_date_to_exif(&value))
+                        }
+                        "com.apple.quicktime.make" => self.meta.make = Some(value),
+                        "com.apple.quicktime.model" => self.meta.model = Some(value),
+                        "com.apple.quicktime.software" => self.meta.software = Some(value),
+                        "com.apple.quicktime.content.identifier" => self.meta.content_identifier = Some(value),
+                        "com.apple.quicktime.location.ISO6709" => {
+                            self.meta.gps = parse_iso6709(&value).or(self.meta.gps)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            pos = item.end;
+        }
+        Ok(())
+    }
+
+    fn parse_iinf(&mut self, data: &[u8]) {
+        let Some(&version) = data.first() else { return };
+        let mut pos = if version == 0 { 6 } else { 8 };
+        while pos + 8 <= data.len() {
+            let size = be_u32(data, pos).unwrap_or(0) as usize;
+            if size < 8 || size > data.len() - pos {
+                break;
+            }
+            if &data[pos + 4..pos + 8] == b"infe" {
+                self.parse_infe(&data[pos + 8..pos + size]);
+            }
+            pos += size;
+        }
+    }
+
+    fn parse_infe(&mut self, data: &[u8]) {
+        let Some(&version) = data.first() else { return };
+        if version < 2 {
+            return;
+        }
+        let (item_id, mut pos) = if version == 2 {
+            (be_u16(data, 4).map(u32::from), 6)
+        } else {
+            (be_u32(data, 4), 8)
+        };
+        let Some(item_id) = item_id else { return };
+        pos += 2; // item_protection_index
+        let Some(item_type) = data.get(pos..pos + 4) else { return };
+        pos += 4;
+
+        match item_type {
+            b"Exif" => self.heif_items.exif_id = Some(item_id),
+            b"mime" => {
+                // item_name, then content_type
+                let mut strings = data[pos..].split(|&b| b == 0);
+                let _name = strings.next();
+                if strings.next() == Some(b"application/rdf+xml".as_slice()) {
+                    self.heif_items.xmp_id = Some(item_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_item(&mut self, item_id: u32) -> Option<Vec<u8>> {
+        let extents = self
+            .heif_items
+            .locations
+            .iter()
+            .find(|(id, _)| *id == item_id)
+            .map(|(_, extents)| extents.clone())?;
+        let mut data = Vec::new();
+        for (offset, length) in extents {
+            if length > (16 * 1024 * 1024 - data.len()) as u64 {
+                return None;
+            }
+            self.reader.seek(SeekFrom::Start(offset)).ok()?;
+            let mut chunk = vec![0u8; length as usize];
+            self.reader.read_exact(&mut chunk).ok()?;
+            data.extend_from_slice(&chunk);
+        }
+        Some(data)
+    }
+
+    fn read_heif_items(&mut self) -> Result<(), String> {
+        let mut blocks = MetadataBlocks::default();
+
+        if let Some(data) = self.heif_items.exif_id.and_then(|id| self.read_item(id)) {
+            // Exif items start with the offset of the TIFF header ("Exif\0\0" prefix)
+            let skip = be_u32(&data, 0).unwrap_or(0) as usize;
+            blocks.tiff = skip.checked_add(4).and_then(|start| data.get(start..)).map(|d| d.to_vec());
+        }
+        if let Some(data) = self.heif_items.xmp_id.and_then(|id| self.read_item(id)) {
+            blocks.xmp = Some(String::from_utf8_lossy(&data).to_string());
+        }
+
+        if blocks.tiff.is_some() || blocks.xmp.is_some() {
+            self.meta.exif = Some(native_exif::metadata_from_blocks(self.meta.file_path.clone(), &blocks));
+        }
+        Ok(())
+    }
+}
+
+fn be_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Big-endian unsigned integer of 0, 4 or 8 bytes
+fn be_uint(data: &[u8], pos: usize, size: usize) -> Option<u64> {
+    match size {
+        0 => Some(0),
+        4 => be_u32(data, pos).map(u64::from),
+        8 => Some(u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// Creation time from an `mvhd` or `tkhd` full box
+fn header_creation_time(data: &[u8]) -> Option<String> {
+    let seconds = match data.first()? {
+        0 => i64::from(be_u32(data, 4)?),
+        1 => i64::try_from(be_uint(data, 4, 8)?).ok()?,
+        _ => return None,
+    };
+    // Zero means "not set" and would decode to 1904
+    if seconds == 0 {
+        return None;
+    }
+    let dt = chrono::DateTime::from_timestamp(seconds.checked_sub(QUICKTIME_EPOCH_OFFSET)?, 0)?;
+    Some(dt.format("%Y:%m:%d %H:%M:%S").to_string())
+}
+
+/// Duration in seconds from an `mvhd` full box
+fn movie_duration(data: &[u8]) -> Option<f64> {
+    let (timescale, duration) = match data.first()? {
+        0 => (be_u32(data, 12)?, be_u32(data, 16)? as u64),
+        1 => (be_u32(data, 20)?, be_uint(data, 24, 8)?),
+        _ => return None,
+    };
+    (timescale != 0).then(|| duration as f64 / timescale as f64)
+}
+
+/// Entries of a `keys` box (full box: version/flags, count, then size+namespace+name)
+fn parse_keys(data: &[u8]) -> Vec<String> {
+    let count = be_u32(data, 4).unwrap_or(0);
+    let mut keys = Vec::new();
+    let mut pos = 8;
+    for _ in 0..count {
+        let Some(size) = be_u32(data, pos).map(|s| s as usize) else { break };
+        if size < 8 || size > data.len() - pos {
+            break;
+        }
+        keys.push(String::from_utf8_lossy(&data[pos + 8..pos + size]).to_string());
+        pos += size;
+    }
+    keys
+}
+
+/// UTF-8 value of the `data` box inside an `ilst` item
+fn ilst_string(data: &[u8]) -> Option<String> {
+    let size = be_u32(data, 0)? as usize;
+    if data.get(4..8)? != b"data" || size < 16 || size > data.len() {
+        return None;
+    }
+    // type indicator 1 = UTF-8
+    if be_u32(data, 8)? & 0x00FF_FFFF != 1 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&data[16..size]).trim().to_string())
+}
+
+/// QuickTime user data string: u16 length, u16 language, text
+fn udta_string(data: &[u8]) -> Option<String> {
+    let len = be_u16(data, 0)? as usize;
+    let text = data.get(4..len.checked_add(4)?)?;
+    Some(String::from_utf8_lossy(text).to_string())
+}
+
+/// Parse ISO 6709 strings like `+37.7858-122.4064+012.345/`
+pub fn parse_iso6709(value: &str) -> Option<GpsCoordinates> {
+    let value = value.trim().trim_end_matches('/');
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for c in value.chars() {
+        if (c == '+' || c == '-') && !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    let latitude: f64 = parts.first()?.parse().ok()?;
+    let longitude: f64 = parts.get(1)?.parse().ok()?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+    let altitude = parts.get(2).and_then(|a| a.parse().ok());
+    Some(GpsCoordinates { latitude, longitude, altitude })
+}
+
+/// `2023-05-14T10:11:12+0200` -> `2023:05:14 10:11:12+02:00`
+fn quicktime_date_to_exif(value: &str) -> String {
+    let mut date = native_exif::xmp_date_to_exif(value.trim());
+    // Normalise a trailing +HHMM offset to +HH:MM like exiftool does
+    let len = date.len();
+    let offset = date.get(len.saturating_sub(5)..).filter(|tail| tail.len() == 5);
+    if offset.is_some_and(|tail| tail.starts_with(['+', '-']) && tail[1..].chars().all(|c| c.is_ascii_digit())) {
+        date.insert(len - 2, ':');
+    }
+    date
+}
+
+/// Item locations from an `iloc` full box, resolved to absolute file offsets
+fn parse_iloc(data: &[u8]) -> Vec<(u32, Vec<(u64, u64)>)> {
+    let mut items = Vec::new();
+    let Some(&version) = data.first() else { return items };
+    let (Some(&sizes), Some(&sizes2)) = (data.get(4), data.get(5)) else { return items };
+    let offset_size = (sizes >> 4) as usize;
+    let length_size = (sizes & 0x0F) as usize;
+    let base_offset_size = (sizes2 >> 4) as usize;
+    let index_size = if version == 1 || version == 2 { (sizes2 & 0x0F) as usize } else { 0 };
+
+    let (count, mut pos) = if version < 2 {
+        (be_u16(data, 6).map(u32::from).unwrap_or(0), 8)
+    } else {
+        (be_u32(data, 6).unwrap_or(0), 10)
+    };
+
+    for _ in 0..count {
+        let item_id = if version < 2 {
+            let Some(id) = be_u16(data, pos) else { break };
+            pos += 2;
+            id as u32
+        } else {
+            let Some(id) = be_u32(data, pos) else { break };
+            pos += 4;
+            id
+        };
+        let mut construction_method = 0;
+        if version == 1 || version == 2 {
+            construction_method = be_u16(data, pos).unwrap_or(0) & 0x0F;
+            pos += 2;
+        }
+        pos += 2; // data_reference_index
+        let Some(base_offset) = be_uint(data, pos, base_offset_size) else { break };
+        pos += base_offset_size;
+        let Some(extent_count) = be_u16(data, pos) else { break };
+        pos += 2;
+
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            pos += index_size;
+            let (Some(offset), Some(length)) =
+                (be_uint(data, pos, offset_size), be_uint(data, pos + offset_size, length_size))
+            else {
+                return items;
+            };
+            pos += offset_size + length_size;
+            let Some(offset) = base_offset.checked_add(offset) else { return items };
+            extents.push((offset, length));
+        }
+
+        // Only file-offset items (method 0) can be read directly
+        if construction_method == 0 {
+            items.push((item_id, extents));
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn make_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut out = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(box_type);
+        out.extend_from_slice(content);
+        out
+    }
+
+    fn full_box(box_type: &[u8; 4], version: u8, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![version, 0, 0, 0];
+        data.extend_from_slice(content);
+        make_box(box_type, &data)
+    }
+
+    fn mvhd(unix_seconds: i64) -> Vec<u8> {
+        let qt = (unix_seconds + QUICKTIME_EPOCH_OFFSET) as u32;
+        let mut content = qt.to_be_bytes().to_vec();
+        content.extend_from_slice(&qt.to_be_bytes());
+        content.extend_from_slice(&[0; 88]);
+        full_box(b"mvhd", 0, &content)
+    }
+
+    fn write_file(data: &[u8]) -> (tempfile::TempDir, String) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("media.bin");
+        File::create(&path).unwrap().write_all(data).unwrap();
+        let path = path.to_string_lossy().to_string();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_mp4_mvhd_creation_time() {
+        let mut file = make_box(b"ftyp", b"isom\0\0\0\0isom");
+        file.extend(make_box(b"mdat", &[0; 32]));
+        // 2023-05-14 10:11:12 UTC
+        file.extend(make_box(b"moov", &mvhd(1_684_059_072)));
+        let (_dir, path) = write_file(&file);
+
+        let meta = read_isobmff(&path).unwrap();
+        assert_eq!(meta.major_brand, Some("isom".to_string()));
+        assert_eq!(meta.create_date, Some("2023:05:14 10:11:12".to_string()));
+        assert!(meta.creation_date.is_none());
+    }
+
+    #[test]
+    fn test_mp4_duration_size_and_codec() {
+        // mvhd v0: times, then timescale 600 and duration 7500 (12.5 s)
+        let mut mvhd_content = [0u8; 96];
+        mvhd_content[8..12].copy_from_slice(&600u32.to_be_bytes());
+        mvhd_content[12..16].copy_from_slice(&7500u32.to_be_bytes());
+
+        let track = |handler: &[u8; 4], width: u32, height: u32, codec: &[u8; 4]| {
+            let mut tkhd_content = vec![0u8; 72];
+            tkhd_content.extend_from_slice(&(width << 16).to_be_bytes());
+            tkhd_content.extend_from_slice(&(height << 16).to_be_bytes());
+            let mut hdlr_content = b"\0\0\0\0".to_vec();
+            hdlr_content.extend_from_slice(handler);
+            hdlr_content.extend_from_slice(&[0; 12]);
+            let mut stsd_content = 1u32.to_be_bytes().to_vec();
+            stsd_content.extend_from_slice(&16u32.to_be_bytes());
+            stsd_content.extend_from_slice(codec);
+            stsd_content.extend_from_slice(&[0; 8]);
+
+            let stbl = make_box(b"stbl", &full_box(b"stsd", 0, &stsd_content));
+            let mut mdia = full_box(b"hdlr", 0, &hdlr_content);
+            mdia.extend(make_box(b"minf", &stbl));
+            let mut trak = full_box(b"tkhd", 0, &tkhd_content);
+            trak.extend(make_box(b"mdia", &mdia));
+            make_box(b"trak", &trak)
+        };
+
+        let mut moov = full_box(b"mvhd", 0, &mvhd_content);
+        moov.extend(track(b"soun", 0, 0, b"mp4a"));
+        moov.extend(track(b"vide", 1920, 1080, b"avc1"));
+        let mut file = make_box(b"ftyp", b"isom\0\0\0\0isom");
+        file.extend(make_box(b"moov", &moov));
+        let (_dir, path) = write_file(&file);
+
+        let meta = read_metadata(&path).unwrap();
+        assert_eq!(meta.duration, Some(12.5));
+        assert_eq!((meta.image_width, meta.image_height), (Some(1920), Some(1080)));
+        assert_eq!(meta.video_codec, Some("avc1".to_string()));
+        assert_eq!(meta.mime_type, Some("video/mp4".to_string()));
+    }
+
+    #[test]
+    fn test_mov_apple_keys_and_gps() {
+        let key = |name: &str| {
+            let mut k = ((name.len() + 8) as u32).to_be_bytes().to_vec();
+            k.extend_from_slice(b"mdta");
+            k.extend_from_slice(name.as_bytes());
+            k
+        };
+        let mut keys_content = 2u32.to_be_bytes().to_vec();
+        keys_content.extend(key("com.apple.quicktime.creationdate"));
+        keys_content.extend(key("com.apple.quicktime.model"));
+
+        let data_box = |value: &str| {
+            let mut content = 1u32.to_be_bytes().to_vec();
+            content.extend_from_slice(&0u32.to_be_bytes());
+            content.extend_from_slice(value.as_bytes());
+            make_box(b"data", &content)
+        };
+        let mut ilst = make_box(&1u32.to_be_bytes(), &data_box("2023-05-14T10:11:12+0200"));
+        ilst.extend(make_box(&2u32.to_be_bytes(), &data_box("iPhone 13")));
+
+        let mut meta = make_box(b"hdlr", &[0; 24]);
+        meta.extend(full_box(b"keys", 0, &keys_content));
+        meta.extend(make_box(b"ilst", &ilst));
+
+        let gps = "+37.7858-122.4064+012.345/";
+        let mut xyz = (gps.len() as u16).to_be_bytes().to_vec();
+        xyz.extend_from_slice(&0x15C7u16.to_be_bytes());
+        xyz.extend_from_slice(gps.as_bytes());
+
+        let mut moov = mvhd(1_684_051_872);
+        moov.extend(make_box(b"udta", &make_box(b"\xA9xyz", &xyz)));
+        moov.extend(make_box(b"meta", &meta));
+
+        let mut file = make_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+        file.extend(make_box(b"moov", &moov));
+        let (_dir, path) = write_file(&file);
+
+        let parsed = read_isobmff(&path).unwrap();
+        assert_eq!(parsed.creation_date, Some("2023:05:14 10:11:12+02:00".to_string()));
+        assert_eq!(parsed.model, Some("iPhone 13".to_string()));
+        let gps = parsed.gps.unwrap();
+        assert!((gps.latitude - 37.7858).abs() < 1e-9);
+        assert!((gps.longitude + 122.4064).abs() < 1e-9);
+        assert_eq!(gps.altitude, Some(12.345));
+
+        let exif = parsed.into_exif_metadata();
+        assert_eq!(exif.date_time_original, Some("2023:05:14 10:11:12+02:00".to_string()));
+        assert_eq!(exif.create_date, Some("2023:05:14 08:11:12".to_string()));
+    }
+
+    #[test]
+    fn test_heic_exif_item() {
+        let tiff = crate::native_exif::tests::sample_tiff("Apple", "iPhone 15", "2024:02:03 04:05:06");
+        let mut exif_item = 6u32.to_be_bytes().to_vec();
+        exif_item.extend_from_slice(b"Exif\0\0");
+        exif_item.extend_from_slice(&tiff);
+
+        let mut infe_content = 1u16.to_be_bytes().to_vec(); // item_ID
+        infe_content.extend_from_slice(&0u16.to_be_bytes()); // protection index
+        infe_content.extend_from_slice(b"Exif");
+        infe_content.push(0); // item_name
+        let infe = full_box(b"infe", 2, &infe_content);
+        let mut iinf_content = 1u16.to_be_bytes().to_vec();
+        iinf_content.extend(infe);
+        let iinf = full_box(b"iinf", 0, &iinf_content);
+
+        let build = |exif_offset: u32| {
+            // iloc v0, offset_size=4, length_size=4, base_offset_size=0
+            let mut iloc_content = vec![0x44, 0x00];
+            iloc_content.extend_from_slice(&1u16.to_be_bytes()); // item count
+            iloc_content.extend_from_slice(&1u16.to_be_bytes()); // item_ID
+            iloc_content.extend_from_slice(&0u16.to_be_bytes()); // data_reference_index
+            iloc_content.extend_from_slice(&1u16.to_be_bytes()); // extent count
+            iloc_content.extend_from_slice(&exif_offset.to_be_bytes());
+            iloc_content.extend_from_slice(&(exif_item.len() as u32).to_be_bytes());
+            let iloc = full_box(b"iloc", 0, &iloc_content);
+
+            let mut meta_content = make_box(b"hdlr", &[0; 24]);
+            meta_content.extend_from_slice(&iinf);
+            meta_content.extend(iloc);
+            let mut file = make_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+            file.extend(full_box(b"meta", 0, &meta_content));
+            file
+        };
+        let header_len = build(0).len() + 8;
+        let mut file = build(header_len as u32);
+        file.extend(make_box(b"mdat", &exif_item));
+        let (_dir, path) = write_file(&file);
+
+        let exif = read_metadata(&path).unwrap();
+        assert_eq!(exif.model, Some("iPhone 15".to_string()));
+        assert_eq!(exif.date_time_original, Some("2024:02:03 04:05:06".to_string()));
+    }
+
+    #[test]
+    fn test_brand_mime_type() {
+        assert_eq!(brand_mime_type("crx "), Some("image/x-canon-cr3"));
+        assert_eq!(brand_mime_type("hevc"), Some("image/heic-sequence"));
+        assert_eq!(brand_mime_type("msf1"), Some("image/heif-sequence"));
+        assert_eq!(brand_mime_type("qt  "), Some("video/quicktime"));
+        assert_eq!(brand_mime_type("mp42"), Some("video/mp4"));
+        assert_eq!(brand_mime_type("zzzz"), None);
+    }
+
+    #[test]
+    fn test_huge_box_size_is_malformed() {
+        let mut file = make_box(b"ftyp", b"isom\0\0\0\0isom");
+        // A 64-bit size that would wrap the position around
+        file.extend_from_slice(&1u32.to_be_bytes());
+        file.extend_from_slice(b"moov");
+        file.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        file.extend_from_slice(&[0; 16]);
+        let (_dir, path) = write_file(&file);
+        assert!(read_isobmff(&path).is_err());
+    }
+
+    #[test]
+    fn test_not_isobmff_is_error() {
+        let (_dir, path) = write_file(b"\xFF\xD8\xFF\xE0 not a movie");
+        assert!(read_isobmff(&path).is_err());
+        assert!(!is_isobmff(Path::new(&path)));
+    }
+
+    #[test]
+    fn test_parse_iso6709_without_altitude() {
+        let gps = parse_iso6709("-33.8688+151.2093/").unwrap();
+        assert_eq!(gps.latitude, -33.8688);
+        assert_eq!(gps.longitude, 151.2093);
+        assert!(gps.altitude.is_none());
+        assert!(parse_iso6709("garbage").is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_dates() {
+        // Version 1 with a 64-bit creation time past i64::MAX
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(header_creation_time(&mvhd), None);
+
+        assert_eq!(quicktime_date_to_exif("2023-05-14T10:11:12+0200"), "2023:05:14 10:11:12+02:00");
+        assert_eq!(quicktime_date_to_exif("2023-05-14 à 10h"), "2023:05:14 à 10h");
+        assert_eq!(quicktime_date_to_exif("été"), "été");
+    }
+}
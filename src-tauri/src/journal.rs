@@ -0,0 +1,623 @@
+//! Undo journal for metadata writes.
+//!
+//! This module provides functions to:
+//! - Run exiftool writes while recording each assigned tag's previous and new value
+//! - Append the records to a JSON Lines log in the app data dir
+//! - List journaled operations and revert whole operations or single files
+//!
+//! Values are read with `-n` and written back with `-TAG#=`, so reverts
+//! restore the exact stored value rather than a formatted one. A revert is
+//! refused when the file's size, modification time or any journaled tag
+//! differs from what the write left behind. Reverts are journaled too, under
+//! `revert-<operation id>`, so they can themselves be undone.
+//!
+//! Renames are journaled as a `FileName` entry holding the old and new path,
+//! and reverted by moving the file back.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use crate::exiftool::{ExiftoolOutput, ExiftoolPool};
+use crate::state::AppState;
+
+/// File name of the journal inside the app data dir
+pub const JOURNAL_FILE: &str = "metadata_journal.jsonl";
+
+/// Tag of entries that record a rename; their values are full paths
+pub const RENAME_TAG: &str = "FileName";
+
+/// One tag changed by one write
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub operation_id: String,
+    /// RFC 3339 time of the write
+    pub timestamp: String,
+    pub file_path: String,
+    pub tag: String,
+    pub previous_value: Option<Value>,
+    pub new_value: Option<Value>,
+    /// Size and modification time (ms since epoch) right after the write
+    pub file_size: u64,
+    pub file_modified: Option<i64>,
+}
+
+/// Summary of one journaled operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalOperation {
+    pub operation_id: String,
+    pub started: String,
+    pub finished: String,
+    pub file_count: usize,
+    pub entry_count: usize,
+    pub tags: Vec<String>,
+}
+
+/// A file a revert left alone, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevertRefusal {
+    pub file_path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RevertResult {
+    pub reverted: Vec<String>,
+    pub refused: Vec<RevertRefusal>,
+}
+
+/// Append-only log of metadata writes
+#[derive(Default)]
+pub struct Journal {
+    path: RwLock<Option<PathBuf>>,
+    // Serializes appends so concurrent writes never interleave lines
+    write_lock: Mutex<()>,
+}
+
+impl Journal {
+    /// Journal to `path`. Until this is called writes are not journaled.
+    pub fn set_path(&self, path: PathBuf) {
+        *self.path.write().unwrap() = Some(path);
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.path.read().unwrap().clone()
+    }
+
+    pub fn append(&self, entries: &[JournalEntry]) -> Result<(), String> {
+        let Some(path) = self.path() else {
+            return Ok(());
+        };
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in entries {
+            let line = serde_json::to_string(entry).map_err(|e| format!("Failed to encode journal entry: {}", e))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        let _guard = self.write_lock.lock().unwrap();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open journal {}: {}", path.display(), e))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|e| format!("Failed to write journal: {}", e))
+    }
+
+    /// All entries, oldest first. Lines that fail to parse are skipped.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, String> {
+        let Some(path) = self.path() else {
+            return Ok(Vec::new());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Ok(contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(format!("Failed to read journal: {}", e)),
+        }
+    }
+}
+
+/// Use the caller's operation id, or make one up for a one-off write
+pub fn operation_id_or(kind: &str, operation_id: Option<String>) -> String {
+    operation_id.unwrap_or_else(|| format!("{}-{}", kind, chrono::Utc::now().timestamp_millis()))
+}
+
+/// Tags assigned by exiftool arguments like `-TAG=`, `-TAG+=` or `-TAG#=`
+fn assigned_tags(args: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for arg in args {
+        let Some((lhs, _)) = arg.strip_prefix('-').and_then(|a| a.split_once('=')) else {
+            continue;
+        };
+        let tag = lhs.trim_end_matches(['+', '-', '#', '<']).to_string();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Current unformatted value of one tag, None if the file doesn't have it
+pub(crate) fn read_tag(pool: &ExiftoolPool, file_path: &str, tag: &str) -> Result<Option<Value>, String> {
+    let output = pool.execute(&["-json", "-n", &format!("-{}", tag), file_path])?;
+    first_value(&output.stdout)
+}
+
+/// The one tag value in `-json` output for a single file
+fn first_value(stdout: &str) -> Result<Option<Value>, String> {
+    if stdout.trim().is_empty() {
+        return Ok(None);
+    }
+    let json: Value = serde_json::from_str(stdout)
+        .map_err(|e| format!("Failed to parse exiftool output: {}", e))?;
+    let value = json
+        .get(0)
+        .and_then(|record| record.as_object())
+        .and_then(|record| record.iter().find(|(key, _)| *key != "SourceFile").map(|(_, v)| v.clone()));
+    Ok(value)
+}
+
+/// Printed by exiftool after each command of an `-execute` batch but the last
+const BATCH_SEPARATOR: &str = "{ready}";
+
+/// Current values of `tags`, in order, from one exiftool request. Each tag
+/// is its own command of an `-execute` batch, since a single `-json` read
+/// merges tags of one name from different groups (`CreateDate` and
+/// `QuickTime:CreateDate`).
+fn read_tags(pool: &ExiftoolPool, file_path: &str, tags: &[String]) -> Result<Vec<Option<Value>>, String> {
+    if tags.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args: Vec<String> = Vec::new();
+    for (i, tag) in tags.iter().enumerate() {
+        if i > 0 {
+            args.push("-execute".to_string());
+        }
+        args.extend(["-json".to_string(), "-n".to_string(), format!("-{}", tag), file_path.to_string()]);
+    }
+    let output = pool.execute(&args)?;
+    let values: Vec<Option<Value>> = split_batch(&output.stdout).into_iter().map(first_value).collect::<Result<_, _>>()?;
+    if values.len() != tags.len() {
+        return Err(format!("Expected {} exiftool results, got {}", tags.len(), values.len()));
+    }
+    Ok(values)
+}
+
+/// Output of each command of a batch, split at the separator lines
+fn split_batch(stdout: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let (mut section_start, mut position) = (0, 0);
+    for line in stdout.split_inclusive('\n') {
+        if line.trim_end() == BATCH_SEPARATOR {
+            sections.push(&stdout[section_start..position]);
+            section_start = position + line.len();
+        }
+        position += line.len();
+    }
+    sections.push(&stdout[section_start..]);
+    sections
+}
+
+/// Size and modification time (ms since epoch) of a file, `(0, None)` if it can't be read
+pub(crate) fn fingerprint(file_path: &str) -> (u64, Option<i64>) {
+    match std::fs::metadata(file_path) {
+        Ok(meta) => {
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64);
+            (meta.len(), modified)
+        }
+        Err(_) => (0, None),
+    }
+}
+
+/// Run an exiftool write on `file_path` and journal every tag it changed.
+///
+/// `args` is the full exiftool command line, including the file path.
+pub fn journaled_write(
+    state: &AppState,
+    operation_id: &str,
+    file_path: &str,
+    args: &[String],
+) -> Result<ExiftoolOutput, String> {
+    let pool = &state.exiftool;
+    let tags = assigned_tags(args);
+    let before = read_tags(pool, file_path, &tags)?;
+
+    let output = pool.execute(args)?;
+    // exiftool -P keeps the modification time, so don't leave it to the index to notice
+    state.metadata_index.forget(file_path);
+    if !output.success() {
+        return Ok(output);
+    }
+
+    let after = read_tags(pool, file_path, &tags)?;
+    let (file_size, file_modified) = fingerprint(file_path);
+    let timestamp = chrono::Utc::now().to_rfc3339();
+
+    let entries: Vec<JournalEntry> = tags
+        .into_iter()
+        .zip(before.into_iter().zip(after))
+        .filter(|(_, (before, after))| before != after)
+        .map(|(tag, (previous_value, new_value))| JournalEntry {
+            operation_id: operation_id.to_string(),
+            timestamp: timestamp.clone(),
+            file_path: file_path.to_string(),
+            tag,
+            previous_value,
+            new_value,
+            file_size,
+            file_modified,
+        })
+        .collect();
+    state.journal.append(&entries)?;
+
+    Ok(output)
+}
+
+/// Rename `from` to `to` unless something is already at `to`, which fails
+/// with `AlreadyExists`. The new name is taken with a hard link, which can't
+/// replace a file the way `rename` does; only file systems without hard
+/// links (FAT, exFAT) fall back to a checked rename.
+pub fn rename_no_clobber(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::io::ErrorKind;
+
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from).inspect_err(|_| {
+            let _ = std::fs::remove_file(to);
+        }),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists || to.symlink_metadata().is_ok() => {
+            Err(std::io::Error::new(ErrorKind::AlreadyExists, e))
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::CrossesDevices) => Err(e),
+        Err(_) => std::fs::rename(from, to),
+    }
+}
+
+/// Rename `from` to `to` and journal it. Returns false, renaming nothing,
+/// when a file already has the name `to`.
+pub fn journaled_rename(state: &AppState, operation_id: &str, from: &str, to: &str) -> Result<bool, String> {
+    match rename_no_clobber(Path::new(from), Path::new(to)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(format!("Failed to rename {}: {}", from, e)),
+    }
+    state.metadata_index.forget(from);
+
+    let (file_size, file_modified) = fingerprint(to);
+    state.journal.append(&[JournalEntry {
+        operation_id: operation_id.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        file_path: to.to_string(),
+        tag: RENAME_TAG.to_string(),
+        previous_value: Some(Value::String(from.to_string())),
+        new_value: Some(Value::String(to.to_string())),
+        file_size,
+        file_modified,
+    }])?;
+    Ok(true)
+}
+
+/// Group entries into operations, newest first
+fn summarize(entries: &[JournalEntry]) -> Vec<JournalOperation> {
+    let mut operations: Vec<JournalOperation> = Vec::new();
+    let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for entry in entries {
+        let op = match operations.iter_mut().find(|op| op.operation_id == entry.operation_id) {
+            Some(op) => op,
+            None => {
+                operations.push(JournalOperation {
+                    operation_id: entry.operation_id.clone(),
+                    started: entry.timestamp.clone(),
+                    finished: entry.timestamp.clone(),
+                    file_count: 0,
+                    entry_count: 0,
+                    tags: Vec::new(),
+                });
+                operations.last_mut().unwrap()
+            }
+        };
+        op.finished = entry.timestamp.clone();
+        op.entry_count += 1;
+        if !op.tags.contains(&entry.tag) {
+            op.tags.push(entry.tag.clone());
+        }
+
+        let op_files = files.entry(entry.operation_id.clone()).or_default();
+        if !op_files.contains(&entry.file_path) {
+            op_files.push(entry.file_path.clone());
+            op.file_count += 1;
+        }
+    }
+
+    operations.reverse();
+    operations
+}
+
+/// exiftool assignments restoring `value` exactly
+fn restore_args(tag: &str, value: &Option<Value>) -> Vec<String> {
+    let scalar = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    match value {
+        None | Some(Value::Null) => vec![format!("-{}=", tag)],
+        Some(Value::Array(items)) if items.is_empty() => vec![format!("-{}=", tag)],
+        // Several assignments to a list tag in one command make up the new list
+        Some(Value::Array(items)) => items.iter().map(|v| format!("-{}#={}", tag, scalar(v))).collect(),
+        Some(v) => vec![format!("-{}#={}", tag, scalar(v))],
+    }
+}
+
+/// Why `file_path` can't be reverted, if it can't
+fn check_unchanged(
+    pool: &ExiftoolPool,
+    file_path: &str,
+    entries: &[&JournalEntry],
+) -> Result<(), String> {
+    let last = entries.last().ok_or("Nothing to revert")?;
+    if !Path::new(file_path).exists() {
+        return Err("File no longer exists".to_string());
+    }
+    if fingerprint(file_path) != (last.file_size, last.file_modified) {
+        return Err("File was modified after the write".to_string());
+    }
+
+    // Each tag must still hold the value the operation last wrote
+    let mut latest: BTreeMap<&str, &Option<Value>> = BTreeMap::new();
+    for entry in entries.iter().filter(|e| e.tag != RENAME_TAG) {
+        latest.insert(&entry.tag, &entry.new_value);
+    }
+    for (tag, expected) in latest {
+        if read_tag(pool, file_path, tag)? != *expected {
+            return Err(format!("{} was changed after the write", tag));
+        }
+    }
+    Ok(())
+}
+
+fn revert_file(
+    state: &AppState,
+    operation_id: &str,
+    file_path: &str,
+    entries: &[&JournalEntry],
+) -> Result<(), String> {
+    check_unchanged(&state.exiftool, file_path, entries)?;
+    let revert_id = format!("revert-{}", operation_id);
+
+    // The value a tag had before the operation first touched it
+    let mut original: Vec<(&str, &Option<Value>)> = Vec::new();
+    for entry in entries.iter().filter(|e| e.tag != RENAME_TAG) {
+        if !original.iter().any(|(tag, _)| *tag == entry.tag) {
+            original.push((&entry.tag, &entry.previous_value));
+        }
+    }
+
+    if !original.is_empty() {
+        let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+        for (tag, value) in original {
+            args.extend(restore_args(tag, value));
+        }
+        args.push(file_path.to_string());
+
+        let output = journaled_write(state, &revert_id, file_path, &args)?;
+        if !output.success() {
+            return Err(format!("exiftool failed: {}", output.stderr));
+        }
+    }
+
+    // Tags are restored under the current name, then the file moves back
+    let renamed_from = entries
+        .iter()
+        .find(|e| e.tag == RENAME_TAG)
+        .and_then(|e| e.previous_value.as_ref())
+        .and_then(Value::as_str);
+    match renamed_from {
+        Some(from) => match journaled_rename(state, &revert_id, file_path, from)? {
+            true => Ok(()),
+            false => Err(format!("{} already exists", from)),
+        },
+        None => Ok(()),
+    }
+}
+
+/// List journaled operations, newest first
+#[tauri::command]
+pub fn list_journal_operations(state: tauri::State<'_, AppState>) -> Result<Vec<JournalOperation>, String> {
+    Ok(summarize(&state.journal.entries()?))
+}
+
+/// List the entries of one operation
+#[tauri::command]
+pub fn list_journal_entries(
+    state: tauri::State<'_, AppState>,
+    operation_id: String,
+) -> Result<Vec<JournalEntry>, String> {
+    Ok(state
+        .journal
+        .entries()?
+        .into_iter()
+        .filter(|e| e.operation_id == operation_id)
+        .collect())
+}
+
+/// Revert an operation, or only `file_paths` of it.
+/// Files changed since the write are refused and left untouched.
+#[tauri::command]
+pub fn revert_operation(
+    state: tauri::State<'_, AppState>,
+    operation_id: String,
+    file_paths: Option<Vec<String>>,
+) -> Result<RevertResult, String> {
+    let entries = state.journal.entries()?;
+
+    let mut by_file: BTreeMap<&str, Vec<&JournalEntry>> = BTreeMap::new();
+    for entry in entries.iter().filter(|e| e.operation_id == operation_id) {
+        by_file.entry(&entry.file_path).or_default().push(entry);
+    }
+    if by_file.is_empty() {
+        return Err(format!("No journal entries for operation {}", operation_id));
+    }
+
+    let mut result = RevertResult::default();
+    for (file_path, file_entries) in by_file {
+        if file_paths.as_ref().is_some_and(|paths| !paths.iter().any(|p| p == file_path)) {
+            continue;
+        }
+        match revert_file(&state, &operation_id, file_path, &file_entries) {
+            Ok(()) => result.reverted.push(file_path.to_string()),
+            Err(reason) => result.refused.push(RevertRefusal {
+                file_path: file_path.to_string(),
+                reason,
+            }),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn entry(op: &str, file: &str, tag: &str, previous: Option<Value>, new: Option<Value>) -> JournalEntry {
+        JournalEntry {
+            operation_id: op.to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            file_path: file.to_string(),
+            tag: tag.to_string(),
+            previous_value: previous,
+            new_value: new,
+            file_size: 10,
+            file_modified: Some(1),
+        }
+    }
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_assigned_tags() {
+        let args = strings(&[
+            "-overwrite_original",
+            "-api",
+            "QuickTimeUTC",
+            "-DateTimeOriginal=2024:01:01 10:00:00",
+            "-XMP-iptcExt:PersonInImage+=Alice",
+            "-XMP-iptcExt:PersonInImage+=Bob",
+            "-GPSLatitude#=33.1",
+            "/a.jpg",
+        ]);
+        assert_eq!(
+            assigned_tags(&args),
+            strings(&["DateTimeOriginal", "XMP-iptcExt:PersonInImage", "GPSLatitude"])
+        );
+    }
+
+    #[test]
+    fn test_split_batch() {
+        let stdout = concat!(
+            "[{\"SourceFile\": \"a.mp4\", \"CreateDate\": \"2024:01:01 10:00:00\"}]\n{ready}\n",
+            "[{\"SourceFile\": \"a.mp4\"}]\n{ready}\n",
+            "[{\"SourceFile\": \"a.mp4\", \"Rating\": 4}]\n",
+        );
+        let values: Vec<Option<Value>> = split_batch(stdout).into_iter().map(|s| first_value(s).unwrap()).collect();
+        assert_eq!(values, vec![Some(Value::from("2024:01:01 10:00:00")), None, Some(Value::from(4))]);
+        assert_eq!(split_batch(""), vec![""]);
+    }
+
+    #[test]
+    fn test_restore_args() {
+        assert_eq!(restore_args("Keywords", &None), strings(&["-Keywords="]));
+        assert_eq!(
+            restore_args("Keywords", &Some(json!(["a, b", "c"]))),
+            strings(&["-Keywords#=a, b", "-Keywords#=c"])
+        );
+        assert_eq!(restore_args("GPSLatitude", &Some(json!(33.5))), strings(&["-GPSLatitude#=33.5"]));
+    }
+
+    #[test]
+    fn test_journal_roundtrip_and_summary() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::default();
+        // Without a path nothing is recorded
+        journal.append(&[entry("op-0", "/x.jpg", "Keywords", None, None)]).unwrap();
+        assert!(journal.entries().unwrap().is_empty());
+
+        journal.set_path(dir.path().join(JOURNAL_FILE));
+        journal
+            .append(&[
+                entry("op-1", "/a.jpg", "DateTimeOriginal", None, Some(json!("2024:01:01 10:00:00"))),
+                entry("op-1", "/a.jpg", "CreateDate", None, Some(json!("2024:01:01 10:00:00"))),
+                entry("op-1", "/b.jpg", "DateTimeOriginal", None, Some(json!("2024:01:02 10:00:00"))),
+            ])
+            .unwrap();
+        journal.append(&[entry("op-2", "/a.jpg", "Keywords", None, Some(json!("Trip")))]).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let operations = summarize(&entries);
+        assert_eq!(operations[0].operation_id, "op-2");
+        assert_eq!(operations[1].file_count, 2);
+        assert_eq!(operations[1].entry_count, 3);
+        assert_eq!(operations[1].tags, strings(&["DateTimeOriginal", "CreateDate"]));
+    }
+
+    #[test]
+    fn test_revert_refuses_modified_file() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.jpg");
+        std::fs::write(&file, b"0123456789abc").unwrap();
+        let file = file.to_string_lossy().to_string();
+
+        // Journaled size is 10 bytes, the file now has 13
+        let journaled = entry("op-1", &file, "Keywords", None, Some(json!("Trip")));
+        let pool = ExiftoolPool::with_program("exiftool-does-not-exist", 1);
+        let err = check_unchanged(&pool, &file, &[&journaled]).unwrap_err();
+        assert_eq!(err, "File was modified after the write");
+
+        let missing = entry("op-1", "/does/not/exist.jpg", "Keywords", None, None);
+        assert_eq!(check_unchanged(&pool, "/does/not/exist.jpg", &[&missing]).unwrap_err(), "File no longer exists");
+    }
+
+    #[test]
+    fn test_rename_is_journaled_and_reverted() {
+        let dir = tempdir().unwrap();
+        let state = AppState::new();
+        state.journal.set_path(dir.path().join(JOURNAL_FILE));
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        std::fs::write(path("a.jpg"), b"png data").unwrap();
+        std::fs::write(path("taken.png"), b"x").unwrap();
+
+        assert!(journaled_rename(&state, "op-1", &path("a.jpg"), &path("a.png")).unwrap());
+        assert!(!journaled_rename(&state, "op-1", &path("a.png"), &path("taken.png")).unwrap());
+        assert_eq!(std::fs::read(path("taken.png")).unwrap(), b"x");
+        let entries = state.journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tag, RENAME_TAG);
+        assert_eq!(entries[0].file_path, path("a.png"));
+
+        let journaled: Vec<&JournalEntry> = entries.iter().collect();
+        revert_file(&state, "op-1", &path("a.png"), &journaled).unwrap();
+        assert!(Path::new(&path("a.jpg")).exists());
+        assert!(!Path::new(&path("a.png")).exists());
+        assert_eq!(state.journal.entries().unwrap()[1].operation_id, "revert-op-1");
+    }
+}
@@ -0,0 +1,315 @@
+//! Archive-wide keyword maintenance.
+//!
+//! This module provides functions to:
+//! - Find every file under a directory carrying a keyword, sidecars included
+//! - Preview a rename, merge or removal as before/after keyword lists; a merge
+//!   needs its target to be in use already
+//! - Apply a previewed edit to IPTC, XPKeywords and XMP, skipping files whose
+//!   keywords changed since the preview (read with the same backend, in any order)
+//!
+//! Edits rewrite every keyword tag from the edited list, so IPTC, Windows and
+//! XMP keywords agree afterwards. XMP sidecars are edited as files of their
+//! own and only get their XMP tags rewritten.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+
+use crate::journal;
+use crate::keywords::{KeywordSet, NON_XMP_KEYWORD_TAGS, XMP_KEYWORD_TAGS};
+use crate::metadata::{self, MetadataBackend};
+use crate::state::AppState;
+use crate::xmp_sidecar;
+
+/// What to do with a keyword
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeywordEdit {
+    Rename { from: String, to: String },
+    /// Fold `from` into an existing keyword
+    Merge { from: String, into: String },
+    Remove { keyword: String },
+}
+
+impl KeywordEdit {
+    /// The keyword being edited and its replacement, if any
+    fn parts(&self) -> (&str, Option<&str>) {
+        match self {
+            KeywordEdit::Rename { from, to } => (from, Some(to)),
+            KeywordEdit::Merge { from, into } => (from, Some(into)),
+            KeywordEdit::Remove { keyword } => (keyword, None),
+        }
+    }
+
+    /// The keyword a merge folds into, which must already exist
+    fn merge_target(&self) -> Option<&str> {
+        match self {
+            KeywordEdit::Merge { into, .. } => Some(into),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (from, to) = self.parts();
+        if from.trim().is_empty() {
+            return Err("No keyword given".to_string());
+        }
+        if to.is_some_and(|to| to.trim().is_empty()) {
+            return Err("The new keyword is empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Keywords of one file before and after the edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordEditPreview {
+    /// A media file, or an XMP sidecar
+    pub file_path: String,
+    pub keywords_before: Vec<String>,
+    pub keywords_after: Vec<String>,
+    pub hierarchical_before: Vec<String>,
+    pub hierarchical_after: Vec<String>,
+}
+
+/// Preview of an edit across a directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordEditPlan {
+    pub edit: KeywordEdit,
+    pub files: Vec<KeywordEditPreview>,
+}
+
+/// Outcome of applying an edit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeywordEditResult {
+    pub edited: usize,
+    /// Files whose keywords no longer match the preview
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct KeywordProgress {
+    id: String,
+    count: usize,
+    total: usize,
+}
+
+/// Build the preview entry for a file, or None if it lacks the keyword
+fn preview_file(file_path: String, keywords: &KeywordSet, edit: &KeywordEdit) -> Option<KeywordEditPreview> {
+    let (from, to) = edit.parts();
+    if !keywords.contains(from) {
+        return None;
+    }
+    let after = keywords.replace(from, to);
+    Some(KeywordEditPreview {
+        file_path,
+        keywords_before: keywords.flat.clone(),
+        keywords_after: after.flat,
+        hierarchical_before: keywords.hierarchical.clone(),
+        hierarchical_after: after.hierarchical,
+    })
+}
+
+/// Current keywords of a media file or sidecar
+fn read_keywords(backend: &dyn MetadataBackend, file_path: &str) -> Result<KeywordSet, String> {
+    let metadata = if xmp_sidecar::is_sidecar(file_path) {
+        xmp_sidecar::read_xmp_file(file_path)?
+    } else {
+        backend.read(file_path)?
+    };
+    Ok(KeywordSet::from_metadata(&metadata))
+}
+
+/// Whether two keyword lists hold the same keywords, in any order
+fn same_keywords(a: &[String], b: &[String]) -> bool {
+    a.iter().collect::<HashSet<_>>() == b.iter().collect::<HashSet<_>>()
+}
+
+/// List every file under `directory` carrying the keyword, with its keywords
+/// before and after the edit
+#[tauri::command]
+pub async fn preview_keyword_edit(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    edit: KeywordEdit,
+    operation_id: String,
+) -> Result<KeywordEditPlan, String> {
+    use tauri::Emitter;
+
+    edit.validate()?;
+    let backend = metadata::select_backend(&state);
+    let cancel_token = state.register_token(&operation_id);
+
+    let mut files = Vec::new();
+    // A Lightroom-style `<stem>.xmp` can be shared by a RAW+JPEG pair
+    let mut seen_sidecars = HashSet::new();
+    let mut target_found = false;
+    let mut count = 0;
+    let walked = metadata::walk_media_files(&state.media_types(), &directory, &cancel_token, |path| {
+        let file_path = path.to_string_lossy().to_string();
+        let sidecar = xmp_sidecar::find_sidecar(&file_path)
+            .map(|p| p.to_string_lossy().to_string())
+            .filter(|p| seen_sidecars.insert(p.clone()));
+        for target in std::iter::once(file_path).chain(sidecar) {
+            if let Ok(keywords) = read_keywords(backend, &target) {
+                target_found |= edit.merge_target().is_some_and(|into| keywords.contains(into));
+                files.extend(preview_file(target, &keywords, &edit));
+            }
+        }
+
+        count += 1;
+        if count % 10 == 0 {
+            let _ = app_handle.emit("keyword-progress", KeywordProgress {
+                id: operation_id.clone(),
+                count,
+                total: 0,
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    walked?;
+    if let Some(into) = edit.merge_target().filter(|_| !target_found) {
+        return Err(format!("No file has the keyword '{}' to merge into; rename instead", into));
+    }
+    Ok(KeywordEditPlan { edit, files })
+}
+
+/// exiftool arguments replacing every keyword tag of the file with the previewed lists
+fn edit_args(preview: &KeywordEditPreview) -> Vec<String> {
+    let mut after = KeywordSet::default();
+    preview.keywords_after.iter().for_each(|k| after.add(k));
+    preview.hierarchical_after.iter().for_each(|k| after.add(k));
+
+    let sidecar = xmp_sidecar::is_sidecar(&preview.file_path);
+    let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+    // Clear each list first, so tags whose list ends up empty are emptied too
+    let tags = XMP_KEYWORD_TAGS.iter().chain(if sidecar { &[][..] } else { NON_XMP_KEYWORD_TAGS });
+    args.extend(tags.map(|tag| format!("-{}=", tag)));
+    args.extend(if sidecar { after.xmp_assignments() } else { after.embedded_assignments() });
+    args.push(preview.file_path.clone());
+    args
+}
+
+/// Apply a previewed edit. Files whose keywords changed since the preview are skipped.
+#[tauri::command]
+pub async fn apply_keyword_edit(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    files: Vec<KeywordEditPreview>,
+    operation_id: String,
+) -> Result<KeywordEditResult, String> {
+    use tauri::Emitter;
+
+    // The backend the preview read with, or every file would look changed
+    let backend = metadata::select_backend(&state);
+    let cancel_token = state.register_token(&operation_id);
+    let mut result = KeywordEditResult::default();
+
+    for (index, preview) in files.iter().enumerate() {
+        if cancel_token.load(Ordering::Relaxed) {
+            state.remove_token(&operation_id);
+            return Err("Operation cancelled".to_string());
+        }
+
+        // The file must still hold the previewed keywords
+        let unchanged = read_keywords(backend, &preview.file_path).is_ok_and(|current| {
+            same_keywords(&current.flat, &preview.keywords_before)
+                && same_keywords(&current.hierarchical, &preview.hierarchical_before)
+        });
+        if !unchanged {
+            result.skipped.push(preview.file_path.clone());
+            continue;
+        }
+
+        match journal::journaled_write(&state, &operation_id, &preview.file_path, &edit_args(preview)) {
+            Ok(output) if output.success() => result.edited += 1,
+            Ok(output) => result.failed.push(format!("{}: {}", preview.file_path, output.stderr.trim())),
+            Err(e) => result.failed.push(format!("{}: {}", preview.file_path, e)),
+        }
+
+        let _ = app_handle.emit("keyword-progress", KeywordProgress {
+            id: operation_id.clone(),
+            count: index + 1,
+            total: files.len(),
+        });
+    }
+
+    state.remove_token(&operation_id);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(list: &[&str]) -> KeywordSet {
+        let mut set = KeywordSet::default();
+        list.iter().for_each(|k| set.add(k));
+        set
+    }
+
+    #[test]
+    fn test_preview_only_matching_files() {
+        let edit = KeywordEdit::Rename { from: "Source|Dad's Phone".into(), to: "Source|Abbu's Phone".into() };
+        assert!(preview_file("/a.jpg".into(), &keywords(&["Trip"]), &edit).is_none());
+
+        let preview = preview_file("/b.jpg".into(), &keywords(&["Source|Dad's Phone", "Trip"]), &edit).unwrap();
+        assert_eq!(preview.hierarchical_before, vec!["Source|Dad's Phone"]);
+        assert_eq!(preview.hierarchical_after, vec!["Source|Abbu's Phone"]);
+        assert_eq!(preview.keywords_after, vec!["Source", "Abbu's Phone", "Trip"]);
+    }
+
+    #[test]
+    fn test_edit_validation() {
+        assert!(KeywordEdit::Remove { keyword: " ".into() }.validate().is_err());
+        assert!(KeywordEdit::Merge { from: "Trip".into(), into: "".into() }.validate().is_err());
+        assert!(KeywordEdit::Merge { from: "Trip".into(), into: "Travel".into() }.validate().is_ok());
+        assert_eq!(KeywordEdit::Merge { from: "Trip".into(), into: "Travel".into() }.merge_target(), Some("Travel"));
+        assert_eq!(KeywordEdit::Rename { from: "Trip".into(), to: "Travel".into() }.merge_target(), None);
+    }
+
+    #[test]
+    fn test_same_keywords_ignores_order() {
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(same_keywords(&list(&["Trip", "Beach"]), &list(&["Beach", "Trip"])));
+        assert!(!same_keywords(&list(&["Trip", "Beach"]), &list(&["Trip"])));
+    }
+
+    #[test]
+    fn test_edit_args_clear_removed_keywords() {
+        let edit = KeywordEdit::Remove { keyword: "Trip".into() };
+        let preview = preview_file("/a.jpg".into(), &keywords(&["Trip"]), &edit).unwrap();
+        assert_eq!(
+            edit_args(&preview),
+            vec![
+                "-overwrite_original",
+                "-P",
+                "-XMP-dc:Subject=",
+                "-XMP-lr:HierarchicalSubject=",
+                "-XMP-digiKam:TagsList=",
+                "-IPTC:Keywords=",
+                "-XPKeywords=",
+                "/a.jpg",
+            ]
+        );
+
+        // Sidecars only hold XMP
+        let preview = preview_file("/a.CR2.xmp".into(), &keywords(&["Trip", "Beach"]), &edit).unwrap();
+        assert_eq!(
+            edit_args(&preview),
+            vec![
+                "-overwrite_original",
+                "-P",
+                "-XMP-dc:Subject=",
+                "-XMP-lr:HierarchicalSubject=",
+                "-XMP-digiKam:TagsList=",
+                "-XMP-dc:Subject=Beach",
+                "/a.CR2.xmp",
+            ]
+        );
+    }
+}
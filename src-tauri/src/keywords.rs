@@ -0,0 +1,284 @@
+//! Flat and hierarchical keywords.
+//!
+//! This module provides functions to:
+//! - Read keyword lists from exiftool JSON without splitting on commas
+//! - Keep hierarchical keywords (`People|Ammi`) and derive flat ones from them
+//! - Build exiftool arguments writing every keyword tag as a proper list
+//! - Rename, merge or remove a keyword within a file's keywords
+//!
+//! Hierarchies use Lightroom's `|` separator, as stored in
+//! `XMP-lr:HierarchicalSubject`. digiKam's `TagsList` stores the same paths
+//! with `/` and is converted on the way in and out. Every level of a
+//! hierarchy also becomes a flat keyword (`People|Ammi` gives `People` and
+//! `Ammi`), as Lightroom does on export, so apps that only read
+//! `Keywords`/`dc:subject` still find the photo.
+
+use serde_json::Value;
+
+use crate::metadata::ExifMetadata;
+
+/// Separator of hierarchical keyword levels
+pub const HIERARCHY_SEPARATOR: char = '|';
+
+/// Separator digiKam uses in `TagsList`
+const DIGIKAM_SEPARATOR: char = '/';
+
+/// Keyword tags an XMP packet or sidecar holds
+pub const XMP_KEYWORD_TAGS: &[&str] = &["XMP-dc:Subject", "XMP-lr:HierarchicalSubject", "XMP-digiKam:TagsList"];
+
+/// Keyword tags embedded in a media file besides the XMP ones
+pub const NON_XMP_KEYWORD_TAGS: &[&str] = &["IPTC:Keywords", "XPKeywords"];
+
+/// Items of a list tag in an exiftool `-json` record. Single items come back
+/// as scalars, and numeric keywords as numbers.
+pub fn json_list(value: Option<&Value>) -> Vec<String> {
+    let item = |v: &Value| match v {
+        Value::String(s) => s.trim().to_string(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    let items = match value {
+        Some(Value::Array(items)) => items.iter().map(item).collect(),
+        Some(v) => vec![item(v)],
+        None => Vec::new(),
+    };
+    items.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+/// `People/Ammi` -> `People|Ammi`
+pub fn from_digikam(tag: &str) -> String {
+    tag.split(DIGIKAM_SEPARATOR).map(str::trim).collect::<Vec<_>>().join("|")
+}
+
+/// `People|Ammi` -> `People/Ammi`
+pub fn to_digikam(keyword: &str) -> String {
+    keyword.split(HIERARCHY_SEPARATOR).collect::<Vec<_>>().join("/")
+}
+
+/// Every level of a hierarchical keyword, outermost first
+pub fn levels(keyword: &str) -> impl Iterator<Item = &str> {
+    keyword.split(HIERARCHY_SEPARATOR).map(str::trim).filter(|l| !l.is_empty())
+}
+
+/// A file's keywords: flat ones and `|`-separated hierarchies, without duplicates
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeywordSet {
+    pub flat: Vec<String>,
+    pub hierarchical: Vec<String>,
+}
+
+impl KeywordSet {
+    /// Keywords as read, deriving flat keywords for hierarchies that lack them
+    pub fn from_metadata(metadata: &ExifMetadata) -> Self {
+        let mut set = KeywordSet::default();
+        metadata.keywords.iter().for_each(|k| set.add(k));
+        metadata.hierarchical_keywords.iter().for_each(|k| set.add(k));
+        set
+    }
+
+    /// Add a keyword; one containing `|` is added as a hierarchy with its levels
+    pub fn add(&mut self, keyword: &str) {
+        let keyword = keyword.trim();
+        if keyword.contains(HIERARCHY_SEPARATOR) {
+            let path = levels(keyword).collect::<Vec<_>>();
+            if path.is_empty() {
+                return;
+            }
+            push_unique(&mut self.hierarchical, &path.join("|"));
+            for level in path {
+                push_unique(&mut self.flat, level);
+            }
+        } else if !keyword.is_empty() {
+            push_unique(&mut self.flat, keyword);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flat.is_empty() && self.hierarchical.is_empty()
+    }
+
+    /// Whether a flat keyword is one of the levels of a listed hierarchy
+    fn is_level(&self, keyword: &str) -> bool {
+        self.hierarchical.iter().any(|h| levels(h).any(|l| l == keyword))
+    }
+
+    /// Flat keywords that don't come from a hierarchy
+    fn standalone(&self) -> impl Iterator<Item = &String> {
+        self.flat.iter().filter(|k| !self.is_level(k))
+    }
+
+    /// All keywords for display, hierarchies in full
+    pub fn display(&self) -> Vec<String> {
+        let mut all = self.hierarchical.clone();
+        all.extend(self.standalone().cloned());
+        all
+    }
+
+    /// Whether `replace(keyword, ..)` would change anything
+    pub fn contains(&self, keyword: &str) -> bool {
+        let keyword = normalize(keyword);
+        self.hierarchical.iter().chain(self.standalone()).any(|k| rewrite(k, &keyword, None).is_some())
+    }
+
+    /// Replace `from` with `to`, or remove it when `to` is None.
+    ///
+    /// A hierarchy matches when it is `from` or lies below it, so renaming
+    /// `People` to `Family` turns `People|Ammi` into `Family|Ammi`, and
+    /// removing `People` removes the whole branch. Flat keywords derived from
+    /// hierarchies follow their hierarchy; other flat keywords match exactly.
+    pub fn replace(&self, from: &str, to: Option<&str>) -> KeywordSet {
+        let from = normalize(from);
+        let to = to.map(normalize);
+        let mut edited = KeywordSet::default();
+        for keyword in self.hierarchical.iter().chain(self.standalone()) {
+            match rewrite(keyword, &from, to.as_deref()) {
+                None => edited.add(keyword),
+                Some(Some(replacement)) => edited.add(&replacement),
+                Some(None) => {}
+            }
+        }
+        edited
+    }
+
+    /// Assignments for the XMP keyword tags. Each item is its own assignment
+    /// so exiftool builds a list instead of splitting a joined string.
+    pub fn xmp_assignments(&self) -> Vec<String> {
+        let mut args: Vec<String> = self.flat.iter().map(|k| format!("-XMP-dc:Subject={}", k)).collect();
+        args.extend(self.hierarchical.iter().map(|k| format!("-XMP-lr:HierarchicalSubject={}", k)));
+        args.extend(self.hierarchical.iter().map(|k| format!("-XMP-digiKam:TagsList={}", to_digikam(k))));
+        args
+    }
+
+    /// Assignments for every keyword tag embedded in a media file
+    pub fn embedded_assignments(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.flat.is_empty() {
+            // IPTC strings are Latin-1 unless the record says otherwise
+            args.push("-IPTC:CodedCharacterSet=UTF8".to_string());
+            args.extend(self.flat.iter().map(|k| format!("-IPTC:Keywords={}", k)));
+            // Windows keeps keywords as one semicolon-separated string
+            args.push(format!("-XPKeywords={}", self.flat.join(";")));
+        }
+        args.extend(self.xmp_assignments());
+        args
+    }
+}
+
+/// A keyword with the spacing around its levels removed
+fn normalize(keyword: &str) -> String {
+    levels(keyword).collect::<Vec<_>>().join("|")
+}
+
+/// `Some(replacement)` when `keyword` is `from` or lies below it, None when it doesn't match
+fn rewrite(keyword: &str, from: &str, to: Option<&str>) -> Option<Option<String>> {
+    if keyword == from {
+        return Some(to.map(str::to_string));
+    }
+    let below = keyword.strip_prefix(from)?.strip_prefix(HIERARCHY_SEPARATOR)?;
+    Some(to.map(|to| format!("{}|{}", to, below)))
+}
+
+fn push_unique(list: &mut Vec<String>, item: &str) {
+    if !list.iter().any(|existing| existing == item) {
+        list.push(item.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_list_keeps_commas() {
+        let record = json!({"Keywords": ["Lahore, Pakistan", "Trip"], "Subject": "Solo", "XPKeywords": 2024});
+        assert_eq!(json_list(record.get("Keywords")), vec!["Lahore, Pakistan", "Trip"]);
+        assert_eq!(json_list(record.get("Subject")), vec!["Solo"]);
+        assert_eq!(json_list(record.get("XPKeywords")), vec!["2024"]);
+        assert!(json_list(record.get("TagsList")).is_empty());
+    }
+
+    #[test]
+    fn test_add_derives_flat_levels() {
+        let mut set = KeywordSet::default();
+        set.add("Source|Dad's Phone");
+        set.add("People | Ammi");
+        set.add("Lahore, Pakistan");
+        set.add("Source|Dad's Phone");
+        assert_eq!(set.hierarchical, vec!["Source|Dad's Phone", "People|Ammi"]);
+        assert_eq!(set.flat, vec!["Source", "Dad's Phone", "People", "Ammi", "Lahore, Pakistan"]);
+        assert_eq!(set.display(), vec!["Source|Dad's Phone", "People|Ammi", "Lahore, Pakistan"]);
+    }
+
+    #[test]
+    fn test_digikam_conversion() {
+        assert_eq!(from_digikam("People/Ammi"), "People|Ammi");
+        assert_eq!(to_digikam("Source|Dad's Phone"), "Source/Dad's Phone");
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let metadata = ExifMetadata {
+            keywords: vec!["Trip".to_string()],
+            hierarchical_keywords: vec!["Events|Eid".to_string()],
+            ..Default::default()
+        };
+        let set = KeywordSet::from_metadata(&metadata);
+        assert_eq!(set.flat, vec!["Trip", "Events", "Eid"]);
+        assert_eq!(set.hierarchical, vec!["Events|Eid"]);
+    }
+
+    #[test]
+    fn test_replace_renames_branches() {
+        let mut set = KeywordSet::default();
+        set.add("People|Ammi");
+        set.add("People|Abbu");
+        set.add("Trip");
+        assert!(set.contains("People"));
+        assert!(!set.contains("people|ammi"));
+        // "Ammi" alone is only a level of a hierarchy
+        assert!(!set.contains("Ammi"));
+
+        let renamed = set.replace("People", Some("Family"));
+        assert_eq!(renamed.hierarchical, vec!["Family|Ammi", "Family|Abbu"]);
+        assert_eq!(renamed.flat, vec!["Family", "Ammi", "Abbu", "Trip"]);
+
+        let merged = set.replace("People|Abbu", Some("People|Ammi"));
+        assert_eq!(merged.hierarchical, vec!["People|Ammi"]);
+        assert_eq!(merged.flat, vec!["People", "Ammi", "Trip"]);
+    }
+
+    #[test]
+    fn test_replace_removes() {
+        let mut set = KeywordSet::default();
+        set.add("People|Ammi");
+        set.add("Trip");
+        let removed = set.replace("Trip", None);
+        assert_eq!(removed.flat, vec!["People", "Ammi"]);
+        let removed = set.replace("People", None);
+        assert!(removed.hierarchical.is_empty());
+        assert_eq!(removed.flat, vec!["Trip"]);
+    }
+
+    #[test]
+    fn test_embedded_assignments() {
+        let mut set = KeywordSet::default();
+        set.add("People|Ammi");
+        set.add("Lahore, Pakistan");
+        assert_eq!(
+            set.embedded_assignments(),
+            vec![
+                "-IPTC:CodedCharacterSet=UTF8",
+                "-IPTC:Keywords=People",
+                "-IPTC:Keywords=Ammi",
+                "-IPTC:Keywords=Lahore, Pakistan",
+                "-XPKeywords=People;Ammi;Lahore, Pakistan",
+                "-XMP-dc:Subject=People",
+                "-XMP-dc:Subject=Ammi",
+                "-XMP-dc:Subject=Lahore, Pakistan",
+                "-XMP-lr:HierarchicalSubject=People|Ammi",
+                "-XMP-digiKam:TagsList=People/Ammi",
+            ]
+        );
+    }
+}
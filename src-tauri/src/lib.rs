@@ -3,7 +3,29 @@ use std::io::Write;
 use tauri::Manager;
 
 mod metadata;
+mod media_types;
 mod dedup;
+mod bursts;
+mod motion_photo;
+mod extension_check;
+mod integrity;
+mod exiftool;
+mod native_exif;
+mod isobmff;
+mod date_patterns;
+mod takeout;
+mod timezone;
+mod time_shift;
+mod journal;
+mod metadata_index;
+mod companions;
+mod write_plan;
+mod xmp_sidecar;
+mod keywords;
+mod keyword_edit;
+mod geotag;
+mod geocode;
+mod date_check;
 mod state; // Add state module
 
 use state::AppState; // Import AppState
@@ -19,15 +41,24 @@ fn cancel_operation(state: tauri::State<AppState>, operation_id: String) {
     state.cancel(&operation_id);
 }
 
-#[tauri::command]
-fn load_settings(app_handle: tauri::AppHandle) -> Result<String, String> {
+/// Path of settings.json in the app data dir, creating the dir if needed
+fn settings_path(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    data_file(app_handle, "settings.json")
+}
+
+fn data_file(app_handle: &tauri::AppHandle, name: &str) -> Result<std::path::PathBuf, String> {
     let data_dir = app_handle.path().app_data_dir()
         .map_err(|e| format!("Failed to get app data dir: {}", e))?;
     
     // Create the directory if it doesn't exist
     fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create data dir: {}", e))?;
     
-    let settings_path = data_dir.join("settings.json");
+    Ok(data_dir.join(name))
+}
+
+#[tauri::command]
+fn load_settings(app_handle: tauri::AppHandle) -> Result<String, String> {
+    let settings_path = settings_path(&app_handle)?;
     
     match fs::read_to_string(&settings_path) {
         Ok(content) => Ok(content),
@@ -36,18 +67,28 @@ fn load_settings(app_handle: tauri::AppHandle) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn save_settings(app_handle: tauri::AppHandle, settings: String) -> Result<(), String> {
-    let data_dir = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    
-    // Create the directory if it doesn't exist
-    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create data dir: {}", e))?;
-    
-    let settings_path = data_dir.join("settings.json");
+fn save_settings(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<AppState>,
+    settings: String,
+) -> Result<(), String> {
+    let settings_path = settings_path(&app_handle)?;
     
     let mut file = fs::File::create(&settings_path).map_err(|e| e.to_string())?;
     file.write_all(settings.as_bytes())
         .map_err(|e| e.to_string())?;
+
+    state.reload_write_target(&settings);
+
+    // Pick up any changed filename date patterns and media types
+    let pattern_errors = state.reload_date_patterns(&settings);
+    let type_errors = state.reload_media_types(&settings);
+    if !pattern_errors.is_empty() {
+        return Err(format!("Settings saved, but some date patterns were skipped: {}", pattern_errors.join("; ")));
+    }
+    if !type_errors.is_empty() {
+        return Err(format!("Settings saved, but some media types were skipped: {}", type_errors.join("; ")));
+    }
     Ok(())
 }
 
@@ -205,6 +246,24 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // Compile user filename date patterns saved in a previous session
+            if let Ok(content) = settings_path(app.handle()).and_then(|p| fs::read_to_string(p).map_err(|e| e.to_string())) {
+                app.state::<AppState>().reload_date_patterns(&content);
+                app.state::<AppState>().reload_write_target(&content);
+                app.state::<AppState>().reload_media_types(&content);
+            }
+            let journal = data_file(app.handle(), journal::JOURNAL_FILE)?;
+            app.state::<AppState>().journal.set_path(journal);
+            let index = data_file(app.handle(), metadata_index::INDEX_FILE)?;
+            app.state::<AppState>().metadata_index.set_path(index);
+            // Optional GeoNames tables for reverse geocoding; the bundled
+            // compact table is used when they're missing or unreadable
+            let geonames_cities = data_file(app.handle(), geocode::GEONAMES_CITIES_FILE)?;
+            let geonames_admin1 = data_file(app.handle(), geocode::GEONAMES_ADMIN1_FILE)?;
+            let _ = geocode::load_geonames(&geonames_cities, &geonames_admin1);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             load_settings,
@@ -220,14 +279,48 @@ pub fn run() {
             metadata::write_exif_date_if_missing,
             metadata::write_exif_keywords,
             metadata::scan_missing_dates,
+            metadata::get_metadata_backend,
+            media_types::list_media_types,
+            isobmff::read_container_metadata,
+            date_patterns::validate_date_pattern,
+            takeout::write_takeout_metadata,
+            time_shift::preview_time_shift,
+            time_shift::apply_time_shift,
+            keyword_edit::preview_keyword_edit,
+            keyword_edit::apply_keyword_edit,
+            geotag::preview_geotag,
+            geotag::apply_geotag,
+            geocode::reverse_geocode,
+            geocode::preview_reverse_geocode,
+            geocode::apply_reverse_geocode,
+            date_check::check_dates,
+            journal::list_journal_operations,
+            journal::list_journal_entries,
+            journal::revert_operation,
+            metadata_index::get_metadata_index_stats,
+            metadata_index::invalidate_metadata_index,
+            metadata_index::compact_metadata_index,
+            metadata_index::rebuild_metadata_index,
+            write_plan::plan_metadata_fixes,
             // Dedup commands
             dedup::check_czkawka,
             dedup::find_duplicates,
             dedup::find_similar_images,
+            dedup::list_trash_companions,
             dedup::delete_to_trash,
+            bursts::find_bursts,
+            motion_photo::scan_motion_photos,
+            extension_check::check_extensions,
+            integrity::check_integrity,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // Let the persistent exiftool workers exit cleanly with the app
+            if let tauri::RunEvent::Exit = event {
+                app_handle.state::<AppState>().exiftool.shutdown();
+            }
+        });
 }
 
 #[cfg(test)]
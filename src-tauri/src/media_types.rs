@@ -0,0 +1,374 @@
+//! Registry of media file types.
+//!
+//! Each type is a lowercase file extension with a category (photo, RAW,
+//! video or sidecar) and a MIME type. Built-in types are ordinary entries of
+//! the registry; user types from settings are merged over them by extension,
+//! and a user entry with `enabled: false` drops a built-in. Every directory
+//! walker asks the registry which files are media.
+//!
+//! Files whose extension is missing or unknown are identified by their first
+//! bytes (see `sniff`), so a JPEG saved as `IMG_1234` or `photo.dat` is still
+//! scanned. Sidecar extensions are never sniffed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Settings key holding user-defined media types
+pub const SETTINGS_KEY: &str = "mediaTypes";
+
+/// What a media file is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaCategory {
+    Photo,
+    /// Camera raw; most are TIFF-based and readable like photos
+    Raw,
+    Video,
+    /// Metadata next to a media file (XMP, Apple AAE, Takeout JSON)
+    Sidecar,
+}
+
+impl MediaCategory {
+    /// Photos, RAWs and videos, as opposed to sidecars
+    pub fn is_media(self) -> bool {
+        self != MediaCategory::Sidecar
+    }
+}
+
+/// A media type as stored in settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaType {
+    /// Extension without the dot; matched case-insensitively
+    pub extension: String,
+    pub category: MediaCategory,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Built-in types: extension, category, MIME type
+const BUILTIN_TYPES: &[(&str, MediaCategory, &str)] = &[
+    ("jpg", MediaCategory::Photo, "image/jpeg"),
+    ("jpeg", MediaCategory::Photo, "image/jpeg"),
+    ("jpe", MediaCategory::Photo, "image/jpeg"),
+    ("png", MediaCategory::Photo, "image/png"),
+    ("gif", MediaCategory::Photo, "image/gif"),
+    ("bmp", MediaCategory::Photo, "image/bmp"),
+    ("tif", MediaCategory::Photo, "image/tiff"),
+    ("tiff", MediaCategory::Photo, "image/tiff"),
+    ("webp", MediaCategory::Photo, "image/webp"),
+    ("heic", MediaCategory::Photo, "image/heic"),
+    ("heif", MediaCategory::Photo, "image/heif"),
+    ("avif", MediaCategory::Photo, "image/avif"),
+    ("jxl", MediaCategory::Photo, "image/jxl"),
+    ("cr2", MediaCategory::Raw, "image/x-canon-cr2"),
+    ("cr3", MediaCategory::Raw, "image/x-canon-cr3"),
+    ("crw", MediaCategory::Raw, "image/x-canon-crw"),
+    ("nef", MediaCategory::Raw, "image/x-nikon-nef"),
+    ("nrw", MediaCategory::Raw, "image/x-nikon-nrw"),
+    ("arw", MediaCategory::Raw, "image/x-sony-arw"),
+    ("srf", MediaCategory::Raw, "image/x-sony-srf"),
+    ("sr2", MediaCategory::Raw, "image/x-sony-sr2"),
+    ("dng", MediaCategory::Raw, "image/x-adobe-dng"),
+    ("raf", MediaCategory::Raw, "image/x-fuji-raf"),
+    ("orf", MediaCategory::Raw, "image/x-olympus-orf"),
+    ("rw2", MediaCategory::Raw, "image/x-panasonic-rw2"),
+    ("pef", MediaCategory::Raw, "image/x-pentax-pef"),
+    ("srw", MediaCategory::Raw, "image/x-samsung-srw"),
+    ("x3f", MediaCategory::Raw, "image/x-sigma-x3f"),
+    ("3fr", MediaCategory::Raw, "image/x-hasselblad-3fr"),
+    ("iiq", MediaCategory::Raw, "image/x-phaseone-iiq"),
+    ("rwl", MediaCategory::Raw, "image/x-leica-rwl"),
+    ("mp4", MediaCategory::Video, "video/mp4"),
+    ("m4v", MediaCategory::Video, "video/x-m4v"),
+    ("mov", MediaCategory::Video, "video/quicktime"),
+    ("3gp", MediaCategory::Video, "video/3gpp"),
+    ("3g2", MediaCategory::Video, "video/3gpp2"),
+    ("avi", MediaCategory::Video, "video/x-msvideo"),
+    ("mkv", MediaCategory::Video, "video/x-matroska"),
+    ("webm", MediaCategory::Video, "video/webm"),
+    ("mts", MediaCategory::Video, "video/mp2t"),
+    ("m2ts", MediaCategory::Video, "video/mp2t"),
+    ("ts", MediaCategory::Video, "video/mp2t"),
+    ("mpg", MediaCategory::Video, "video/mpeg"),
+    ("mpeg", MediaCategory::Video, "video/mpeg"),
+    ("vob", MediaCategory::Video, "video/mpeg"),
+    ("mod", MediaCategory::Video, "video/mpeg"),
+    ("tod", MediaCategory::Video, "video/mpeg"),
+    ("wmv", MediaCategory::Video, "video/x-ms-wmv"),
+    ("asf", MediaCategory::Video, "video/x-ms-asf"),
+    ("flv", MediaCategory::Video, "video/x-flv"),
+    ("xmp", MediaCategory::Sidecar, "application/rdf+xml"),
+    ("aae", MediaCategory::Sidecar, "application/xml"),
+    ("json", MediaCategory::Sidecar, "application/json"),
+    ("thm", MediaCategory::Sidecar, "image/jpeg"),
+];
+
+/// Built-in types, in the shape of settings entries
+pub fn builtin_types() -> Vec<MediaType> {
+    BUILTIN_TYPES
+        .iter()
+        .map(|&(extension, category, mime_type)| MediaType {
+            extension: extension.to_string(),
+            category,
+            mime_type: Some(mime_type.to_string()),
+            enabled: true,
+        })
+        .collect()
+}
+
+/// Enabled types by lowercase extension
+#[derive(Debug, Clone)]
+pub struct MediaTypeRegistry {
+    types: HashMap<String, MediaType>,
+}
+
+impl MediaTypeRegistry {
+    /// Build a registry from built-ins plus user overrides.
+    ///
+    /// User entries without a usable extension are skipped and reported in
+    /// the returned errors.
+    pub fn with_overrides(user_types: &[MediaType]) -> (Self, Vec<String>) {
+        let mut types: HashMap<String, MediaType> =
+            builtin_types().into_iter().map(|t| (t.extension.clone(), t)).collect();
+
+        let mut errors = Vec::new();
+        for user in user_types {
+            let extension = user.extension.trim().trim_start_matches('.').to_lowercase();
+            if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+                errors.push(format!("Invalid media type extension '{}'", user.extension));
+                continue;
+            }
+            if !user.enabled {
+                types.remove(&extension);
+                continue;
+            }
+            let mime_type = user.mime_type.clone().or_else(|| types.get(&extension).and_then(|t| t.mime_type.clone()));
+            types.insert(extension.clone(), MediaType { extension, mime_type, ..user.clone() });
+        }
+
+        (Self { types }, errors)
+    }
+
+    /// Registry from the settings JSON (`mediaTypes` array)
+    pub fn from_settings_json(settings: &str) -> (Self, Vec<String>) {
+        let user_types: Vec<MediaType> = serde_json::from_str::<serde_json::Value>(settings)
+            .ok()
+            .and_then(|v| v.get(SETTINGS_KEY).cloned())
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+        Self::with_overrides(&user_types)
+    }
+
+    /// The registry with only built-in types, built once per process
+    pub fn builtin() -> &'static MediaTypeRegistry {
+        static BUILTIN: OnceLock<MediaTypeRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(|| Self::with_overrides(&[]).0)
+    }
+
+    /// The registered type of an extension, with or without the dot
+    pub fn lookup(&self, extension: &str) -> Option<&MediaType> {
+        self.types.get(&extension.trim_start_matches('.').to_lowercase())
+    }
+
+    /// Category by extension alone
+    pub fn category_by_extension(&self, path: &Path) -> Option<MediaCategory> {
+        let extension = path.extension()?.to_str()?;
+        self.lookup(extension).map(|t| t.category)
+    }
+
+    /// Category by extension, or by content when the extension is missing or unknown
+    pub fn category(&self, path: &Path) -> Option<MediaCategory> {
+        self.category_by_extension(path)
+            .or_else(|| sniff(path).map(|sniffed| sniffed.category))
+    }
+
+    /// Whether a walker should pick up `path` as a photo, RAW or video
+    pub fn is_media(&self, path: &Path) -> bool {
+        self.category(path).is_some_and(MediaCategory::is_media)
+    }
+
+    /// Registered media extensions, sorted; sidecars excluded
+    pub fn media_extensions(&self) -> Vec<&str> {
+        let mut extensions: Vec<&str> = self
+            .types
+            .values()
+            .filter(|t| t.category.is_media())
+            .map(|t| t.extension.as_str())
+            .collect();
+        extensions.sort_unstable();
+        extensions
+    }
+}
+
+impl Default for MediaTypeRegistry {
+    fn default() -> Self {
+        Self::builtin().clone()
+    }
+}
+
+/// What a file's first bytes say it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SniffedType {
+    pub category: MediaCategory,
+    /// The usual extension for the format
+    pub extension: &'static str,
+    pub mime_type: &'static str,
+}
+
+/// Bytes read from the start of a file for sniffing
+const SNIFF_LEN: usize = 200;
+
+/// Identify a file by its magic bytes; None if unreadable or not a known format
+pub fn sniff(path: &Path) -> Option<SniffedType> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    std::fs::File::open(path).ok()?.take(SNIFF_LEN as u64).read_to_end(&mut header).ok()?;
+    sniff_bytes(&header)
+}
+
+/// Identify a format from the first bytes of a file
+pub fn sniff_bytes(header: &[u8]) -> Option<SniffedType> {
+    let at = |pos: usize, magic: &[u8]| header.get(pos..pos + magic.len()) == Some(magic);
+    let found = |category, extension, mime_type| Some(SniffedType { category, extension, mime_type });
+    use MediaCategory::{Photo, Raw, Video};
+
+    if at(0, &[0xFF, 0xD8, 0xFF]) {
+        found(Photo, "jpg", "image/jpeg")
+    } else if at(0, b"\x89PNG\r\n\x1a\n") {
+        found(Photo, "png", "image/png")
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        found(Photo, "gif", "image/gif")
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        found(Photo, "webp", "image/webp")
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        found(Video, "avi", "video/x-msvideo")
+    } else if at(0, b"FUJIFILMCCD-RAW") {
+        found(Raw, "raf", "image/x-fuji-raf")
+    } else if at(0, b"IIRO") || at(0, b"IIRS") || at(0, b"MMOR") {
+        found(Raw, "orf", "image/x-olympus-orf")
+    } else if at(0, b"IIU\0") {
+        found(Raw, "rw2", "image/x-panasonic-rw2")
+    } else if at(0, b"II*\0") && at(8, b"CR") {
+        found(Raw, "cr2", "image/x-canon-cr2")
+    } else if at(0, b"II*\0") || at(0, b"MM\0*") {
+        // NEF, ARW, DNG and friends are TIFF too; only exiftool tells them apart
+        found(Photo, "tif", "image/tiff")
+    } else if at(4, b"ftyp") {
+        match header.get(8..12)? {
+            b"heic" | b"heix" | b"heim" | b"heis" => found(Photo, "heic", "image/heic"),
+            b"mif1" | b"msf1" => found(Photo, "heif", "image/heif"),
+            b"avif" | b"avis" => found(Photo, "avif", "image/avif"),
+            b"crx " => found(Raw, "cr3", "image/x-canon-cr3"),
+            b"qt  " => found(Video, "mov", "video/quicktime"),
+            b"M4V " | b"M4VH" | b"M4VP" => found(Video, "m4v", "video/x-m4v"),
+            brand if brand.starts_with(b"3g2") => found(Video, "3g2", "video/3gpp2"),
+            brand if brand.starts_with(b"3g") => found(Video, "3gp", "video/3gpp"),
+            brand if brand.starts_with(b"iso") || brand.starts_with(b"mp4") => found(Video, "mp4", "video/mp4"),
+            b"avc1" | b"dash" | b"MSNV" | b"XAVC" => found(Video, "mp4", "video/mp4"),
+            // M4A audio, F4V, JPEG 2000 and other ISO files are not ours to guess
+            _ => None,
+        }
+    } else if at(0, &[0x1A, 0x45, 0xDF, 0xA3]) {
+        // Matroska; WebM declares its DocType early in the EBML header
+        match header.windows(4).take(64).any(|w| w == b"webm") {
+            true => found(Video, "webm", "video/webm"),
+            false => found(Video, "mkv", "video/x-matroska"),
+        }
+    } else if at(0, &[0x47]) && at(188, &[0x47]) {
+        found(Video, "ts", "video/mp2t")
+    } else if at(4, &[0x47]) && at(196, &[0x47]) {
+        // 192-byte packets with a 4-byte timecode, as AVCHD cameras write
+        found(Video, "mts", "video/mp2t")
+    } else if at(0, &[0x00, 0x00, 0x01, 0xBA]) {
+        found(Video, "mpg", "video/mpeg")
+    } else if at(0, &[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        found(Video, "wmv", "video/x-ms-wmv")
+    } else if at(0, b"FLV\x01") {
+        found(Video, "flv", "video/x-flv")
+    } else if at(0, b"BM") && at(6, &[0, 0, 0, 0]) {
+        found(Photo, "bmp", "image/bmp")
+    } else if at(0, &[0xFF, 0x0A]) || at(0, b"\0\0\0\x0CJXL \r\n\x87\n") {
+        found(Photo, "jxl", "image/jxl")
+    } else {
+        None
+    }
+}
+
+/// List the registered media types, built-in and from settings
+#[tauri::command]
+pub fn list_media_types(state: tauri::State<'_, crate::state::AppState>) -> Vec<MediaType> {
+    let registry = state.media_types();
+    let mut types: Vec<MediaType> = registry.types.values().cloned().collect();
+    types.sort_by(|a, b| a.extension.cmp(&b.extension));
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_builtin_categories() {
+        let registry = MediaTypeRegistry::builtin();
+        let category = |name: &str| registry.category_by_extension(Path::new(name));
+        assert_eq!(category("/a/IMG_0001.CR3"), Some(MediaCategory::Raw));
+        assert_eq!(category("/a/00012.MTS"), Some(MediaCategory::Video));
+        assert_eq!(category("/a/anim.gif"), Some(MediaCategory::Photo));
+        assert_eq!(category("/a/IMG_0001.xmp"), Some(MediaCategory::Sidecar));
+        assert_eq!(category("/a/notes.txt"), None);
+        assert!(registry.media_extensions().contains(&"avif"));
+        assert!(!registry.media_extensions().contains(&"json"));
+    }
+
+    #[test]
+    fn test_settings_overrides() {
+        let settings = r#"{"mediaTypes": [
+            {"extension": ".INSV", "category": "video"},
+            {"extension": "bmp", "category": "photo", "enabled": false},
+            {"extension": "", "category": "raw"}
+        ]}"#;
+        let (registry, errors) = MediaTypeRegistry::from_settings_json(settings);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(registry.lookup("insv").map(|t| t.category), Some(MediaCategory::Video));
+        assert!(registry.lookup("bmp").is_none());
+        assert!(registry.lookup("jpg").is_some());
+    }
+
+    #[test]
+    fn test_sniff_bytes() {
+        let sniffed = |bytes: &[u8]| sniff_bytes(bytes).map(|s| s.extension);
+        assert_eq!(sniffed(&[0xFF, 0xD8, 0xFF, 0xE1]), Some("jpg"));
+        assert_eq!(sniffed(b"\0\0\0\x18ftypheic\0\0\0\0"), Some("heic"));
+        assert_eq!(sniffed(b"\0\0\0\x18ftypcrx \0\0\0\0"), Some("cr3"));
+        assert_eq!(sniffed(b"\0\0\0\x14ftypqt  \0\0\0\0"), Some("mov"));
+        assert_eq!(sniffed(b"\0\0\0\x18ftypisom\0\0\0\0"), Some("mp4"));
+        assert_eq!(sniffed(b"\0\0\0\x18ftypmp42\0\0\0\0"), Some("mp4"));
+        assert_eq!(sniffed(b"\0\0\0\x18ftypM4A \0\0\0\0"), None);
+        assert_eq!(sniffed(b"II*\0\x10\0\0\0CR\x02\0"), Some("cr2"));
+        assert_eq!(sniffed(b"GIF89a"), Some("gif"));
+        assert_eq!(sniffed(b"hello world"), None);
+    }
+
+    #[test]
+    fn test_unknown_extension_is_sniffed() {
+        let dir = tempdir().unwrap();
+        let disguised = dir.path().join("IMG_1234");
+        std::fs::write(&disguised, [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]).unwrap();
+        let text = dir.path().join("notes.dat");
+        std::fs::write(&text, b"not a photo").unwrap();
+
+        let registry = MediaTypeRegistry::builtin();
+        assert_eq!(registry.category(&disguised), Some(MediaCategory::Photo));
+        assert!(registry.is_media(&disguised));
+        assert!(!registry.is_media(&text));
+    }
+}
@@ -1,16 +1,46 @@
 //! Metadata module for EXIF operations using ExifTool.
 //!
 //! This module provides functions to:
-//! - Scan files for missing DateTimeOriginal
-//! - Extract dates from filename patterns (WhatsApp, screenshots, etc.)
+//! - Scan files for missing DateTimeOriginal (and check suspicious ones via `date_check`)
+//! - Extract dates from filename patterns (WhatsApp, screenshots, etc.) via `date_patterns`
+//! - Pick up Google Takeout JSON sidecars via `takeout`
 //! - Read and write EXIF metadata safely (never overwriting valid data)
+//! - Write dates with their UTC offset (OffsetTimeOriginal, QuickTime UTC)
+//! - Optionally look up where GPS-tagged files were taken via `geocode`
+//! - Keep Live Photos, RAW+JPEG pairs and edit files together via `companions`
+//!
+//! Every write is recorded in the undo journal (see `journal`) and can be
+//! previewed as a dry run (see `write_plan`). Date and keyword writes can go
+//! to an XMP sidecar instead of the file (see `xmp_sidecar`).
+//!
+//! Reads go through a `MetadataBackend`: exiftool when it is installed, or the
+//! pure-Rust reader in `native_exif` otherwise. Writes always need exiftool.
+//! Scans record what they read in `metadata_index` and skip files that haven't
+//! changed since, except in `ScanMode::Directory`, which always reads everything.
 
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::companions::{self, Companion, CompanionKind};
+use crate::date_check;
+use crate::date_patterns::{DateConfidence, PatternRegistry};
+use crate::exiftool::ExiftoolPool;
+use crate::geocode::{self, Place};
+use crate::isobmff::GpsCoordinates;
+use crate::journal;
+use crate::keywords::{self, KeywordSet};
+use crate::media_types::MediaTypeRegistry;
+use crate::state::AppState;
+use crate::takeout::{SidecarIndex, TakeoutMetadata};
+use crate::timezone;
+use crate::write_plan::{self, FilePlan, WriteOutcome};
+use crate::xmp_sidecar::{self, MetadataSource, WriteTarget};
 
 /// Represents extracted EXIF metadata from a file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ExifMetadata {
     pub file_path: String,
     pub date_time_original: Option<String>,
@@ -19,6 +49,43 @@ pub struct ExifMetadata {
     pub model: Option<String>,
     pub software: Option<String>,
     pub keywords: Vec<String>,
+    /// `|`-separated keyword paths from `HierarchicalSubject` and digiKam's `TagsList`
+    #[serde(default)]
+    pub hierarchical_keywords: Vec<String>,
+    pub gps: Option<GpsCoordinates>,
+    /// Pixel size as stored, before applying `orientation`
+    pub image_width: Option<u32>,
+    pub image_height: Option<u32>,
+    /// EXIF orientation, 1 (upright) to 8
+    pub orientation: Option<u16>,
+    /// Video length in seconds
+    pub duration: Option<f64>,
+    /// Video codec identifier, e.g. `avc1`, `hvc1`
+    pub video_codec: Option<String>,
+    pub lens_model: Option<String>,
+    pub iso: Option<u32>,
+    /// Exposure time in seconds
+    pub exposure_time: Option<f64>,
+    pub f_number: Option<f64>,
+    pub mime_type: Option<String>,
+    /// Fractional seconds of `date_time_original`, e.g. `345`
+    pub sub_sec_time_original: Option<String>,
+    /// UTC offset of `date_time_original` from `OffsetTimeOriginal`, e.g. `+02:00`
+    #[serde(default)]
+    pub offset_time_original: Option<String>,
+    pub image_unique_id: Option<String>,
+    /// Apple's `ContentIdentifier`, shared by the photo and video of a Live Photo
+    pub content_identifier: Option<String>,
+    /// Apple's `BurstUUID`, shared by the frames of one burst
+    #[serde(default)]
+    pub burst_uuid: Option<String>,
+    /// Star rating, 0 to 5 (-1 for rejected)
+    pub rating: Option<i32>,
+    pub description: Option<String>,
+    /// Where each field was read from, keyed by field name; filled by
+    /// sidecar-aware reads (see `xmp_sidecar::read_merged`)
+    #[serde(default)]
+    pub sources: BTreeMap<String, MetadataSource>,
 }
 
 /// Result of date extraction from filename
@@ -27,6 +94,12 @@ pub struct ExtractedDate {
     pub date: String,        // Format: YYYY-MM-DD
     pub time: Option<String>, // Format: HH:MM:SS if available
     pub source: String,      // e.g., "WhatsApp", "Screenshot", "Android Camera"
+    #[serde(default)]
+    pub subsec: Option<String>, // Fractional seconds digits, e.g. "345"
+    #[serde(default)]
+    pub confidence: DateConfidence, // How much the UI should trust this suggestion
+    #[serde(default)]
+    pub offset: Option<String>, // UTC offset of `time`, e.g. "+00:00"; None for local time
 }
 
 /// File info with metadata status
@@ -36,261 +109,579 @@ pub struct FileMetadataInfo {
     pub has_date: bool,
     pub extracted_date: Option<ExtractedDate>,
     pub camera_model: Option<String>,
+    /// Values from a Google Takeout sidecar next to the file
+    #[serde(default)]
+    pub takeout: Option<TakeoutMetadata>,
+    /// Place of the file's GPS position, when the scan was asked to geocode
+    #[serde(default)]
+    pub place: Option<Place>,
+    /// Files that belong with this one (Live Photo video, RAW, `.AAE`, ...)
+    #[serde(default)]
+    pub companions: Vec<Companion>,
 }
 
-/// Extract date from common filename patterns
-/// 
-/// Supported patterns:
-/// - WhatsApp Android: IMG-20240115-WA0042.jpg
-/// - WhatsApp iOS: WhatsApp Image 2024-01-15 at 10.30.45.jpeg
-/// - Screenshot Mac: Screenshot 2024-01-15 at 14.30.00.png
-/// - Android Camera: 20240115_143000.jpg
-/// - iOS Camera: IMG_20240115_143000.jpg
-pub fn extract_date_from_filename(filename: &str) -> Option<ExtractedDate> {
-    // WhatsApp Android: IMG-20240115-WA0042.jpg
-    let whatsapp_android = Regex::new(r"IMG-(\d{4})(\d{2})(\d{2})-WA").unwrap();
-    if let Some(caps) = whatsapp_android.captures(filename) {
-        return Some(ExtractedDate {
-            date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-            time: None,
-            source: "WhatsApp".to_string(),
-        });
+/// Source of metadata reads
+pub trait MetadataBackend {
+    /// Short name for logs and the UI
+    fn name(&self) -> &'static str;
+    fn read(&self, file_path: &str) -> Result<ExifMetadata, String>;
+}
+
+impl MetadataBackend for ExiftoolPool {
+    fn name(&self) -> &'static str {
+        "exiftool"
     }
 
-    // WhatsApp iOS: WhatsApp Image 2024-01-15 at 10.30.45
-    let whatsapp_ios = Regex::new(r"WhatsApp.*(\d{4})-(\d{2})-(\d{2})(?:\s+at\s+(\d{2})\.(\d{2})\.(\d{2}))?").unwrap();
-    if let Some(caps) = whatsapp_ios.captures(filename) {
-        let time = if caps.get(4).is_some() {
-            Some(format!("{}:{}:{}", &caps[4], &caps[5], &caps[6]))
-        } else {
-            None
-        };
-        return Some(ExtractedDate {
-            date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-            time,
-            source: "WhatsApp".to_string(),
-        });
+    fn read(&self, file_path: &str) -> Result<ExifMetadata, String> {
+        read_metadata(self, file_path.to_string())
     }
+}
 
-    // Screenshot Mac: Screenshot 2024-01-15 at 14.30.00
-    let screenshot_mac = Regex::new(r"Screenshot\s+(\d{4})-(\d{2})-(\d{2})\s+at\s+(\d{2})\.(\d{2})\.(\d{2})").unwrap();
-    if let Some(caps) = screenshot_mac.captures(filename) {
-        return Some(ExtractedDate {
-            date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-            time: Some(format!("{}:{}:{}", &caps[4], &caps[5], &caps[6])),
-            source: "Screenshot".to_string(),
-        });
+/// Pure-Rust reader for JPEG, TIFF, PNG, WebP and ISO-BMFF (MP4/MOV/HEIC)
+pub struct NativeBackend;
+
+impl MetadataBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
     }
 
-    // Android Camera: 20240115_143000.jpg or IMG_20240115_143000.jpg
-    let android_camera = Regex::new(r"(?:IMG_)?(\d{4})(\d{2})(\d{2})_(\d{2})(\d{2})(\d{2})").unwrap();
-    if let Some(caps) = android_camera.captures(filename) {
-        let year: u32 = caps[1].parse().unwrap_or(0);
-        let month: u32 = caps[2].parse().unwrap_or(0);
-        let day: u32 = caps[3].parse().unwrap_or(0);
-        
-        // Validate it looks like a real date
-        if year >= 1990 && year <= 2100 && month >= 1 && month <= 12 && day >= 1 && day <= 31 {
-            return Some(ExtractedDate {
-                date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-                time: Some(format!("{}:{}:{}", &caps[4], &caps[5], &caps[6])),
-                source: "Camera".to_string(),
-            });
+    fn read(&self, file_path: &str) -> Result<ExifMetadata, String> {
+        if crate::isobmff::is_isobmff(std::path::Path::new(file_path)) {
+            crate::isobmff::read_metadata(file_path)
+        } else {
+            crate::native_exif::read_metadata(file_path)
         }
     }
+}
 
-    // Generic date pattern: YYYY-MM-DD or YYYYMMDD anywhere in filename
-    let generic_date = Regex::new(r"(\d{4})[-_]?(\d{2})[-_]?(\d{2})").unwrap();
-    if let Some(caps) = generic_date.captures(filename) {
-        let year: u32 = caps[1].parse().unwrap_or(0);
-        let month: u32 = caps[2].parse().unwrap_or(0);
-        let day: u32 = caps[3].parse().unwrap_or(0);
-        
-        // Validate it looks like a real date
-        if year >= 1990 && year <= 2100 && month >= 1 && month <= 12 && day >= 1 && day <= 31 {
-            return Some(ExtractedDate {
-                date: format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]),
-                time: None,
-                source: "Filename".to_string(),
-            });
-        }
+/// Use exiftool when it is on PATH, otherwise fall back to the native reader
+pub fn select_backend(state: &AppState) -> &dyn MetadataBackend {
+    if state.exiftool.is_installed() {
+        &state.exiftool
+    } else {
+        &NativeBackend
     }
+}
 
-    None
+/// Report which backend metadata reads will use ("exiftool" or "native")
+#[tauri::command]
+pub fn get_metadata_backend(state: tauri::State<'_, AppState>) -> String {
+    select_backend(&state).name().to_string()
 }
 
-/// Read EXIF metadata from a file using exiftool
+/// Read EXIF metadata from a file using the best available backend, with
+/// values from its XMP sidecar merged over the embedded ones
 #[tauri::command]
-pub fn read_exif_metadata(file_path: String) -> Result<ExifMetadata, String> {
-    let output = Command::new("exiftool")
-        .args([
-            "-json",
-            "-DateTimeOriginal",
-            "-CreateDate",
-            "-Make",
-            "-Model",
-            "-Software",
-            "-Keywords",
-            "-XPKeywords",
-            &file_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run exiftool: {}", e))?;
+pub fn read_exif_metadata(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+) -> Result<ExifMetadata, String> {
+    xmp_sidecar::read_merged(select_backend(&state), &file_path)
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("exiftool failed: {}", stderr));
+/// Tags requested from exiftool for every metadata read
+const METADATA_TAGS: &[&str] = &[
+    "-DateTimeOriginal",
+    "-CreateDate",
+    "-Make",
+    "-Model",
+    "-Software",
+    "-Keywords",
+    "-XPKeywords",
+    "-Subject",
+    "-HierarchicalSubject",
+    "-TagsList",
+    // `#` asks for the unformatted value: signed decimal degrees, seconds, 1-8
+    "-GPSLatitude#",
+    "-GPSLongitude#",
+    "-GPSAltitude#",
+    "-ImageWidth",
+    "-ImageHeight",
+    "-Orientation#",
+    "-Duration#",
+    "-CompressorID",
+    "-VideoCodec",
+    "-LensModel",
+    "-ISO",
+    "-ExposureTime#",
+    "-FNumber#",
+    "-MIMEType",
+    "-SubSecTimeOriginal",
+    "-OffsetTimeOriginal",
+    "-ImageUniqueID",
+    "-ContentIdentifier",
+    "-BurstUUID",
+    "-Rating",
+    "-Description",
+    "-ImageDescription",
+];
+
+/// Read EXIF metadata through a pooled exiftool worker
+pub fn read_metadata(pool: &ExiftoolPool, file_path: String) -> Result<ExifMetadata, String> {
+    let mut args = vec!["-json"];
+    args.extend_from_slice(METADATA_TAGS);
+    args.push(&file_path);
+    let output = pool.execute(&args)?;
+
+    if !output.success() {
+        return Err(format!("exiftool failed: {}", output.stderr));
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let parsed: Vec<serde_json::Value> = serde_json::from_str(&stdout)
+    parse_exif_json(file_path, &output.stdout)
+}
+
+/// Parse the `-json` output of exiftool for a single file
+fn parse_exif_json(file_path: String, json: &str) -> Result<ExifMetadata, String> {
+    let parsed: Vec<serde_json::Value> = serde_json::from_str(json)
         .map_err(|e| format!("Failed to parse exiftool output: {}", e))?;
 
     if parsed.is_empty() {
         return Err("No EXIF data found".to_string());
     }
 
-    let data = &parsed[0];
-    
-    // Parse keywords from both Keywords and XPKeywords
-    let mut keywords = Vec::new();
-    if let Some(kw) = data.get("Keywords") {
-        if let Some(arr) = kw.as_array() {
-            for k in arr {
-                if let Some(s) = k.as_str() {
-                    keywords.push(s.to_string());
-                }
-            }
-        } else if let Some(s) = kw.as_str() {
-            keywords.push(s.to_string());
+    Ok(metadata_from_value(file_path, &parsed[0]))
+}
+
+/// Build `ExifMetadata` from a single exiftool JSON record
+fn metadata_from_value(file_path: String, data: &serde_json::Value) -> ExifMetadata {
+    // Flat keywords from IPTC, XMP and Windows, which keeps them `;`-separated
+    let mut keywords: Vec<String> = Vec::new();
+    let xp_keywords = keywords::json_list(data.get("XPKeywords"))
+        .iter()
+        .flat_map(|s| s.split(';').map(|k| k.trim().to_string()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    for keyword in keywords::json_list(data.get("Keywords"))
+        .into_iter()
+        .chain(keywords::json_list(data.get("Subject")))
+        .chain(xp_keywords)
+    {
+        if !keyword.is_empty() && !keywords.contains(&keyword) {
+            keywords.push(keyword);
         }
     }
-    if let Some(xp_kw) = data.get("XPKeywords") {
-        if let Some(s) = xp_kw.as_str() {
-            for k in s.split(';') {
-                let trimmed = k.trim().to_string();
-                if !trimmed.is_empty() && !keywords.contains(&trimmed) {
-                    keywords.push(trimmed);
-                }
-            }
+
+    let mut hierarchical_keywords = keywords::json_list(data.get("HierarchicalSubject"));
+    for tag in keywords::json_list(data.get("TagsList")) {
+        let keyword = keywords::from_digikam(&tag);
+        if !hierarchical_keywords.contains(&keyword) {
+            hierarchical_keywords.push(keyword);
         }
     }
 
-    Ok(ExifMetadata {
+    let gps = json_number(data, "GPSLatitude")
+        .zip(json_number(data, "GPSLongitude"))
+        .map(|(latitude, longitude)| GpsCoordinates {
+            latitude,
+            longitude,
+            altitude: json_number(data, "GPSAltitude"),
+        });
+
+    ExifMetadata {
         file_path,
-        date_time_original: data.get("DateTimeOriginal").and_then(|v| v.as_str()).map(|s| s.to_string()),
-        create_date: data.get("CreateDate").and_then(|v| v.as_str()).map(|s| s.to_string()),
-        make: data.get("Make").and_then(|v| v.as_str()).map(|s| s.to_string()),
-        model: data.get("Model").and_then(|v| v.as_str()).map(|s| s.to_string()),
-        software: data.get("Software").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        date_time_original: json_text(data, "DateTimeOriginal"),
+        create_date: json_text(data, "CreateDate"),
+        make: json_text(data, "Make"),
+        model: json_text(data, "Model"),
+        software: json_text(data, "Software"),
         keywords,
-    })
+        hierarchical_keywords,
+        gps,
+        image_width: json_number(data, "ImageWidth"),
+        image_height: json_number(data, "ImageHeight"),
+        orientation: json_number(data, "Orientation"),
+        duration: json_number(data, "Duration"),
+        video_codec: json_text(data, "CompressorID").or_else(|| json_text(data, "VideoCodec")),
+        lens_model: json_text(data, "LensModel"),
+        iso: json_number(data, "ISO"),
+        exposure_time: json_number(data, "ExposureTime"),
+        f_number: json_number(data, "FNumber"),
+        mime_type: json_text(data, "MIMEType"),
+        sub_sec_time_original: json_text(data, "SubSecTimeOriginal"),
+        offset_time_original: json_text(data, "OffsetTimeOriginal"),
+        image_unique_id: json_text(data, "ImageUniqueID"),
+        content_identifier: json_text(data, "ContentIdentifier"),
+        burst_uuid: json_text(data, "BurstUUID"),
+        rating: json_number(data, "Rating"),
+        description: json_text(data, "Description").or_else(|| json_text(data, "ImageDescription")),
+        sources: BTreeMap::new(),
+    }
+}
+
+/// A text value from an exiftool `-json` record. Numeric-looking text such
+/// as a sub-second `345` comes back as a number and is kept as its digits.
+pub(crate) fn json_text(data: &serde_json::Value, tag: &str) -> Option<String> {
+    let text = match data.get(tag)? {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// A numeric value from an exiftool `-json` record, which may also arrive as
+/// a string (`"100"`). Values that don't fit `T` are treated as missing.
+pub(crate) fn json_number<T: std::str::FromStr>(data: &serde_json::Value, tag: &str) -> Option<T> {
+    match data.get(tag)? {
+        serde_json::Value::Number(n) => n.to_string().parse().ok(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
 }
 
 /// Get camera model string from EXIF (Make + Model)
 #[tauri::command]
-pub fn get_camera_model(file_path: String) -> Result<Option<String>, String> {
-    match read_exif_metadata(file_path) {
-        Ok(metadata) => {
-            match (metadata.make, metadata.model) {
-                (Some(make), Some(model)) => Ok(Some(format!("{} {}", make.trim(), model.trim()))),
-                (None, Some(model)) => Ok(Some(model)),
-                (Some(make), None) => Ok(Some(make)),
-                (None, None) => Ok(None),
-            }
-        }
+pub fn get_camera_model(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+) -> Result<Option<String>, String> {
+    match select_backend(&state).read(&file_path) {
+        Ok(metadata) => Ok(camera_model_of(metadata)),
         Err(_) => Ok(None), // No EXIF data is not an error for this function
     }
 }
 
+/// Combine Make and Model into a single display string
+pub(crate) fn camera_model_of(metadata: ExifMetadata) -> Option<String> {
+    match (metadata.make, metadata.model) {
+        (Some(make), Some(model)) => Some(format!("{} {}", make.trim(), model.trim())),
+        (None, Some(model)) => Some(model),
+        (Some(make), None) => Some(make),
+        (None, None) => None,
+    }
+}
+
 /// Write EXIF date to file ONLY if DateTimeOriginal is missing
+///
+/// With `replace_implausible` a placeholder or future date (see `date_check`)
+/// counts as missing too; the journal keeps the old value for undo.
+/// `timezone` is an offset (`+05:00`) or zone name (`Asia/Karachi`). Without
+/// it the zone is guessed from the file's GPS position when it has one, and
+/// the message says so.
+/// With `dry_run` nothing is written and the planned changes are returned.
+/// `write_target` defaults to the one in settings.
 #[tauri::command]
-pub fn write_exif_date_if_missing(file_path: String, date: String, time: Option<String>) -> Result<String, String> {
-    // First check if date already exists
-    if let Ok(metadata) = read_exif_metadata(file_path.clone()) {
-        if metadata.date_time_original.is_some() {
-            return Ok("Date already exists, skipping".to_string());
+#[allow(clippy::too_many_arguments)]
+pub fn write_exif_date_if_missing(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    date: String,
+    time: Option<String>,
+    timezone: Option<String>,
+    operation_id: Option<String>,
+    dry_run: Option<bool>,
+    write_target: Option<WriteTarget>,
+    replace_implausible: Option<bool>,
+) -> Result<WriteOutcome, String> {
+    let target = write_target.unwrap_or_else(|| state.write_target());
+    let replace_implausible = replace_implausible.unwrap_or(false);
+    let prepared = prepare_date_write(&state, &file_path, &date, time, timezone, target, replace_implausible)?;
+    let operation_id = journal::operation_id_or("write-date", operation_id);
+    prepared.run(&state, &file_path, &operation_id, dry_run.unwrap_or(false))
+}
+
+/// A write that has been decided on but not performed yet
+pub(crate) enum PreparedWrite {
+    /// Nothing to write; `message` is what the command reports
+    Skip { plan: FilePlan, message: String },
+    /// exiftool command lines, each ending with the file it writes (the media
+    /// file or its sidecar)
+    Write { writes: Vec<Vec<String>>, message: String },
+}
+
+impl PreparedWrite {
+    pub(crate) fn plan(self, state: &AppState, file_path: &str) -> FilePlan {
+        match self {
+            PreparedWrite::Skip { plan, .. } => plan,
+            PreparedWrite::Write { writes, .. } => FilePlan {
+                file_path: file_path.to_string(),
+                changes: writes
+                    .iter()
+                    .flat_map(|args| write_plan::describe_write(state, written_file(args), args).changes)
+                    .collect(),
+            },
         }
     }
 
+    /// Perform the write through the journal, or only describe it in dry-run
+    pub(crate) fn run(self, state: &AppState, file_path: &str, operation_id: &str, dry_run: bool) -> Result<WriteOutcome, String> {
+        if dry_run {
+            return Ok(WriteOutcome::Planned(self.plan(state, file_path)));
+        }
+        match self {
+            PreparedWrite::Skip { message, .. } => Ok(WriteOutcome::Written(message)),
+            PreparedWrite::Write { writes, message } => {
+                for args in &writes {
+                    let output = journal::journaled_write(state, operation_id, written_file(args), args)?;
+                    if !output.success() {
+                        return Err(format!("exiftool failed: {}", output.stderr));
+                    }
+                }
+                Ok(WriteOutcome::Written(message))
+            }
+        }
+    }
+}
+
+/// The file an exiftool command line writes: its last argument
+fn written_file(args: &[String]) -> &str {
+    args.last().map(String::as_str).unwrap_or_default()
+}
+
+/// Suffix naming where a write went, unless it was only the file itself
+fn target_note(in_place: bool, sidecar: bool) -> &'static str {
+    match (in_place, sidecar) {
+        (true, true) => " (file and XMP sidecar)",
+        (false, true) => " (XMP sidecar)",
+        _ => "",
+    }
+}
+
+/// Decide what writing `date`/`time` to a file involves
+pub(crate) fn prepare_date_write(
+    state: &AppState,
+    file_path: &str,
+    date: &str,
+    time: Option<String>,
+    timezone: Option<String>,
+    target: WriteTarget,
+    replace_implausible: bool,
+) -> Result<PreparedWrite, String> {
+    let pool = &state.exiftool;
+
+    // First check if date already exists. The file needs one only if it has
+    // none embedded; the sidecar only if neither the file nor it has one, so
+    // a guessed date never hides a real one. Epoch and future dates only
+    // count as missing when the caller asked to replace them.
+    let embedded = select_backend(state).read(file_path).ok();
+    let merged = xmp_sidecar::merge(file_path, embedded.clone(), xmp_sidecar::read_sidecar(file_path).ok().flatten());
+    let lacks_date = |m: &ExifMetadata| {
+        m.date_time_original.as_deref().is_none_or(|d| replace_implausible && date_check::is_implausible(d))
+    };
+    let write_file = target.in_place() && embedded.as_ref().is_none_or(lacks_date);
+    let write_sidecar = target.sidecar() && lacks_date(&merged);
+    if !write_file && !write_sidecar {
+        return Ok(PreparedWrite::Skip {
+            plan: FilePlan::skipped(file_path, "DateTimeOriginal", merged.date_time_original, "Date already exists"),
+            message: "Date already exists, skipping".to_string(),
+        });
+    }
+
     // Format the datetime for EXIF
     let datetime = match time {
         Some(t) => format!("{} {}", date.replace('-', ":"), t),
         None => format!("{} 12:00:00", date.replace('-', ":")),
     };
+    let local = chrono::NaiveDateTime::parse_from_str(&datetime, "%Y:%m:%d %H:%M:%S")
+        .map_err(|e| format!("Invalid date {}: {}", datetime, e))?;
 
-    let output = Command::new("exiftool")
-        .args([
-            "-overwrite_original",
-            &format!("-DateTimeOriginal={}", datetime),
-            &format!("-CreateDate={}", datetime),
-            &file_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run exiftool: {}", e))?;
+    let guessed = timezone.is_none();
+    let zone = match timezone {
+        Some(spec) => Some(timezone::parse_zone(&spec)?),
+        None => read_gps(pool, file_path).and_then(|gps| timezone::guess_zone_for_location(gps.latitude, gps.longitude)),
+    };
+    let offset = zone
+        .and_then(|zone| zone.offset_at(&local))
+        .map(|offset| timezone::format_offset(&offset));
+
+    let message = match (&offset, zone) {
+        (Some(offset), Some(zone)) if guessed => {
+            format!("Date written: {}{} ({}, guessed from the GPS position)", datetime, offset, zone.name())
+        }
+        (Some(offset), Some(zone)) => format!("Date written: {}{} ({})", datetime, offset, zone.name()),
+        _ => format!("Date written: {}", datetime),
+    };
 
-    if output.status.success() {
-        Ok(format!("Date written: {}", datetime))
+    let mut writes = Vec::new();
+    if write_file {
+        writes.push(date_write_args(file_path, &datetime, offset.as_deref()));
+    }
+    if write_sidecar {
+        writes.push(xmp_sidecar::date_args(file_path, &datetime, offset.as_deref()));
+    }
+    Ok(PreparedWrite::Write {
+        writes,
+        message: message + target_note(write_file, write_sidecar),
+    })
+}
+
+/// Containers whose QuickTime date atoms are defined as UTC
+const QUICKTIME_EXTENSIONS: &[&str] = &["mp4", "mov", "m4v", "3gp"];
+
+fn is_quicktime_file(file_path: &str) -> bool {
+    std::path::Path::new(file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| QUICKTIME_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// exiftool arguments to write a local `datetime` with an optional UTC offset
+pub(crate) fn date_write_args(file_path: &str, datetime: &str, offset: Option<&str>) -> Vec<String> {
+    let mut args = vec!["-overwrite_original".to_string()];
+    args.extend(date_assignments(file_path, datetime, offset));
+    args.push(file_path.to_string());
+    args
+}
+
+/// The date assignments of `date_write_args`, without options or the file
+pub(crate) fn date_assignments(file_path: &str, datetime: &str, offset: Option<&str>) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if is_quicktime_file(file_path) {
+        // With QuickTimeUTC exiftool converts a zoned time to UTC, and assumes
+        // the system zone for a naive one
+        let zoned = format!("{}{}", datetime, offset.unwrap_or(""));
+        args.push("-api".to_string());
+        args.push("QuickTimeUTC".to_string());
+        args.push(format!("-QuickTime:CreateDate={}", zoned));
+        args.push(format!("-DateTimeOriginal={}", zoned));
+        // Apple's CreationDate keeps the local time and offset
+        if offset.is_some() {
+            args.push(format!("-Keys:CreationDate={}", zoned));
+        }
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(format!("exiftool failed: {}", stderr))
+        args.push(format!("-DateTimeOriginal={}", datetime));
+        args.push(format!("-CreateDate={}", datetime));
+        if let Some(offset) = offset {
+            args.push(format!("-OffsetTimeOriginal={}", offset));
+            args.push(format!("-OffsetTimeDigitized={}", offset));
+        }
     }
+    args
 }
 
-/// Write keywords/tags to EXIF, avoiding duplicates
+/// GPS position of a file from the native readers (EXIF, XMP, container
+/// atoms), or else exiftool for formats they don't cover
+pub(crate) fn read_gps(pool: &ExiftoolPool, file_path: &str) -> Option<GpsCoordinates> {
+    let native = NativeBackend.read(file_path).ok().and_then(|m| m.gps);
+    if native.is_some() || !pool.is_installed() {
+        return native;
+    }
+
+    let output = pool.execute(&["-json", "-n", "-GPSLatitude", "-GPSLongitude", file_path]).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&output.stdout).ok()?;
+    let record = json.get(0)?;
+    Some(GpsCoordinates {
+        latitude: record.get("GPSLatitude")?.as_f64()?,
+        longitude: record.get("GPSLongitude")?.as_f64()?,
+        altitude: None,
+    })
+}
+
+/// Write keywords/tags to EXIF and XMP, avoiding duplicates
+///
+/// Keywords containing `|` are hierarchies (`People|Ammi`): they go to
+/// `HierarchicalSubject` and digiKam's `TagsList`, and each level is added
+/// as a flat keyword. With `dry_run` nothing is written and the planned changes are returned.
+/// `write_target` defaults to the one in settings.
+///
+/// With `include_companions` the file's Live Photo, motion photo or RAW+JPEG
+/// partners get the keywords too when they lack any of them. Off unless asked
+/// for; a dry run only plans the file itself. Async, since finding the
+/// companions reads the whole folder.
 #[tauri::command]
-pub fn write_exif_keywords(file_path: String, keywords: Vec<String>) -> Result<String, String> {
+#[allow(clippy::too_many_arguments)]
+pub async fn write_exif_keywords(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    keywords: Vec<String>,
+    operation_id: Option<String>,
+    dry_run: Option<bool>,
+    write_target: Option<WriteTarget>,
+    include_companions: Option<bool>,
+) -> Result<WriteOutcome, String> {
+    let target = write_target.unwrap_or_else(|| state.write_target());
+    let prepared = prepare_keyword_write(&state, &file_path, &keywords, target)?;
+    let operation_id = journal::operation_id_or("write-keywords", operation_id);
+    let dry_run = dry_run.unwrap_or(false);
+    let outcome = prepared.run(&state, &file_path, &operation_id, dry_run)?;
+    if dry_run || !include_companions.unwrap_or(false) {
+        return Ok(outcome);
+    }
+
+    let companions = companions::companions_of_files(&state, &[&file_path]).remove(&file_path).unwrap_or_default();
+    let mut tagged = 0;
+    for companion in companions.iter().filter(|c| c.kind != CompanionKind::Edit) {
+        let existing = xmp_sidecar::read_merged(select_backend(&state), &companion.file_path)
+            .map(|m| KeywordSet::from_metadata(&m))
+            .unwrap_or_default();
+        if keywords.iter().all(|k| existing.contains(k)) {
+            continue;
+        }
+        prepare_keyword_write(&state, &companion.file_path, &keywords, target)?
+            .run(&state, &companion.file_path, &operation_id, false)?;
+        tagged += 1;
+    }
+    Ok(match outcome {
+        WriteOutcome::Written(message) if tagged > 0 => {
+            WriteOutcome::Written(format!("{} (and {} companion files)", message, tagged))
+        }
+        outcome => outcome,
+    })
+}
+
+/// Decide what adding `keywords` to a file involves
+pub(crate) fn prepare_keyword_write(
+    state: &AppState,
+    file_path: &str,
+    keywords: &[String],
+    target: WriteTarget,
+) -> Result<PreparedWrite, String> {
     if keywords.is_empty() {
-        return Ok("No keywords to write".to_string());
-    }
-
-    // First, read existing keywords
-    let existing_output = Command::new("exiftool")
-        .args(["-Keywords", "-s", "-s", "-s", &file_path])
-        .output()
-        .map_err(|e| format!("Failed to read existing keywords: {}", e))?;
-
-    let existing_keywords: std::collections::HashSet<String> = if existing_output.status.success() {
-        String::from_utf8_lossy(&existing_output.stdout)
-            .trim()
-            .split(", ")
-            .filter(|s| !s.is_empty())
-            .map(|s| s.trim().to_string())
-            .collect()
-    } else {
-        std::collections::HashSet::new()
-    };
+        return Ok(PreparedWrite::Skip {
+            plan: FilePlan::skipped(file_path, "Keywords", None, "No keywords to write"),
+            message: "No keywords to write".to_string(),
+        });
+    }
 
-    // Merge with new keywords, avoiding duplicates
-    let mut all_keywords: std::collections::HashSet<String> = existing_keywords;
-    for kw in &keywords {
-        all_keywords.insert(kw.clone());
-    }
-
-    // Convert back to sorted vec for consistent output
-    let mut merged: Vec<String> = all_keywords.into_iter().collect();
-    merged.sort();
-
-    let keywords_str = merged.join(", ");
-    
-    let output = Command::new("exiftool")
-        .args([
-            "-overwrite_original",
-            "-P", // Preserve file modification date
-            &format!("-XPKeywords={}", keywords_str),
-            &format!("-Keywords={}", keywords_str),
-            &format!("-IPTC:Keywords={}", keywords_str),
-            &file_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run exiftool: {}", e))?;
+    let mut writes = Vec::new();
+    let mut written = KeywordSet::default();
 
-    if output.status.success() {
-        Ok(format!("Keywords written: {}", keywords_str))
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(format!("exiftool failed: {}", stderr))
+    if target.in_place() {
+        // First, read existing keywords as lists, so ones containing commas survive
+        let existing = read_metadata(&state.exiftool, file_path.to_string())
+            .map_err(|e| format!("Failed to read existing keywords: {}", e))?;
+        let merged = with_keywords(KeywordSet::from_metadata(&existing), keywords);
+
+        let mut args = vec![
+            "-overwrite_original".to_string(),
+            "-P".to_string(), // Preserve file modification date
+        ];
+        args.extend(merged.embedded_assignments());
+        args.push(file_path.to_string());
+        writes.push(args);
+        written = merged;
+    }
+
+    if target.sidecar() {
+        // A sidecar keyword list replaces the embedded one when read, so it
+        // starts from what the file currently shows
+        let existing = xmp_sidecar::read_merged(select_backend(state), file_path)
+            .map(|m| KeywordSet::from_metadata(&m))
+            .unwrap_or_default();
+        let merged = with_keywords(existing, keywords);
+        writes.push(xmp_sidecar::keyword_args(file_path, &merged));
+        if written.is_empty() {
+            written = merged;
+        }
     }
+
+    Ok(PreparedWrite::Write {
+        writes,
+        message: format!(
+            "Keywords written: {}{}",
+            written.display().join(", "),
+            target_note(target.in_place(), target.sidecar())
+        ),
+    })
+}
+
+/// `existing` with `keywords` added; hierarchical ones also add their levels
+fn with_keywords(mut existing: KeywordSet, keywords: &[String]) -> KeywordSet {
+    for keyword in keywords {
+        existing.add(keyword);
+    }
+    existing
 }
 
 /// Scan a directory recursively for media files
@@ -300,107 +691,337 @@ struct ScanProgress {
     count: usize,
 }
 
+/// How `scan_missing_dates` asks exiftool for metadata
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScanMode {
+    /// One pooled exiftool request per file
+    #[default]
+    PerFile,
+    /// A single `exiftool -json -r` over the whole directory, refreshing the index
+    Directory,
+    /// Walk the tree and hand exiftool argfiles of up to `batch_size` paths
+    Argfile { batch_size: usize },
+}
+
+/// Build the scan result for one file from whatever EXIF could be read
+fn build_file_info(
+    patterns: &PatternRegistry,
+    file_path: String,
+    metadata: Option<ExifMetadata>,
+    takeout: Option<TakeoutMetadata>,
+) -> FileMetadataInfo {
+    let filename = std::path::Path::new(&file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    let (has_date, camera_model) = match metadata {
+        Some(metadata) => (metadata.date_time_original.is_some(), camera_model_of(metadata)),
+        None => (false, None),
+    };
+
+    FileMetadataInfo {
+        file_path,
+        has_date,
+        // Prefer the Takeout capture time, then try the filename
+        extracted_date: takeout
+            .as_ref()
+            .and_then(|t| t.extracted_date())
+            .or_else(|| patterns.extract(&filename)),
+        camera_model,
+        takeout,
+        place: None,
+        companions: Vec::new(),
+    }
+}
+
 /// Scan a directory recursively for media files with progress and cancellation
+///
+/// With `geocode`, files with GPS also get the place they were taken (see `geocode`).
 #[tauri::command]
 pub async fn scan_missing_dates(
     app_handle: tauri::AppHandle,
-    state: tauri::State<'_, crate::state::AppState>,
+    state: tauri::State<'_, AppState>,
     path: String,
     operation_id: String,
+    mode: Option<ScanMode>,
+    geocode: Option<bool>,
 ) -> Result<Vec<FileMetadataInfo>, String> {
-    use walkdir::WalkDir;
-    use tauri::{Emitter, Manager};
-    use std::sync::atomic::Ordering;
+    use tauri::Emitter;
 
     let cancel_token = state.register_token(&operation_id);
-    
-    // First count total files for progress (optional but good UX)
-    // This might be slow on huge dirs, so maybe we just send "processed count" without total?
-    // Let's do a quick count first if possible, or just emit processed count.
-    
+
     let mut results = Vec::new();
-    let mut scanned_count = 0;
-    
-    let walker = WalkDir::new(&path).follow_links(true);
-    
-    // We can't easily count efficiently without walking twice.
-    // Let's walk once and emit progress.
-    
+    let mut on_file = |info: FileMetadataInfo| {
+        results.push(info);
+
+        // Emit progress every 10 files to avoid flooding events
+        if results.len() % 10 == 0 {
+            let _ = app_handle.emit("scan-progress", ScanProgress {
+                id: operation_id.clone(),
+                count: results.len(),
+            });
+        }
+    };
+
+    // Batched modes run exiftool directly; without it, read file by file natively
+    let mode = match mode.unwrap_or_default() {
+        ScanMode::PerFile => ScanMode::PerFile,
+        _ if !state.exiftool.is_installed() => ScanMode::PerFile,
+        batched => batched,
+    };
+
+    let scan = match mode {
+        ScanMode::PerFile => scan_per_file(&state, &path, &cancel_token, &mut on_file),
+        ScanMode::Directory => {
+            // exiftool filters by extension only, so sniffed files are missed here
+            let mut args = vec!["-r".to_string(), path.clone()];
+            let types = state.media_types();
+            args.extend(types.media_extensions().iter().flat_map(|ext| ["-ext".to_string(), ext.to_string()]));
+            scan_with_exiftool_json(&state, &operation_id, &cancel_token, args, &mut on_file)
+        }
+        ScanMode::Argfile { batch_size } => {
+            scan_with_argfiles(&state, &path, &operation_id, &cancel_token, batch_size, &mut on_file)
+        }
+    };
+
+    // Whatever was read is worth keeping, even from a cancelled scan
+    let flushed = state.metadata_index.flush();
+    let scan = scan.and(flushed).and_then(|_| match geocode {
+        Some(true) => geocode::add_places(&state, &mut results, &cancel_token),
+        _ => Ok(()),
+    });
+    if scan.is_ok() {
+        companions::add_companions(&state, &mut results);
+    }
+
+    state.remove_token(&operation_id);
+    scan.map(|_| results)
+}
+
+/// Walk `path` for the files `types` considers media, stopping early if the
+/// scan is cancelled
+pub(crate) fn walk_media_files(
+    types: &MediaTypeRegistry,
+    path: &str,
+    cancel_token: &AtomicBool,
+    mut on_path: impl FnMut(&std::path::Path) -> Result<(), String>,
+) -> Result<(), String> {
+    use walkdir::WalkDir;
+
+    let walker = WalkDir::new(path).follow_links(true);
+
     for entry in walker.into_iter().filter_map(|e| e.ok()) {
         // Check cancellation
         if cancel_token.load(Ordering::Relaxed) {
-             state.remove_token(&operation_id);
-             return Err("Operation cancelled".to_string());
+            return Err("Operation cancelled".to_string());
         }
 
         let file_path = entry.path();
-        
-        // Skip directories
-        if file_path.is_dir() {
+
+        // Skip directories and anything that isn't an image or video
+        if file_path.is_dir() || !types.is_media(file_path) {
             continue;
         }
 
-        // Check if it's an image or video
-        if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
-            let ext_lower = ext.to_lowercase();
-            if !matches!(ext_lower.as_str(), 
-                "jpg" | "jpeg" | "png" | "heic" | "heif" | "webp" | 
-                "mp4" | "mov" | "avi" | "mkv" | "m4v"
-            ) {
-                continue;
-            }
-        } else {
-            continue;
+        on_path(file_path)?;
+    }
+    Ok(())
+}
+
+/// Read each file that changed since it was indexed through the selected
+/// metadata backend
+pub(crate) fn scan_per_file(
+    state: &AppState,
+    path: &str,
+    cancel_token: &AtomicBool,
+    on_file: &mut impl FnMut(FileMetadataInfo),
+) -> Result<(), String> {
+    let backend = select_backend(state);
+    let patterns = state.date_patterns();
+    let mut sidecars = SidecarIndex::new();
+    walk_media_files(&state.media_types(), path, cancel_token, |file_path| {
+        let file_path_str = file_path.to_string_lossy().to_string();
+        let takeout = sidecars.lookup(file_path);
+        if let Some(entry) = state.metadata_index.lookup(&file_path_str) {
+            on_file(build_file_info(&patterns, file_path_str, entry.metadata, takeout));
+            return Ok(());
         }
 
+        // Fast path: container boxes already carry the capture date for most
+        // phone videos and HEICs, so skip exiftool when they do.
+        let container = crate::isobmff::is_isobmff(file_path)
+            .then(|| crate::isobmff::read_metadata(&file_path_str).ok())
+            .flatten()
+            .filter(|m| m.date_time_original.is_some());
+
+        let metadata = container.or_else(|| backend.read(&file_path_str).ok());
+        let info = build_file_info(&patterns, file_path_str.clone(), metadata.clone(), takeout);
+        state.metadata_index.record(&file_path_str, metadata, Some(info.clone()));
+        on_file(info);
+        Ok(())
+    })
+}
+
+/// Temp argfile for a scan. The operation id comes from the frontend, so
+/// anything but `[A-Za-z0-9_-]` is replaced to keep the file in the temp dir.
+fn argfile_path(operation_id: &str) -> PathBuf {
+    let name: String = operation_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    std::env::temp_dir().join(format!("tasaveer_scan_{}.args", name))
+}
+
+/// A path as one argfile line, or None when it can't be one. exiftool reads
+/// a line starting with `-` as an option and one starting with `#` as a
+/// comment, so relative paths get a `./` prefix; a newline would split the
+/// path into two arguments.
+fn argfile_line(file_path: &str) -> Option<String> {
+    if file_path.contains(['\n', '\r']) {
+        return None;
+    }
+    match Path::new(file_path).is_absolute() {
+        true => Some(file_path.to_string()),
+        false => Some(format!("./{}", file_path)),
+    }
+}
+
+/// Collect media paths and read them in argfile batches of up to `batch_size`
+fn scan_with_argfiles(
+    state: &AppState,
+    path: &str,
+    operation_id: &str,
+    cancel_token: &AtomicBool,
+    batch_size: usize,
+    on_file: &mut impl FnMut(FileMetadataInfo),
+) -> Result<(), String> {
+    // Indexed files are reported straight away; only changed ones go to exiftool
+    let patterns = state.date_patterns();
+    let mut sidecars = SidecarIndex::new();
+    let mut paths = Vec::new();
+    walk_media_files(&state.media_types(), path, cancel_token, |file_path| {
         let file_path_str = file_path.to_string_lossy().to_string();
-        let filename = file_path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-
-        // Try to read EXIF
-        let (has_date, camera_model) = match read_exif_metadata(file_path_str.clone()) {
-            Ok(metadata) => (
-                metadata.date_time_original.is_some(),
-                match (metadata.make, metadata.model) {
-                    (Some(make), Some(model)) => Some(format!("{} {}", make.trim(), model.trim())),
-                    (None, Some(model)) => Some(model),
-                    (Some(make), None) => Some(make),
-                    (None, None) => None,
-                }
-            ),
-            Err(_) => (false, None),
-        };
-
-        // Try to extract date from filename
-        let extracted_date = extract_date_from_filename(filename);
-
-        results.push(FileMetadataInfo {
-            file_path: file_path_str,
-            has_date,
-            extracted_date,
-            camera_model,
-        });
-        
-        scanned_count += 1;
-        
-        // Emit progress every 10 files to avoid flooding events
-        if scanned_count % 10 == 0 {
-            let _ = app_handle.emit("scan-progress", ScanProgress { 
-                id: operation_id.clone(), 
-                count: scanned_count 
-            });
+        match state.metadata_index.lookup(&file_path_str) {
+            Some(entry) => {
+                let takeout = sidecars.lookup(file_path);
+                on_file(build_file_info(&patterns, file_path_str, entry.metadata, takeout));
+            }
+            None => match argfile_line(&file_path_str) {
+                Some(line) => paths.push(line),
+                // Reported without metadata rather than handed to exiftool as two paths
+                None => on_file(build_file_info(&patterns, file_path_str, None, sidecars.lookup(file_path))),
+            },
+        }
+        Ok(())
+    })?;
+
+    let argfile = argfile_path(operation_id);
+    let argfile_str = argfile.to_string_lossy().to_string();
+
+    let mut result = Ok(());
+    for batch in paths.chunks(batch_size.max(1)) {
+        let mut contents = batch.join("\n");
+        contents.push('\n');
+        if let Err(e) = std::fs::write(&argfile, contents) {
+            result = Err(format!("Failed to write exiftool argfile: {}", e));
+            break;
+        }
+
+        let args = vec!["-@".to_string(), argfile_str.clone()];
+        result = scan_with_exiftool_json(state, operation_id, cancel_token, args, on_file);
+        if result.is_err() {
+            break;
         }
     }
 
-    state.remove_token(&operation_id);
-    Ok(results)
+    let _ = std::fs::remove_file(&argfile);
+    result
+}
+
+/// Run a one-off `exiftool -json` process and stream its records into `on_file`.
+///
+/// The process is registered under `operation_id` so `cancel_operation` can kill it.
+fn scan_with_exiftool_json(
+    state: &AppState,
+    operation_id: &str,
+    cancel_token: &AtomicBool,
+    extra_args: Vec<String>,
+    on_file: &mut impl FnMut(FileMetadataInfo),
+) -> Result<(), String> {
+    use std::io::{BufReader, Read};
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("exiftool")
+        .args(["-json", "-q"])
+        .args(METADATA_TAGS)
+        .args(&extra_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run exiftool: {}", e))?;
+
+    state.running_processes.lock().unwrap().insert(operation_id.to_string(), child.id());
+
+    // Drain stderr in the background so exiftool never blocks on it
+    let stderr_reader = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut buf = String::new();
+            let _ = stderr.read_to_string(&mut buf);
+            buf
+        })
+    });
+
+    let patterns = state.date_patterns();
+    let mut sidecars = SidecarIndex::new();
+    let stdout = child.stdout.take().ok_or("Failed to open exiftool stdout")?;
+    let streamed = crate::exiftool::stream_json_array(BufReader::new(stdout), |record| {
+        if cancel_token.load(Ordering::Relaxed) {
+            return Err("Operation cancelled".to_string());
+        }
+        if let Some(file_path) = record.get("SourceFile").and_then(|v| v.as_str()) {
+            let metadata = metadata_from_value(file_path.to_string(), &record);
+            let takeout = sidecars.lookup(std::path::Path::new(file_path));
+            let info = build_file_info(&patterns, file_path.to_string(), Some(metadata.clone()), takeout);
+            state.metadata_index.record(file_path, Some(metadata), Some(info.clone()));
+            on_file(info);
+        }
+        Ok(())
+    });
+
+    if streamed.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait();
+    state.running_processes.lock().unwrap().remove(operation_id);
+    let stderr = stderr_reader.and_then(|h| h.join().ok()).unwrap_or_default();
+
+    if cancel_token.load(Ordering::Relaxed) {
+        return Err("Operation cancelled".to_string());
+    }
+    let count = streamed?;
+
+    // exiftool exits non-zero if any file had an error; that only matters
+    // when nothing at all could be read.
+    match status {
+        Ok(status) if !status.success() && count == 0 && !stderr.trim().is_empty() => {
+            Err(format!("exiftool failed: {}", stderr))
+        }
+        _ => Ok(()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Filename date extraction with the built-in patterns only
+    fn extract_date_from_filename(filename: &str) -> Option<ExtractedDate> {
+        PatternRegistry::builtin().extract(filename)
+    }
+
     #[test]
     fn test_extract_date_whatsapp_android() {
         let result = extract_date_from_filename("IMG-20240115-WA0042.jpg");
@@ -464,6 +1085,166 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_parse_exif_json_merges_keywords() {
+        let json = r#"[{
+            "SourceFile": "/a/img.jpg",
+            "DateTimeOriginal": "2024:01:15 10:30:45",
+            "Make": "Apple",
+            "Model": "iPhone 12",
+            "Keywords": ["Family", "Trip"],
+            "XPKeywords": "Trip; Beach"
+        }]"#;
+        let metadata = parse_exif_json("/a/img.jpg".to_string(), json).unwrap();
+        assert_eq!(metadata.date_time_original, Some("2024:01:15 10:30:45".to_string()));
+        assert_eq!(metadata.keywords, vec!["Family", "Trip", "Beach"]);
+        assert_eq!(camera_model_of(metadata), Some("Apple iPhone 12".to_string()));
+    }
+
+    #[test]
+    fn test_parse_exif_json_hierarchical_keywords() {
+        let json = r#"[{
+            "SourceFile": "/a/img.jpg",
+            "Keywords": "Lahore, Pakistan",
+            "Subject": ["Lahore, Pakistan", "People", "Ammi", 2024],
+            "HierarchicalSubject": "People|Ammi",
+            "TagsList": ["People/Ammi", "Source/Dad's Phone"]
+        }]"#;
+        let metadata = parse_exif_json("/a/img.jpg".to_string(), json).unwrap();
+        assert_eq!(metadata.keywords, vec!["Lahore, Pakistan", "People", "Ammi", "2024"]);
+        assert_eq!(metadata.hierarchical_keywords, vec!["People|Ammi", "Source|Dad's Phone"]);
+    }
+
+    #[test]
+    fn test_parse_exif_json_typed_fields() {
+        let json = r#"[{
+            "SourceFile": "/a/clip.mov",
+            "GPSLatitude": 48.8584,
+            "GPSLongitude": "2.2945",
+            "GPSAltitude": 35.5,
+            "ImageWidth": 1920,
+            "ImageHeight": "1080",
+            "Orientation": 6,
+            "Duration": 12.345,
+            "CompressorID": "hvc1",
+            "ISO": 125,
+            "ExposureTime": 0.008,
+            "FNumber": 1.8,
+            "MIMEType": "video/quicktime",
+            "SubSecTimeOriginal": "045",
+            "ContentIdentifier": "7A1C1F30-0D5B-4F6E-9E8A-2B3C4D5E6F70",
+            "Rating": 5,
+            "ImageDescription": "  "
+        }]"#;
+        let metadata = parse_exif_json("/a/clip.mov".to_string(), json).unwrap();
+        assert_eq!(
+            metadata.gps,
+            Some(GpsCoordinates { latitude: 48.8584, longitude: 2.2945, altitude: Some(35.5) })
+        );
+        assert_eq!((metadata.image_width, metadata.image_height), (Some(1920), Some(1080)));
+        assert_eq!(metadata.orientation, Some(6));
+        assert_eq!(metadata.duration, Some(12.345));
+        assert_eq!(metadata.video_codec, Some("hvc1".to_string()));
+        assert_eq!(metadata.iso, Some(125));
+        assert_eq!(metadata.f_number, Some(1.8));
+        assert_eq!(metadata.mime_type, Some("video/quicktime".to_string()));
+        assert_eq!(metadata.sub_sec_time_original, Some("045".to_string()));
+        assert!(metadata.content_identifier.is_some());
+        assert_eq!(metadata.rating, Some(5));
+        assert_eq!(metadata.description, None);
+    }
+
+    #[test]
+    fn test_parse_exif_json_empty_is_error() {
+        assert!(parse_exif_json("/a/img.jpg".to_string(), "[]").is_err());
+    }
+
+    #[test]
+    fn test_native_backend_reads_jpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.jpg");
+        let tiff = crate::native_exif::tests::sample_tiff("Apple", "iPhone 12", "2024:01:15 10:30:45");
+        std::fs::write(&path, crate::native_exif::tests::sample_jpeg(&tiff)).unwrap();
+
+        let metadata = NativeBackend.read(&path.to_string_lossy()).unwrap();
+        assert_eq!(camera_model_of(metadata), Some("Apple iPhone 12".to_string()));
+    }
+
+    #[test]
+    fn test_read_gps_without_exiftool() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.jpg");
+        let tiff = crate::native_exif::tests::sample_gps_tiff();
+        std::fs::write(&path, crate::native_exif::tests::sample_jpeg(&tiff)).unwrap();
+
+        let pool = ExiftoolPool::with_program("exiftool-does-not-exist", 1);
+        let gps = read_gps(&pool, &path.to_string_lossy()).unwrap();
+        assert!((gps.latitude - -33.8586).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_build_file_info_without_metadata() {
+        let info = build_file_info(PatternRegistry::builtin(), "/a/IMG-20240115-WA0042.jpg".to_string(), None, None);
+        assert!(!info.has_date);
+        assert!(info.camera_model.is_none());
+        assert_eq!(info.extracted_date.unwrap().date, "2024-01-15");
+    }
+
+    #[test]
+    fn test_date_write_args_image_offset() {
+        let args = date_write_args("/a/IMG_1.jpg", "2024:01:15 10:30:00", Some("+05:00"));
+        assert!(args.contains(&"-DateTimeOriginal=2024:01:15 10:30:00".to_string()));
+        assert!(args.contains(&"-OffsetTimeOriginal=+05:00".to_string()));
+        assert_eq!(args.last().unwrap(), "/a/IMG_1.jpg");
+
+        let args = date_write_args("/a/IMG_1.jpg", "2024:01:15 10:30:00", None);
+        assert!(!args.iter().any(|a| a.starts_with("-OffsetTime")));
+    }
+
+    #[test]
+    fn test_date_write_args_quicktime_utc() {
+        let args = date_write_args("/a/VID_1.MOV", "2024:01:15 10:30:00", Some("-03:00"));
+        assert!(args.windows(2).any(|w| w == ["-api", "QuickTimeUTC"]));
+        assert!(args.contains(&"-QuickTime:CreateDate=2024:01:15 10:30:00-03:00".to_string()));
+        assert!(args.contains(&"-Keys:CreationDate=2024:01:15 10:30:00-03:00".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("-OffsetTime")));
+    }
+
+    #[test]
+    fn test_argfile_line() {
+        assert_eq!(argfile_line("/a/-n.jpg").as_deref(), Some("/a/-n.jpg"));
+        assert_eq!(argfile_line("-n.jpg").as_deref(), Some("./-n.jpg"));
+        assert_eq!(argfile_line("#1.jpg").as_deref(), Some("./#1.jpg"));
+        assert_eq!(argfile_line("/a/b\n-delete.jpg"), None);
+        assert_eq!(argfile_line("/a/b\r.jpg"), None);
+    }
+
+    #[test]
+    fn test_argfile_path_stays_in_temp_dir() {
+        let argfile = argfile_path("../../etc/scan 1");
+        assert_eq!(argfile.parent(), Some(std::env::temp_dir().as_path()));
+        assert_eq!(argfile.file_name().unwrap(), "tasaveer_scan_______etc_scan_1.args");
+    }
+
+    #[test]
+    fn test_is_media_file() {
+        let types = MediaTypeRegistry::builtin();
+        assert!(types.is_media(std::path::Path::new("/a/photo.JPG")));
+        assert!(types.is_media(std::path::Path::new("/a/clip.mov")));
+        assert!(types.is_media(std::path::Path::new("/a/IMG_0001.NEF")));
+        assert!(!types.is_media(std::path::Path::new("/a/photo.JPG.xmp")));
+        assert!(!types.is_media(std::path::Path::new("/a/notes.txt")));
+        assert!(!types.is_media(std::path::Path::new("/a/no_extension")));
+    }
+
+    #[test]
+    fn test_scan_mode_deserialize() {
+        let mode: ScanMode = serde_json::from_str(r#"{"kind": "argfile", "batch_size": 500}"#).unwrap();
+        assert!(matches!(mode, ScanMode::Argfile { batch_size: 500 }));
+        let mode: ScanMode = serde_json::from_str(r#"{"kind": "directory"}"#).unwrap();
+        assert!(matches!(mode, ScanMode::Directory));
+    }
+
     #[test]
     fn test_extract_date_generic_pattern() {
         let result = extract_date_from_filename("photo_2024-03-20_something.jpg");
@@ -0,0 +1,467 @@
+//! Persistent index of metadata reads, so rescans only read changed files.
+//!
+//! This module provides functions to:
+//! - Look up the `ExifMetadata` and scan result last read for a file
+//! - Record new reads in a JSON Lines file in the app data dir
+//! - Invalidate files or whole folders, compact the file and rebuild a folder
+//!
+//! Entries are keyed by canonical path and only returned while the file's
+//! size, modification time, change time and inode still match, so any write
+//! makes the next scan read the file again, including exiftool writes with
+//! `-P` that keep the modification time. Writes through the journal also
+//! forget the file right away. Later lines supersede
+//! earlier ones, and removals are written as tombstones until the next
+//! compaction. Only embedded metadata is indexed; XMP sidecars are always
+//! read fresh.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::journal;
+use crate::metadata::{self, ExifMetadata, FileMetadataInfo};
+use crate::state::AppState;
+
+/// File name of the index inside the app data dir
+pub const INDEX_FILE: &str = "metadata_index.jsonl";
+
+/// Raised whenever `ExifMetadata` gains fields, so entries without them are reread
+const INDEX_VERSION: u32 = 2;
+
+/// Recorded entries are appended to disk in batches of this many
+const FLUSH_EVERY: usize = 256;
+
+/// What was read from one file, and the file's state at the time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Canonical path
+    pub file_path: String,
+    pub file_size: u64,
+    /// Modification time, ms since epoch
+    pub file_modified: Option<i64>,
+    /// Status change time, ms since epoch (Unix only)
+    #[serde(default)]
+    pub file_changed: Option<i64>,
+    #[serde(default)]
+    pub file_inode: Option<u64>,
+    /// None when the file could not be read
+    pub metadata: Option<ExifMetadata>,
+    /// Result of the scan that read the file, if a scan did
+    pub info: Option<FileMetadataInfo>,
+    #[serde(default)]
+    version: u32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    removed: bool,
+}
+
+impl IndexEntry {
+    fn tombstone(file_path: String) -> Self {
+        IndexEntry {
+            file_path,
+            file_size: 0,
+            file_modified: None,
+            file_changed: None,
+            file_inode: None,
+            metadata: None,
+            info: None,
+            version: INDEX_VERSION,
+            removed: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub entries: usize,
+    /// Lines in the index file, including superseded ones
+    pub lines: usize,
+    pub file_bytes: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    loaded: bool,
+    entries: HashMap<String, IndexEntry>,
+    lines: usize,
+    pending: Vec<IndexEntry>,
+}
+
+impl Inner {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        let Some(contents) = self.path.as_ref().and_then(|p| std::fs::read_to_string(p).ok()) else {
+            return;
+        };
+        // Lines that fail to parse (an interrupted append) are skipped
+        for line in contents.lines() {
+            self.lines += 1;
+            if let Ok(entry) = serde_json::from_str::<IndexEntry>(line) {
+                self.apply(entry);
+            }
+        }
+    }
+
+    fn apply(&mut self, entry: IndexEntry) {
+        if entry.removed {
+            self.entries.remove(&entry.file_path);
+        } else {
+            self.entries.insert(entry.file_path.clone(), entry);
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let pending = std::mem::take(&mut self.pending);
+        let Some(path) = &self.path else { return Ok(()) };
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in &pending {
+            let line = serde_json::to_string(entry).map_err(|e| format!("Failed to encode index entry: {}", e))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open metadata index {}: {}", path.display(), e))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|e| format!("Failed to write metadata index: {}", e))?;
+        self.lines += pending.len();
+        Ok(())
+    }
+
+    fn push(&mut self, entry: IndexEntry) {
+        self.pending.push(entry.clone());
+        self.apply(entry);
+        if self.pending.len() >= FLUSH_EVERY {
+            // A failed append only costs a reread next time
+            let _ = self.flush();
+        }
+    }
+}
+
+/// Metadata of every file read so far, loaded from disk on first use
+#[derive(Default)]
+pub struct MetadataIndex {
+    inner: Mutex<Inner>,
+}
+
+/// Change time (ms since epoch) and inode of a file, where the platform has them
+#[cfg(unix)]
+fn change_identity(file_path: &str) -> (Option<i64>, Option<u64>) {
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(file_path) {
+        Ok(meta) => (Some(meta.ctime() * 1000 + meta.ctime_nsec() / 1_000_000), Some(meta.ino())),
+        Err(_) => (None, None),
+    }
+}
+
+#[cfg(not(unix))]
+fn change_identity(_file_path: &str) -> (Option<i64>, Option<u64>) {
+    (None, None)
+}
+
+/// Whether `entry` still describes the file at `file_path` as it is now
+fn is_current(entry: &IndexEntry, file_path: &str) -> bool {
+    let (size, modified) = journal::fingerprint(file_path);
+    modified.is_some()
+        && entry.file_size == size
+        && entry.file_modified == modified
+        && (entry.file_changed, entry.file_inode) == change_identity(file_path)
+        && entry.version == INDEX_VERSION
+}
+
+/// The key of a file: its canonical path, or the path as given if it has none
+fn index_key(file_path: &str) -> String {
+    std::fs::canonicalize(file_path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| file_path.to_string())
+}
+
+impl MetadataIndex {
+    /// Persist to `path`. Until this is called the index only lives in memory.
+    pub fn set_path(&self, path: PathBuf) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner = Inner { path: Some(path), ..Default::default() };
+    }
+
+    /// The entry for a file, if it hasn't changed since it was read. Paths in
+    /// the returned metadata are the one asked for.
+    pub fn lookup(&self, file_path: &str) -> Option<IndexEntry> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        let entry = inner.entries.get(&index_key(file_path))?;
+        if !is_current(entry, file_path) {
+            return None;
+        }
+
+        let mut entry = entry.clone();
+        if let Some(metadata) = &mut entry.metadata {
+            metadata.file_path = file_path.to_string();
+        }
+        if let Some(info) = &mut entry.info {
+            info.file_path = file_path.to_string();
+        }
+        Some(entry)
+    }
+
+    /// Remember what was read from a file in its current state
+    pub fn record(&self, file_path: &str, metadata: Option<ExifMetadata>, info: Option<FileMetadataInfo>) {
+        let (file_size, file_modified) = journal::fingerprint(file_path);
+        let (file_changed, file_inode) = change_identity(file_path);
+        let entry = IndexEntry {
+            file_path: index_key(file_path),
+            file_size,
+            file_modified,
+            file_changed,
+            file_inode,
+            metadata,
+            info,
+            version: INDEX_VERSION,
+            removed: false,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        inner.push(entry);
+    }
+
+    /// Write recorded entries to disk
+    pub fn flush(&self) -> Result<(), String> {
+        self.inner.lock().unwrap().flush()
+    }
+
+    /// Forget one file that was just written. The tombstone is appended with
+    /// the next flush; until then the changed change time keeps it from matching.
+    pub fn forget(&self, file_path: &str) {
+        let key = index_key(file_path);
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        if inner.entries.contains_key(&key) {
+            inner.push(IndexEntry::tombstone(key));
+        }
+    }
+
+    /// Forget the given files, and every file under the given folders
+    pub fn invalidate(&self, paths: &[String]) -> Result<usize, String> {
+        let prefixes: Vec<String> = paths.iter().map(|p| index_key(p)).collect();
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        let removed: Vec<String> = inner
+            .entries
+            .keys()
+            .filter(|key| prefixes.iter().any(|prefix| Path::new(key).starts_with(prefix)))
+            .cloned()
+            .collect();
+        for key in &removed {
+            inner.push(IndexEntry::tombstone(key.clone()));
+        }
+        inner.flush()?;
+        Ok(removed.len())
+    }
+
+    /// Drop entries of files that are gone or changed, and rewrite the index
+    /// file with one line per remaining entry
+    pub fn compact(&self) -> Result<IndexStats, String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        inner.pending.clear();
+        inner.entries.retain(|key, entry| is_current(entry, key));
+
+        if let Some(path) = inner.path.clone() {
+            let mut lines = String::new();
+            for entry in inner.entries.values() {
+                let line = serde_json::to_string(entry).map_err(|e| format!("Failed to encode index entry: {}", e))?;
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+            // Replace the file in one step so a crash never leaves half an index
+            let temp = path.with_extension("jsonl.tmp");
+            std::fs::write(&temp, lines).map_err(|e| format!("Failed to write metadata index: {}", e))?;
+            std::fs::rename(&temp, &path).map_err(|e| format!("Failed to replace metadata index: {}", e))?;
+        }
+        inner.lines = inner.entries.len();
+        drop(inner);
+        Ok(self.stats())
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        IndexStats {
+            entries: inner.entries.len(),
+            lines: inner.lines + inner.pending.len(),
+            file_bytes: inner.path.as_ref().and_then(|p| std::fs::metadata(p).ok()).map_or(0, |m| m.len()),
+        }
+    }
+}
+
+/// Metadata of a file from the index, reading and recording it if it changed
+pub(crate) fn read_cached(state: &AppState, file_path: &str) -> Option<ExifMetadata> {
+    if let Some(entry) = state.metadata_index.lookup(file_path) {
+        return entry.metadata;
+    }
+    let metadata = metadata::select_backend(state).read(file_path).ok();
+    state.metadata_index.record(file_path, metadata.clone(), None);
+    metadata
+}
+
+#[derive(Clone, Serialize)]
+struct IndexProgress {
+    id: String,
+    count: usize,
+}
+
+#[tauri::command]
+pub fn get_metadata_index_stats(state: tauri::State<'_, AppState>) -> IndexStats {
+    state.metadata_index.stats()
+}
+
+/// Forget files, or every file under folders, so the next scan rereads them
+#[tauri::command]
+pub fn invalidate_metadata_index(state: tauri::State<'_, AppState>, paths: Vec<String>) -> Result<usize, String> {
+    state.metadata_index.invalidate(&paths)
+}
+
+/// Drop entries of files that were deleted or changed, and shrink the index file
+#[tauri::command]
+pub fn compact_metadata_index(state: tauri::State<'_, AppState>) -> Result<IndexStats, String> {
+    state.metadata_index.compact()
+}
+
+/// Forget everything under `path` and read it all again
+#[tauri::command]
+pub async fn rebuild_metadata_index(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+    operation_id: String,
+) -> Result<IndexStats, String> {
+    use tauri::Emitter;
+
+    state.metadata_index.invalidate(std::slice::from_ref(&path))?;
+    let cancel_token = state.register_token(&operation_id);
+    let mut count = 0;
+    let scan = metadata::scan_per_file(&state, &path, &cancel_token, &mut |_| {
+        count += 1;
+        if count % 10 == 0 {
+            let _ = app_handle.emit("index-progress", IndexProgress {
+                id: operation_id.clone(),
+                count,
+            });
+        }
+    });
+    state.remove_token(&operation_id);
+
+    let flushed = state.metadata_index.flush();
+    scan.and(flushed).map(|_| state.metadata_index.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    fn sample(file_path: &str) -> ExifMetadata {
+        ExifMetadata {
+            file_path: file_path.to_string(),
+            date_time_original: Some("2023:05:14 10:11:12".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_only_while_file_unchanged() {
+        let dir = tempdir().unwrap();
+        let photo = dir.path().join("a.jpg");
+        std::fs::write(&photo, b"one").unwrap();
+        let photo = photo.to_string_lossy().to_string();
+
+        let index = MetadataIndex::default();
+        assert!(index.lookup(&photo).is_none());
+        index.record(&photo, Some(sample(&photo)), None);
+        let entry = index.lookup(&photo).unwrap();
+        assert_eq!(entry.metadata.unwrap().date_time_original, Some("2023:05:14 10:11:12".to_string()));
+
+        std::fs::write(&photo, b"changed").unwrap();
+        assert!(index.lookup(&photo).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_keeping_size_and_mtime_is_noticed() {
+        let dir = tempdir().unwrap();
+        let photo = dir.path().join("a.jpg");
+        std::fs::write(&photo, b"one").unwrap();
+        let modified = std::fs::metadata(&photo).unwrap().modified().unwrap();
+        let photo_path = photo.to_string_lossy().to_string();
+
+        let index = MetadataIndex::default();
+        index.record(&photo_path, Some(sample(&photo_path)), None);
+        assert!(index.lookup(&photo_path).is_some());
+
+        // Like exiftool -P: same size, modification time put back
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&photo, b"two").unwrap();
+        File::options().write(true).open(&photo).unwrap().set_modified(modified).unwrap();
+        assert_eq!(std::fs::metadata(&photo).unwrap().modified().unwrap(), modified);
+        assert!(index.lookup(&photo_path).is_none());
+
+        index.record(&photo_path, Some(sample(&photo_path)), None);
+        index.forget(&photo_path);
+        assert!(index.lookup(&photo_path).is_none());
+    }
+
+    #[test]
+    fn test_persist_invalidate_and_compact() {
+        let dir = tempdir().unwrap();
+        let index_path = dir.path().join(INDEX_FILE);
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        let paths: Vec<String> = ["a.jpg", "b.jpg", "c.jpg"]
+            .iter()
+            .map(|name| {
+                let path = photos.join(name);
+                File::create(&path).unwrap().write_all(name.as_bytes()).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let index = MetadataIndex::default();
+        index.set_path(index_path.clone());
+        for path in &paths {
+            index.record(path, Some(sample(path)), None);
+        }
+        index.record(&paths[0], None, None);
+        index.flush().unwrap();
+
+        // A fresh index reads the file back, later lines winning
+        let reloaded = MetadataIndex::default();
+        reloaded.set_path(index_path.clone());
+        assert_eq!(reloaded.stats().entries, 3);
+        assert_eq!(reloaded.stats().lines, 4);
+        assert!(reloaded.lookup(&paths[0]).unwrap().metadata.is_none());
+
+        assert_eq!(reloaded.invalidate(std::slice::from_ref(&paths[1])).unwrap(), 1);
+        assert!(reloaded.lookup(&paths[1]).is_none());
+
+        std::fs::remove_file(&paths[2]).unwrap();
+        let stats = reloaded.compact().unwrap();
+        assert_eq!((stats.entries, stats.lines), (1, 1));
+
+        let compacted = MetadataIndex::default();
+        compacted.set_path(index_path);
+        assert!(compacted.lookup(&paths[0]).is_some());
+        assert_eq!(compacted.invalidate(&[photos.to_string_lossy().to_string()]).unwrap(), 1);
+        assert_eq!(compacted.stats().entries, 0);
+    }
+}
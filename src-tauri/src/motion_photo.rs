@@ -0,0 +1,801 @@
+//! Videos embedded at the end of Google and Samsung motion photos.
+//!
+//! This module provides functions to:
+//! - Find the MP4 trailer of a JPEG from Google's `GCamera:MicroVideoOffset`
+//!   (MVIMG), `GCamera:MotionPhoto` container directory, or Samsung's
+//!   `MotionPhoto_Data` record
+//! - Extract the video to a sibling `.mp4` with the photo's capture date
+//! - Strip the video from the photo to save space
+//!
+//! Offsets from XMP are only trusted when an ISO-BMFF `ftyp` box starts
+//! there, so a stale tag left by an editor never cuts a photo short.
+//!
+//! Scanning reads only the XMP at the start of each JPEG and the SEF
+//! directory in its last `SEF_TAIL_LEN` bytes, seeking to the few bytes that
+//! confirm a video; extracting copies just the video's range. Only stripping
+//! loads the whole photo, since it rewrites it.
+//!
+//! Stripping can't be journaled like a tag write, so the original is moved to
+//! the Trash before the stripped copy takes its place. From a Samsung photo
+//! only the video's SEF record is removed; the other records stay and the SEF
+//! directory is rebuilt to match. From a Google container only the
+//! `MotionPhoto` item is removed, so an Ultra HDR gain map stays listed.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::date_check;
+use crate::journal;
+use crate::media_types::MediaCategory;
+use crate::metadata;
+use crate::native_exif;
+use crate::state::AppState;
+use crate::timezone;
+
+/// Name of the Samsung SEF record holding the video
+const SAMSUNG_MARKER: &[u8] = b"MotionPhoto_Data";
+
+/// Header of a Samsung SEF record, before its name length and name
+const SEF_RECORD_HEADER: &[u8] = &[0x00, 0x00, 0x30, 0x0A];
+
+/// Length of the `SEFH` directory header (magic, version, count) and of each entry
+const SEF_HEADER_LEN: usize = 12;
+const SEF_ENTRY_LEN: usize = 12;
+
+/// Bytes read from the end of a photo to find the SEF directory
+const SEF_TAIL_LEN: usize = 64 * 1024;
+
+/// Fields of a Google container item kept when the directory is rewritten
+const CONTAINER_ITEM_FIELDS: &[&str] = &["Semantic", "Mime", "Length", "Padding", "Label"];
+
+/// XMP tags that describe the embedded video, removed along with it
+const GOOGLE_XMP_TAGS: &[&str] = &[
+    "XMP-GCamera:MicroVideo",
+    "XMP-GCamera:MicroVideoVersion",
+    "XMP-GCamera:MicroVideoOffset",
+    "XMP-GCamera:MicroVideoPresentationTimestampUs",
+    "XMP-GCamera:MotionPhoto",
+    "XMP-GCamera:MotionPhotoVersion",
+    "XMP-GCamera:MotionPhotoPresentationTimestampUs",
+];
+
+/// How the video is embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionPhotoFormat {
+    /// Google MVIMG: `GCamera:MicroVideoOffset` counted from the end of the file
+    MicroVideo,
+    /// Google Motion Photo: `Container:Directory` item with the video's length
+    MotionPhoto,
+    /// Samsung `MotionPhoto_Data` SEF record
+    Samsung,
+}
+
+/// What to do with each motion photo found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionPhotoAction {
+    /// Only list them
+    #[default]
+    Report,
+    /// Write the video next to the photo
+    Extract,
+    /// Cut the video off the photo
+    Strip,
+}
+
+/// Where the video sits in a motion photo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedVideo {
+    pub format: MotionPhotoFormat,
+    pub video_offset: usize,
+    pub video_length: usize,
+    /// Where the cut starts: the video, or for Samsung its whole SEF record
+    pub photo_length: usize,
+}
+
+/// One entry of a Samsung SEF directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SefEntry {
+    /// Record type, as stored
+    kind: [u8; 4],
+    /// Start of the record, counted back from the `SEFH` directory
+    distance: usize,
+    size: usize,
+}
+
+/// The `SEFH` directory at the end of a Samsung file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SefDirectory {
+    start: usize,
+    version: [u8; 4],
+    entries: Vec<SefEntry>,
+}
+
+impl SefDirectory {
+    fn record_start(&self, entry: &SefEntry) -> Option<usize> {
+        self.start.checked_sub(entry.distance)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = b"SEFH".to_vec();
+        out.extend_from_slice(&self.version);
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.kind);
+            out.extend_from_slice(&(entry.distance as u32).to_le_bytes());
+            out.extend_from_slice(&(entry.size as u32).to_le_bytes());
+        }
+        let len = out.len() as u32;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(b"SEFT");
+        out
+    }
+}
+
+/// A motion photo and what was done with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotionPhotoFile {
+    pub file_path: String,
+    pub format: MotionPhotoFormat,
+    pub video_bytes: u64,
+    /// The extracted video, when it was extracted
+    pub video_path: Option<String>,
+    pub dates_copied: bool,
+    pub stripped: bool,
+    pub error: Option<String>,
+}
+
+fn container_item_regex() -> &'static Regex {
+    static ITEM: OnceLock<Regex> = OnceLock::new();
+    ITEM.get_or_init(|| Regex::new(r"(?s)<Container:Item\b([^>]*)>").unwrap())
+}
+
+/// Random access to a photo's bytes, in memory or in the file
+trait ByteSource {
+    fn len(&self) -> usize;
+    /// `len` bytes at `offset`, None past the end
+    fn read_at(&mut self, offset: usize, len: usize) -> Option<Vec<u8>>;
+}
+
+impl ByteSource for &[u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn read_at(&mut self, offset: usize, len: usize) -> Option<Vec<u8>> {
+        self.get(offset..offset.checked_add(len)?).map(<[u8]>::to_vec)
+    }
+}
+
+/// An open photo, read only where a video could start
+struct PhotoFile {
+    file: fs::File,
+    len: usize,
+}
+
+impl ByteSource for PhotoFile {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn read_at(&mut self, offset: usize, len: usize) -> Option<Vec<u8>> {
+        if offset.checked_add(len)? > self.len {
+            return None;
+        }
+        self.file.seek(SeekFrom::Start(offset as u64)).ok()?;
+        let mut buf = vec![0; len];
+        self.file.read_exact(&mut buf).ok()?;
+        Some(buf)
+    }
+}
+
+fn is_mp4_at(source: &mut impl ByteSource, offset: usize) -> bool {
+    offset.checked_add(4).and_then(|start| source.read_at(start, 4)).as_deref() == Some(b"ftyp")
+}
+
+/// Length of the `MotionPhoto` item in a Google container directory
+fn container_video_length(xmp: &str) -> Option<usize> {
+    container_item_regex().captures_iter(xmp).find_map(|item| {
+        let attributes = &item[1];
+        if native_exif::xmp_property(attributes, "Item:Semantic")? != "MotionPhoto" {
+            return None;
+        }
+        native_exif::xmp_property(attributes, "Item:Length")?.trim().parse().ok()
+    })
+}
+
+fn le_u32(data: &[u8], pos: usize) -> Option<usize> {
+    let bytes = data.get(pos..pos.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+/// The Samsung SEF directory, found from the `SEFT` footer's directory size
+fn sef_directory(data: &[u8]) -> Option<SefDirectory> {
+    let footer = data.len().checked_sub(8)?;
+    if &data[footer + 4..] != b"SEFT" {
+        return None;
+    }
+    let start = footer.checked_sub(le_u32(data, footer)?)?;
+    if data.get(start..start + 4) != Some(b"SEFH") {
+        return None;
+    }
+    let count = le_u32(data, start + 8)?;
+    if count > (footer - start) / SEF_ENTRY_LEN {
+        return None;
+    }
+    let entries = (0..count)
+        .map(|i| {
+            let pos = start + SEF_HEADER_LEN + i * SEF_ENTRY_LEN;
+            Some(SefEntry {
+                kind: data.get(pos..pos + 4)?.try_into().ok()?,
+                distance: le_u32(data, pos + 4)?,
+                size: le_u32(data, pos + 8)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(SefDirectory {
+        start,
+        version: data[start + 4..start + 8].try_into().ok()?,
+        entries,
+    })
+}
+
+/// A Samsung file without the SEF record starting at `record`, the other
+/// records moved up and the directory rebuilt. None when the directory doesn't
+/// list that record.
+fn remove_sef_record(data: &[u8], directory: &SefDirectory, record: usize) -> Option<Vec<u8>> {
+    let index = directory.entries.iter().position(|e| directory.record_start(e) == Some(record))?;
+    let removed = directory.entries[index];
+    let end = record.checked_add(removed.size).filter(|&end| end <= directory.start)?;
+
+    let mut rebuilt = directory.clone();
+    rebuilt.entries.remove(index);
+    if rebuilt.entries.is_empty() {
+        return Some(data[..record].to_vec());
+    }
+    rebuilt.start -= removed.size;
+    // Records before the removed one are now that much closer to the directory
+    for entry in &mut rebuilt.entries {
+        if directory.record_start(entry).is_some_and(|start| start < record) {
+            entry.distance -= removed.size;
+        }
+    }
+    Some([&data[..record], &data[end..directory.start], &rebuilt.encode()].concat())
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The embedded video of a JPEG, if it has one. `xmp` is the photo's XMP packet.
+pub fn locate_video(data: &[u8], xmp: Option<&str>) -> Option<EmbeddedVideo> {
+    let mut data = data;
+    locate(&mut data, xmp)
+}
+
+fn locate(source: &mut impl ByteSource, xmp: Option<&str>) -> Option<EmbeddedVideo> {
+    if source.read_at(0, 2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let len = source.len();
+    let mut from_end = |format, length: usize| {
+        let offset = len.checked_sub(length).filter(|_| length > 0)?;
+        is_mp4_at(source, offset).then_some(EmbeddedVideo {
+            format,
+            video_offset: offset,
+            video_length: length,
+            photo_length: offset,
+        })
+    };
+
+    if let Some(xmp) = xmp {
+        if let Some(video) = container_video_length(xmp).and_then(|l| from_end(MotionPhotoFormat::MotionPhoto, l)) {
+            return Some(video);
+        }
+        let micro_offset = native_exif::xmp_property(xmp, "GCamera:MicroVideoOffset").and_then(|o| o.trim().parse().ok());
+        if let Some(video) = micro_offset.and_then(|l| from_end(MotionPhotoFormat::MicroVideo, l)) {
+            return Some(video);
+        }
+    }
+    samsung_video(source)
+}
+
+/// The `MotionPhoto_Data` record of a Samsung photo, found through the SEF
+/// directory at the end of the file or, without one listing it, by
+/// searching the last `SEF_TAIL_LEN` bytes for its name
+fn samsung_video(source: &mut impl ByteSource) -> Option<EmbeddedVideo> {
+    let len = source.len();
+    let base = len.saturating_sub(SEF_TAIL_LEN);
+    let tail = source.read_at(base, len - base)?;
+    let directory = sef_directory(&tail).map(|d| SefDirectory { start: d.start + base, ..d });
+
+    // Header, name length, name
+    let header_len = SEF_RECORD_HEADER.len() + 4 + SAMSUNG_MARKER.len();
+    let listed = directory.as_ref().and_then(|directory| {
+        directory.entries.iter().find_map(|entry| {
+            let record = directory.record_start(entry)?;
+            let header = source.read_at(record, header_len)?;
+            let is_video = header.starts_with(SEF_RECORD_HEADER)
+                && le_u32(&header, SEF_RECORD_HEADER.len()) == Some(SAMSUNG_MARKER.len())
+                && header.ends_with(SAMSUNG_MARKER);
+            let end = record.checked_add(entry.size).filter(|&end| end > record + header_len && end <= directory.start)?;
+            is_video.then_some((record, end))
+        })
+    });
+    let (photo_length, offset, end) = match listed {
+        Some((record, end)) => (record, record + header_len, end),
+        None => {
+            let marker = base + find(&tail, SAMSUNG_MARKER)?;
+            let offset = marker + SAMSUNG_MARKER.len();
+            // Cut from the start of the record when the header is there
+            let record = marker.checked_sub(8).filter(|&r| source.read_at(r, 4).as_deref() == Some(SEF_RECORD_HEADER));
+            let end = directory.as_ref().map(|d| d.start).filter(|&start| start > offset).unwrap_or(len);
+            (record.unwrap_or(marker), offset, end)
+        }
+    };
+    is_mp4_at(source, offset).then_some(EmbeddedVideo {
+        format: MotionPhotoFormat::Samsung,
+        video_offset: offset,
+        video_length: end - offset,
+        photo_length,
+    })
+}
+
+/// The bytes of the photo without its video
+pub fn stripped_photo(data: &[u8], video: &EmbeddedVideo) -> Result<Vec<u8>, String> {
+    if video.format != MotionPhotoFormat::Samsung {
+        return Ok(data[..video.photo_length].to_vec());
+    }
+    match sef_directory(data) {
+        // No directory, so nothing after the video to keep
+        None => Ok(data[..video.photo_length].to_vec()),
+        Some(directory) => remove_sef_record(data, &directory, video.photo_length)
+            .ok_or_else(|| "The SEF directory doesn't list the video record".to_string()),
+    }
+}
+
+/// Find a file's embedded video, with the XMP packet that may describe it
+fn read_motion_photo(path: &Path) -> Result<Option<(EmbeddedVideo, Option<String>)>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let len = file.metadata().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?.len();
+    let xmp = native_exif::read_blocks(path).ok().and_then(|b| b.xmp);
+    let mut source = PhotoFile { file, len: len as usize };
+    Ok(locate(&mut source, xmp.as_deref()).map(|video| (video, xmp)))
+}
+
+/// Sibling `.mp4` for a motion photo: `PXL_1.MP.jpg` and `MVIMG_1.jpg`
+/// become `PXL_1.mp4` and `MVIMG_1.mp4`, so they pair as companions
+pub fn video_path_for(photo: &Path) -> PathBuf {
+    let stem = photo.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let stem = match stem.len().checked_sub(3) {
+        Some(cut) if stem.is_char_boundary(cut) && stem[cut..].eq_ignore_ascii_case(".mp") => &stem[..cut],
+        _ => stem,
+    };
+    photo.with_file_name(format!("{}.mp4", stem))
+}
+
+/// Copy the video out of the photo to a file next to it, refusing to
+/// overwrite anything
+pub fn extract_video(photo: &Path, video: &EmbeddedVideo) -> Result<PathBuf, String> {
+    let target = video_path_for(photo);
+    let mut source = fs::File::open(photo).map_err(|e| format!("Failed to open {}: {}", photo.display(), e))?;
+    source
+        .seek(SeekFrom::Start(video.video_offset as u64))
+        .map_err(|e| format!("Failed to read {}: {}", photo.display(), e))?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&target)
+        .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    let copied = std::io::copy(&mut source.take(video.video_length as u64), &mut file);
+    if copied.as_ref().ok() != Some(&(video.video_length as u64)) {
+        let _ = fs::remove_file(&target);
+        return Err(match copied {
+            Ok(_) => format!("{} ended before its video did", photo.display()),
+            Err(e) => format!("Failed to write {}: {}", target.display(), e),
+        });
+    }
+    Ok(target)
+}
+
+/// Rewrite the photo without its video, keeping its modification time.
+/// `set_aside` moves the original out of the way (to the Trash) first, so
+/// the strip can be undone.
+pub fn strip_video(
+    photo: &Path,
+    data: &[u8],
+    video: &EmbeddedVideo,
+    set_aside: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    let stripped = stripped_photo(data, video)?;
+    let modified = fs::metadata(photo).and_then(|m| m.modified()).ok();
+    let mut temp = photo.as_os_str().to_owned();
+    temp.push(".strip-tmp");
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, &stripped).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    if let Err(e) = set_aside(photo) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&temp, photo) {
+        return Err(format!("Failed to replace {} (the original is in the Trash): {}", photo.display(), e));
+    }
+    if let Some(modified) = modified {
+        let _ = fs::File::options().write(true).open(photo).and_then(|f| f.set_modified(modified));
+    }
+    Ok(())
+}
+
+/// Write the photo's capture date to its extracted video. Returns whether
+/// there was a date to copy.
+fn copy_dates(state: &AppState, operation_id: &str, photo: &str, video: &str) -> Result<bool, String> {
+    let Ok(exif) = metadata::select_backend(state).read(photo) else { return Ok(false) };
+    let Some(datetime) = exif.date_time_original.as_deref().filter(|d| !date_check::is_implausible(d)) else {
+        return Ok(false);
+    };
+    let datetime = datetime.get(..19).ok_or_else(|| format!("Invalid date {}", datetime))?;
+    let local = chrono::NaiveDateTime::parse_from_str(datetime, "%Y:%m:%d %H:%M:%S")
+        .map_err(|e| format!("Invalid date {}: {}", datetime, e))?;
+    // The video's atoms are UTC, so it needs the zone the photo was taken in:
+    // the photo's own offset, or else a guess from its position
+    let offset = exif
+        .offset_time_original
+        .as_deref()
+        .and_then(|offset| timezone::parse_zone(offset).ok())
+        .or_else(|| exif.gps.and_then(|gps| timezone::guess_zone_for_location(gps.latitude, gps.longitude)))
+        .and_then(|zone| zone.offset_at(&local))
+        .map(|offset| timezone::format_offset(&offset));
+
+    let args = metadata::date_write_args(video, datetime, offset.as_deref());
+    let output = journal::journaled_write(state, operation_id, video, &args)?;
+    if !output.success() {
+        return Err(format!("exiftool failed: {}", output.stderr));
+    }
+    Ok(true)
+}
+
+/// A value escaped for exiftool's structure syntax
+fn struct_value(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, ',' | '|' | '{' | '}' | '[' | ']') {
+            escaped.push('|');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// exiftool arguments rewriting a Google container directory without its
+/// `MotionPhoto` item, keeping the primary image and any other item (an
+/// Ultra HDR gain map). The directory is deleted when only the primary
+/// image would be left.
+fn container_directory_args(xmp: Option<&str>) -> Vec<String> {
+    let kept: Vec<String> = container_item_regex()
+        .captures_iter(xmp.unwrap_or_default())
+        .filter_map(|item| {
+            let attributes = &item[1];
+            if native_exif::xmp_property(attributes, "Item:Semantic")? == "MotionPhoto" {
+                return None;
+            }
+            let fields: Vec<String> = CONTAINER_ITEM_FIELDS
+                .iter()
+                .filter_map(|field| {
+                    let value = native_exif::xmp_property(attributes, &format!("Item:{}", field))?;
+                    Some(format!("{}={}", field, struct_value(&value)))
+                })
+                .collect();
+            Some(format!("{{Item={{{}}}}}", fields.join(",")))
+        })
+        .collect();
+
+    let mut args = vec!["-XMP-Container:Directory=".to_string()];
+    if kept.len() > 1 {
+        args.extend(kept.iter().map(|item| format!("-XMP-Container:Directory+={}", item)));
+    }
+    args
+}
+
+/// Remove Google's motion photo tags once the video is gone
+fn remove_google_tags(state: &AppState, operation_id: &str, photo: &str, xmp: Option<&str>) -> Result<(), String> {
+    let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+    args.extend(GOOGLE_XMP_TAGS.iter().map(|tag| format!("-{}=", tag)));
+    args.extend(container_directory_args(xmp));
+    args.push(photo.to_string());
+    let output = journal::journaled_write(state, operation_id, photo, &args)?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(format!("exiftool failed: {}", output.stderr))
+    }
+}
+
+fn move_to_trash(path: &Path) -> Result<(), String> {
+    trash::delete(path).map_err(|e| format!("Failed to move {} to Trash: {}", path.display(), e))
+}
+
+/// Apply `action` to one motion photo
+fn process(
+    state: &AppState,
+    operation_id: &str,
+    path: &Path,
+    video: &EmbeddedVideo,
+    xmp: Option<&str>,
+    action: MotionPhotoAction,
+) -> MotionPhotoFile {
+    let file_path = path.to_string_lossy().to_string();
+    let mut result = MotionPhotoFile {
+        file_path: file_path.clone(),
+        format: video.format,
+        video_bytes: video.video_length as u64,
+        video_path: None,
+        dates_copied: false,
+        stripped: false,
+        error: None,
+    };
+    let exiftool = state.exiftool.is_installed();
+
+    let outcome = match action {
+        MotionPhotoAction::Report => Ok(()),
+        MotionPhotoAction::Extract => extract_video(path, video).and_then(|target| {
+            let target = target.to_string_lossy().to_string();
+            result.video_path = Some(target.clone());
+            if exiftool {
+                result.dates_copied = copy_dates(state, operation_id, &file_path, &target)?;
+            }
+            Ok(())
+        }),
+        MotionPhotoAction::Strip => fs::read(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+            .and_then(|data| {
+                // Located again in what is about to be rewritten, in case the file changed
+                let video = locate_video(&data, xmp).ok_or_else(|| "The video is no longer there".to_string())?;
+                strip_video(path, &data, &video, move_to_trash)
+            })
+            .and_then(|_| {
+                result.stripped = true;
+                if exiftool && video.format != MotionPhotoFormat::Samsung {
+                    remove_google_tags(state, operation_id, &file_path, xmp)?;
+                }
+                Ok(())
+            }),
+    };
+    result.error = outcome.err();
+    result
+}
+
+/// Find the motion photos under `directory` and apply `action` to each
+pub(crate) fn process_directory(
+    state: &AppState,
+    directory: &str,
+    action: MotionPhotoAction,
+    operation_id: &str,
+    cancel_token: &std::sync::atomic::AtomicBool,
+    mut on_progress: impl FnMut(usize),
+) -> Result<Vec<MotionPhotoFile>, String> {
+    let types = state.media_types();
+    let mut scanned = 0;
+    let mut results = Vec::new();
+    metadata::walk_media_files(&types, directory, cancel_token, |path| {
+        if types.category(path) != Some(MediaCategory::Photo) {
+            return Ok(());
+        }
+        scanned += 1;
+        if scanned % 10 == 0 {
+            on_progress(scanned);
+        }
+        match read_motion_photo(path) {
+            Ok(Some((video, xmp))) => results.push(process(state, operation_id, path, &video, xmp.as_deref(), action)),
+            Ok(None) => {}
+            // Unreadable files are left to the other scans to report
+            Err(_) => {}
+        }
+        Ok(())
+    })?;
+    Ok(results)
+}
+
+#[derive(Clone, Serialize)]
+struct MotionPhotoProgress {
+    id: String,
+    count: usize,
+}
+
+/// Report, extract or strip the videos of motion photos under `directory`
+/// (async, cancellable). `action` defaults to report.
+#[tauri::command]
+pub async fn scan_motion_photos(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    directory: String,
+    operation_id: String,
+    action: Option<MotionPhotoAction>,
+) -> Result<Vec<MotionPhotoFile>, String> {
+    use tauri::Emitter;
+
+    let cancel_token = state.register_token(&operation_id);
+    let results = process_directory(&state, &directory, action.unwrap_or_default(), &operation_id, &cancel_token, |count| {
+        let _ = app_handle.emit("motion-photo-progress", MotionPhotoProgress {
+            id: operation_id.clone(),
+            count,
+        });
+    });
+    state.remove_token(&operation_id);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const PHOTO: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x02, 0xFF, 0xD9];
+
+    fn mp4() -> Vec<u8> {
+        let mut video = vec![0, 0, 0, 16];
+        video.extend_from_slice(b"ftypisom");
+        video.extend_from_slice(&[0, 0, 0, 0]);
+        video.extend_from_slice(b"moov data");
+        video
+    }
+
+    /// A SEF record: header, name length, name, data
+    fn sef_record(name: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut record = SEF_RECORD_HEADER.to_vec();
+        record.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        record.extend_from_slice(name);
+        record.extend_from_slice(data);
+        record
+    }
+
+    /// A photo followed by SEF records and their directory
+    fn with_sef(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = PHOTO.to_vec();
+        let mut starts = Vec::new();
+        for record in records {
+            starts.push(data.len());
+            data.extend_from_slice(record);
+        }
+        let directory = SefDirectory {
+            start: data.len(),
+            version: [0x6A, 0, 0, 0],
+            entries: records
+                .iter()
+                .zip(&starts)
+                .map(|(record, &start)| SefEntry {
+                    kind: SEF_RECORD_HEADER.try_into().unwrap(),
+                    distance: data.len() - start,
+                    size: record.len(),
+                })
+                .collect(),
+        };
+        data.extend_from_slice(&directory.encode());
+        data
+    }
+
+    fn samsung() -> Vec<u8> {
+        with_sef(&[sef_record(SAMSUNG_MARKER, &mp4())])
+    }
+
+    #[test]
+    fn test_locate_google_videos() {
+        let data = [PHOTO, &mp4()].concat();
+        let length = mp4().len();
+
+        let xmp = format!(r#"<rdf:Description GCamera:MicroVideo="1" GCamera:MicroVideoOffset="{}"/>"#, length);
+        let video = locate_video(&data, Some(&xmp)).unwrap();
+        assert_eq!(video.format, MotionPhotoFormat::MicroVideo);
+        assert_eq!((video.video_offset, video.video_length, video.photo_length), (PHOTO.len(), length, PHOTO.len()));
+
+        let xmp = format!(
+            r#"<Container:Directory><rdf:Seq>
+            <rdf:li rdf:parseType="Resource"><Container:Item Item:Mime="image/jpeg" Item:Semantic="Primary" Item:Length="0"/></rdf:li>
+            <rdf:li rdf:parseType="Resource"><Container:Item Item:Mime="video/mp4" Item:Semantic="MotionPhoto" Item:Length="{}"/></rdf:li>
+            </rdf:Seq></Container:Directory>"#,
+            length
+        );
+        assert_eq!(locate_video(&data, Some(&xmp)).unwrap().format, MotionPhotoFormat::MotionPhoto);
+
+        // A stale offset that doesn't land on a video is ignored
+        let xmp = r#"<rdf:Description GCamera:MicroVideoOffset="3"/>"#;
+        assert!(locate_video(&data, Some(xmp)).is_none());
+        assert!(locate_video(PHOTO, None).is_none());
+    }
+
+    #[test]
+    fn test_locate_samsung_video() {
+        let data = samsung();
+        let video = locate_video(&data, None).unwrap();
+        assert_eq!(video.format, MotionPhotoFormat::Samsung);
+        assert_eq!(video.photo_length, PHOTO.len());
+        assert_eq!(&data[video.video_offset..video.video_offset + video.video_length], mp4().as_slice());
+    }
+
+    #[test]
+    fn test_extract_and_strip() {
+        let dir = tempdir().unwrap();
+        let photo = dir.path().join("PXL_20230101_120000000.MP.jpg");
+        let data = samsung();
+        fs::write(&photo, &data).unwrap();
+        let video = locate_video(&data, None).unwrap();
+
+        let target = extract_video(&photo, &video).unwrap();
+        assert_eq!(target, dir.path().join("PXL_20230101_120000000.mp4"));
+        assert_eq!(fs::read(&target).unwrap(), mp4());
+        // Never overwrites an existing video
+        assert!(extract_video(&photo, &video).is_err());
+
+        // The original is set aside before the stripped copy replaces it
+        let backup = dir.path().join("original.jpg");
+        strip_video(&photo, &data, &video, |p| fs::rename(p, &backup).map_err(|e| e.to_string())).unwrap();
+        assert_eq!(fs::read(&photo).unwrap(), PHOTO);
+        assert_eq!(fs::read(&backup).unwrap(), data);
+        assert_eq!(video_path_for(Path::new("/p/MVIMG_1.jpg")), PathBuf::from("/p/MVIMG_1.mp4"));
+
+        // A failed set-aside leaves the photo alone
+        let data = samsung();
+        fs::write(&photo, &data).unwrap();
+        assert!(strip_video(&photo, &data, &video, |_| Err("no Trash".to_string())).is_err());
+        assert_eq!(fs::read(&photo).unwrap(), data);
+    }
+
+    #[test]
+    fn test_strip_samsung_keeps_other_records() {
+        let utc = sef_record(b"Image_UTC_Data", b"1672574400000");
+        let info = sef_record(b"Camera_Capture_Mode_Info", b"1");
+        let data = with_sef(&[utc.clone(), sef_record(SAMSUNG_MARKER, &mp4()), info.clone()]);
+        let video = locate_video(&data, None).unwrap();
+        assert_eq!(&data[video.video_offset..video.video_offset + video.video_length], mp4().as_slice());
+
+        let stripped = stripped_photo(&data, &video).unwrap();
+        assert_eq!(stripped, with_sef(&[utc, info]));
+        let directory = sef_directory(&stripped).unwrap();
+        assert_eq!(directory.entries.len(), 2);
+        assert!(locate_video(&stripped, None).is_none());
+    }
+
+    #[test]
+    fn test_read_motion_photo_from_file() {
+        let dir = tempdir().unwrap();
+        // A video bigger than the tail read for the SEF directory
+        let mut video = mp4();
+        video.resize(SEF_TAIL_LEN * 2, 0);
+        let data = with_sef(&[sef_record(SAMSUNG_MARKER, &video), sef_record(b"Image_UTC_Data", b"1")]);
+        let photo = dir.path().join("20230101_120000.jpg");
+        fs::write(&photo, &data).unwrap();
+
+        let (found, _) = read_motion_photo(&photo).unwrap().unwrap();
+        assert_eq!(Some(found), locate_video(&data, None));
+        assert_eq!(found.video_length, video.len());
+
+        fs::write(&photo, PHOTO).unwrap();
+        assert!(read_motion_photo(&photo).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_container_directory_keeps_gain_map() {
+        let item = |semantic: &str, mime: &str, length: usize| {
+            format!(r#"<rdf:li><Container:Item Item:Semantic="{}" Item:Mime="{}" Item:Length="{}"/></rdf:li>"#, semantic, mime, length)
+        };
+        let ultra_hdr = [item("Primary", "image/jpeg", 0), item("GainMap", "image/jpeg", 3000), item("MotionPhoto", "video/mp4", 9000)].concat();
+        assert_eq!(
+            container_directory_args(Some(&ultra_hdr)),
+            vec![
+                "-XMP-Container:Directory=",
+                "-XMP-Container:Directory+={Item={Semantic=Primary,Mime=image/jpeg,Length=0}}",
+                "-XMP-Container:Directory+={Item={Semantic=GainMap,Mime=image/jpeg,Length=3000}}",
+            ]
+        );
+
+        let motion = [item("Primary", "image/jpeg", 0), item("MotionPhoto", "video/mp4", 9000)].concat();
+        assert_eq!(container_directory_args(Some(&motion)), vec!["-XMP-Container:Directory="]);
+        assert_eq!(struct_value("a,b}"), "a|,b|}");
+    }
+}
@@ -0,0 +1,1109 @@
+//! Pure-Rust EXIF/XMP reader used when ExifTool is not installed.
+//!
+//! This module provides functions to:
+//! - Locate metadata blocks in JPEG (APP1/APP13), PNG (eXIf/iTXt), WebP (EXIF/XMP) and TIFF files
+//! - Parse TIFF IFDs for the same tags `read_exif_metadata` asks exiftool for
+//! - Extract dates, camera, GPS, rating and keywords from XMP packets and IPTC records
+//! - Take the pixel size from the JPEG frame header, PNG `IHDR` or WebP `VP8X`
+//!
+//! Values are reported in exiftool's formatting (e.g. `2024:01:15 10:30:45`)
+//! so callers can't tell which backend produced them.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::isobmff::GpsCoordinates;
+use crate::metadata::ExifMetadata;
+
+// TIFF tag IDs
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_XP_KEYWORDS: u16 = 0x9C9E;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_CREATE_DATE: u16 = 0x9004;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xA003;
+const TAG_IMAGE_UNIQUE_ID: u16 = 0xA420;
+const TAG_LENS_MODEL: u16 = 0xA434;
+
+/// Metadata blocks larger than this are treated as corrupt rather than read
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+// GPS IFD tag IDs
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+const XMP_JPEG_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// Raw metadata blocks found in a file container
+#[derive(Debug, Default)]
+pub struct MetadataBlocks {
+    pub tiff: Option<Vec<u8>>,
+    pub xmp: Option<String>,
+    pub iptc: Option<Vec<u8>>,
+    /// MIME type of the container the blocks came from
+    pub mime_type: Option<&'static str>,
+    /// Width and height from the container's own image header
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// Read metadata from a file without exiftool
+pub fn read_metadata(file_path: &str) -> Result<ExifMetadata, String> {
+    let blocks = read_blocks(Path::new(file_path))?;
+    Ok(metadata_from_blocks(file_path.to_string(), &blocks))
+}
+
+/// Find the EXIF/XMP/IPTC blocks in a supported container
+pub fn read_blocks(path: &Path) -> Result<MetadataBlocks, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 12];
+    let read = read_up_to(&mut reader, &mut magic)?;
+    let magic = &magic[..read];
+    reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+
+    let (blocks, mime_type) = if magic.starts_with(&[0xFF, 0xD8]) {
+        (read_jpeg_blocks(&mut reader)?, "image/jpeg")
+    } else if magic.starts_with(b"\x89PNG\r\n\x1a\n") {
+        (read_png_blocks(&mut reader)?, "image/png")
+    } else if magic.len() >= 12 && &magic[0..4] == b"RIFF" && &magic[8..12] == b"WEBP" {
+        (read_webp_blocks(&mut reader)?, "image/webp")
+    } else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+        (MetadataBlocks { tiff: Some(read_tiff_ifds(&mut reader)?), ..Default::default() }, "image/tiff")
+    } else {
+        return Err(format!("Unsupported format for native metadata reader: {}", path.display()));
+    };
+    Ok(MetadataBlocks { mime_type: Some(mime_type), ..blocks })
+}
+
+/// Combine whatever blocks were found into `ExifMetadata`.
+///
+/// EXIF values take precedence over XMP, matching exiftool's tag priority.
+pub fn metadata_from_blocks(file_path: String, blocks: &MetadataBlocks) -> ExifMetadata {
+    let ifd = blocks.tiff.as_deref().and_then(|data| TiffFields::parse(data).ok()).unwrap_or_default();
+    let xmp = blocks.xmp.as_deref().map(XmpFields::parse).unwrap_or_default();
+
+    let mut keywords = Vec::new();
+    let mut push_keyword = |k: String| {
+        let k = k.trim().to_string();
+        if !k.is_empty() && !keywords.contains(&k) {
+            keywords.push(k);
+        }
+    };
+    if let Some(iptc) = &blocks.iptc {
+        iptc_keywords(iptc).into_iter().for_each(&mut push_keyword);
+    }
+    xmp.subject.into_iter().for_each(&mut push_keyword);
+    if let Some(xp) = ifd.xp_keywords {
+        xp.split(';').map(|s| s.to_string()).for_each(&mut push_keyword);
+    }
+
+    // The frame header is the real size; EXIF dimensions can predate a crop
+    let (image_width, image_height) = match blocks.dimensions {
+        Some((width, height)) => (Some(width), Some(height)),
+        None => (ifd.image_width, ifd.image_height),
+    };
+
+    ExifMetadata {
+        file_path,
+        date_time_original: ifd.date_time_original.or(xmp.date_time_original),
+        create_date: ifd.create_date.or(xmp.create_date),
+        make: ifd.make.or(xmp.make),
+        model: ifd.model.or(xmp.model),
+        software: ifd.software.or(xmp.software),
+        keywords,
+        hierarchical_keywords: xmp.hierarchical_subject,
+        gps: ifd.gps.or(xmp.gps),
+        image_width,
+        image_height,
+        orientation: ifd.orientation,
+        duration: None,
+        video_codec: None,
+        lens_model: ifd.lens_model.or(xmp.lens_model),
+        iso: ifd.iso,
+        exposure_time: ifd.exposure_time,
+        f_number: ifd.f_number,
+        mime_type: blocks.mime_type.map(str::to_string),
+        sub_sec_time_original: ifd.sub_sec_time_original,
+        offset_time_original: ifd.offset_time_original,
+        image_unique_id: ifd.image_unique_id,
+        content_identifier: xmp.content_identifier,
+        // Only in Apple's maker notes, which this reader doesn't parse
+        burst_uuid: None,
+        rating: xmp.rating,
+        description: xmp.description.or(ifd.image_description),
+        sources: Default::default(),
+    }
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, String> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(total)
+}
+
+/// Size in bytes of one value of a TIFF field type
+fn tiff_type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Largest out-of-line value copied by `read_tiff_ifds`; strip offset tables
+/// of big images and maker notes are left behind
+const MAX_TIFF_VALUE: usize = 64 * 1024;
+
+/// Most entries read from one IFD, so a corrupt count can't allocate much
+const MAX_IFD_ENTRIES: usize = 1024;
+
+/// Copy IFD0 and its Exif and GPS sub-IFDs out of a TIFF-based file (TIFF,
+/// NEF, DNG, ...) into a compact TIFF, seeking to each IFD and value rather
+/// than reading the image data. Values beyond `MAX_TIFF_VALUE` or the end of
+/// the file get a count of zero.
+fn read_tiff_ifds<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).map_err(|e| format!("Truncated TIFF header: {}", e))?;
+    let little_endian = header.starts_with(b"II");
+    let u16_of = |b: &[u8]| if little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) };
+    let u32_of = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) }
+    };
+    let bytes_of = |v: u32| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+    let mut read_at = |offset: u32, len: usize| -> Result<Vec<u8>, String> {
+        reader.seek(SeekFrom::Start(offset as u64)).map_err(|e| e.to_string())?;
+        read_exact_vec(reader, len)
+    };
+
+    let mut out = header.to_vec();
+    // IFDs still to copy: their offset in the file and where the pointer to
+    // them sits in `out`. Sub-IFDs are only followed from IFD0.
+    let mut pending = vec![(u32_of(&header[4..8]), 4usize)];
+    while let Some((offset, pointer)) = pending.pop() {
+        let ifd = read_at(offset, 2).and_then(|count| {
+            let count = (u16_of(&count) as usize).min(MAX_IFD_ENTRIES);
+            Ok((count, read_at(offset.saturating_add(2), 12 * count)?))
+        });
+        // A broken IFD0 is a broken file; a broken sub-IFD is just left out
+        let (count, entries) = match ifd {
+            Ok(ifd) => ifd,
+            Err(e) if pointer == 4 => return Err(e),
+            Err(_) => {
+                out[pointer..pointer + 4].copy_from_slice(&bytes_of(u32::MAX));
+                continue;
+            }
+        };
+        let start = out.len();
+        out[pointer..pointer + 4].copy_from_slice(&bytes_of(start as u32));
+        out.extend(if little_endian { (count as u16).to_le_bytes() } else { (count as u16).to_be_bytes() });
+        out.extend(entries);
+        out.extend([0u8; 4]);
+
+        for i in 0..count {
+            let pos = start + 2 + i * 12;
+            let tag = u16_of(&out[pos..]);
+            let len = tiff_type_size(u16_of(&out[pos + 2..])).map(|unit| unit.saturating_mul(u32_of(&out[pos + 4..]) as usize));
+            let value = u32_of(&out[pos + 8..]);
+            if pointer == 4 && matches!(tag, TAG_EXIF_IFD | TAG_GPS_IFD) {
+                pending.push((value, pos + 8));
+            } else if let Some(len) = len.filter(|&len| len > 4) {
+                match (len <= MAX_TIFF_VALUE).then(|| read_at(value, len).ok()).flatten() {
+                    Some(data) => {
+                        let moved = bytes_of(out.len() as u32);
+                        out[pos + 8..pos + 12].copy_from_slice(&moved);
+                        out.extend(data);
+                    }
+                    None => out[pos + 4..pos + 8].copy_from_slice(&[0; 4]),
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn read_exact_vec<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, String> {
+    if len > MAX_BLOCK_LEN {
+        return Err(format!("Metadata block of {} bytes is too large", len));
+    }
+    // Grow as data arrives, so a corrupt length in a short file allocates little
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data).map_err(|e| format!("Truncated metadata block: {}", e))?;
+    if data.len() < len {
+        return Err("Truncated metadata block: unexpected end of file".to_string());
+    }
+    Ok(data)
+}
+
+/// Walk JPEG segments up to the start of scan
+fn read_jpeg_blocks<R: Read + Seek>(reader: &mut R) -> Result<MetadataBlocks, String> {
+    let mut blocks = MetadataBlocks::default();
+    reader.seek(SeekFrom::Start(2)).map_err(|e| e.to_string())?;
+
+    loop {
+        let mut marker = [0u8; 2];
+        if read_up_to(reader, &mut marker)? < 2 || marker[0] != 0xFF {
+            break;
+        }
+        // Fill bytes before a marker are allowed
+        if marker[1] == 0xFF {
+            reader.seek(SeekFrom::Current(-1)).map_err(|e| e.to_string())?;
+            continue;
+        }
+        // Start of scan / end of image: no more metadata segments
+        if marker[1] == 0xDA || marker[1] == 0xD9 {
+            break;
+        }
+        // Standalone markers carry no length
+        if (0xD0..=0xD7).contains(&marker[1]) || marker[1] == 0x01 {
+            continue;
+        }
+
+        let mut len_bytes = [0u8; 2];
+        reader.read_exact(&mut len_bytes).map_err(|e| e.to_string())?;
+        let len = u16::from_be_bytes(len_bytes) as usize;
+        if len < 2 {
+            break;
+        }
+
+        match marker[1] {
+            // Start of frame (baseline, progressive, ...); C4, C8 and CC are other tables
+            0xC0..=0xCF if ![0xC4, 0xC8, 0xCC].contains(&marker[1]) => {
+                let data = read_exact_vec(reader, len - 2)?;
+                if let (Some(height), Some(width)) = (data.get(1..3), data.get(3..5)) {
+                    let size = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]) as u32;
+                    blocks.dimensions = Some((size(width), size(height)));
+                }
+            }
+            0xE1 | 0xED => {
+                let data = read_exact_vec(reader, len - 2)?;
+                if marker[1] == 0xE1 && data.starts_with(EXIF_HEADER) && blocks.tiff.is_none() {
+                    blocks.tiff = Some(data[EXIF_HEADER.len()..].to_vec());
+                } else if marker[1] == 0xE1 && data.starts_with(XMP_JPEG_HEADER) && blocks.xmp.is_none() {
+                    blocks.xmp = Some(String::from_utf8_lossy(&data[XMP_JPEG_HEADER.len()..]).to_string());
+                } else if marker[1] == 0xED && blocks.iptc.is_none() {
+                    blocks.iptc = photoshop_iptc(&data);
+                }
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64 - 2)).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Extract the IPTC-NAA resource (0x0404) from a Photoshop APP13 segment
+fn photoshop_iptc(data: &[u8]) -> Option<Vec<u8>> {
+    let header = b"Photoshop 3.0\0";
+    let mut pos = if data.starts_with(header) { header.len() } else { return None };
+
+    while pos + 12 <= data.len() && &data[pos..pos + 4] == b"8BIM" {
+        let id = u16::from_be_bytes([data[pos + 4], data[pos + 5]]);
+        pos += 6;
+        // Pascal string name, padded to an even length including the length byte
+        let name_len = *data.get(pos)? as usize;
+        pos += (name_len + 2) & !1;
+        let size = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        pos += 4;
+        let resource = data.get(pos..pos + size)?;
+        if id == 0x0404 {
+            return Some(resource.to_vec());
+        }
+        pos += (size + 1) & !1;
+    }
+    None
+}
+
+/// Keywords (record 2, dataset 25) from raw IPTC-IIM data
+fn iptc_keywords(data: &[u8]) -> Vec<String> {
+    let mut keywords = Vec::new();
+    let mut pos = 0;
+    while pos + 5 <= data.len() && data[pos] == 0x1C {
+        let record = data[pos + 1];
+        let dataset = data[pos + 2];
+        let len = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        pos += 5;
+        let Some(value) = data.get(pos..pos + len) else { break };
+        if record == 2 && dataset == 25 {
+            keywords.push(String::from_utf8_lossy(value).to_string());
+        }
+        pos += len;
+    }
+    keywords
+}
+
+/// Walk PNG chunks looking for eXIf and XMP iTXt
+fn read_png_blocks<R: Read + Seek>(reader: &mut R) -> Result<MetadataBlocks, String> {
+    let mut blocks = MetadataBlocks::default();
+    reader.seek(SeekFrom::Start(8)).map_err(|e| e.to_string())?;
+
+    loop {
+        let mut header = [0u8; 8];
+        if read_up_to(reader, &mut header)? < 8 {
+            break;
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+
+        match chunk_type {
+            b"IHDR" => {
+                let data = read_exact_vec(reader, len)?;
+                let size = |pos: usize| data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+                blocks.dimensions = size(0).zip(size(4));
+            }
+            b"eXIf" => {
+                let data = read_exact_vec(reader, len)?;
+                // Some writers keep the JPEG-style "Exif\0\0" prefix
+                let data = data.strip_prefix(EXIF_HEADER).map(|d| d.to_vec()).unwrap_or(data);
+                blocks.tiff = Some(data);
+            }
+            b"iTXt" => {
+                let data = read_exact_vec(reader, len)?;
+                if let Some(xmp) = png_itxt_xmp(&data) {
+                    blocks.xmp = Some(xmp);
+                }
+            }
+            b"IEND" => break,
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64)).map_err(|e| e.to_string())?;
+            }
+        }
+        // Skip CRC
+        reader.seek(SeekFrom::Current(4)).map_err(|e| e.to_string())?;
+    }
+
+    Ok(blocks)
+}
+
+/// Uncompressed iTXt chunk with the `XML:com.adobe.xmp` keyword
+fn png_itxt_xmp(data: &[u8]) -> Option<String> {
+    let keyword_end = data.iter().position(|&b| b == 0)?;
+    if &data[..keyword_end] != b"XML:com.adobe.xmp" {
+        return None;
+    }
+    let compressed = *data.get(keyword_end + 1)? != 0;
+    if compressed {
+        return None;
+    }
+    // Skip compression method, then language tag and translated keyword
+    let mut pos = keyword_end + 3;
+    for _ in 0..2 {
+        pos += data.get(pos..)?.iter().position(|&b| b == 0)? + 1;
+    }
+    Some(String::from_utf8_lossy(data.get(pos..)?).to_string())
+}
+
+/// Walk WebP RIFF chunks looking for EXIF and XMP
+fn read_webp_blocks<R: Read + Seek>(reader: &mut R) -> Result<MetadataBlocks, String> {
+    let mut blocks = MetadataBlocks::default();
+    reader.seek(SeekFrom::Start(12)).map_err(|e| e.to_string())?;
+
+    loop {
+        let mut header = [0u8; 8];
+        if read_up_to(reader, &mut header)? < 8 {
+            break;
+        }
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let padded = len + (len & 1);
+
+        match &header[0..4] {
+            b"VP8X" => {
+                let data = read_exact_vec(reader, len)?;
+                // Canvas width and height minus one, 24-bit little-endian
+                let size = |pos: usize| data.get(pos..pos + 3).map(|b| u32::from_le_bytes([b[0], b[1], b[2], 0]) + 1);
+                blocks.dimensions = size(4).zip(size(7));
+                reader.seek(SeekFrom::Current((padded - len) as i64)).map_err(|e| e.to_string())?;
+            }
+            b"EXIF" => {
+                let data = read_exact_vec(reader, len)?;
+                let data = data.strip_prefix(EXIF_HEADER).map(|d| d.to_vec()).unwrap_or(data);
+                blocks.tiff = Some(data);
+                reader.seek(SeekFrom::Current((padded - len) as i64)).map_err(|e| e.to_string())?;
+            }
+            b"XMP " => {
+                let data = read_exact_vec(reader, len)?;
+                blocks.xmp = Some(String::from_utf8_lossy(&data).to_string());
+                reader.seek(SeekFrom::Current((padded - len) as i64)).map_err(|e| e.to_string())?;
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(padded as i64)).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// A TIFF byte stream with its byte order
+pub struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// One IFD entry
+#[derive(Debug, Clone, Copy)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    // Offset of the 4-byte value/offset field within the TIFF data
+    value_field: usize,
+}
+
+impl<'a> Tiff<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        let little_endian = match data.get(0..4) {
+            Some(b"II*\0") => true,
+            Some(b"MM\0*") => false,
+            _ => return Err("Invalid TIFF header".to_string()),
+        };
+        Ok(Self { data, little_endian })
+    }
+
+    pub fn u16_at(&self, pos: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    pub fn u32_at(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    /// Offset of IFD0
+    pub fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|o| o as usize)
+    }
+
+    /// Entries of the IFD at `offset`
+    pub fn entries(&self, offset: usize) -> Vec<IfdEntry> {
+        let Some(count) = self.u16_at(offset) else { return Vec::new() };
+        (0..count as usize)
+            .map_while(|i| {
+                let pos = offset + 2 + i * 12;
+                Some(IfdEntry {
+                    tag: self.u16_at(pos)?,
+                    field_type: self.u16_at(pos + 2)?,
+                    count: self.u32_at(pos + 4)?,
+                    value_field: pos + 8,
+                })
+            })
+            .collect()
+    }
+
+    /// Raw bytes of an entry's value, inline or at its offset
+    pub fn value_bytes(&self, entry: &IfdEntry) -> Option<&'a [u8]> {
+        let len = tiff_type_size(entry.field_type)?.checked_mul(entry.count as usize)?;
+        let start = if len <= 4 { entry.value_field } else { self.u32_at(entry.value_field)? as usize };
+        self.data.get(start..start.checked_add(len)?)
+    }
+
+    /// ASCII value with trailing NULs and whitespace removed
+    pub fn ascii(&self, entry: &IfdEntry) -> Option<String> {
+        let bytes = self.value_bytes(entry)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        let s = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+        (!s.is_empty()).then_some(s)
+    }
+
+    /// First SHORT or LONG value of an entry
+    pub fn unsigned(&self, entry: &IfdEntry) -> Option<u32> {
+        match entry.field_type {
+            3 => self.u16_at(entry.value_field).map(u32::from),
+            4 | 13 => self.u32_at(entry.value_field),
+            _ => None,
+        }
+    }
+
+    /// Values of a RATIONAL entry
+    pub fn rationals(&self, entry: &IfdEntry) -> Vec<f64> {
+        if entry.field_type != 5 {
+            return Vec::new();
+        }
+        let Some(bytes) = self.value_bytes(entry) else { return Vec::new() };
+        let start = bytes.as_ptr() as usize - self.data.as_ptr() as usize;
+        (0..entry.count as usize)
+            .filter_map(|i| {
+                let numerator = self.u32_at(start + i * 8)?;
+                let denominator = self.u32_at(start + i * 8 + 4)?;
+                (denominator != 0).then(|| numerator as f64 / denominator as f64)
+            })
+            .collect()
+    }
+
+    /// First value of a RATIONAL entry
+    pub fn rational(&self, entry: &IfdEntry) -> Option<f64> {
+        self.rationals(entry).first().copied()
+    }
+}
+
+/// Values read from IFD0 and the Exif and GPS sub-IFDs
+#[derive(Debug, Default)]
+struct TiffFields {
+    date_time_original: Option<String>,
+    create_date: Option<String>,
+    make: Option<String>,
+    model: Option<String>,
+    software: Option<String>,
+    xp_keywords: Option<String>,
+    image_description: Option<String>,
+    orientation: Option<u16>,
+    image_width: Option<u32>,
+    image_height: Option<u32>,
+    lens_model: Option<String>,
+    iso: Option<u32>,
+    exposure_time: Option<f64>,
+    f_number: Option<f64>,
+    sub_sec_time_original: Option<String>,
+    offset_time_original: Option<String>,
+    image_unique_id: Option<String>,
+    gps: Option<GpsCoordinates>,
+}
+
+impl TiffFields {
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        let tiff = Tiff::new(data)?;
+        let mut fields = TiffFields::default();
+        let ifd0 = tiff.first_ifd().ok_or("Missing IFD0")?;
+
+        let mut exif_ifd = None;
+        let mut gps_ifd = None;
+        for entry in tiff.entries(ifd0) {
+            match entry.tag {
+                TAG_IMAGE_DESCRIPTION => fields.image_description = tiff.ascii(&entry),
+                TAG_MAKE => fields.make = tiff.ascii(&entry),
+                TAG_MODEL => fields.model = tiff.ascii(&entry),
+                TAG_ORIENTATION => fields.orientation = tiff.unsigned(&entry).and_then(|o| u16::try_from(o).ok()),
+                TAG_SOFTWARE => fields.software = tiff.ascii(&entry),
+                TAG_EXIF_IFD => exif_ifd = tiff.unsigned(&entry),
+                TAG_GPS_IFD => gps_ifd = tiff.unsigned(&entry),
+                TAG_XP_KEYWORDS => fields.xp_keywords = tiff.value_bytes(&entry).map(decode_ucs2),
+                _ => {}
+            }
+        }
+
+        if let Some(offset) = exif_ifd {
+            for entry in tiff.entries(offset as usize) {
+                match entry.tag {
+                    TAG_DATE_TIME_ORIGINAL => fields.date_time_original = tiff.ascii(&entry),
+                    TAG_CREATE_DATE => fields.create_date = tiff.ascii(&entry),
+                    TAG_SUB_SEC_TIME_ORIGINAL => fields.sub_sec_time_original = tiff.ascii(&entry),
+                    TAG_OFFSET_TIME_ORIGINAL => fields.offset_time_original = tiff.ascii(&entry),
+                    TAG_EXPOSURE_TIME => fields.exposure_time = tiff.rational(&entry),
+                    TAG_F_NUMBER => fields.f_number = tiff.rational(&entry),
+                    TAG_ISO => fields.iso = tiff.unsigned(&entry),
+                    TAG_PIXEL_X_DIMENSION => fields.image_width = tiff.unsigned(&entry),
+                    TAG_PIXEL_Y_DIMENSION => fields.image_height = tiff.unsigned(&entry),
+                    TAG_IMAGE_UNIQUE_ID => fields.image_unique_id = tiff.ascii(&entry),
+                    TAG_LENS_MODEL => fields.lens_model = tiff.ascii(&entry),
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(offset) = gps_ifd {
+            fields.gps = parse_gps_ifd(&tiff, offset as usize);
+        }
+
+        Ok(fields)
+    }
+}
+
+/// Signed decimal position from the GPS IFD's degree/minute/second rationals
+fn parse_gps_ifd(tiff: &Tiff, offset: usize) -> Option<GpsCoordinates> {
+    let (mut latitude, mut longitude, mut altitude) = (None, None, None);
+    let (mut south, mut west, mut below_sea_level) = (false, false, false);
+    let degrees = |dms: Vec<f64>| match dms.as_slice() {
+        [d, m, s, ..] => Some(d + m / 60.0 + s / 3600.0),
+        [d, m] => Some(d + m / 60.0),
+        [d] => Some(*d),
+        [] => None,
+    };
+
+    for entry in tiff.entries(offset) {
+        match entry.tag {
+            TAG_GPS_LATITUDE_REF => south = tiff.ascii(&entry).is_some_and(|r| r.eq_ignore_ascii_case("S")),
+            TAG_GPS_LATITUDE => latitude = degrees(tiff.rationals(&entry)),
+            TAG_GPS_LONGITUDE_REF => west = tiff.ascii(&entry).is_some_and(|r| r.eq_ignore_ascii_case("W")),
+            TAG_GPS_LONGITUDE => longitude = degrees(tiff.rationals(&entry)),
+            TAG_GPS_ALTITUDE_REF => below_sea_level = tiff.value_bytes(&entry).is_some_and(|b| b.first() == Some(&1)),
+            TAG_GPS_ALTITUDE => altitude = tiff.rational(&entry),
+            _ => {}
+        }
+    }
+
+    let sign = |negative: bool| if negative { -1.0 } else { 1.0 };
+    Some(GpsCoordinates {
+        latitude: latitude? * sign(south),
+        longitude: longitude? * sign(west),
+        altitude: altitude.map(|a| a * sign(below_sea_level)),
+    })
+}
+
+/// XPKeywords are stored as little-endian UCS-2 regardless of TIFF byte order
+fn decode_ucs2(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Values read from an XMP packet
+#[derive(Debug, Default)]
+struct XmpFields {
+    date_time_original: Option<String>,
+    create_date: Option<String>,
+    make: Option<String>,
+    model: Option<String>,
+    software: Option<String>,
+    subject: Vec<String>,
+    hierarchical_subject: Vec<String>,
+    gps: Option<GpsCoordinates>,
+    lens_model: Option<String>,
+    content_identifier: Option<String>,
+    rating: Option<i32>,
+    description: Option<String>,
+}
+
+impl XmpFields {
+    fn parse(xmp: &str) -> Self {
+        let gps = xmp_property(xmp, "exif:GPSLatitude")
+            .and_then(|lat| xmp_gps_coordinate(&lat))
+            .zip(xmp_property(xmp, "exif:GPSLongitude").and_then(|lon| xmp_gps_coordinate(&lon)))
+            .map(|(latitude, longitude)| GpsCoordinates {
+                latitude,
+                longitude,
+                altitude: xmp_property(xmp, "exif:GPSAltitude").and_then(|a| xmp_rational(&a)).map(|a| {
+                    let below = xmp_property(xmp, "exif:GPSAltitudeRef").is_some_and(|r| r.trim() == "1");
+                    if below { -a } else { a }
+                }),
+            });
+
+        XmpFields {
+            gps,
+            lens_model: xmp_property(xmp, "exifEX:LensModel").or_else(|| xmp_property(xmp, "aux:Lens")),
+            content_identifier: xmp_property(xmp, "apple-fi:ContentIdentifier"),
+            rating: xmp_property(xmp, "xmp:Rating").and_then(|r| r.trim().parse::<f64>().ok()).map(|r| r.round() as i32),
+            // An rdf:Alt of languages; the first is the default
+            description: xmp_list(xmp, "dc:description").into_iter().next(),
+            date_time_original: xmp_property(xmp, "exif:DateTimeOriginal").map(|d| xmp_date_to_exif(&d)),
+            create_date: xmp_property(xmp, "xmp:CreateDate").map(|d| xmp_date_to_exif(&d)),
+            make: xmp_property(xmp, "tiff:Make"),
+            model: xmp_property(xmp, "tiff:Model"),
+            software: xmp_property(xmp, "tiff:Software"),
+            subject: xmp_list(xmp, "dc:subject"),
+            hierarchical_subject: xmp_list(xmp, "lr:hierarchicalSubject")
+                .into_iter()
+                .chain(xmp_list(xmp, "digiKam:TagsList").iter().map(|t| crate::keywords::from_digikam(t)))
+                .fold(Vec::new(), |mut list, k| {
+                    if !list.contains(&k) {
+                        list.push(k);
+                    }
+                    list
+                }),
+        }
+    }
+}
+
+/// Regex built from `template` with every `{}` replaced by the escaped
+/// property name, compiled once per property
+fn property_regex(template: &'static str, name: &str) -> Option<Regex> {
+    static CACHE: OnceLock<Mutex<HashMap<(&'static str, String), Regex>>> = OnceLock::new();
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    let key = (template, name.to_string());
+    if let Some(regex) = cache.get(&key) {
+        return Some(regex.clone());
+    }
+    let regex = Regex::new(&template.replace("{}", &regex::escape(name))).ok()?;
+    cache.insert(key, regex.clone());
+    Some(regex)
+}
+
+/// Simple XMP property, written either as an attribute or as an element
+pub fn xmp_property(xmp: &str, name: &str) -> Option<String> {
+    let attr = property_regex(r#"{}\s*=\s*["']([^"']*)["']"#, name)?;
+    if let Some(caps) = attr.captures(xmp) {
+        return Some(xml_unescape(&caps[1]));
+    }
+    let elem = property_regex(r"(?s)<{}>([^<]*)</{}>", name)?;
+    elem.captures(xmp).map(|caps| xml_unescape(caps[1].trim()))
+}
+
+/// Items of an XMP Bag/Seq property such as `dc:subject`
+pub fn xmp_list(xmp: &str, name: &str) -> Vec<String> {
+    let Some(container) = property_regex(r"(?s)<{}>(.*?)</{}>", name) else {
+        return Vec::new();
+    };
+    let Some(caps) = container.captures(xmp) else { return Vec::new() };
+    rdf_li_regex()
+        .captures_iter(&caps[1])
+        .map(|li| xml_unescape(li[1].trim()))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn rdf_li_regex() -> &'static Regex {
+    static RDF_LI: OnceLock<Regex> = OnceLock::new();
+    RDF_LI.get_or_init(|| Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap())
+}
+
+pub fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// XMP rational such as `120/1`, or a plain number
+fn xmp_rational(value: &str) -> Option<f64> {
+    match value.trim().split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f64 = denominator.parse().ok()?;
+            (denominator != 0.0).then_some(numerator.parse::<f64>().ok()? / denominator)
+        }
+        None => value.trim().parse().ok(),
+    }
+}
+
+/// XMP GPS coordinate, `DDD,MM.mmmK` or `DDD,MM,SSK`, as signed decimal degrees
+fn xmp_gps_coordinate(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (split, hemisphere) = value.char_indices().last()?;
+    let parts: Vec<f64> = value[..split].split(',').map(|p| p.trim().parse().ok()).collect::<Option<_>>()?;
+    let degrees = match parts.as_slice() {
+        [d, m, s] => d + m / 60.0 + s / 3600.0,
+        [d, m] => d + m / 60.0,
+        _ => return None,
+    };
+    match hemisphere.to_ascii_uppercase() {
+        'N' | 'E' => Some(degrees),
+        'S' | 'W' => Some(-degrees),
+        _ => None,
+    }
+}
+
+/// `2024-01-15T10:30:45+05:00` -> `2024:01:15 10:30:45+05:00` (exiftool style)
+pub fn xmp_date_to_exif(date: &str) -> String {
+    let (day, rest) = match date.split_once('T') {
+        Some((day, rest)) => (day, Some(rest)),
+        None => (date, None),
+    };
+    let day = day.replace('-', ":");
+    match rest {
+        Some(rest) => format!("{} {}", day, rest),
+        None => day,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    /// Build a little-endian TIFF with Make/Model in IFD0 and
+    /// DateTimeOriginal in the Exif sub-IFD.
+    pub(crate) fn sample_tiff(make: &str, model: &str, date: &str) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let ifd0_entries = 3u16;
+        let ifd0_size = 2 + 12 * ifd0_entries as usize + 4;
+        let exif_entries = 1u16;
+        let exif_offset = 8 + ifd0_size;
+        let exif_size = 2 + 12 * exif_entries as usize + 4;
+        let mut data_offset = exif_offset + exif_size;
+
+        let mut ascii_entry = |tag: u16, value: &str, out: &mut Vec<u8>| {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&2u16.to_le_bytes());
+            out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(data_offset as u32).to_le_bytes());
+            data_offset += bytes.len();
+            strings.extend_from_slice(&bytes);
+        };
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+
+        tiff.extend_from_slice(&ifd0_entries.to_le_bytes());
+        ascii_entry(TAG_MAKE, make, &mut tiff);
+        ascii_entry(TAG_MODEL, model, &mut tiff);
+        tiff.extend_from_slice(&TAG_EXIF_IFD.to_le_bytes());
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&(exif_offset as u32).to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+
+        tiff.extend_from_slice(&exif_entries.to_le_bytes());
+        ascii_entry(TAG_DATE_TIME_ORIGINAL, date, &mut tiff);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+
+        tiff.extend_from_slice(&strings);
+        tiff
+    }
+
+    pub(crate) fn sample_jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((tiff.len() + EXIF_HEADER.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(EXIF_HEADER);
+        jpeg.extend_from_slice(tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x00, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn write_file(dir: &Path, name: &str, data: &[u8]) -> String {
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_read_exact_vec_bounds() {
+        let mut short = std::io::Cursor::new(vec![1u8; 10]);
+        assert_eq!(read_exact_vec(&mut short, 4).unwrap(), vec![1; 4]);
+        assert!(read_exact_vec(&mut short, 100).is_err());
+        // A corrupt length is refused before anything is allocated
+        assert!(read_exact_vec(&mut short, u32::MAX as usize).is_err());
+    }
+
+    #[test]
+    fn test_read_jpeg_exif() {
+        let dir = tempdir().unwrap();
+        let tiff = sample_tiff("Canon", "EOS 80D", "2023:05:14 10:11:12");
+        let path = write_file(dir.path(), "a.jpg", &sample_jpeg(&tiff));
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.make, Some("Canon".to_string()));
+        assert_eq!(metadata.model, Some("EOS 80D".to_string()));
+        assert_eq!(metadata.date_time_original, Some("2023:05:14 10:11:12".to_string()));
+    }
+
+    #[test]
+    fn test_read_jpeg_iptc_and_xmp_keywords() {
+        let dir = tempdir().unwrap();
+
+        let mut iptc = vec![0x1C, 2, 25, 0, 6];
+        iptc.extend_from_slice(b"Family");
+        let mut app13 = b"Photoshop 3.0\08BIM".to_vec();
+        app13.extend_from_slice(&0x0404u16.to_be_bytes());
+        app13.extend_from_slice(&[0, 0]);
+        app13.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+        app13.extend_from_slice(&iptc);
+
+        let xmp = r#"<x:xmpmeta><rdf:RDF><rdf:Description exif:DateTimeOriginal="2022-01-01T09:08:07">
+            <dc:subject><rdf:Bag><rdf:li>Family</rdf:li><rdf:li>Trip &amp; Beach</rdf:li></rdf:Bag></dc:subject>
+            </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let mut app1 = XMP_JPEG_HEADER.to_vec();
+        app1.extend_from_slice(xmp.as_bytes());
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        for (marker, data) in [(0xEDu8, &app13), (0xE1u8, &app1)] {
+            jpeg.extend_from_slice(&[0xFF, marker]);
+            jpeg.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+            jpeg.extend_from_slice(data);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        let path = write_file(dir.path(), "b.jpg", &jpeg);
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.keywords, vec!["Family", "Trip & Beach"]);
+        assert_eq!(metadata.date_time_original, Some("2022:01:01 09:08:07".to_string()));
+    }
+
+    #[test]
+    fn test_read_png_exif_chunk() {
+        let dir = tempdir().unwrap();
+        let tiff = sample_tiff("Google", "Pixel 7", "2023:01:02 03:04:05");
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"eXIf");
+        png.extend_from_slice(&tiff);
+        png.extend_from_slice(&[0; 4]);
+        png.extend_from_slice(&0u32.to_be_bytes());
+        png.extend_from_slice(b"IEND");
+        png.extend_from_slice(&[0; 4]);
+        let path = write_file(dir.path(), "c.png", &png);
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.model, Some("Pixel 7".to_string()));
+        assert_eq!(metadata.date_time_original, Some("2023:01:02 03:04:05".to_string()));
+    }
+
+    #[test]
+    fn test_read_webp_exif_chunk() {
+        let dir = tempdir().unwrap();
+        let tiff = sample_tiff("Samsung", "SM-G991B", "2021:07:08 09:10:11");
+        let mut chunks = b"VP8 ".to_vec();
+        chunks.extend_from_slice(&4u32.to_le_bytes());
+        chunks.extend_from_slice(&[0; 4]);
+        chunks.extend_from_slice(b"EXIF");
+        chunks.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(&tiff);
+        if tiff.len() % 2 == 1 {
+            chunks.push(0);
+        }
+        let mut webp = b"RIFF".to_vec();
+        webp.extend_from_slice(&((chunks.len() + 4) as u32).to_le_bytes());
+        webp.extend_from_slice(b"WEBP");
+        webp.extend_from_slice(&chunks);
+        let path = write_file(dir.path(), "d.webp", &webp);
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.make, Some("Samsung".to_string()));
+        assert_eq!(metadata.date_time_original, Some("2021:07:08 09:10:11".to_string()));
+    }
+
+    #[test]
+    fn test_read_tiff_file() {
+        let dir = tempdir().unwrap();
+        let tiff = sample_tiff("Nikon", "D750", "2019:12:31 23:59:59");
+        let path = write_file(dir.path(), "e.tif", &tiff);
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!(metadata.make, Some("Nikon".to_string()));
+        assert_eq!(metadata.date_time_original, Some("2019:12:31 23:59:59".to_string()));
+    }
+
+    #[test]
+    fn test_read_tiff_ifds_skips_image_data() {
+        let dir = tempdir().unwrap();
+        // Image data after the IFDs is never copied
+        let mut tiff = sample_gps_tiff();
+        tiff.extend(vec![0u8; 1_000_000]);
+        let path = write_file(dir.path(), "g.tif", &tiff);
+
+        let blocks = read_blocks(Path::new(&path)).unwrap();
+        assert!(blocks.tiff.as_ref().unwrap().len() < 1000);
+        let gps = read_metadata(&path).unwrap().gps.unwrap();
+        assert!((gps.longitude - 151.21).abs() < 1e-4);
+    }
+
+    /// A TIFF whose only IFD0 entry points to a GPS IFD at 33°51'30.96"S 151°12'36"E
+    pub(crate) fn sample_gps_tiff() -> Vec<u8> {
+        let entry = |tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+            let mut e = tag.to_le_bytes().to_vec();
+            e.extend_from_slice(&field_type.to_le_bytes());
+            e.extend_from_slice(&count.to_le_bytes());
+            e.extend_from_slice(&value);
+            e
+        };
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend(entry(TAG_GPS_IFD, 4, 1, 26u32.to_le_bytes()));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+
+        // GPS IFD at 26 with four entries, rationals after it at 80
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        tiff.extend(entry(TAG_GPS_LATITUDE_REF, 2, 2, *b"S\0\0\0"));
+        tiff.extend(entry(TAG_GPS_LATITUDE, 5, 3, 80u32.to_le_bytes()));
+        tiff.extend(entry(TAG_GPS_LONGITUDE_REF, 2, 2, *b"E\0\0\0"));
+        tiff.extend(entry(TAG_GPS_LONGITUDE, 5, 3, 104u32.to_le_bytes()));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        for (numerator, denominator) in [(33u32, 1u32), (51, 1), (3096, 100), (151, 1), (12, 1), (3600, 100)] {
+            tiff.extend_from_slice(&numerator.to_le_bytes());
+            tiff.extend_from_slice(&denominator.to_le_bytes());
+        }
+        tiff
+    }
+
+    #[test]
+    fn test_read_tiff_gps_ifd() {
+        let gps = TiffFields::parse(&sample_gps_tiff()).unwrap().gps.unwrap();
+        assert!((gps.latitude - -33.8586).abs() < 1e-4);
+        assert!((gps.longitude - 151.21).abs() < 1e-4);
+        assert_eq!(gps.altitude, None);
+    }
+
+    #[test]
+    fn test_read_jpeg_frame_size_and_xmp_details() {
+        let dir = tempdir().unwrap();
+        let xmp = r#"<x:xmpmeta><rdf:RDF><rdf:Description xmp:Rating="4"
+            exif:GPSLatitude="31,35.28N" exif:GPSLongitude="74,18.6E" exif:GPSAltitude="2170/10">
+            <dc:description><rdf:Alt><rdf:li xml:lang="x-default">Badshahi Mosque</rdf:li></rdf:Alt></dc:description>
+            </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let mut app1 = XMP_JPEG_HEADER.to_vec();
+        app1.extend_from_slice(xmp.as_bytes());
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(&app1);
+        // SOF0: precision, height 3024, width 4032, one component
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x0B, 0xD0, 0x0F, 0xC0, 0x01, 0x01, 0x11, 0x00]);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x00, 0xFF, 0xD9]);
+        let path = write_file(dir.path(), "g.jpg", &jpeg);
+
+        let metadata = read_metadata(&path).unwrap();
+        assert_eq!((metadata.image_width, metadata.image_height), (Some(4032), Some(3024)));
+        assert_eq!(metadata.mime_type, Some("image/jpeg".to_string()));
+        assert_eq!(metadata.rating, Some(4));
+        assert_eq!(metadata.description, Some("Badshahi Mosque".to_string()));
+        let gps = metadata.gps.unwrap();
+        assert!((gps.latitude - 31.588).abs() < 1e-6);
+        assert!((gps.longitude - 74.31).abs() < 1e-6);
+        assert_eq!(gps.altitude, Some(217.0));
+    }
+
+    #[test]
+    fn test_xmp_gps_coordinate() {
+        assert_eq!(xmp_gps_coordinate("33,51.5S"), Some(-(33.0 + 51.5 / 60.0)));
+        assert_eq!(xmp_gps_coordinate("10,30,0W"), Some(-10.5));
+        assert_eq!(xmp_gps_coordinate("10.5"), None);
+        assert_eq!(xmp_gps_coordinate("10,30°"), None);
+        assert_eq!(xmp_gps_coordinate("é"), None);
+    }
+
+    #[test]
+    fn test_unsupported_format_is_error() {
+        let dir = tempdir().unwrap();
+        let path = write_file(dir.path(), "f.txt", b"just some text");
+        assert!(read_metadata(&path).is_err());
+    }
+
+    #[test]
+    fn test_xmp_date_to_exif() {
+        assert_eq!(xmp_date_to_exif("2024-01-15T10:30:45+05:00"), "2024:01:15 10:30:45+05:00");
+        assert_eq!(xmp_date_to_exif("2024-01-15"), "2024:01:15");
+    }
+
+    #[test]
+    fn test_decode_ucs2_keywords() {
+        let bytes: Vec<u8> = "Dad; Trip\0".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        assert_eq!(decode_ucs2(&bytes), "Dad; Trip");
+    }
+}
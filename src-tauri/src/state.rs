@@ -1,5 +1,12 @@
 use std::collections::HashMap;
-use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock};
+
+use crate::date_patterns::PatternRegistry;
+use crate::exiftool::ExiftoolPool;
+use crate::journal::Journal;
+use crate::media_types::MediaTypeRegistry;
+use crate::metadata_index::MetadataIndex;
+use crate::xmp_sidecar::WriteTarget;
 
 pub struct AppState {
     pub cancellation_tokens: Mutex<HashMap<String, Arc<AtomicBool>>>,
@@ -7,6 +14,18 @@ pub struct AppState {
     // On Unix, Command::spawn() returns a generic Child, but tauri's Command is different.
     // If we use std::process::Command, we get a Child which has an ID.
     pub running_processes: Mutex<HashMap<String, u32>>,
+    // Persistent exiftool workers shared by all metadata commands
+    pub exiftool: ExiftoolPool,
+    // Compiled filename date patterns (built-ins plus user patterns from settings)
+    pub date_patterns: RwLock<Arc<PatternRegistry>>,
+    // File types the walkers treat as media (built-ins plus user types from settings)
+    pub media_types: RwLock<Arc<MediaTypeRegistry>>,
+    // Undo log of metadata writes, stored in the app data dir
+    pub journal: Journal,
+    // Metadata already read, so rescans only read changed files
+    pub metadata_index: MetadataIndex,
+    // Default destination of metadata writes, from settings
+    pub write_target: RwLock<WriteTarget>,
 }
 
 impl AppState {
@@ -14,9 +33,48 @@ impl AppState {
         Self {
             cancellation_tokens: Mutex::new(HashMap::new()),
             running_processes: Mutex::new(HashMap::new()),
+            exiftool: ExiftoolPool::new(),
+            date_patterns: RwLock::new(Arc::new(PatternRegistry::default())),
+            media_types: RwLock::new(Arc::new(MediaTypeRegistry::default())),
+            journal: Journal::default(),
+            metadata_index: MetadataIndex::default(),
+            write_target: RwLock::new(WriteTarget::default()),
         }
     }
 
+    pub fn date_patterns(&self) -> Arc<PatternRegistry> {
+        self.date_patterns.read().unwrap().clone()
+    }
+
+    /// Recompile the date pattern registry from the settings JSON.
+    /// Returns errors for user patterns that were skipped.
+    pub fn reload_date_patterns(&self, settings: &str) -> Vec<String> {
+        let (registry, errors) = PatternRegistry::from_settings_json(settings);
+        *self.date_patterns.write().unwrap() = Arc::new(registry);
+        errors
+    }
+
+    pub fn media_types(&self) -> Arc<MediaTypeRegistry> {
+        self.media_types.read().unwrap().clone()
+    }
+
+    /// Rebuild the media type registry from the settings JSON.
+    /// Returns errors for user types that were skipped.
+    pub fn reload_media_types(&self, settings: &str) -> Vec<String> {
+        let (registry, errors) = MediaTypeRegistry::from_settings_json(settings);
+        *self.media_types.write().unwrap() = Arc::new(registry);
+        errors
+    }
+
+    pub fn write_target(&self) -> WriteTarget {
+        *self.write_target.read().unwrap()
+    }
+
+    /// Pick up the default write target from the settings JSON
+    pub fn reload_write_target(&self, settings: &str) {
+        *self.write_target.write().unwrap() = WriteTarget::from_settings_json(settings);
+    }
+
     pub fn register_token(&self, id: &str) -> Arc<AtomicBool> {
         let token = Arc::new(AtomicBool::new(false));
         self.cancellation_tokens
@@ -0,0 +1,469 @@
+//! Google Takeout JSON sidecar support.
+//!
+//! This module provides functions to:
+//! - Match media files to their Takeout sidecars (`IMG_1234.jpg.json`,
+//!   `IMG_1234.jpg.supplemental-metadata.json`, ...)
+//! - Parse `photoTakenTime`, `geoData`, `description` and `people`
+//! - Write sidecar values into EXIF/XMP without overwriting existing data
+//!
+//! Takeout mangles sidecar names in a few ways that all need undoing:
+//! - the name before `.json` is truncated to 46 characters
+//! - a duplicate counter moves behind the extension (`IMG(1).jpg` -> `IMG.jpg(1).json`)
+//! - edited copies (`IMG-edited.jpg`) share the original's sidecar
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::date_patterns::DateConfidence;
+use crate::isobmff::GpsCoordinates;
+use crate::journal;
+use crate::metadata::{self, ExtractedDate};
+use crate::state::AppState;
+use crate::timezone;
+
+/// Takeout truncates sidecar names (without `.json`) to this many characters
+const MAX_SIDECAR_STEM: usize = 46;
+
+const SUPPLEMENTAL_SUFFIX: &str = ".supplemental-metadata";
+
+/// Suffixes Google Photos appends to edited copies, per export language
+const EDITED_SUFFIXES: &[&str] = &["-edited", "-bearbeitet", "-modifié", "-editado", "-modificato"];
+
+/// Values recovered from a Takeout sidecar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TakeoutMetadata {
+    pub sidecar_path: String,
+    /// Capture time as Unix seconds (UTC)
+    pub photo_taken_time: Option<i64>,
+    pub geo_data: Option<GpsCoordinates>,
+    pub description: Option<String>,
+    pub people: Vec<String>,
+}
+
+impl TakeoutMetadata {
+    /// The capture time in the shape of a filename suggestion: local time in
+    /// the zone guessed from `geo_data` (see `timezone`), otherwise UTC.
+    /// Either way it carries its offset, never the zone of the machine
+    /// running ingest.
+    pub fn extracted_date(&self) -> Option<ExtractedDate> {
+        let taken = DateTime::<Utc>::from_timestamp(self.photo_taken_time?, 0)?;
+        let guessed = self
+            .geo_data
+            .as_ref()
+            .and_then(|gps| timezone::guess_zone_for_location(gps.latitude, gps.longitude))
+            .map(|zone| zone.offset_at_utc(&taken.naive_utc()));
+        let offset = guessed.unwrap_or_else(|| chrono::FixedOffset::east_opt(0).unwrap());
+        let local = taken.with_timezone(&offset);
+        Some(ExtractedDate {
+            date: local.format("%Y-%m-%d").to_string(),
+            time: Some(local.format("%H:%M:%S").to_string()),
+            source: match guessed {
+                Some(_) => "Google Takeout (zone guessed from location)".to_string(),
+                None => "Google Takeout".to_string(),
+            },
+            subsec: None,
+            confidence: DateConfidence::High,
+            offset: Some(timezone::format_offset(&offset)),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSidecar {
+    description: Option<String>,
+    photo_taken_time: Option<RawTimestamp>,
+    geo_data: Option<RawGeo>,
+    geo_data_exif: Option<RawGeo>,
+    #[serde(default)]
+    people: Vec<RawPerson>,
+}
+
+#[derive(Deserialize)]
+struct RawTimestamp {
+    timestamp: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RawGeo {
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct RawPerson {
+    name: String,
+}
+
+impl RawGeo {
+    /// Takeout writes 0/0 when a photo has no location
+    fn into_coordinates(self) -> Option<GpsCoordinates> {
+        if self.latitude == 0.0 && self.longitude == 0.0 {
+            return None;
+        }
+        Some(GpsCoordinates {
+            latitude: self.latitude,
+            longitude: self.longitude,
+            altitude: self.altitude,
+        })
+    }
+}
+
+/// Parse the contents of a Takeout sidecar
+pub fn parse_sidecar(sidecar_path: &str, json: &str) -> Result<TakeoutMetadata, String> {
+    let raw: RawSidecar = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse Takeout sidecar {}: {}", sidecar_path, e))?;
+
+    // The timestamp is a string in current exports and a number in some old ones
+    let photo_taken_time = raw.photo_taken_time.and_then(|t| match t.timestamp {
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Number(n) => n.as_i64(),
+        _ => None,
+    });
+
+    let geo_data = raw
+        .geo_data
+        .and_then(RawGeo::into_coordinates)
+        .or_else(|| raw.geo_data_exif.and_then(RawGeo::into_coordinates));
+
+    Ok(TakeoutMetadata {
+        sidecar_path: sidecar_path.to_string(),
+        photo_taken_time,
+        geo_data,
+        description: raw.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+        people: raw.people.into_iter().map(|p| p.name).filter(|n| !n.is_empty()).collect(),
+    })
+}
+
+/// Read and parse a sidecar file
+pub fn read_sidecar(sidecar_path: &Path) -> Result<TakeoutMetadata, String> {
+    let json = std::fs::read_to_string(sidecar_path)
+        .map_err(|e| format!("Failed to read {}: {}", sidecar_path.display(), e))?;
+    parse_sidecar(&sidecar_path.to_string_lossy(), &json)
+}
+
+fn counter_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(?P<stem>.*?)(?P<counter>\(\d+\))$").unwrap())
+}
+
+/// Split a media filename into the name its sidecar was built from and the
+/// duplicate counter, undoing `-edited` and `(N)` mangling
+fn sidecar_base(filename: &str) -> (String, String) {
+    let (stem, ext) = match filename.rfind('.') {
+        Some(i) if i > 0 => (&filename[..i], &filename[i..]),
+        _ => (filename, ""),
+    };
+
+    let stem = EDITED_SUFFIXES
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix))
+        .unwrap_or(stem);
+
+    match counter_regex().captures(stem) {
+        Some(caps) => (format!("{}{}", &caps["stem"], ext), caps["counter"].to_string()),
+        None => (format!("{}{}", stem, ext), String::new()),
+    }
+}
+
+fn truncate_chars(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+/// Pick the sidecar for `filename` among the JSON files of its directory
+pub fn match_sidecar<'a>(filename: &str, json_files: &'a [String]) -> Option<&'a String> {
+    let (base, counter) = sidecar_base(filename);
+
+    // Names Takeout generates directly, most specific first
+    let candidates = [
+        format!("{}{}.json", truncate_chars(&format!("{}{}", base, SUPPLEMENTAL_SUFFIX), MAX_SIDECAR_STEM), counter),
+        format!("{}{}.json", truncate_chars(&base, MAX_SIDECAR_STEM), counter),
+    ];
+    if let Some(found) = candidates.iter().find_map(|c| json_files.iter().find(|f| *f == c)) {
+        return Some(found);
+    }
+
+    // Other truncations of `.supplemental-metadata` (`.supplemental-me.json`, `.suppl.json`)
+    let full = format!("{}{}", base, SUPPLEMENTAL_SUFFIX);
+    let by_prefix = json_files.iter().find(|f| {
+        match f.strip_suffix(".json").and_then(|s| s.strip_suffix(counter.as_str())) {
+            Some(stem) => stem.len() > base.len() && full.starts_with(stem),
+            None => false,
+        }
+    });
+    if by_prefix.is_some() {
+        return by_prefix;
+    }
+
+    // Very old exports named the sidecar after the stem only
+    let stem_only = match base.rfind('.') {
+        Some(i) => format!("{}{}.json", &base[..i], counter),
+        None => return None,
+    };
+    json_files.iter().find(|f| **f == stem_only)
+}
+
+/// Caches the JSON files of each directory so scans list every directory once
+#[derive(Default)]
+pub struct SidecarIndex {
+    dirs: HashMap<PathBuf, Vec<String>>,
+}
+
+impl SidecarIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find the sidecar path for a media file, if its directory has one
+    pub fn find(&mut self, media_path: &Path) -> Option<PathBuf> {
+        let dir = media_path.parent()?;
+        let filename = media_path.file_name()?.to_str()?;
+
+        let json_files = self.dirs.entry(dir.to_path_buf()).or_insert_with(|| list_json_files(dir));
+        match_sidecar(filename, json_files).map(|f| dir.join(f))
+    }
+
+    /// Find and parse the sidecar for a media file; unreadable sidecars are ignored
+    pub fn lookup(&mut self, media_path: &Path) -> Option<TakeoutMetadata> {
+        self.find(media_path).and_then(|p| read_sidecar(&p).ok())
+    }
+}
+
+fn list_json_files(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+        .filter(|name| name.to_lowercase().ends_with(".json"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Tags read before writing so existing values are never overwritten
+const EXISTING_TAGS: &[&str] = &[
+    "-DateTimeOriginal",
+    "-GPSLatitude",
+    "-ImageDescription",
+    "-XMP-dc:Description",
+    "-XMP-iptcExt:PersonInImage",
+];
+
+/// A sidecar text on one line: exiftool takes one argument per line, so line
+/// breaks in a description become spaces
+fn single_line(text: &str) -> String {
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// Build exiftool assignments for the sidecar values the file doesn't have yet.
+///
+/// `existing` is the exiftool `-json` record of `file_path`.
+fn plan_writes(file_path: &str, takeout: &TakeoutMetadata, existing: &serde_json::Value) -> Vec<String> {
+    let has = |tag: &str| existing.get(tag).map(|v| !v.is_null() && v != "").unwrap_or(false);
+    let mut args = Vec::new();
+
+    if !has("DateTimeOriginal") {
+        if let Some(date) = takeout.extracted_date() {
+            let datetime = format!("{} {}", date.date.replace('-', ":"), date.time.unwrap_or_default());
+            args.extend(metadata::date_assignments(file_path, &datetime, date.offset.as_deref()));
+        }
+    }
+
+    if !has("GPSLatitude") {
+        if let Some(gps) = takeout.geo_data {
+            args.extend(gps.exif_assignments());
+        }
+    }
+
+    if !has("ImageDescription") && !has("Description") {
+        if let Some(description) = takeout.description.as_deref().map(single_line) {
+            args.push(format!("-ImageDescription={}", description));
+            args.push(format!("-XMP-dc:Description={}", description));
+        }
+    }
+
+    // People are a list: add the names that aren't tagged yet
+    let tagged: Vec<String> = match existing.get("PersonInImage") {
+        Some(serde_json::Value::Array(names)) => {
+            names.iter().filter_map(|n| n.as_str().map(|s| s.to_string())).collect()
+        }
+        Some(serde_json::Value::String(name)) => vec![name.clone()],
+        _ => Vec::new(),
+    };
+    for person in takeout.people.iter().filter(|p| !tagged.contains(p)) {
+        args.push(format!("-XMP-iptcExt:PersonInImage+={}", single_line(person)));
+    }
+
+    args
+}
+
+/// Write Takeout sidecar values into a file, only filling in missing fields
+#[tauri::command]
+pub fn write_takeout_metadata(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let sidecar = SidecarIndex::new()
+        .find(Path::new(&file_path))
+        .ok_or_else(|| format!("No Takeout sidecar found for {}", file_path))?;
+    let takeout = read_sidecar(&sidecar)?;
+
+    let pool = &state.exiftool;
+
+    let mut read_args = vec!["-json"];
+    read_args.extend_from_slice(EXISTING_TAGS);
+    read_args.push(&file_path);
+    let existing_output = pool.execute(&read_args)?;
+    let existing = serde_json::from_str::<serde_json::Value>(&existing_output.stdout)
+        .ok()
+        .and_then(|v| v.get(0).cloned())
+        .ok_or_else(|| format!("Failed to read existing metadata: {}", existing_output.stderr))?;
+
+    let assignments = plan_writes(&file_path, &takeout, &existing);
+    if assignments.is_empty() {
+        return Ok("Metadata already present, skipping".to_string());
+    }
+
+    let mut args = vec!["-overwrite_original".to_string()];
+    args.extend(assignments.iter().cloned());
+    args.push(file_path.clone());
+    let operation_id = journal::operation_id_or("takeout", operation_id);
+    let output = journal::journaled_write(&state, &operation_id, &file_path, &args)?;
+
+    if output.success() {
+        Ok(format!("Takeout metadata written: {}", assignments.join(" ")))
+    } else {
+        Err(format!("exiftool failed: {}", output.stderr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const SIDECAR: &str = r#"{
+        "title": "IMG_1234.jpg",
+        "description": "Beach day ",
+        "photoTakenTime": { "timestamp": "1600000000", "formatted": "Sep 13, 2020" },
+        "geoData": { "latitude": 0.0, "longitude": 0.0, "altitude": 0.0 },
+        "geoDataExif": { "latitude": -33.8568, "longitude": 151.2153, "altitude": 12.0 },
+        "people": [{ "name": "Alice" }, { "name": "Bob" }]
+    }"#;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_sidecar() {
+        let takeout = parse_sidecar("IMG_1234.jpg.json", SIDECAR).unwrap();
+        assert_eq!(takeout.photo_taken_time, Some(1_600_000_000));
+        assert_eq!(takeout.description.as_deref(), Some("Beach day"));
+        assert_eq!(takeout.people, names(&["Alice", "Bob"]));
+        // geoData is 0/0, so the EXIF copy is used
+        let gps = takeout.geo_data.unwrap();
+        assert_eq!(gps.latitude, -33.8568);
+        assert_eq!(gps.altitude, Some(12.0));
+    }
+
+    #[test]
+    fn test_match_plain_and_supplemental() {
+        let files = names(&["IMG_1234.jpg.json", "IMG_5678.jpg.supplemental-metadata.json"]);
+        assert_eq!(match_sidecar("IMG_1234.jpg", &files).unwrap(), "IMG_1234.jpg.json");
+        assert_eq!(
+            match_sidecar("IMG_5678.jpg", &files).unwrap(),
+            "IMG_5678.jpg.supplemental-metadata.json"
+        );
+        assert!(match_sidecar("IMG_9999.jpg", &files).is_none());
+    }
+
+    #[test]
+    fn test_match_counter_and_edited() {
+        let files = names(&["IMG_1234.jpg.json", "IMG_1234.jpg(1).json"]);
+        assert_eq!(match_sidecar("IMG_1234(1).jpg", &files).unwrap(), "IMG_1234.jpg(1).json");
+        assert_eq!(match_sidecar("IMG_1234-edited.jpg", &files).unwrap(), "IMG_1234.jpg.json");
+    }
+
+    #[test]
+    fn test_match_truncated_names() {
+        let long = "Screenshot_20200913-122640_Some Long App Name.jpg";
+        let truncated = format!("{}.json", truncate_chars(long, MAX_SIDECAR_STEM));
+        let files = vec![truncated.clone()];
+        assert_eq!(match_sidecar(long, &files).unwrap(), &truncated);
+
+        let files = names(&["PXL_20230514_101112345.jpg.supplemental-me.json"]);
+        assert!(match_sidecar("PXL_20230514_101112345.jpg", &files).is_some());
+        // A different photo sharing a prefix must not match
+        assert!(match_sidecar("PXL_20230514_10111.jpg", &files).is_none());
+    }
+
+    #[test]
+    fn test_sidecar_index_lookup() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("IMG_1234.jpg"), b"jpeg").unwrap();
+        fs::write(dir.path().join("IMG_1234.jpg.json"), SIDECAR).unwrap();
+
+        let mut index = SidecarIndex::new();
+        let takeout = index.lookup(&dir.path().join("IMG_1234.jpg")).unwrap();
+        assert_eq!(takeout.people.len(), 2);
+        assert_eq!(takeout.extracted_date().unwrap().source, "Google Takeout (zone guessed from location)");
+        assert!(index.lookup(&dir.path().join("IMG_0000.jpg")).is_none());
+    }
+
+    #[test]
+    fn test_plan_writes_never_overwrites() {
+        let takeout = parse_sidecar("IMG_1234.jpg.json", SIDECAR).unwrap();
+
+        let empty = serde_json::json!({ "SourceFile": "IMG_1234.jpg" });
+        let args = plan_writes("IMG_1234.jpg", &takeout, &empty);
+        // 1600000000 is 12:26:40 UTC, 22:26:40 in Sydney
+        assert!(args.contains(&"-DateTimeOriginal=2020:09:13 22:26:40".to_string()));
+        assert!(args.contains(&"-OffsetTimeOriginal=+10:00".to_string()));
+        assert!(args.contains(&"-GPSLatitudeRef=S".to_string()));
+        assert!(args.contains(&"-XMP-dc:Description=Beach day".to_string()));
+        assert!(args.contains(&"-XMP-iptcExt:PersonInImage+=Bob".to_string()));
+
+        let full = serde_json::json!({
+            "DateTimeOriginal": "2020:01:01 00:00:00",
+            "GPSLatitude": "1 deg",
+            "Description": "Existing",
+            "PersonInImage": ["Alice", "Bob"],
+        });
+        assert!(plan_writes("IMG_1234.jpg", &takeout, &full).is_empty());
+    }
+
+    #[test]
+    fn test_extracted_date_without_location_stays_utc() {
+        let mut takeout = parse_sidecar("IMG_1234.jpg.json", SIDECAR).unwrap();
+        takeout.geo_data = None;
+        let date = takeout.extracted_date().unwrap();
+        assert_eq!((date.date.as_str(), date.time.as_deref()), ("2020-09-13", Some("12:26:40")));
+        assert_eq!(date.offset.as_deref(), Some("+00:00"));
+
+        let args = plan_writes("VID_1234.mp4", &takeout, &serde_json::json!({}));
+        assert!(args.contains(&"-DateTimeOriginal=2020:09:13 12:26:40+00:00".to_string()));
+    }
+
+    #[test]
+    fn test_plan_writes_multi_line_description() {
+        let mut takeout = parse_sidecar("IMG_1234.jpg.json", SIDECAR).unwrap();
+        takeout.description = Some("Beach day\r\nwith Alice\n\n  and Bob ".to_string());
+
+        let args = plan_writes("IMG_1234.jpg", &takeout, &serde_json::json!({}));
+        assert!(args.contains(&"-XMP-dc:Description=Beach day with Alice and Bob".to_string()));
+        assert!(args.iter().all(|a| !a.contains('\n') && !a.contains('\r')));
+    }
+}
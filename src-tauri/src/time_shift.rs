@@ -0,0 +1,401 @@
+//! Bulk camera clock correction.
+//!
+//! This module provides functions to:
+//! - Select files by camera model, directory and capture date range
+//! - Compute a shift from a signed delta or from a photo of a known clock
+//! - Preview before/after DateTimeOriginal and CreateDate for every match
+//! - Apply a previewed shift, skipping files whose dates changed since
+//!
+//! Shifted values are computed here rather than with exiftool's `+=` syntax,
+//! so the values written are exactly the ones the preview showed. Only the
+//! date and time move: sub-seconds and zone suffixes are kept, and
+//! `SubSecTimeOriginal` and the `OffsetTime*` tags are never written.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+
+use crate::journal;
+use crate::metadata::{self, ExifMetadata};
+use crate::state::AppState;
+
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Which files a shift applies to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShiftFilter {
+    pub directory: String,
+    /// Camera as reported by `get_camera_model`
+    pub camera_model: Option<String>,
+    /// Inclusive bounds on the current (wrong) DateTimeOriginal,
+    /// as `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+/// How far to move the dates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShiftDelta {
+    /// Signed number of seconds to add
+    Seconds { seconds: i64 },
+    /// The reference photo shows a clock reading `actual` (`YYYY-MM-DD HH:MM:SS`)
+    Reference { file_path: String, actual: String },
+}
+
+/// Before/after dates of one file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeShiftPreview {
+    pub file_path: String,
+    pub camera_model: Option<String>,
+    pub date_time_original_before: Option<String>,
+    pub date_time_original_after: Option<String>,
+    pub create_date_before: Option<String>,
+    pub create_date_after: Option<String>,
+}
+
+/// Preview of a whole shift
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeShiftPlan {
+    pub delta_seconds: i64,
+    pub files: Vec<TimeShiftPreview>,
+}
+
+/// Outcome of applying a shift
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeShiftResult {
+    pub shifted: usize,
+    /// Files whose dates no longer match the preview
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct ShiftProgress {
+    id: String,
+    count: usize,
+    total: usize,
+}
+
+/// Parse an exiftool date, ignoring sub-seconds and any zone suffix
+fn parse_exif_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.get(..19)?, EXIF_DATE_FORMAT).ok()
+}
+
+/// Parse a user-entered date or date-time (dashes or colons in the date)
+fn parse_user_date(value: &str, end_of_day: bool) -> Result<NaiveDateTime, String> {
+    let value = value.trim();
+    let normalized = match value.get(..10) {
+        Some(date) => format!("{}{}", date.replace(':', "-"), &value[10..]),
+        None => value.to_string(),
+    };
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(&normalized, "%Y-%m-%d %H:%M:%S") {
+        return Ok(datetime);
+    }
+    let date = NaiveDate::parse_from_str(&normalized, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date: {}", value))?;
+    Ok(if end_of_day {
+        date.and_hms_opt(23, 59, 59).unwrap()
+    } else {
+        date.and_hms_opt(0, 0, 0).unwrap()
+    })
+}
+
+/// Move a date by `delta`, keeping any sub-seconds and zone suffix after it
+fn shift_value(value: &Option<String>, delta: Duration) -> Option<String> {
+    let value = value.as_ref()?;
+    let shifted = parse_exif_date(value)? + delta;
+    Some(format!("{}{}", shifted.format(EXIF_DATE_FORMAT), &value[19..]))
+}
+
+/// Seconds to add so the reference photo's recorded time becomes `actual`
+fn reference_delta(recorded: &str, actual: &str) -> Result<i64, String> {
+    let recorded = parse_exif_date(recorded).ok_or_else(|| format!("Unreadable date: {}", recorded))?;
+    let actual = parse_user_date(actual, false)?;
+    Ok((actual - recorded).num_seconds())
+}
+
+fn resolve_delta(state: &AppState, delta: &ShiftDelta) -> Result<i64, String> {
+    match delta {
+        ShiftDelta::Seconds { seconds } => Ok(*seconds),
+        ShiftDelta::Reference { file_path, actual } => {
+            let metadata = metadata::select_backend(state).read(file_path)?;
+            let recorded = metadata
+                .date_time_original
+                .or(metadata.create_date)
+                .ok_or_else(|| format!("Reference photo has no date: {}", file_path))?;
+            reference_delta(&recorded, actual)
+        }
+    }
+}
+
+/// Filter bounds, parsed once per request
+struct DateRange {
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+}
+
+impl DateRange {
+    fn new(filter: &ShiftFilter) -> Result<Self, String> {
+        Ok(Self {
+            start: filter.start.as_deref().map(|s| parse_user_date(s, false)).transpose()?,
+            end: filter.end.as_deref().map(|s| parse_user_date(s, true)).transpose()?,
+        })
+    }
+
+    fn contains(&self, date: Option<NaiveDateTime>) -> bool {
+        match date {
+            Some(date) => self.start.is_none_or(|s| date >= s) && self.end.is_none_or(|e| date <= e),
+            // Undated files only match an unbounded range
+            None => self.start.is_none() && self.end.is_none(),
+        }
+    }
+}
+
+/// Build the preview entry for a file, or None if it doesn't match the filter
+fn preview_file(
+    metadata: ExifMetadata,
+    camera_model: Option<&str>,
+    range: &DateRange,
+    delta: Duration,
+) -> Option<TimeShiftPreview> {
+    let original = metadata.date_time_original.as_deref().and_then(parse_exif_date);
+    if !range.contains(original) {
+        return None;
+    }
+
+    let file_path = metadata.file_path.clone();
+    let date_time_original = metadata.date_time_original.clone();
+    let create_date = metadata.create_date.clone();
+    let model = metadata::camera_model_of(metadata);
+    if camera_model.is_some_and(|wanted| model.as_deref() != Some(wanted)) {
+        return None;
+    }
+    // Nothing to shift
+    if date_time_original.is_none() && create_date.is_none() {
+        return None;
+    }
+
+    Some(TimeShiftPreview {
+        file_path,
+        camera_model: model,
+        date_time_original_after: shift_value(&date_time_original, delta),
+        date_time_original_before: date_time_original,
+        create_date_after: shift_value(&create_date, delta),
+        create_date_before: create_date,
+    })
+}
+
+/// List every matching file with its dates before and after the shift
+#[tauri::command]
+pub async fn preview_time_shift(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    filter: ShiftFilter,
+    delta: ShiftDelta,
+    operation_id: String,
+) -> Result<TimeShiftPlan, String> {
+    use tauri::Emitter;
+
+    let delta_seconds = resolve_delta(&state, &delta)?;
+    let range = DateRange::new(&filter)?;
+    let backend = metadata::select_backend(&state);
+    let cancel_token = state.register_token(&operation_id);
+
+    let mut files = Vec::new();
+    let mut count = 0;
+    let walked = metadata::walk_media_files(&state.media_types(), &filter.directory, &cancel_token, |path| {
+        let file_path = path.to_string_lossy().to_string();
+        if let Ok(metadata) = backend.read(&file_path) {
+            let preview = preview_file(
+                metadata,
+                filter.camera_model.as_deref(),
+                &range,
+                Duration::seconds(delta_seconds),
+            );
+            files.extend(preview);
+        }
+
+        count += 1;
+        if count % 10 == 0 {
+            let _ = app_handle.emit("shift-progress", ShiftProgress {
+                id: operation_id.clone(),
+                count,
+                total: 0,
+            });
+        }
+        Ok(())
+    });
+
+    state.remove_token(&operation_id);
+    walked.map(|_| TimeShiftPlan { delta_seconds, files })
+}
+
+/// exiftool assignments that turn `preview`'s before values into its after
+/// values. Only the date and time are assigned, so the sub-second and offset
+/// tags the suffixes came from stay as they are.
+fn shift_args(preview: &TimeShiftPreview) -> Vec<String> {
+    let mut args = vec!["-overwrite_original".to_string(), "-P".to_string()];
+    let date_time = |value: &String| value.get(..19).unwrap_or(value).to_string();
+    if let Some(after) = &preview.date_time_original_after {
+        args.push(format!("-DateTimeOriginal={}", date_time(after)));
+    }
+    if let Some(after) = &preview.create_date_after {
+        args.push(format!("-CreateDate={}", date_time(after)));
+    }
+    args.push(preview.file_path.clone());
+    args
+}
+
+/// Apply a previewed shift. Files whose dates changed since the preview are skipped.
+#[tauri::command]
+pub async fn apply_time_shift(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    files: Vec<TimeShiftPreview>,
+    operation_id: String,
+) -> Result<TimeShiftResult, String> {
+    use tauri::Emitter;
+
+    // The backend the preview read with, or every file would look changed
+    let backend = metadata::select_backend(&state);
+    let cancel_token = state.register_token(&operation_id);
+    let mut result = TimeShiftResult::default();
+
+    for (index, preview) in files.iter().enumerate() {
+        if cancel_token.load(Ordering::Relaxed) {
+            state.remove_token(&operation_id);
+            return Err("Operation cancelled".to_string());
+        }
+
+        // Never shift twice: the file must still hold the previewed values
+        let unchanged = backend.read(&preview.file_path).is_ok_and(|current| {
+            current.date_time_original == preview.date_time_original_before
+                && current.create_date == preview.create_date_before
+        });
+        if !unchanged {
+            result.skipped.push(preview.file_path.clone());
+            continue;
+        }
+
+        match journal::journaled_write(&state, &operation_id, &preview.file_path, &shift_args(preview)) {
+            Ok(output) if output.success() => result.shifted += 1,
+            Ok(output) => result.failed.push(format!("{}: {}", preview.file_path, output.stderr.trim())),
+            Err(e) => result.failed.push(format!("{}: {}", preview.file_path, e)),
+        }
+
+        let _ = app_handle.emit("shift-progress", ShiftProgress {
+            id: operation_id.clone(),
+            count: index + 1,
+            total: files.len(),
+        });
+    }
+
+    state.remove_token(&operation_id);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(make: &str, model: &str, date: Option<&str>) -> ExifMetadata {
+        ExifMetadata {
+            file_path: "/trip/IMG_0001.JPG".to_string(),
+            date_time_original: date.map(|d| d.to_string()),
+            create_date: date.map(|d| d.to_string()),
+            make: Some(make.to_string()),
+            model: Some(model.to_string()),
+            software: None,
+            keywords: vec![],
+            ..Default::default()
+        }
+    }
+
+    fn range(start: Option<&str>, end: Option<&str>) -> DateRange {
+        DateRange::new(&ShiftFilter {
+            directory: "/trip".to_string(),
+            camera_model: None,
+            start: start.map(|s| s.to_string()),
+            end: end.map(|s| s.to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_shift_value_crosses_year() {
+        let shifted = shift_value(&Some("2023:12:31 23:30:00".to_string()), Duration::hours(1));
+        assert_eq!(shifted.as_deref(), Some("2024:01:01 00:30:00"));
+        // Sub-seconds and zones are kept, garbage is left alone
+        let shifted = shift_value(&Some("2024:01:01 10:00:00.123+05:00".to_string()), Duration::seconds(-60));
+        assert_eq!(shifted.as_deref(), Some("2024:01:01 09:59:00.123+05:00"));
+        assert_eq!(shift_value(&Some("0000:00:00 00:00:00".to_string()), Duration::hours(1)), None);
+    }
+
+    #[test]
+    fn test_reference_delta() {
+        // Camera was a year and five minutes slow
+        let delta = reference_delta("2023:06:01 10:00:00", "2024-06-01 10:05:00").unwrap();
+        assert_eq!(delta, 366 * 86400 + 300);
+        assert!(reference_delta("not a date", "2024-06-01").is_err());
+    }
+
+    #[test]
+    fn test_preview_filters_by_camera_and_range() {
+        let delta = Duration::hours(-2);
+        let all = range(None, None);
+
+        let preview = preview_file(sample("Canon", "EOS 80D", Some("2024:03:10 14:00:00")), Some("Canon EOS 80D"), &all, delta).unwrap();
+        assert_eq!(preview.date_time_original_after.as_deref(), Some("2024:03:10 12:00:00"));
+        assert_eq!(preview.create_date_before.as_deref(), Some("2024:03:10 14:00:00"));
+
+        assert!(preview_file(sample("Apple", "iPhone 12", Some("2024:03:10 14:00:00")), Some("Canon EOS 80D"), &all, delta).is_none());
+
+        let march = range(Some("2024-03-01"), Some("2024-03-10"));
+        assert!(preview_file(sample("Canon", "EOS 80D", Some("2024:03:10 23:00:00")), None, &march, delta).is_some());
+        assert!(preview_file(sample("Canon", "EOS 80D", Some("2024:03:11 00:00:00")), None, &march, delta).is_none());
+        assert!(preview_file(sample("Canon", "EOS 80D", None), None, &march, delta).is_none());
+        assert!(preview_file(sample("Canon", "EOS 80D", None), None, &all, delta).is_none());
+    }
+
+    #[test]
+    fn test_shift_args() {
+        let preview = preview_file(
+            sample("Canon", "EOS 80D", Some("2024:03:10 14:00:00")),
+            None,
+            &range(None, None),
+            Duration::days(365),
+        )
+        .unwrap();
+        assert_eq!(
+            shift_args(&preview),
+            vec![
+                "-overwrite_original",
+                "-P",
+                "-DateTimeOriginal=2025:03:10 14:00:00",
+                "-CreateDate=2025:03:10 14:00:00",
+                "/trip/IMG_0001.JPG",
+            ]
+        );
+
+        // Suffixes stay out of the assignments, so their tags are untouched
+        let preview = preview_file(
+            sample("Canon", "EOS 80D", Some("2024:03:10 14:00:00.50+01:00")),
+            None,
+            &range(None, None),
+            Duration::hours(1),
+        )
+        .unwrap();
+        assert_eq!(preview.date_time_original_after.as_deref(), Some("2024:03:10 15:00:00.50+01:00"));
+        assert_eq!(shift_args(&preview)[2], "-DateTimeOriginal=2024:03:10 15:00:00");
+    }
+
+    #[test]
+    fn test_delta_deserialize() {
+        let delta: ShiftDelta = serde_json::from_str(r#"{"kind":"seconds","seconds":-3600}"#).unwrap();
+        assert!(matches!(delta, ShiftDelta::Seconds { seconds: -3600 }));
+        let delta: ShiftDelta =
+            serde_json::from_str(r#"{"kind":"reference","file_path":"/a.jpg","actual":"2024-01-01 10:00:00"}"#).unwrap();
+        assert!(matches!(delta, ShiftDelta::Reference { .. }));
+    }
+}
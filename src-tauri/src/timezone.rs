@@ -0,0 +1,254 @@
+//! Time zone handling for date writes.
+//!
+//! This module provides functions to:
+//! - Parse a user-supplied zone (`+05:00`, `-0330`, `UTC`, `Asia/Karachi`)
+//! - Resolve the UTC offset of a zone at a given local time (DST aware)
+//! - Guess a zone from GPS coordinates without network access
+//!
+//! The guess is a nearest-place lookup, not a boundary test: the app ships no
+//! zone polygons, only tzdb's `zone.tab` (one principal location per zone) and
+//! `cities.tsv` (several hundred cities with their zone). It is only made
+//! where no other zone is near: a known place within `MAX_ZONE_DISTANCE_KM`,
+//! and no place in another zone within `BORDER_MARGIN_KM` more than that.
+//! Everywhere else, including at sea, there is no zone and the caller writes
+//! the date without an offset. Zones with few sample points or borders that
+//! don't follow distance (Indiana and Kentucky, Spain and Portugal) can still
+//! be guessed wrong, so callers report a guessed zone as such.
+
+use chrono::{FixedOffset, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use std::sync::OnceLock;
+
+/// tzdb's table of zones and their principal locations (public domain)
+const ZONE_TAB: &str = include_str!("../resources/zone.tab");
+
+/// Cities with their zone, to refine `ZONE_TAB` inside large zones and near borders
+pub(crate) const CITIES: &str = include_str!("../resources/cities.tsv");
+
+/// Points further than this from every known place get no zone
+const MAX_ZONE_DISTANCE_KM: f64 = 100.0;
+
+/// A place in another zone this much further away than the nearest one makes
+/// the point too close to a border to call
+const BORDER_MARGIN_KM: f64 = 150.0;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A zone given by the user or guessed from a location
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoneSpec {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl ZoneSpec {
+    /// The UTC offset in effect at `local` wall-clock time.
+    ///
+    /// Ambiguous times (DST fall-back) resolve to the earlier offset; times
+    /// skipped by a DST jump have no offset.
+    pub fn offset_at(&self, local: &NaiveDateTime) -> Option<FixedOffset> {
+        match self {
+            ZoneSpec::Fixed(offset) => Some(*offset),
+            ZoneSpec::Named(tz) => tz.from_local_datetime(local).earliest().map(|dt| dt.offset().fix()),
+        }
+    }
+
+    /// The UTC offset in effect at the UTC time `utc`
+    pub fn offset_at_utc(&self, utc: &NaiveDateTime) -> FixedOffset {
+        match self {
+            ZoneSpec::Fixed(offset) => *offset,
+            ZoneSpec::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            ZoneSpec::Fixed(offset) => format_offset(offset),
+            ZoneSpec::Named(tz) => tz.name().to_string(),
+        }
+    }
+}
+
+/// Parse `Z`, `UTC`, `+05:00`, `+0500`, `+05`, `UTC+5:30` or an IANA zone name
+pub fn parse_zone(spec: &str) -> Result<ZoneSpec, String> {
+    let spec = spec.trim();
+    if spec.eq_ignore_ascii_case("z") || spec.eq_ignore_ascii_case("utc") || spec.eq_ignore_ascii_case("gmt") {
+        return Ok(ZoneSpec::Fixed(FixedOffset::east_opt(0).unwrap()));
+    }
+
+    let offset = spec
+        .strip_prefix("UTC")
+        .or_else(|| spec.strip_prefix("GMT"))
+        .unwrap_or(spec);
+    if offset.starts_with('+') || offset.starts_with('-') {
+        return parse_offset(offset).map(ZoneSpec::Fixed);
+    }
+
+    spec.parse::<Tz>()
+        .map(ZoneSpec::Named)
+        .map_err(|_| format!("Unknown time zone: {}", spec))
+}
+
+fn parse_offset(offset: &str) -> Result<FixedOffset, String> {
+    let invalid = || format!("Invalid UTC offset: {}", offset);
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+    let digits = &offset[1..];
+
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() > 2 => digits.split_at(digits.len() - 2),
+        None => (digits, "0"),
+    };
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 14 || minutes >= 60 {
+        return Err(invalid());
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+/// Format an offset the way EXIF `OffsetTime*` tags expect (`+05:00`)
+pub fn format_offset(offset: &FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+struct ZoneLocation {
+    latitude: f64,
+    longitude: f64,
+    zone: Tz,
+}
+
+fn zone_locations() -> &'static [ZoneLocation] {
+    static ZONES: OnceLock<Vec<ZoneLocation>> = OnceLock::new();
+    ZONES.get_or_init(|| {
+        let zone_tab = data_lines(ZONE_TAB).filter_map(|line| {
+            let mut columns = line.split('\t');
+            let _country = columns.next()?;
+            let (latitude, longitude) = parse_iso6709_dms(columns.next()?)?;
+            let zone = columns.next()?.parse::<Tz>().ok()?;
+            Some(ZoneLocation { latitude, longitude, zone })
+        });
+
+        // name, region, country, latitude, longitude, zone
+        let cities = data_lines(CITIES).filter_map(|line| {
+            let columns: Vec<&str> = line.split('\t').collect();
+            Some(ZoneLocation {
+                latitude: columns.get(3)?.parse().ok()?,
+                longitude: columns.get(4)?.parse().ok()?,
+                zone: columns.get(5)?.parse().ok()?,
+            })
+        });
+
+        zone_tab.chain(cities).collect()
+    })
+}
+
+pub(crate) fn data_lines(table: &str) -> impl Iterator<Item = &str> {
+    table.lines().filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Parse zone.tab coordinates: `±DDMM±DDDMM` or `±DDMMSS±DDDMMSS`
+fn parse_iso6709_dms(coordinates: &str) -> Option<(f64, f64)> {
+    let split = coordinates[1..].find(['+', '-'])? + 1;
+    let (latitude, longitude) = coordinates.split_at(split);
+    Some((parse_dms(latitude, 2)?, parse_dms(longitude, 3)?))
+}
+
+fn parse_dms(value: &str, degree_digits: usize) -> Option<f64> {
+    let sign = if value.starts_with('-') { -1.0 } else { 1.0 };
+    let digits = &value[1..];
+    let degrees: f64 = digits.get(..degree_digits)?.parse().ok()?;
+    let minutes: f64 = digits.get(degree_digits..degree_digits + 2)?.parse().ok()?;
+    let seconds: f64 = digits.get(degree_digits + 2..).filter(|s| !s.is_empty()).map_or(Some(0.0), |s| s.parse().ok())?;
+    Some(sign * (degrees + minutes / 60.0 + seconds / 3600.0))
+}
+
+/// Great-circle distance in kilometres
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Guess the zone at a location from the nearest places in the bundled
+/// tables, or None when places of another zone are close too
+pub fn guess_zone_for_location(latitude: f64, longitude: f64) -> Option<ZoneSpec> {
+    let distances: Vec<(f64, Tz)> = zone_locations()
+        .iter()
+        .map(|z| (haversine_km(latitude, longitude, z.latitude, z.longitude), z.zone))
+        .collect();
+    let &(nearest, zone) = distances.iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
+    if nearest > MAX_ZONE_DISTANCE_KM {
+        return None;
+    }
+    let contested = distances.iter().any(|&(distance, other)| other != zone && distance <= nearest + BORDER_MARGIN_KM);
+    (!contested).then_some(ZoneSpec::Named(zone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_zone_offsets() {
+        let offset = |s: &str| parse_zone(s).unwrap().offset_at(&at(2024, 1, 1)).unwrap();
+        assert_eq!(format_offset(&offset("+05:00")), "+05:00");
+        assert_eq!(format_offset(&offset("-0330")), "-03:30");
+        assert_eq!(format_offset(&offset("UTC+5:45")), "+05:45");
+        assert_eq!(format_offset(&offset("+09")), "+09:00");
+        assert_eq!(format_offset(&offset("Z")), "+00:00");
+        assert!(parse_zone("+25:00").is_err());
+        assert!(parse_zone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_named_zone_follows_dst() {
+        let zone = parse_zone("Europe/Berlin").unwrap();
+        assert_eq!(format_offset(&zone.offset_at(&at(2024, 1, 15)).unwrap()), "+01:00");
+        assert_eq!(format_offset(&zone.offset_at(&at(2024, 7, 15)).unwrap()), "+02:00");
+        // 02:30 on the spring-forward night doesn't exist
+        let skipped = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap().and_hms_opt(2, 30, 0).unwrap();
+        assert!(zone.offset_at(&skipped).is_none());
+    }
+
+    #[test]
+    fn test_zone_for_location() {
+        let name = |lat, lon| guess_zone_for_location(lat, lon).map(|z| z.name());
+        assert_eq!(name(24.8607, 67.0011).as_deref(), Some("Asia/Karachi")); // Karachi
+        assert_eq!(name(48.8566, 2.3522).as_deref(), Some("Europe/Paris"));
+        assert_eq!(name(-33.8688, 151.2093).as_deref(), Some("Australia/Sydney"));
+        // Between Geneva and the French border: not for a nearest-city guess
+        assert_eq!(name(46.2, 6.05), None);
+        // Middle of the South Pacific
+        assert_eq!(name(-40.0, -130.0), None);
+    }
+
+    #[test]
+    fn test_bundled_tables_parse() {
+        let cities = data_lines(CITIES).count();
+        let zones = data_lines(ZONE_TAB).count();
+        // Every row must load, or lookups silently lose coverage
+        assert_eq!(zone_locations().len(), cities + zones);
+    }
+
+    #[test]
+    fn test_parse_zone_tab_coordinates() {
+        let (lat, lon) = parse_iso6709_dms("+4230+00131").unwrap();
+        assert!((lat - 42.5).abs() < 1e-9);
+        assert!((lon - (1.0 + 31.0 / 60.0)).abs() < 1e-9);
+        let (lat, lon) = parse_iso6709_dms("-332754-0703959").unwrap();
+        assert!(lat < -33.46 && lat > -33.47);
+        assert!(lon < -70.66 && lon > -70.67);
+    }
+}
@@ -0,0 +1,260 @@
+//! Dry-run plans for metadata writes.
+//!
+//! This module provides functions to:
+//! - Describe an exiftool write as per-tag changes (current vs proposed value)
+//! - Return either a write's message or its plan from the write commands
+//! - Plan date fixes for a whole `scan_missing_dates` result at once
+//!
+//! Plans are built from the exact exiftool arguments a real write would use,
+//! so a plan never drifts from what applying it does.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::journal;
+use crate::metadata::{self, FileMetadataInfo};
+use crate::state::AppState;
+use crate::xmp_sidecar::WriteTarget;
+
+/// One tag a write would touch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagChange {
+    pub tag: String,
+    pub current_value: Option<String>,
+    pub proposed_value: Option<String>,
+    /// Set when this tag would be left alone
+    pub skip_reason: Option<String>,
+}
+
+/// Everything a write would do to one file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilePlan {
+    pub file_path: String,
+    pub changes: Vec<TagChange>,
+}
+
+impl FilePlan {
+    /// A plan that leaves the file untouched
+    pub fn skipped(file_path: &str, tag: &str, current_value: Option<String>, reason: &str) -> Self {
+        Self {
+            file_path: file_path.to_string(),
+            changes: vec![TagChange {
+                tag: tag.to_string(),
+                current_value,
+                proposed_value: None,
+                skip_reason: Some(reason.to_string()),
+            }],
+        }
+    }
+
+    pub fn will_change(&self) -> bool {
+        self.changes.iter().any(|c| c.skip_reason.is_none())
+    }
+}
+
+/// Aggregated plan over many files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WritePlan {
+    pub files: Vec<FilePlan>,
+    pub files_to_change: usize,
+    pub files_skipped: usize,
+}
+
+impl WritePlan {
+    pub fn push(&mut self, plan: FilePlan) {
+        if plan.will_change() {
+            self.files_to_change += 1;
+        } else {
+            self.files_skipped += 1;
+        }
+        self.files.push(plan);
+    }
+}
+
+/// Result of a write command: a message after writing, or the plan in dry-run.
+/// Untagged so a real write still serializes as a plain string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WriteOutcome {
+    Written(String),
+    Planned(FilePlan),
+}
+
+/// Display form of an exiftool JSON value
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(display_value).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn value_items(value: &Option<Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items.iter().map(display_value).collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(other) => vec![display_value(other)],
+    }
+}
+
+/// Split `-TAG=value` / `-TAG+=value` / `-TAG#=value` into (tag, is_addition, value)
+fn parse_assignment(arg: &str) -> Option<(&str, bool, &str)> {
+    let (lhs, value) = arg.strip_prefix('-')?.split_once('=')?;
+    let tag = lhs.trim_end_matches('#');
+    match tag.strip_suffix('+') {
+        Some(tag) => Some((tag, true, value)),
+        None => Some((tag, false, value)),
+    }
+}
+
+/// Compute the per-tag changes of an exiftool write, given each tag's current value
+pub fn changes_for_args(args: &[String], mut current: impl FnMut(&str) -> Option<Value>) -> Vec<TagChange> {
+    let mut proposed: Vec<(String, Option<Value>, Vec<String>)> = Vec::new();
+
+    for (tag, is_addition, value) in args.iter().filter_map(|a| parse_assignment(a)) {
+        let index = match proposed.iter().position(|(t, _, _)| t == tag) {
+            Some(index) => index,
+            None => {
+                let before = current(tag);
+                // Additions extend the existing list, assignments replace it
+                let items = if is_addition { value_items(&before) } else { Vec::new() };
+                proposed.push((tag.to_string(), before, items));
+                proposed.len() - 1
+            }
+        };
+        let items = &mut proposed[index].2;
+        if !value.is_empty() && !items.iter().any(|i| i == value) {
+            items.push(value.to_string());
+        }
+    }
+
+    proposed
+        .into_iter()
+        .map(|(tag, before, items)| {
+            let current_value = before.as_ref().map(display_value).filter(|v| !v.is_empty());
+            let proposed_value = Some(items.join(", ")).filter(|v| !v.is_empty());
+            let skip_reason = (current_value == proposed_value).then(|| "Already set".to_string());
+            TagChange {
+                tag,
+                current_value,
+                proposed_value,
+                skip_reason,
+            }
+        })
+        .collect()
+}
+
+/// Describe what `args` would do to `file_path`. Current values need exiftool;
+/// without it they are reported as unknown (None).
+pub fn describe_write(state: &AppState, file_path: &str, args: &[String]) -> FilePlan {
+    let installed = state.exiftool.is_installed();
+    let changes = changes_for_args(args, |tag| {
+        installed
+            .then(|| journal::read_tag(&state.exiftool, file_path, tag).ok().flatten())
+            .flatten()
+    });
+    FilePlan {
+        file_path: file_path.to_string(),
+        changes,
+    }
+}
+
+/// Plan the date fixes for a `scan_missing_dates` result without writing anything
+#[tauri::command]
+pub fn plan_metadata_fixes(
+    state: tauri::State<'_, AppState>,
+    files: Vec<FileMetadataInfo>,
+    timezone: Option<String>,
+    write_target: Option<WriteTarget>,
+) -> Result<WritePlan, String> {
+    let target = write_target.unwrap_or_else(|| state.write_target());
+    let mut plan = WritePlan::default();
+
+    for file in files {
+        let file_plan = match (&file.extracted_date, file.has_date) {
+            (_, true) => FilePlan::skipped(&file.file_path, "DateTimeOriginal", None, "Date already exists"),
+            (None, false) => FilePlan::skipped(&file.file_path, "DateTimeOriginal", None, "No date suggestion"),
+            (Some(date), false) => {
+                let prepared = metadata::prepare_date_write(
+                    &state,
+                    &file.file_path,
+                    &date.date,
+                    date.time.clone(),
+                    // A UTC filename stamp is written with its own offset
+                    date.offset.clone().or_else(|| timezone.clone()),
+                    target,
+                    false,
+                );
+                match prepared {
+                    Ok(prepared) => prepared.plan(&state, &file.file_path),
+                    Err(e) => FilePlan::skipped(&file.file_path, "DateTimeOriginal", None, &e),
+                }
+            }
+        };
+        plan.push(file_plan);
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_changes_for_date_write() {
+        let args = strings(&[
+            "-overwrite_original",
+            "-DateTimeOriginal=2024:01:15 10:30:00",
+            "-CreateDate=2024:01:15 10:30:00",
+            "/a.jpg",
+        ]);
+        let changes = changes_for_args(&args, |tag| match tag {
+            "CreateDate" => Some(json!("2024:01:15 10:30:00")),
+            _ => None,
+        });
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].current_value, None);
+        assert_eq!(changes[0].proposed_value.as_deref(), Some("2024:01:15 10:30:00"));
+        assert!(changes[0].skip_reason.is_none());
+        assert_eq!(changes[1].skip_reason.as_deref(), Some("Already set"));
+    }
+
+    #[test]
+    fn test_changes_for_list_additions() {
+        let args = strings(&[
+            "-XMP-iptcExt:PersonInImage+=Bob",
+            "-XMP-iptcExt:PersonInImage+=Carol",
+            "/a.jpg",
+        ]);
+        let changes = changes_for_args(&args, |_| Some(json!(["Alice", "Bob"])));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].current_value.as_deref(), Some("Alice, Bob"));
+        assert_eq!(changes[0].proposed_value.as_deref(), Some("Alice, Bob, Carol"));
+    }
+
+    #[test]
+    fn test_write_plan_counts() {
+        let mut plan = WritePlan::default();
+        plan.push(FilePlan::skipped("/a.jpg", "DateTimeOriginal", Some("2024:01:01 00:00:00".into()), "Date already exists"));
+        plan.push(FilePlan {
+            file_path: "/b.jpg".to_string(),
+            changes: changes_for_args(&strings(&["-Keywords=Trip", "/b.jpg"]), |_| None),
+        });
+        assert_eq!(plan.files_to_change, 1);
+        assert_eq!(plan.files_skipped, 1);
+    }
+
+    #[test]
+    fn test_outcome_serializes_written_as_string() {
+        let written = serde_json::to_value(WriteOutcome::Written("Date written".into())).unwrap();
+        assert_eq!(written, json!("Date written"));
+        let planned = serde_json::to_value(WriteOutcome::Planned(FilePlan::skipped("/a.jpg", "Keywords", None, "No keywords to write"))).unwrap();
+        assert_eq!(planned["file_path"], "/a.jpg");
+    }
+}
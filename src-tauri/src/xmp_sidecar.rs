@@ -0,0 +1,319 @@
+//! XMP sidecar files, for archives whose originals must never be rewritten.
+//!
+//! This module provides functions to:
+//! - Choose where metadata writes go (`WriteTarget`), per call or from settings
+//! - Locate the sidecar of a media file
+//! - Build exiftool arguments writing dates and keywords into a sidecar
+//! - Merge sidecar values over embedded ones, recording where each came from
+//!
+//! Writes go into the sidecar a file already has, including the `<stem>.xmp`
+//! form Lightroom writes, so its edits and ours stay in one place. Only a file
+//! without one gets a new `<file name>.xmp` (`IMG_1234.CR2.xmp`), the form
+//! darktable and Immich read, which exiftool creates on the first write.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::keywords::KeywordSet;
+use crate::metadata::{ExifMetadata, MetadataBackend};
+use crate::native_exif::{self, MetadataBlocks};
+
+/// Settings key holding the default `WriteTarget`
+pub const SETTINGS_KEY: &str = "metadataWriteTarget";
+
+/// Where metadata writes go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteTarget {
+    /// Rewrite the media file itself
+    #[default]
+    InPlace,
+    /// Write or merge the file's sidecar, leaving the media file untouched
+    Sidecar,
+    /// Write the media file and its sidecar
+    Both,
+}
+
+impl WriteTarget {
+    /// The target saved in settings, or `InPlace` when unset or unknown
+    pub fn from_settings_json(settings: &str) -> Self {
+        serde_json::from_str::<serde_json::Value>(settings)
+            .ok()
+            .and_then(|v| v.get(SETTINGS_KEY).cloned())
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn in_place(self) -> bool {
+        matches!(self, WriteTarget::InPlace | WriteTarget::Both)
+    }
+
+    pub fn sidecar(self) -> bool {
+        matches!(self, WriteTarget::Sidecar | WriteTarget::Both)
+    }
+}
+
+/// Where a merged metadata value was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSource {
+    Embedded,
+    Sidecar,
+}
+
+/// The sidecar writes go to: the existing one (see `find_sidecar`), else
+/// `IMG_1234.CR2` -> `IMG_1234.CR2.xmp`
+pub fn sidecar_path(file_path: &str) -> String {
+    match find_sidecar(file_path) {
+        Some(existing) => existing.to_string_lossy().to_string(),
+        None => format!("{}.xmp", file_path),
+    }
+}
+
+/// An existing sidecar: `<file>.xmp` first, then `<stem>.xmp`
+pub fn find_sidecar(file_path: &str) -> Option<PathBuf> {
+    let path = Path::new(file_path);
+    let candidates = [
+        PathBuf::from(format!("{}.xmp", file_path)),
+        PathBuf::from(format!("{}.XMP", file_path)),
+        path.with_extension("xmp"),
+        path.with_extension("XMP"),
+    ];
+    candidates.into_iter().find(|c| c.as_path() != path && c.is_file())
+}
+
+/// Metadata from the sidecar of `file_path`, if it has one
+pub fn read_sidecar(file_path: &str) -> Result<Option<ExifMetadata>, String> {
+    match find_sidecar(file_path) {
+        Some(sidecar) => read_xmp_file(&sidecar.to_string_lossy()).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Metadata from a standalone XMP file
+pub fn read_xmp_file(path: &str) -> Result<ExifMetadata, String> {
+    let xmp = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let blocks = MetadataBlocks {
+        xmp: Some(xmp),
+        ..Default::default()
+    };
+    Ok(native_exif::metadata_from_blocks(path.to_string(), &blocks))
+}
+
+/// Whether `path` is an XMP sidecar rather than a media file
+pub fn is_sidecar(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xmp"))
+}
+
+/// Take the sidecar value when it has one, and note where the value came from
+fn pick<T>(
+    sources: &mut BTreeMap<String, MetadataSource>,
+    field: &str,
+    embedded: Option<T>,
+    sidecar: Option<T>,
+) -> Option<T> {
+    let (value, source) = match (embedded, sidecar) {
+        (_, Some(value)) => (value, MetadataSource::Sidecar),
+        (Some(value), None) => (value, MetadataSource::Embedded),
+        (None, None) => return None,
+    };
+    sources.insert(field.to_string(), source);
+    Some(value)
+}
+
+/// Merge sidecar values over embedded ones. A non-empty sidecar keyword list
+/// replaces the embedded one, as it does in darktable and Immich.
+pub fn merge(file_path: &str, embedded: Option<ExifMetadata>, sidecar: Option<ExifMetadata>) -> ExifMetadata {
+    let embedded = embedded.unwrap_or_default();
+    let sidecar = sidecar.unwrap_or_default();
+    let non_empty = |list: Vec<String>| Some(list).filter(|l| !l.is_empty());
+
+    let mut sources = BTreeMap::new();
+    ExifMetadata {
+        file_path: file_path.to_string(),
+        date_time_original: pick(&mut sources, "date_time_original", embedded.date_time_original, sidecar.date_time_original),
+        create_date: pick(&mut sources, "create_date", embedded.create_date, sidecar.create_date),
+        make: pick(&mut sources, "make", embedded.make, sidecar.make),
+        model: pick(&mut sources, "model", embedded.model, sidecar.model),
+        software: pick(&mut sources, "software", embedded.software, sidecar.software),
+        keywords: pick(&mut sources, "keywords", non_empty(embedded.keywords), non_empty(sidecar.keywords)).unwrap_or_default(),
+        hierarchical_keywords: pick(
+            &mut sources,
+            "hierarchical_keywords",
+            non_empty(embedded.hierarchical_keywords),
+            non_empty(sidecar.hierarchical_keywords),
+        )
+        .unwrap_or_default(),
+        gps: pick(&mut sources, "gps", embedded.gps, sidecar.gps),
+        // Properties of the media stream itself; a sidecar would only describe the .xmp file
+        image_width: pick(&mut sources, "image_width", embedded.image_width, None),
+        image_height: pick(&mut sources, "image_height", embedded.image_height, None),
+        duration: pick(&mut sources, "duration", embedded.duration, None),
+        video_codec: pick(&mut sources, "video_codec", embedded.video_codec, None),
+        mime_type: pick(&mut sources, "mime_type", embedded.mime_type, None),
+        orientation: pick(&mut sources, "orientation", embedded.orientation, sidecar.orientation),
+        lens_model: pick(&mut sources, "lens_model", embedded.lens_model, sidecar.lens_model),
+        iso: pick(&mut sources, "iso", embedded.iso, sidecar.iso),
+        exposure_time: pick(&mut sources, "exposure_time", embedded.exposure_time, sidecar.exposure_time),
+        f_number: pick(&mut sources, "f_number", embedded.f_number, sidecar.f_number),
+        sub_sec_time_original: pick(
+            &mut sources,
+            "sub_sec_time_original",
+            embedded.sub_sec_time_original,
+            sidecar.sub_sec_time_original,
+        ),
+        offset_time_original: pick(
+            &mut sources,
+            "offset_time_original",
+            embedded.offset_time_original,
+            sidecar.offset_time_original,
+        ),
+        image_unique_id: pick(&mut sources, "image_unique_id", embedded.image_unique_id, sidecar.image_unique_id),
+        content_identifier: pick(
+            &mut sources,
+            "content_identifier",
+            embedded.content_identifier,
+            sidecar.content_identifier,
+        ),
+        burst_uuid: pick(&mut sources, "burst_uuid", embedded.burst_uuid, sidecar.burst_uuid),
+        rating: pick(&mut sources, "rating", embedded.rating, sidecar.rating),
+        description: pick(&mut sources, "description", embedded.description, sidecar.description),
+        sources,
+    }
+}
+
+/// Read a file's embedded metadata and merge its sidecar over it
+pub fn read_merged(backend: &dyn MetadataBackend, file_path: &str) -> Result<ExifMetadata, String> {
+    let sidecar = read_sidecar(file_path)?;
+    match backend.read(file_path) {
+        Ok(embedded) => Ok(merge(file_path, Some(embedded), sidecar)),
+        Err(_) if sidecar.is_some() => Ok(merge(file_path, None, sidecar)),
+        Err(e) => Err(e),
+    }
+}
+
+/// exiftool arguments writing a capture date into a sidecar.
+/// XMP dates carry their UTC offset inline.
+pub fn date_args(file_path: &str, datetime: &str, offset: Option<&str>) -> Vec<String> {
+    let zoned = format!("{}{}", datetime, offset.unwrap_or(""));
+    vec![
+        "-overwrite_original".to_string(),
+        format!("-XMP-exif:DateTimeOriginal={}", zoned),
+        format!("-XMP-xmp:CreateDate={}", zoned),
+        sidecar_path(file_path),
+    ]
+}
+
+/// exiftool arguments replacing the keyword lists of a sidecar
+pub fn keyword_args(file_path: &str, keywords: &KeywordSet) -> Vec<String> {
+    let mut args = vec!["-overwrite_original".to_string()];
+    args.extend(keywords.xmp_assignments());
+    args.push(sidecar_path(file_path));
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF>
+<rdf:Description exif:DateTimeOriginal="2021-06-01T08:00:00+02:00">
+<dc:subject><rdf:Bag><rdf:li>Holiday</rdf:li><rdf:li>Beach, North</rdf:li></rdf:Bag></dc:subject>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    fn embedded() -> ExifMetadata {
+        ExifMetadata {
+            file_path: "/a/IMG_1.CR2".to_string(),
+            date_time_original: Some("2020:01:01 00:00:00".to_string()),
+            model: Some("EOS R5".to_string()),
+            keywords: vec!["Camera".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_write_target_from_settings() {
+        assert_eq!(WriteTarget::from_settings_json(r#"{"metadataWriteTarget": "sidecar"}"#), WriteTarget::Sidecar);
+        assert_eq!(WriteTarget::from_settings_json(r#"{"metadataWriteTarget": "both"}"#), WriteTarget::Both);
+        assert_eq!(WriteTarget::from_settings_json(r#"{"archivePath": "/a"}"#), WriteTarget::InPlace);
+        assert_eq!(WriteTarget::from_settings_json("not json"), WriteTarget::InPlace);
+        assert!(WriteTarget::Both.in_place() && WriteTarget::Both.sidecar());
+        assert!(!WriteTarget::Sidecar.in_place());
+    }
+
+    #[test]
+    fn test_find_sidecar_forms() {
+        let dir = tempdir().unwrap();
+        let raw = dir.path().join("IMG_1.CR2");
+        fs::write(&raw, b"raw").unwrap();
+        let raw = raw.to_string_lossy().to_string();
+        assert!(find_sidecar(&raw).is_none());
+
+        assert_eq!(sidecar_path(&raw), format!("{}.xmp", raw));
+
+        // Lightroom-style stem sidecar, which writes then go into
+        fs::write(dir.path().join("IMG_1.xmp"), SIDECAR).unwrap();
+        assert_eq!(find_sidecar(&raw).unwrap(), dir.path().join("IMG_1.xmp"));
+        assert_eq!(sidecar_path(&raw), dir.path().join("IMG_1.xmp").to_string_lossy());
+
+        // The full-name form wins
+        fs::write(format!("{}.xmp", raw), SIDECAR).unwrap();
+        assert_eq!(find_sidecar(&raw).unwrap(), PathBuf::from(format!("{}.xmp", raw)));
+        assert_eq!(sidecar_path(&raw), format!("{}.xmp", raw));
+    }
+
+    #[test]
+    fn test_merge_prefers_sidecar_and_reports_sources() {
+        let dir = tempdir().unwrap();
+        let raw = dir.path().join("IMG_1.CR2").to_string_lossy().to_string();
+        fs::write(sidecar_path(&raw), SIDECAR).unwrap();
+
+        let merged = merge(&raw, Some(embedded()), read_sidecar(&raw).unwrap());
+        assert_eq!(merged.file_path, raw);
+        assert_eq!(merged.date_time_original.as_deref(), Some("2021:06:01 08:00:00+02:00"));
+        assert_eq!(merged.keywords, vec!["Holiday", "Beach, North"]);
+        assert_eq!(merged.model.as_deref(), Some("EOS R5"));
+        assert_eq!(merged.sources["date_time_original"], MetadataSource::Sidecar);
+        assert_eq!(merged.sources["keywords"], MetadataSource::Sidecar);
+        assert_eq!(merged.sources["model"], MetadataSource::Embedded);
+        assert!(!merged.sources.contains_key("software"));
+    }
+
+    #[test]
+    fn test_merge_without_sidecar_is_embedded() {
+        let merged = merge("/a/IMG_1.CR2", Some(embedded()), None);
+        assert_eq!(merged.keywords, vec!["Camera"]);
+        assert!(merged.sources.values().all(|s| *s == MetadataSource::Embedded));
+    }
+
+    #[test]
+    fn test_sidecar_args() {
+        assert_eq!(
+            date_args("/a/IMG_1.CR2", "2024:01:15 10:30:00", Some("+05:00")),
+            vec![
+                "-overwrite_original",
+                "-XMP-exif:DateTimeOriginal=2024:01:15 10:30:00+05:00",
+                "-XMP-xmp:CreateDate=2024:01:15 10:30:00+05:00",
+                "/a/IMG_1.CR2.xmp",
+            ]
+        );
+        let mut keywords = KeywordSet::default();
+        keywords.add("Trip");
+        keywords.add("Lahore, Pakistan");
+        assert_eq!(
+            keyword_args("/a/IMG_1.CR2", &keywords),
+            vec![
+                "-overwrite_original",
+                "-XMP-dc:Subject=Trip",
+                "-XMP-dc:Subject=Lahore, Pakistan",
+                "/a/IMG_1.CR2.xmp",
+            ]
+        );
+    }
+}
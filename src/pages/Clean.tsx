@@ -22,6 +22,7 @@ interface ExtractedDate {
     date: string;
     time: string | null;
     source: string;
+    offset: string | null;
 }
 
 interface FileMetadataInfo {
@@ -31,6 +32,24 @@ interface FileMetadataInfo {
     camera_model: string | null;
 }
 
+interface TagChange {
+    tag: string;
+    current_value: string | null;
+    proposed_value: string | null;
+    skip_reason: string | null;
+}
+
+interface FilePlan {
+    file_path: string;
+    changes: TagChange[];
+}
+
+interface WritePlan {
+    files: FilePlan[];
+    files_to_change: number;
+    files_skipped: number;
+}
+
 interface DuplicateFile {
     path: string;
     size: number;
@@ -78,10 +97,13 @@ export function Clean() {
     const [metadataResults, setMetadataResults] = useState<FileMetadataInfo[]>([]);
     const [showOnlyMissing, setShowOnlyMissing] = useState(true);
     const [selectedForFix, setSelectedForFix] = useState<Set<string>>(new Set());
+    const [fixPlan, setFixPlan] = useState<WritePlan | null>(null);
 
     // Duplicate state
     const [dupResults, setDupResults] = useState<DedupResult | null>(null);
     const [selectedForDelete, setSelectedForDelete] = useState<Set<string>>(new Set());
+    const [deleteCompanions, setDeleteCompanions] = useState<string[]>([]);
+    const [includeCompanions, setIncludeCompanions] = useState(false);
 
     // Similar state
     const [similarResults, setSimilarResults] = useState<SimilarResult | null>(null);
@@ -192,10 +214,22 @@ export function Clean() {
         }
     };
 
+    const handlePreviewFixes = async () => {
+        const selected = metadataResults.filter((f) => selectedForFix.has(f.file_path));
+        try {
+            const plan = await invoke<WritePlan>("plan_metadata_fixes", { files: selected });
+            setFixPlan(plan);
+        } catch (err) {
+            setError(`Failed to preview fixes: ${err}`);
+        }
+    };
+
     const handleFixSelected = async () => {
         const toFix = metadataResults.filter(
             (f) => selectedForFix.has(f.file_path) && f.extracted_date
         );
+        // One journal operation for the whole batch so it can be undone together
+        const fixOperationId = `fix-dates-${Date.now()}`;
 
         for (const file of toFix) {
             if (file.extracted_date) {
@@ -204,6 +238,8 @@ export function Clean() {
                         filePath: file.file_path,
                         date: file.extracted_date.date,
                         time: file.extracted_date.time,
+                        timezone: file.extracted_date.offset,
+                        operationId: fixOperationId,
                     });
                 } catch (err) {
                     console.error(`Failed to fix ${file.file_path}:`, err);
@@ -213,6 +249,7 @@ export function Clean() {
         // Rescan after fixing
         handleScanMetadata();
         setSelectedForFix(new Set());
+        setFixPlan(null);
     };
 
     const handleScanDuplicates = async () => {
@@ -261,12 +298,24 @@ export function Clean() {
         }
     };
 
+    // Companions (Live Photo video, RAW partner, edits) only go along when opted in
+    useEffect(() => {
+        const files = Array.from(selectedForDelete);
+        if (files.length === 0) {
+            setDeleteCompanions([]);
+            return;
+        }
+        invoke<string[]>("list_trash_companions", { files })
+            .then(setDeleteCompanions)
+            .catch(() => setDeleteCompanions([]));
+    }, [selectedForDelete]);
+
     const handleDeleteSelected = async () => {
         const toDelete = Array.from(selectedForDelete);
         if (toDelete.length === 0) return;
 
         try {
-            const result = await invoke<string>("delete_to_trash", { files: toDelete });
+            const result = await invoke<string>("delete_to_trash", { files: toDelete, includeCompanions });
             console.log(result);
             // Rescan after deletion
             handleScanDuplicates();
@@ -510,8 +559,37 @@ export function Clean() {
                                     ))}
                                 </div>
 
+                                {fixPlan && (
+                                    <div className="mt-4 p-4 rounded-lg bg-slate-800/50 border border-slate-700">
+                                        <p className="text-sm text-slate-300 mb-2">
+                                            {fixPlan.files_to_change} file(s) will change, {fixPlan.files_skipped} skipped
+                                        </p>
+                                        <div className="max-h-64 overflow-y-auto space-y-2">
+                                            {fixPlan.files.map((plan) => (
+                                                <div key={plan.file_path} className="text-xs">
+                                                    <p className="text-slate-300 truncate">{plan.file_path.split("/").pop()}</p>
+                                                    {plan.changes.map((change) => (
+                                                        <p key={change.tag} className="text-slate-500 pl-3">
+                                                            {change.tag}:{" "}
+                                                            {change.skip_reason
+                                                                ? `skipped (${change.skip_reason})`
+                                                                : `${change.current_value ?? "(empty)"} → ${change.proposed_value ?? "(removed)"}`}
+                                                        </p>
+                                                    ))}
+                                                </div>
+                                            ))}
+                                        </div>
+                                    </div>
+                                )}
+
                                 {selectedForFix.size > 0 && (
-                                    <div className="mt-4 flex justify-end">
+                                    <div className="mt-4 flex justify-end gap-2">
+                                        <button
+                                            onClick={handlePreviewFixes}
+                                            className="px-4 py-2 rounded-lg bg-slate-700 hover:bg-slate-600 text-sm"
+                                        >
+                                            Preview Changes
+                                        </button>
                                         <button
                                             onClick={handleFixSelected}
                                             className="btn-primary px-4 py-2 flex items-center gap-2"
@@ -656,7 +734,25 @@ export function Clean() {
                 </div>
 
                                 {selectedForDelete.size > 0 && (
-                                    <div className="mt-4 flex justify-end">
+                                    <div className="mt-4 flex items-start justify-end gap-4">
+                                        {deleteCompanions.length > 0 && (
+                                            <div className="text-sm text-slate-400">
+                                                <label className="flex items-center gap-2">
+                                                    <input
+                                                        type="checkbox"
+                                                        checked={includeCompanions}
+                                                        onChange={(e) => setIncludeCompanions(e.target.checked)}
+                                                        className="rounded border-slate-600"
+                                                    />
+                                                    Also delete {deleteCompanions.length} companion files
+                                                </label>
+                                                <ul className="mt-1 ml-6 text-xs text-slate-500">
+                                                    {deleteCompanions.map((path) => (
+                                                        <li key={path}>{path}</li>
+                                                    ))}
+                                                </ul>
+                                            </div>
+                                        )}
                                         <button
                                             onClick={handleDeleteSelected}
                                             className="px-4 py-2 rounded-lg bg-red-500/20 text-red-400 hover:bg-red-500/30 flex items-center gap-2 font-medium"
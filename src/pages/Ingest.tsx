@@ -72,6 +72,11 @@ export function Ingest() {
   const [sourceTags, setSourceTags] = useState<SourceTag[]>([]);
   const [newTagName, setNewTagName] = useState("");
   const [enableTagging, setEnableTagging] = useState(true);
+  const [tagDryRun, setTagDryRun] = useState(false);
+  const [motionPhotoIngest, setMotionPhotoIngest] = useState<'off' | 'report' | 'extract' | 'strip'>('off');
+  const [integrityIngest, setIntegrityIngest] = useState<'off' | 'report' | 'quarantine'>('off');
+  const [quarantinePath, setQuarantinePath] = useState<string | null>(null);
+  const [integrityDecode, setIntegrityDecode] = useState(false);
 
   const [cameraModels, setCameraModels] = useState<CameraModelGroup[]>([]);
   const [directoryGroups, setDirectoryGroups] = useState<DirectoryGroup[]>([]);
@@ -193,6 +198,18 @@ export function Ingest() {
           if (settings.sourceTags) {
             setSourceTags(settings.sourceTags);
           }
+          if (settings.motionPhotoIngest) {
+            setMotionPhotoIngest(settings.motionPhotoIngest);
+          }
+          if (settings.integrityIngest) {
+            setIntegrityIngest(settings.integrityIngest);
+          }
+          if (settings.integrityDecode) {
+            setIntegrityDecode(true);
+          }
+          if (settings.quarantinePath) {
+            setQuarantinePath(settings.quarantinePath);
+          }
         }
       } catch (err) {
         console.error("Failed to load settings:", err);
@@ -386,8 +403,12 @@ export function Ingest() {
       if (ingestType === 'local') {
         // MULTI-STEP WORKFLOW
 
-        // If tagging is disabled, skip staging and tagging -> Run Phockup directly on source
-        if (!enableTagging) {
+        // Integrity and motion photo steps change files, so they only ever run on a staged copy
+        const stagingSteps = integrityIngest !== 'off' || motionPhotoIngest !== 'off';
+        const dryRun = enableTagging && tagDryRun;
+
+        // If tagging and the staging steps are disabled, skip staging -> Run Phockup directly on source
+        if (!enableTagging && !stagingSteps) {
           setStatus('organizing');
           addToLogs('Tagging skipped. Running Phockup on source directly...');
           const phockupCmd = navigator.platform.toLowerCase().includes('win') ? 'phockup.bat' : 'phockup';
@@ -423,110 +444,187 @@ export function Ingest() {
         await invoke('copy_to_staging', { source: sourcePath, staging: stagingPath });
         addToLogs('Copy completed.');
 
-        // 2. Tag Files in Staging
-        setStatus('tagging');
-        addToLogs('Applying tags to staged files...');
-
-        // Use the scannedFiles state but re-map paths to staging?
-        // Actually, we can just run apply logic based on the TAGS we have.
-        // We need to find files in STAGING that match our tags.
-        // Option: Rescan staging to get exact file paths.
-        addToLogs('Scanning staging directory to apply tags...');
-        const stagedFiles = await invoke<FileMetadataInfo[]>("scan_missing_dates", {
-          path: stagingPath,
-        });
+        // Optional: keep empty, truncated and corrupt files out of the archive
+        if (integrityIngest !== 'off') {
+          addToLogs(integrityDecode
+            ? 'Checking staged files for corruption (decoding JPEG and PNG images)...'
+            : 'Checking the structure of staged files (images are not decoded)...');
+          // Quarantine inside the archive would put the files back in Immich's library
+          const archive = destPath.replace(/[\\/]+$/, '');
+          const quarantineInArchive = !!quarantinePath
+            && (quarantinePath === archive || quarantinePath.startsWith(`${archive}/`) || quarantinePath.startsWith(`${archive}\\`));
+          let quarantineDir: string | null = null;
+          if (integrityIngest === 'quarantine' && !dryRun) {
+            if (!quarantinePath) {
+              addToLogs('No quarantine folder set in Settings; only reporting bad files.');
+            } else if (quarantineInArchive) {
+              addToLogs(`Quarantine folder ${quarantinePath} is inside the archive; only reporting bad files.`);
+            } else {
+              quarantineDir = quarantinePath;
+            }
+          }
+          const integrity = await invoke<{ files: { file_path: string; status: string; detail: string | null; quarantined_to: string | null }[]; checked: number | null; bad: number; quarantined: number }>("check_integrity", {
+            directory: stagingPath,
+            operationId: `ingest-integrity-${Date.now()}`,
+            // A dry run changes nothing, so bad files stay where they are
+            quarantineDir,
+            decode: integrityDecode,
+          });
+          for (const file of integrity.files) {
+            if (file.status === 'unchecked') continue;
+            addToLogs(`${file.status}: ${file.file_path}${file.detail ? ` (${file.detail})` : ''}${file.quarantined_to ? ` -> ${file.quarantined_to}` : ''}`);
+          }
+          addToLogs(`${integrity.checked !== null ? `Checked ${integrity.checked} files: ` : ''}${integrity.bad} bad, ${integrity.quarantined} quarantined.`);
+        }
+
+        // Optional: report, extract or strip motion photo videos in staging
+        if (motionPhotoIngest !== 'off') {
+          // A dry run only reports, since extracting and stripping change files
+          const action = dryRun ? 'report' : motionPhotoIngest;
+          addToLogs(`Checking staged photos for embedded motion photo videos (${action})...`);
+          const motionPhotos = await invoke<{ file_path: string; video_bytes: number; video_path: string | null; stripped: boolean; error: string | null }[]>("scan_motion_photos", {
+            directory: stagingPath,
+            operationId: `ingest-motion-${Date.now()}`,
+            action,
+          });
+          for (const photo of motionPhotos) {
+            if (photo.error) {
+              addToLogs(`Motion photo ${photo.file_path}: ${photo.error}`);
+            } else if (photo.video_path) {
+              addToLogs(`Extracted ${photo.video_path}`);
+            } else if (photo.stripped) {
+              addToLogs(`Stripped ${Math.round(photo.video_bytes / 1024)} KB video from ${photo.file_path}`);
+            }
+          }
+          addToLogs(`Found ${motionPhotos.length} motion photos.`);
+        }
+
+        // 2. Tag Files in Staging (tagging may be off when only the steps above need staging)
+        if (enableTagging) {
+          setStatus('tagging');
+          addToLogs('Applying tags to staged files...');
+
+          // Use the scannedFiles state but re-map paths to staging?
+          // Actually, we can just run apply logic based on the TAGS we have.
+          // We need to find files in STAGING that match our tags.
+          // Option: Rescan staging to get exact file paths.
+          addToLogs('Scanning staging directory to apply tags...');
+          const stagedFiles = await invoke<FileMetadataInfo[]>("scan_missing_dates", {
+            path: stagingPath,
+          });
 
-        let taggedCount = 0;
-        for (const file of stagedFiles) {
-          if (cancelledRef.current) throw new Error("Cancelled");
-
-          // Match logic
-          const model = file.camera_model || "Unknown";
-          let tag = sourceTags.find((t) => t.cameraAliases.includes(model));
-
-          if (!tag) {
-            // Calculate relative path in STAGING
-            // file.file_path is in stagingPath.
-            // We need relative path from stagingPath to match the relative path from sourcePath used in groupings.
-
-            let fileDir = file.file_path.substring(0, file.file_path.lastIndexOf('/'));
-            if (fileDir.startsWith(stagingPath)) {
-              let relDir = fileDir.substring(stagingPath.length);
-              if (relDir.startsWith('/')) relDir = relDir.substring(1);
-              // The "staging" dir structure mirrors "source" dir structure (rsync -a source/ staging/source_name/)
-              // WAIT: rsync creates a subdirectory with the source folder name inside stagingPath? 
-              // Let's check copy_to_staging implementation.
-              // "rsync -a source staging" -> if source is /a/b, and staging is /x/y, rsync makes /x/y/b/...
-              // So we need to strip the first component of the relative path to match the source relative path?
-
-              // Actually, in scanSource, we stripped sourcePath. 
-              // Example: Source=/Users/me/Photos. File=/Users/me/Photos/2023/Image.jpg. RelDir=2023.
-              // In Staging: Staging=/Tmp/Stage. rsync creates /Tmp/Stage/Photos/2023/Image.jpg.
-              // So file.file_path is /Tmp/Stage/Photos/2023/Image.jpg.
-              // We need to extract "2023".
-              // So relative path from staging is "Photos/2023".
-              // We need to strip the first component "Photos".
-
-              if (relDir) {
-                const parts = relDir.split('/');
-                if (parts.length > 0) {
-                  // The first part is the source directory name itself.
-                  // The rest is the relative path inside source.
-                  // If parts.length == 1, it means it's in the root of source dir. (e.g. "Photos"), so RelDir should be "Root"?
-                  // Re-check scanSource logic:
-                  // if RelDir empty -> "Root".
-                  // In internal relative path, it was "Relative from Source Root".
-
-                  // Here, RelDir is "SourceDirName/SubDir/..."
-                  // matches = parts.slice(1).join('/');
-                  // if (parts.length === 1) matches = "Root";
-
-                  let matchPath = parts.length > 1 ? parts.slice(1).join('/') : "Root";
-                  tag = sourceTags.find((t) =>
-                    (t.directoryPatterns || []).some(pattern => matchPath === pattern) // Exact match on relative path string?
-                    // Previous logic was simple string includes. Now we have full relative paths.
-                    // Let's assume user selected "2023/Trip" in dropdown. Tag pattern is "2023/Trip".
-                    // matchPath is "2023/Trip".
-                    // We should check exact match or at least "starts with"?
-                    // For now, let's use check if one includes the other or exact match.
-                    // Actually, the dropdown assigns the specific grouping key.
-                    // The grouping key IS the relative path.
-                    // So we should look for exact match of the key.
-                  );
-
-                  if (!tag) {
-                    // Fallback to simple inclusion check just in case
+          let taggedCount = 0;
+          const tagOperationId = `ingest-tags-${Date.now()}`;
+          for (const file of stagedFiles) {
+            if (cancelledRef.current) throw new Error("Cancelled");
+
+            // Match logic
+            const model = file.camera_model || "Unknown";
+            let tag = sourceTags.find((t) => t.cameraAliases.includes(model));
+
+            if (!tag) {
+              // Calculate relative path in STAGING
+              // file.file_path is in stagingPath.
+              // We need relative path from stagingPath to match the relative path from sourcePath used in groupings.
+
+              let fileDir = file.file_path.substring(0, file.file_path.lastIndexOf('/'));
+              if (fileDir.startsWith(stagingPath)) {
+                let relDir = fileDir.substring(stagingPath.length);
+                if (relDir.startsWith('/')) relDir = relDir.substring(1);
+                // The "staging" dir structure mirrors "source" dir structure (rsync -a source/ staging/source_name/)
+                // WAIT: rsync creates a subdirectory with the source folder name inside stagingPath? 
+                // Let's check copy_to_staging implementation.
+                // "rsync -a source staging" -> if source is /a/b, and staging is /x/y, rsync makes /x/y/b/...
+                // So we need to strip the first component of the relative path to match the source relative path?
+
+                // Actually, in scanSource, we stripped sourcePath. 
+                // Example: Source=/Users/me/Photos. File=/Users/me/Photos/2023/Image.jpg. RelDir=2023.
+                // In Staging: Staging=/Tmp/Stage. rsync creates /Tmp/Stage/Photos/2023/Image.jpg.
+                // So file.file_path is /Tmp/Stage/Photos/2023/Image.jpg.
+                // We need to extract "2023".
+                // So relative path from staging is "Photos/2023".
+                // We need to strip the first component "Photos".
+
+                if (relDir) {
+                  const parts = relDir.split('/');
+                  if (parts.length > 0) {
+                    // The first part is the source directory name itself.
+                    // The rest is the relative path inside source.
+                    // If parts.length == 1, it means it's in the root of source dir. (e.g. "Photos"), so RelDir should be "Root"?
+                    // Re-check scanSource logic:
+                    // if RelDir empty -> "Root".
+                    // In internal relative path, it was "Relative from Source Root".
+
+                    // Here, RelDir is "SourceDirName/SubDir/..."
+                    // matches = parts.slice(1).join('/');
+                    // if (parts.length === 1) matches = "Root";
+
+                    let matchPath = parts.length > 1 ? parts.slice(1).join('/') : "Root";
                     tag = sourceTags.find((t) =>
-                      (t.directoryPatterns || []).some(pattern => matchPath.includes(pattern))
+                      (t.directoryPatterns || []).some(pattern => matchPath === pattern) // Exact match on relative path string?
+                      // Previous logic was simple string includes. Now we have full relative paths.
+                      // Let's assume user selected "2023/Trip" in dropdown. Tag pattern is "2023/Trip".
+                      // matchPath is "2023/Trip".
+                      // We should check exact match or at least "starts with"?
+                      // For now, let's use check if one includes the other or exact match.
+                      // Actually, the dropdown assigns the specific grouping key.
+                      // The grouping key IS the relative path.
+                      // So we should look for exact match of the key.
                     );
+
+                    if (!tag) {
+                      // Fallback to simple inclusion check just in case
+                      tag = sourceTags.find((t) =>
+                        (t.directoryPatterns || []).some(pattern => matchPath.includes(pattern))
+                      );
+                    }
                   }
                 }
               }
             }
-          }
 
-          // Original fallback if relative path logic fails or is mismatched
-          if (!tag) {
-            const parts = file.file_path.split("/");
-            const parentDir = parts.length > 1 ? parts[parts.length - 2] : "Root";
-            tag = sourceTags.find((t) =>
-              (t.directoryPatterns || []).some(pattern => parentDir.includes(pattern))
-            );
-          }
+            // Original fallback if relative path logic fails or is mismatched
+            if (!tag) {
+              const parts = file.file_path.split("/");
+              const parentDir = parts.length > 1 ? parts[parts.length - 2] : "Root";
+              tag = sourceTags.find((t) =>
+                (t.directoryPatterns || []).some(pattern => parentDir.includes(pattern))
+              );
+            }
 
-          if (tag) {
-            try {
-              await invoke("write_exif_keywords", {
-                filePath: file.file_path,
-                keywords: [tag.name],
-              });
-              taggedCount++;
-            } catch (e) {
-              addToLogs(`Failed to tag ${file.file_path}: ${e}`);
+            if (tag) {
+              try {
+                const result = await invoke<string | { changes: { tag: string; current_value: string | null; proposed_value: string | null; skip_reason: string | null }[] }>("write_exif_keywords", {
+                  filePath: file.file_path,
+                  keywords: [tag.name],
+                  operationId: tagOperationId,
+                  dryRun,
+                });
+                if (typeof result !== "string") {
+                  for (const change of result.changes) {
+                    addToLogs(change.skip_reason
+                      ? `[dry run] ${file.file_path} ${change.tag}: skipped (${change.skip_reason})`
+                      : `[dry run] ${file.file_path} ${change.tag}: ${change.current_value ?? "(empty)"} -> ${change.proposed_value ?? "(removed)"}`);
+                  }
+                }
+                taggedCount++;
+              } catch (e) {
+                addToLogs(`Failed to tag ${file.file_path}: ${e}`);
+              }
             }
           }
+          addToLogs(dryRun ? `Dry run: ${taggedCount} files would be tagged.` : `Tagged ${taggedCount} files.`);
+        } else {
+          addToLogs('Tagging skipped.');
+        }
+
+        // A dry run stops before anything is organized
+        if (dryRun) {
+          await invoke('clean_staging', { path: stagingPath });
+          addToLogs("Dry run complete. Staging directory cleaned; no files were changed.");
+          setStatus('success');
+          return;
         }
-        addToLogs(`Tagged ${taggedCount} files.`);
 
         // 3. Phockup Staging -> Dest
         setStatus('organizing');
@@ -746,6 +844,11 @@ export function Ingest() {
                 <span className="w-8 h-8 rounded-full bg-slate-700 flex items-center justify-center text-sm">2</span>
                 Assign Tags
               </h2>
+              <div className="flex items-center gap-6">
+              <label className="flex items-center gap-2 cursor-pointer text-sm text-slate-300">
+                <input type="checkbox" checked={tagDryRun} onChange={(e) => setTagDryRun(e.target.checked)} disabled={!enableTagging} />
+                Dry run (preview tags only)
+              </label>
               <label className="flex items-center gap-3 cursor-pointer">
                 <span className="text-sm font-medium text-slate-300">Enable Tagging & Staging</span>
                 <div className="relative inline-flex items-center cursor-pointer">
@@ -753,6 +856,7 @@ export function Ingest() {
                   <div className="w-11 h-6 bg-slate-700 peer-focus:outline-none rounded-full peer peer-checked:after:translate-x-full rtl:peer-checked:after:-translate-x-full peer-checked:after:border-white after:content-[''] after:absolute after:top-[2px] after:start-[2px] after:bg-white after:border-gray-300 after:border after:rounded-full after:h-5 after:w-5 after:transition-all peer-checked:bg-purple-600"></div>
                 </div>
               </label>
+              </div>
             </div>
 
             {enableTagging && (
@@ -12,6 +12,16 @@ interface SettingsData {
   // Custom binary path overrides (empty = use bundled/PATH)
   phockupPath: string;
   immichGoPath: string;
+  // Where metadata fixes are written: the file, an XMP sidecar, or both
+  metadataWriteTarget: 'in_place' | 'sidecar' | 'both';
+  // What ingest does with videos embedded in Google/Samsung motion photos
+  motionPhotoIngest: 'off' | 'report' | 'extract' | 'strip';
+  // Whether ingest checks files for corruption and moves bad ones aside
+  integrityIngest: 'off' | 'report' | 'quarantine';
+  // Whether the integrity check decodes JPEG and PNG image data, not just the file structure
+  integrityDecode: boolean;
+  // Where quarantined files go; must be outside the archive so Immich never sees them
+  quarantinePath: string;
 }
 
 interface ValidationStatus {
@@ -29,6 +39,11 @@ export function Settings() {
     immichApiKey: "",
     phockupPath: "",
     immichGoPath: "",
+    metadataWriteTarget: "in_place",
+    motionPhotoIngest: "off",
+    integrityIngest: "off",
+    integrityDecode: false,
+    quarantinePath: "",
   });
   const [validation, setValidation] = useState<ValidationStatus>({
     phockup: false,
@@ -170,6 +185,26 @@ export function Settings() {
     }
   };
 
+  const handleBrowseQuarantine = async () => {
+    try {
+      const selected = await openDialog({
+        directory: true,
+        multiple: false,
+        title: "Select Quarantine Folder",
+      });
+      if (selected) {
+        setSettings(prev => ({ ...prev, quarantinePath: selected as string }));
+      }
+    } catch (err) {
+      console.error("Failed to list files", err);
+    }
+  };
+
+  const isInsideArchive = (path: string) => {
+    const archive = settings.archivePath.replace(/[\\/]+$/, '');
+    return archive !== '' && (path === archive || path.startsWith(`${archive}/`) || path.startsWith(`${archive}\\`));
+  };
+
   const handleChange = (key: keyof SettingsData, value: string) => {
     setSettings(prev => ({ ...prev, [key]: value }));
   };
@@ -373,6 +408,98 @@ export function Settings() {
               </button>
             </div>
           </div>
+
+          <div className="space-y-2">
+            <label className="block text-sm font-medium text-slate-300">
+              Metadata Write Target
+            </label>
+            <p className="text-xs text-slate-500 mb-2">
+              Where date and keyword fixes are saved. Sidecars (an existing <code>photo.xmp</code>, else <code>photo.jpg.xmp</code>) leave originals untouched and are read by Immich and darktable
+            </p>
+            <select
+              className="input-field w-full"
+              value={settings.metadataWriteTarget}
+              onChange={(e) => handleChange('metadataWriteTarget', e.target.value)}
+            >
+              <option value="in_place">Rewrite the original file</option>
+              <option value="sidecar">XMP sidecar only</option>
+              <option value="both">Original file and XMP sidecar</option>
+            </select>
+          </div>
+
+          <div className="space-y-2">
+            <label className="block text-sm font-medium text-slate-300">
+              Motion Photos During Ingest
+            </label>
+            <p className="text-xs text-slate-500 mb-2">
+              Google and Samsung motion photos carry a short MP4 inside the JPEG. Extracting saves it as a separate <code>.mp4</code> with the photo's date
+            </p>
+            <select
+              className="input-field w-full"
+              value={settings.motionPhotoIngest}
+              onChange={(e) => handleChange('motionPhotoIngest', e.target.value)}
+            >
+              <option value="off">Leave them as they are</option>
+              <option value="report">Only report them</option>
+              <option value="extract">Extract the video to a separate file</option>
+              <option value="strip">Strip the video from the photo</option>
+            </select>
+          </div>
+
+          <div className="space-y-2">
+            <label className="block text-sm font-medium text-slate-300">
+              Corrupt Files During Ingest
+            </label>
+            <p className="text-xs text-slate-500 mb-2">
+              Checks for empty, truncated and structurally broken photos and videos. Quarantined files go to the quarantine folder instead of being organized
+            </p>
+            <select
+              className="input-field w-full"
+              value={settings.integrityIngest}
+              onChange={(e) => handleChange('integrityIngest', e.target.value)}
+            >
+              <option value="off">Don't check</option>
+              <option value="report">Only report them</option>
+              <option value="quarantine">Move them to quarantine</option>
+            </select>
+            <label className="flex items-center gap-2 cursor-pointer text-sm text-slate-300">
+              <input
+                type="checkbox"
+                checked={settings.integrityDecode}
+                onChange={(e) => setSettings(prev => ({ ...prev, integrityDecode: e.target.checked }))}
+              />
+              Decode JPEG and PNG images (slower; without it only the file structure is checked)
+            </label>
+          </div>
+
+          <div className="space-y-2">
+            <label className="block text-sm font-medium text-slate-300">
+              Quarantine Folder
+            </label>
+            <p className="text-xs text-slate-500 mb-2">
+              Keep it outside the archive, or Immich will import the broken files again
+            </p>
+            <div className="flex gap-3">
+              <input
+                type="text"
+                className="input-field flex-1"
+                placeholder="/Users/username/Pictures/Quarantine"
+                value={settings.quarantinePath}
+                onChange={(e) => handleChange('quarantinePath', e.target.value)}
+              />
+              <button
+                onClick={handleBrowseQuarantine}
+                className="btn-secondary whitespace-nowrap px-6"
+              >
+                Browse
+              </button>
+            </div>
+            {settings.quarantinePath && isInsideArchive(settings.quarantinePath) && (
+              <p className="text-xs text-amber-400 flex items-center gap-1">
+                <AlertTriangle className="w-3 h-3" /> This folder is inside the archive; quarantine will be skipped
+              </p>
+            )}
+          </div>
         </div>
       </div>
 